@@ -103,14 +103,14 @@ assert(a, nil)
 
 a = 0
 255.times do |n|
-  a = a + n
+  a += n
 end
 assert(a, 32385)
 
 a = 0
 b = 0
 24.times do |n|
-  b = b + n + a
+  b += n + a
   a = b
 end
 
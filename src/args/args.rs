@@ -1,21 +1,32 @@
-// use crate::node::node::*;
-// use crate::util::util::*;
+use crate::proc::proc::*;
 use crate::value::value::*;
-use crate::vm::vm::*;
+
+/// The block passed to a method.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// A `do ... end` or `{ ... }` block written at the call.
+    Captured(CapturedBlock),
+    /// A Proc passed with `&`.
+    Proc(ProcRef),
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Args {
-    pub body: Vec<ISeq>,
+    pub block: Option<Block>,
     pub args: Vec<Value>,
-    pub table: u8,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Args {
     pub fn new() -> Self {
         Self {
-            body: vec![],
+            block: None,
             args: vec![],
-            table: 0,
         }
     }
 }
@@ -82,7 +82,7 @@ impl GlobalClassTable {
         new_class
     }
 
-    pub fn get(&mut self, class_ref: ClassRef) -> &ClassInfo {
+    pub fn get(&self, class_ref: ClassRef) -> &ClassInfo {
         self.table
             .get(&class_ref)
             .unwrap_or_else(|| panic!("GlobalClassTable#get(): ClassRef is not valid."))
@@ -102,6 +102,11 @@ pub enum Class {
     FixNum,
     FixDecimalNum,
     String,
+    Symbol,
     Class,
     Instance,
+    Array,
+    Range,
+    Hash,
+    Proc,
 }
@@ -0,0 +1,251 @@
+use crate::lexer::lexer::Error;
+use crate::parser::parser::*;
+use crate::util::annot::*;
+use crate::util::util::*;
+use crate::vm::vm::*;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// A span of source code with a short explanation printed under it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub loc: Loc,
+    pub message: String,
+}
+
+/// A structured error report. The parser and the VM only produce error
+/// values; turning them into text for the user happens here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic positioned at the start of `loc`.
+    pub fn new(source_info: &SourceInfo, message: impl Into<String>, loc: Loc) -> Self {
+        let (line, start, _) = line_bounds(source_info, loc.0);
+        Diagnostic {
+            path: source_info.path.clone(),
+            line: line + 1,
+            column: loc.0.saturating_sub(start) + 1,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn with_label(mut self, loc: Loc, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            loc,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn from_parse_error(source_info: &SourceInfo, err: &ParseError) -> Self {
+        let (message, label) = match &err.kind {
+            ParseErrorKind::UnexpectedToken { expected, found } => (
+                format!("syntax error, expected {}, found {}", expected, found),
+                "unexpected token",
+            ),
+            ParseErrorKind::EOF => (
+                "syntax error, unexpected end-of-input".to_string(),
+                "input ends here",
+            ),
+            ParseErrorKind::LiteralBeforeDefinition => (
+                format!(
+                    "syntax error, unexpected `{}` before definition",
+                    source_text(source_info, err.loc)
+                ),
+                "expected a line break before this definition",
+            ),
+            ParseErrorKind::RetryOutsideRescue => (
+                "Invalid retry".to_string(),
+                "only allowed inside a `rescue` clause",
+            ),
+            ParseErrorKind::InvalidAssignTarget => (
+                "Invalid assignment target".to_string(),
+                "only variables, elements and attributes can be assigned to",
+            ),
+            ParseErrorKind::InvalidParamOrder => (
+                "Invalid parameter order".to_string(),
+                "parameters go in the order: positional, `*rest`, keywords, `**opts`, `&block`",
+            ),
+            ParseErrorKind::InvalidJump(keyword) => (
+                format!("Invalid {}", keyword.as_str()),
+                "not inside a loop or block",
+            ),
+            ParseErrorKind::UnsupportedPattern => (
+                "pattern matching is unsupported".to_string(),
+                "`in` takes values, ranges and classes",
+            ),
+            ParseErrorKind::InnerClassDefinitionInMethodDefinition => (
+                "class definition in method body".to_string(),
+                "method defined here",
+            ),
+            ParseErrorKind::Lexer(kind) => {
+                return Self::from_lexer_error(source_info, *kind, err.loc);
+            }
+        };
+        Self::new(source_info, message, err.loc).with_label(err.loc, label)
+    }
+
+    pub fn from_lexer_error(source_info: &SourceInfo, err: Error, loc: Loc) -> Self {
+        let (message, label) = match err {
+            Error::EOF => (
+                "unterminated literal meets end of file".to_string(),
+                "literal starts here",
+            ),
+            Error::UnexpectedChar => (
+                format!("invalid character `{}`", source_text(source_info, loc)),
+                "not valid here",
+            ),
+            Error::NotMatchPunctuation => ("unmatched punctuation".to_string(), "not matched"),
+            Error::ForbiddenTab => (
+                "tab character is not allowed".to_string(),
+                "use spaces for indentation",
+            ),
+        };
+        let loc = Loc(loc.0, loc.0);
+        Self::new(source_info, message, loc).with_label(loc, label)
+    }
+
+    /// Builds a diagnostic for an uncaught error, using the message and
+    /// class name ruby would print and listing the backtrace as a note.
+    pub fn from_runtime_error(vm: &mut VM, err: &RuntimeError) -> Self {
+        let message = vm.error_to_s(err);
+        let mut diag =
+            Self::new(&vm.source_info, message, err.loc).with_label(err.loc, "raised here");
+        if !err.backtrace.is_empty() {
+            diag = diag.with_note(format!("backtrace:\n{}", err.backtrace.join("\n")));
+        }
+        diag
+    }
+
+    /// Renders the diagnostic with the annotated source lines. ANSI colour
+    /// escapes are only emitted when `colour` is set.
+    pub fn render(&self, source_info: &SourceInfo, colour: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if colour {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let mut labels = self.labels.clone();
+        labels.sort_by_key(|label| label.loc.0);
+        let width = labels
+            .iter()
+            .map(|label| line_bounds(source_info, label.loc.0).0 + 1)
+            .max()
+            .unwrap_or(self.line)
+            .to_string()
+            .len();
+        let gutter = paint(BLUE, &format!("{} |", " ".repeat(width)));
+
+        let mut s = format!(
+            "{}{}\n",
+            paint(RED, "error"),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+        s += &format!(
+            "{}{} {}:{}:{}\n",
+            " ".repeat(width),
+            paint(BLUE, "-->"),
+            self.path,
+            self.line,
+            self.column
+        );
+        if !labels.is_empty() {
+            s += &format!("{}\n", gutter);
+        }
+        let mut last_line = None;
+        for label in &labels {
+            let (line, start, end) = line_bounds(source_info, label.loc.0);
+            if last_line != Some(line) {
+                let text: String = source_info.code[start..end].iter().collect();
+                s += &format!(
+                    "{} {}\n",
+                    paint(BLUE, &format!("{:>width$} |", line + 1, width = width)),
+                    text
+                );
+                last_line = Some(line);
+            }
+            let first = label.loc.0.min(end);
+            let last = label.loc.1.min(end.max(start + 1) - 1).max(first);
+            let mut marker = "^".repeat(last - first + 1);
+            if !label.message.is_empty() {
+                marker = format!("{} {}", marker, label.message);
+            }
+            s += &format!(
+                "{} {}{}\n",
+                gutter,
+                " ".repeat(first - start),
+                paint(RED, &marker)
+            );
+        }
+        for note in &self.notes {
+            let mut lines = note.lines();
+            if let Some(first) = lines.next() {
+                s += &format!(
+                    "{} {} {}\n",
+                    " ".repeat(width),
+                    paint(BLUE, "="),
+                    paint(BOLD, &format!("note: {}", first))
+                );
+            }
+            for line in lines {
+                s += &format!("{}   {}\n", " ".repeat(width), line);
+            }
+        }
+        s
+    }
+
+    /// Writes the rendered diagnostic to stderr.
+    pub fn emit(&self, source_info: &SourceInfo, colour: bool) {
+        eprint!("{}", self.render(source_info, colour));
+    }
+}
+
+/// Returns the 0-origin line number containing `pos` along with the
+/// positions of its first character and of its terminating newline (or the
+/// end of the code). Computed from the code itself so that it also works
+/// when the lexer stopped before recording every line.
+fn line_bounds(source_info: &SourceInfo, pos: usize) -> (usize, usize, usize) {
+    let code = &source_info.code;
+    let pos = pos.min(code.len());
+    let line = code[..pos].iter().filter(|ch| **ch == '\n').count();
+    let start = code[..pos]
+        .iter()
+        .rposition(|ch| *ch == '\n')
+        .map_or(0, |i| i + 1);
+    let end = code[pos..]
+        .iter()
+        .position(|ch| *ch == '\n')
+        .map_or(code.len(), |i| pos + i);
+    (line, start, end)
+}
+
+/// Returns the source text covered by `loc`, cut at the end of its first line.
+fn source_text(source_info: &SourceInfo, loc: Loc) -> String {
+    let code = &source_info.code;
+    let start = loc.0.min(code.len());
+    let end = (loc.1 + 1).min(code.len()).max(start);
+    code[start..end]
+        .iter()
+        .take_while(|ch| **ch != '\n')
+        .collect()
+}
@@ -0,0 +1,134 @@
+#[cfg(test)]
+mod test {
+    use crate::diagnostics::diagnostics::*;
+    use crate::parser::parser::*;
+    use crate::vm::vm::*;
+
+    fn assert_parse_diagnostic(script: &str, expected: &str) {
+        let mut parser = Parser::new();
+        parser.lexer.source_info.path = "test.rb".to_string();
+        let err = parser.parse_program(script.to_string()).unwrap_err();
+        let source_info = &parser.lexer.source_info;
+        let got = Diagnostic::from_parse_error(source_info, &err).render(source_info, false);
+        if got != expected {
+            panic!("Expected:\n{}\nGot:\n{}", expected, got);
+        }
+    }
+
+    fn assert_runtime_diagnostic(script: &str, expected: &str) {
+        let mut parser = Parser::new();
+        parser.lexer.source_info.path = "test.rb".to_string();
+        let node = parser.parse_program(script.to_string()).unwrap();
+        let mut vm = VM::new();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        let err = vm.eval().unwrap_err();
+        let got = Diagnostic::from_runtime_error(&mut vm, &err).render(&vm.source_info, false);
+        if got != expected {
+            panic!("Expected:\n{}\nGot:\n{}", expected, got);
+        }
+    }
+
+    #[test]
+    fn diagnostic_unexpected_token() {
+        let program = "a = 1\nb = a + )\n";
+        let expected = "error: syntax error, expected an expression, found `)`
+ --> test.rb:2:9
+  |
+2 | b = a + )
+  |         ^ unexpected token
+";
+        assert_parse_diagnostic(program, expected);
+    }
+
+    #[test]
+    fn diagnostic_inner_class_definition() {
+        let program = "def foo\n  class Bar\n  end\nend";
+        let expected = "error: class definition in method body
+ --> test.rb:1:1
+  |
+1 | def foo
+  | ^^^ method defined here
+";
+        assert_parse_diagnostic(program, expected);
+    }
+
+    #[test]
+    fn diagnostic_inner_class_definition_after_body() {
+        let program = "def foo(a, b)\n  x = 1\n  class Bar\n  end\nend";
+        let expected = "error: class definition in method body
+ --> test.rb:1:1
+  |
+1 | def foo(a, b)
+  | ^^^ method defined here
+";
+        assert_parse_diagnostic(program, expected);
+        let program = "class Foo\n  def bar\n    class Baz\n    end\n  end\nend";
+        let expected = "error: class definition in method body
+ --> test.rb:2:3
+  |
+2 |   def bar
+  |   ^^^ method defined here
+";
+        assert_parse_diagnostic(program, expected);
+    }
+
+    #[test]
+    fn diagnostic_unsupported_pattern() {
+        let program = "case [1, 2]\nin [a, b] then a\nend\n";
+        let expected = "error: pattern matching is unsupported
+ --> test.rb:2:4
+  |
+2 | in [a, b] then a
+  |    ^^^^^^ `in` takes values, ranges and classes
+";
+        assert_parse_diagnostic(program, expected);
+    }
+
+    #[test]
+    fn diagnostic_lexer_error() {
+        let program = "a = 1\n\tb = 2\n";
+        let expected = "error: tab character is not allowed
+ --> test.rb:2:1
+  |
+2 | \tb = 2
+  | ^ use spaces for indentation
+";
+        assert_parse_diagnostic(program, expected);
+    }
+
+    #[test]
+    fn diagnostic_runtime_error() {
+        let program = "def foo
+  1 / 0
+end
+foo()";
+        let expected = "error: divided by 0 (ZeroDivisionError)
+ --> test.rb:2:3
+  |
+2 |   1 / 0
+  |   ^^^^^ raised here
+  = note: backtrace:
+    test.rb:2:in 'foo'
+    test.rb:4:in '<main>'
+";
+        assert_runtime_diagnostic(program, expected);
+    }
+
+    #[test]
+    fn diagnostic_notes_and_colour() {
+        let mut parser = Parser::new();
+        parser.parse_program("x = 1".to_string()).unwrap();
+        let source_info = &parser.lexer.source_info;
+        let diag = Diagnostic::new(source_info, "oops", crate::util::annot::Loc(4, 4))
+            .with_label(crate::util::annot::Loc(4, 4), "here")
+            .with_note("first note");
+        assert_eq!(diag.line, 1);
+        assert_eq!(diag.column, 5);
+        let plain = diag.render(source_info, false);
+        assert!(plain.ends_with("  = note: first note\n"));
+        assert!(!plain.contains('\x1b'));
+        assert!(diag
+            .render(source_info, true)
+            .contains("\x1b[1;31merror\x1b[0m"));
+    }
+}
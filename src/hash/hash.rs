@@ -0,0 +1,226 @@
+use crate::value::value::*;
+use rustc_hash::FxHashMap;
+
+/// A value used as a hash key, made by `GlobalHashTable::key`. Keys are
+/// compared the way `eql?` does: `1` and `1.0` are different keys, strings,
+/// arrays and hashes are compared by content and other objects by identity.
+#[derive(Debug, Clone)]
+pub struct HashKey {
+    pub val: Value,
+    repr: KeyRepr,
+}
+
+/// The part of a key that is hashed and compared. Hashes are replaced by
+/// their entries as they were when the key was made.
+#[derive(Debug, Clone)]
+enum KeyRepr {
+    Value(Value),
+    Array(Vec<KeyRepr>),
+    Hash(Vec<(KeyRepr, KeyRepr)>),
+}
+
+impl PartialEq for KeyRepr {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (KeyRepr::Value(lhs), KeyRepr::Value(rhs)) => lhs == rhs,
+            (KeyRepr::Array(lhs), KeyRepr::Array(rhs)) => lhs == rhs,
+            // Entry order does not matter, and keys within a hash are unique.
+            (KeyRepr::Hash(lhs), KeyRepr::Hash(rhs)) => {
+                lhs.len() == rhs.len() && lhs.iter().all(|entry| rhs.contains(entry))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for KeyRepr {}
+
+impl std::hash::Hash for KeyRepr {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        use std::hash::Hasher;
+        std::mem::discriminant(self).hash(state);
+        match self {
+            KeyRepr::Value(val) => hash_value(val, state),
+            KeyRepr::Array(reprs) => reprs.hash(state),
+            KeyRepr::Hash(entries) => {
+                // Sum the entry hashes so that the order does not matter.
+                let sum = entries.iter().fold(0u64, |sum, entry| {
+                    let mut hasher = rustc_hash::FxHasher::default();
+                    entry.hash(&mut hasher);
+                    sum.wrapping_add(hasher.finish())
+                });
+                sum.hash(state);
+            }
+        }
+    }
+}
+
+fn hash_value<H: std::hash::Hasher>(val: &Value, state: &mut H) {
+    use std::hash::Hash;
+    std::mem::discriminant(val).hash(state);
+    match val {
+        Value::Nil | Value::SelfClass(_) => {}
+        Value::Bool(b) => b.hash(state),
+        Value::FixNum(i) => i.hash(state),
+        Value::BigNum(i) => i.hash(state),
+        // 0.0 and -0.0 are equal keys.
+        Value::FixDecimalNum(f) => (if *f == 0.0 { 0.0 } else { *f }).to_bits().hash(state),
+        Value::String(s) => s.hash(state),
+        Value::Symbol(id) => id.hash(state),
+        Value::Class(r) => r.hash(state),
+        Value::Instance(r) => r.hash(state),
+        Value::Array(vals) => {
+            for val in vals {
+                hash_value(val, state);
+            }
+        }
+        Value::Range(end, start) => {
+            hash_value(start, state);
+            hash_value(end, state);
+        }
+        Value::Hash(r) => r.hash(state),
+        Value::Proc(r) => r.hash(state),
+    }
+}
+
+/// Entries of a hash, kept in insertion order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HashInfo {
+    entries: Vec<(Value, Value)>,
+    index: FxHashMap<KeyRepr, usize>,
+}
+
+impl HashInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Value> {
+        self.index.get(&key.repr).map(|i| &self.entries[*i].1)
+    }
+
+    pub fn contains_key(&self, key: &HashKey) -> bool {
+        self.index.contains_key(&key.repr)
+    }
+
+    /// Sets the value for `key`. A key that is already present keeps its
+    /// position.
+    pub fn insert(&mut self, key: HashKey, val: Value) {
+        match self.index.get(&key.repr) {
+            Some(i) => self.entries[*i].1 = val,
+            None => {
+                self.index.insert(key.repr, self.entries.len());
+                self.entries.push((key.val, val));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &HashKey) -> Option<Value> {
+        let i = self.index.remove(&key.repr)?;
+        let (_, val) = self.entries.remove(i);
+        for pos in self.index.values_mut() {
+            if *pos > i {
+                *pos -= 1;
+            }
+        }
+        Some(val)
+    }
+
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    pub fn keys(&self) -> Vec<Value> {
+        self.entries.iter().map(|(k, _)| k.clone()).collect()
+    }
+
+    pub fn values(&self) -> Vec<Value> {
+        self.entries.iter().map(|(_, v)| v.clone()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashRef(pub usize);
+
+impl std::hash::Hash for HashRef {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalHashTable {
+    table: FxHashMap<HashRef, HashInfo>,
+    hash_id: usize,
+}
+
+impl Default for GlobalHashTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlobalHashTable {
+    pub fn new() -> Self {
+        Self {
+            table: FxHashMap::default(),
+            hash_id: 0,
+        }
+    }
+
+    pub fn new_hash(&mut self, info: HashInfo) -> HashRef {
+        let new_hash = HashRef(self.hash_id);
+        self.hash_id += 1;
+        self.table.insert(new_hash, info);
+        new_hash
+    }
+
+    /// Makes the key for `val`, looking up the entries of any hash in it.
+    pub fn key(&self, val: &Value) -> HashKey {
+        HashKey {
+            val: val.clone(),
+            repr: self.key_repr(val, &mut vec![]),
+        }
+    }
+
+    /// A hash that contains itself is compared by identity where it recurs.
+    fn key_repr(&self, val: &Value, seen: &mut Vec<HashRef>) -> KeyRepr {
+        match val {
+            Value::Array(vals) => {
+                KeyRepr::Array(vals.iter().map(|v| self.key_repr(v, seen)).collect())
+            }
+            Value::Hash(r) if !seen.contains(r) => {
+                seen.push(*r);
+                let entries = self
+                    .get(*r)
+                    .entries()
+                    .iter()
+                    .map(|(k, v)| (self.key_repr(k, seen), self.key_repr(v, seen)))
+                    .collect();
+                seen.pop();
+                KeyRepr::Hash(entries)
+            }
+            _ => KeyRepr::Value(val.clone()),
+        }
+    }
+
+    pub fn get(&self, hash_ref: HashRef) -> &HashInfo {
+        self.table
+            .get(&hash_ref)
+            .expect("GlobalHashTable#get(): HashRef is not valid.")
+    }
+
+    pub fn get_mut(&mut self, hash_ref: HashRef) -> &mut HashInfo {
+        self.table
+            .get_mut(&hash_ref)
+            .expect("GlobalHashTable#get_mut(): HashRef is not valid.")
+    }
+}
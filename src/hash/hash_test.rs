@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod test {
+    use crate::hash::hash::*;
+    use crate::value::value::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn key(val: Value) -> HashKey {
+        GlobalHashTable::new().key(&val)
+    }
+
+    #[test]
+    fn insertion_order() {
+        let mut info = HashInfo::new();
+        info.insert(key(string("b")), Value::FixNum(1));
+        info.insert(key(string("a")), Value::FixNum(2));
+        info.insert(key(string("b")), Value::FixNum(3));
+        assert_eq!(info.keys(), vec![string("b"), string("a")]);
+        assert_eq!(info.values(), vec![Value::FixNum(3), Value::FixNum(2)]);
+    }
+
+    #[test]
+    fn remove_keeps_order() {
+        let mut info = HashInfo::new();
+        for i in 0..4 {
+            info.insert(key(Value::FixNum(i)), Value::FixNum(i * 10));
+        }
+        assert_eq!(info.remove(&key(Value::FixNum(1))), Some(Value::FixNum(10)));
+        assert_eq!(info.remove(&key(Value::FixNum(1))), None);
+        assert_eq!(info.get(&key(Value::FixNum(3))), Some(&Value::FixNum(30)));
+        info.insert(key(Value::FixNum(1)), Value::FixNum(11));
+        assert_eq!(
+            info.keys(),
+            vec![
+                Value::FixNum(0),
+                Value::FixNum(2),
+                Value::FixNum(3),
+                Value::FixNum(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn key_equality() {
+        let mut info = HashInfo::new();
+        info.insert(key(Value::FixNum(1)), string("int"));
+        info.insert(key(Value::FixDecimalNum(1.0)), string("float"));
+        info.insert(key(Value::Array(vec![string("x")])), string("array"));
+        info.insert(key(Value::FixDecimalNum(0.0)), string("zero"));
+        assert_eq!(info.len(), 4);
+        assert_eq!(info.get(&key(Value::FixNum(1))), Some(&string("int")));
+        assert_eq!(
+            info.get(&key(Value::FixDecimalNum(1.0))),
+            Some(&string("float"))
+        );
+        assert_eq!(
+            info.get(&key(Value::Array(vec![string("x")]))),
+            Some(&string("array"))
+        );
+        assert_eq!(
+            info.get(&key(Value::FixDecimalNum(-0.0))),
+            Some(&string("zero"))
+        );
+    }
+}
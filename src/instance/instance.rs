@@ -1,4 +1,5 @@
 use crate::class::class::*;
+use crate::util::annot::*;
 use crate::util::util::*;
 use crate::value::value::*;
 use rustc_hash::FxHashMap;
@@ -9,6 +10,9 @@ pub struct InstanceInfo {
     pub class_name: String,
     pub instance_var: FxHashMap<IdentId, Value>,
     pub subclass: FxHashMap<IdentId, ClassRef>,
+    /// Where the instance was first raised, when it is an exception.
+    pub raised_loc: Option<Loc>,
+    pub backtrace: Vec<String>,
 }
 
 impl InstanceInfo {
@@ -18,6 +22,8 @@ impl InstanceInfo {
             class_name,
             instance_var: FxHashMap::default(),
             subclass: FxHashMap::default(),
+            raised_loc: None,
+            backtrace: vec![],
         }
     }
 }
@@ -37,6 +43,12 @@ pub struct GlobalInstanceTable {
     instance_id: usize,
 }
 
+impl Default for GlobalInstanceTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GlobalInstanceTable {
     pub fn new() -> Self {
         Self {
@@ -61,7 +73,7 @@ impl GlobalInstanceTable {
         new_instance
     }
 
-    pub fn get(&mut self, instance_ref: InstanceRef) -> &InstanceInfo {
+    pub fn get(&self, instance_ref: InstanceRef) -> &InstanceInfo {
         self.table
             .get(&instance_ref)
             .expect("GlobalInstanceTable#get(): InstanceRef is not valid.")
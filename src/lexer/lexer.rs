@@ -1,6 +1,8 @@
 use crate::token::token::*;
 use crate::util::annot::*;
 use crate::util::util::*;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 use rustc_hash::FxHashMap;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -13,6 +15,9 @@ pub struct Lexer {
     relative_column_pos: usize,
     line_pos: usize,
     reserved: FxHashMap<String, Reserved>,
+    /// Where lexing resumes after the line that started the pending
+    /// heredocs, past their bodies.
+    heredoc_end: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -26,6 +31,24 @@ impl LexerResult {
     }
 }
 
+/// The contents of a string literal being read.
+#[derive(Debug, Default)]
+struct StringParts {
+    parts: Vec<StringPart>,
+    /// Text since the last interpolation.
+    buf: String,
+}
+
+impl StringParts {
+    fn push_code(&mut self, tokens: Vec<Token>) {
+        if !self.buf.is_empty() {
+            self.parts
+                .push(StringPart::Str(std::mem::take(&mut self.buf)));
+        }
+        self.parts.push(StringPart::Code(tokens));
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Error {
     EOF,
@@ -34,6 +57,12 @@ pub enum Error {
     ForbiddenTab,
 }
 
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Lexer {
     pub fn new() -> Self {
         let mut reserved = FxHashMap::default();
@@ -60,12 +89,23 @@ impl Lexer {
             "elsif" => Reserved::Elsif,
             "end" => Reserved::End,
             "false" => Reserved::False,
+            "ensure" => Reserved::Ensure,
             "if" => Reserved::If,
+            "rescue" => Reserved::Rescue,
+            "retry" => Reserved::Retry,
             "return" => Reserved::Return,
             "then" => Reserved::Then,
             "true" => Reserved::True,
             "for" => Reserved::For,
-            "in" => Reserved::In
+            "in" => Reserved::In,
+            "next" => Reserved::Next,
+            "not" => Reserved::Not,
+            "redo" => Reserved::Redo,
+            "unless" => Reserved::Unless,
+            "until" => Reserved::Until,
+            "when" => Reserved::When,
+            "while" => Reserved::While,
+            "yield" => Reserved::Yield
         };
 
         let source_info = SourceInfo::new();
@@ -80,6 +120,7 @@ impl Lexer {
             relative_column_pos: 0,
             line_pos: 0,
             reserved,
+            heredoc_end: None,
         }
     }
 
@@ -110,22 +151,23 @@ impl Lexer {
         self.absolute_column_pos += 1;
         self.line_start_pos = self.absolute_column_pos;
         self.relative_column_pos = 0;
+        self.skip_heredoc_bodies();
         tok
     }
 
+    /// Steps over the bodies of heredocs started on the line just ended,
+    /// which were already read along with their headers.
+    fn skip_heredoc_bodies(&mut self) {
+        if let Some(end) = self.heredoc_end.take() {
+            while self.absolute_column_pos < end && self.get().is_ok() {}
+        }
+    }
+
     fn read_space(&mut self) -> Token {
         self.token_start_pos = self.absolute_column_pos;
-        loop {
-            match self.peek() {
-                Ok(c) => match c {
-                    ' ' => {
-                        self.absolute_column_pos += 1;
-                        self.relative_column_pos += 1;
-                    }
-                    _ => break,
-                },
-                Err(_) => break,
-            }
+        while let Ok(' ') = self.peek() {
+            self.absolute_column_pos += 1;
+            self.relative_column_pos += 1;
         }
         Token::new_space(Loc(self.token_start_pos, self.absolute_column_pos - 1))
     }
@@ -136,6 +178,8 @@ impl Lexer {
             if ch == '\n' {
                 return Ok(Some(self.read_eol()));
             } else if ch == '\t' {
+                self.token_start_pos = absolute_column_pos;
+                self.absolute_column_pos = absolute_column_pos;
                 return Err(Error::ForbiddenTab);
             } else if ch == ' ' {
                 return Ok(Some(self.read_space()));
@@ -173,47 +217,135 @@ impl Lexer {
         }
     }
 
+    fn peek_next(&self) -> Result<char, Error> {
+        match self.source_info.code.get(self.absolute_column_pos + 1) {
+            Some(ch) => Ok(*ch),
+            None => Err(Error::EOF),
+        }
+    }
+
+    /// Returns the span of the token being read when `tokenize` failed.
+    pub fn error_loc(&self) -> Loc {
+        let end = self.absolute_column_pos.saturating_sub(1);
+        Loc(self.token_start_pos, end.max(self.token_start_pos))
+    }
+
     fn cur_loc(&self) -> Loc {
         Loc(self.token_start_pos, self.absolute_column_pos - 1)
     }
 
     fn read_number_literal(&mut self, ch: char) -> Result<Token, Error> {
+        if ch == '0' {
+            let radix = match self.peek() {
+                Ok('x') | Ok('X') => Some(16),
+                Ok('o') | Ok('O') => Some(8),
+                Ok('b') | Ok('B') => Some(2),
+                Ok('d') | Ok('D') => Some(10),
+                Ok(ch) if ch.is_ascii_digit() || ch == '_' => Some(8),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                return self.read_radix_literal(radix);
+            }
+        }
         let mut tok = ch.to_string();
-        loop {
-            let ch: char;
-            match self.peek() {
-                Ok(_ch) => ch = _ch,
-                Err(_) => break,
+        self.read_digits(&mut tok)?;
+        let mut is_float = false;
+        // `1.5`, but not `1..5` or `1.times`.
+        if self.peek() == Ok('.') && self.peek_next().is_ok_and(|ch| ch.is_ascii_digit()) {
+            is_float = true;
+            tok.push(self.get()?);
+            self.read_digits(&mut tok)?;
+        }
+        if let Ok('e') | Ok('E') = self.peek() {
+            let exponent_start = match self.peek_next() {
+                Ok('+') | Ok('-') => self
+                    .source_info
+                    .code
+                    .get(self.absolute_column_pos + 2)
+                    .copied(),
+                Ok(ch) => Some(ch),
+                Err(_) => None,
+            };
+            if exponent_start.is_some_and(|ch| ch.is_ascii_digit()) {
+                is_float = true;
+                tok.push(self.get()?);
+                if let Ok('+') | Ok('-') = self.peek() {
+                    tok.push(self.get()?);
+                }
+                self.read_digits(&mut tok)?;
             }
-            if ch.is_numeric() {
+        }
+        if is_float {
+            let f = tok.parse::<f64>().unwrap();
+            Ok(self.new_floatlit(f))
+        } else {
+            Ok(self.new_integer(tok.parse::<BigInt>().unwrap()))
+        }
+    }
+
+    /// Reads the digits of `0x1f`, `0o17`, `017`, `0b101` or `0d99` after
+    /// the leading `0`.
+    fn read_radix_literal(&mut self, radix: u32) -> Result<Token, Error> {
+        if let Ok(ch) = self.peek() {
+            if ch.is_ascii_alphabetic() {
+                self.get()?;
+            }
+        }
+        let mut tok = String::new();
+        while let Ok(ch) = self.peek() {
+            if ch.is_digit(radix) {
                 tok.push(self.get()?);
-            } else if ch == '_' {
+            } else if ch == '_'
+                && !tok.is_empty()
+                && self.peek_next().is_ok_and(|ch| ch.is_digit(radix))
+            {
                 self.get()?;
+            } else if ch.is_ascii_alphanumeric() || ch == '_' {
+                // `0b102`, `0x` or a doubled `_`.
+                return Err(Error::UnexpectedChar);
             } else {
                 break;
             }
         }
-        let i = tok.parse::<i64>().unwrap();
-        Ok(self.new_numlit(i))
+        if tok.is_empty() {
+            // A plain `0` followed by `_`, or a prefix without digits.
+            return Err(Error::UnexpectedChar);
+        }
+        let num = BigInt::parse_bytes(tok.as_bytes(), radix).unwrap();
+        Ok(self.new_integer(num))
+    }
+
+    /// Reads digits into `tok`, dropping the `_` separators between them.
+    fn read_digits(&mut self, tok: &mut String) -> Result<(), Error> {
+        while let Ok(ch) = self.peek() {
+            if ch.is_ascii_digit() {
+                tok.push(self.get()?);
+            } else if ch == '_' && self.peek_next().is_ok_and(|ch| ch.is_ascii_digit()) {
+                self.get()?;
+            } else {
+                break;
+            }
+        }
+        Ok(())
     }
 
     fn read_ascii_alphabetic(&mut self, ch: char) -> Result<Token, Error> {
         let is_const = ch.is_ascii_uppercase();
         let mut tok = ch.to_string();
-        loop {
-            let ch: char;
-            match self.peek() {
-                Ok(_ch) => ch = _ch,
-                Err(_) => {
-                    break;
-                }
-            };
+        while let Ok(ch) = self.peek() {
             if ch.is_ascii_alphanumeric() || ch == '_' {
                 tok.push(self.get()?);
             } else {
                 break;
             }
         }
+        // Method names like `key?` and `merge!`, but not `a!=b`.
+        if let Ok('?' | '!') = self.peek() {
+            if !is_const && self.peek_next() != Ok('=') {
+                tok.push(self.get()?);
+            }
+        }
         match self.reserved.get(&tok) {
             Some(reserved) => Ok(self.new_reserved(*reserved)),
             None => {
@@ -238,17 +370,20 @@ impl Lexer {
 
         reg_punct! {
             "+" => Punct::Plus,
-            "-" => Punct::Minus,
-            "*" => Punct::Mul,
             "/" => Punct::Div,
+            "%" => Punct::Rem,
             "(" => Punct::LParen,
             ")" => Punct::RParen,
             "[" => Punct::LBoxBrackets,
             "]" => Punct::RBoxBrackets,
+            "{" => Punct::LBrace,
+            "}" => Punct::RBrace,
             ";" => Punct::Semi,
-            ":" => Punct::Colon,
             "," => Punct::Comma,
-            "|" => Punct::Pipe
+            "$" => Punct::Dollar,
+            "^" => Punct::Caret,
+            "~" => Punct::Tilde,
+            "?" => Punct::Question
         }
 
         match punct.contains_key(&ch.to_string()) {
@@ -263,11 +398,35 @@ impl Lexer {
                         Ok(self.new_punct(Punct::Dot))
                     }
                 }
+                '-' => {
+                    if self.peek() == Ok('>') {
+                        self.get()?;
+                        Ok(self.new_punct(Punct::Arrow))
+                    } else {
+                        Ok(self.new_punct(Punct::Minus))
+                    }
+                }
+                '*' => {
+                    if self.peek() == Ok('*') {
+                        self.get()?;
+                        Ok(self.new_punct(Punct::Pow))
+                    } else {
+                        Ok(self.new_punct(Punct::Mul))
+                    }
+                }
                 '=' => {
                     let ch = self.peek()?;
                     if ch == '=' {
                         self.get()?;
-                        Ok(self.new_punct(Punct::Eq))
+                        if self.peek() == Ok('=') {
+                            self.get()?;
+                            Ok(self.new_punct(Punct::TEq))
+                        } else {
+                            Ok(self.new_punct(Punct::Eq))
+                        }
+                    } else if ch == '>' {
+                        self.get()?;
+                        Ok(self.new_punct(Punct::FatArrow))
                     } else {
                         Ok(self.new_punct(Punct::Assign))
                     }
@@ -277,6 +436,9 @@ impl Lexer {
                     if ch == '=' {
                         self.get()?;
                         Ok(self.new_punct(Punct::GE))
+                    } else if ch == '>' {
+                        self.get()?;
+                        Ok(self.new_punct(Punct::Shr))
                     } else {
                         Ok(self.new_punct(Punct::GT))
                     }
@@ -285,18 +447,25 @@ impl Lexer {
                     let ch = self.peek()?;
                     if ch == '=' {
                         self.get()?;
-                        Ok(self.new_punct(Punct::LE))
+                        if self.peek() == Ok('>') {
+                            self.get()?;
+                            Ok(self.new_punct(Punct::Cmp))
+                        } else {
+                            Ok(self.new_punct(Punct::LE))
+                        }
+                    } else if ch == '<' {
+                        self.get()?;
+                        Ok(self.new_punct(Punct::Shl))
                     } else {
                         Ok(self.new_punct(Punct::LT))
                     }
                 }
                 '!' => {
-                    let ch1 = self.peek()?;
-                    if ch1 == '=' {
+                    if self.peek() == Ok('=') {
                         self.get()?;
                         Ok(self.new_punct(Punct::NE))
                     } else {
-                        unimplemented!("{}", ch)
+                        Ok(self.new_punct(Punct::Not))
                     }
                 }
                 '&' => {
@@ -309,12 +478,19 @@ impl Lexer {
                     }
                 }
                 '|' => {
-                    let ch = self.peek()?;
-                    if ch == '|' {
+                    if self.peek() == Ok('|') {
                         self.get()?;
                         Ok(self.new_punct(Punct::LOr))
                     } else {
-                        Ok(self.new_punct(Punct::Or))
+                        Ok(self.new_punct(Punct::Pipe))
+                    }
+                }
+                ':' => {
+                    if self.peek() == Ok(':') {
+                        self.get()?;
+                        Ok(self.new_punct(Punct::Scope))
+                    } else {
+                        Ok(self.new_punct(Punct::Colon))
                     }
                 }
                 '@' => {
@@ -326,20 +502,23 @@ impl Lexer {
                         Ok(self.new_punct(Punct::At))
                     }
                 }
-                _ => unimplemented!("{}", ch),
+                _ => Err(Error::UnexpectedChar),
             },
         }
     }
 
+    /// Reads a comment up to the end of the line, leaving the newline to
+    /// end the statement before it.
     fn read_comment(&mut self) -> Token {
         self.token_start_pos = self.absolute_column_pos - 1;
-        let line_end_pos = match self.goto_eol() {
-            None => self.line_start_pos - self.last_coordinate().0 - 2,
-            Some(Error::EOF) => self.absolute_column_pos - 1,
-            _ => unimplemented!(),
-        };
-
-        Token::new_comment(Loc(self.token_start_pos, line_end_pos))
+        while let Ok(ch) = self.peek() {
+            if ch == '\n' {
+                break;
+            }
+            self.absolute_column_pos += 1;
+            self.relative_column_pos += 1;
+        }
+        Token::new_comment(Loc(self.token_start_pos, self.absolute_column_pos - 1))
     }
 
     fn read_string_literal_single(&mut self) -> Result<Token, Error> {
@@ -359,15 +538,193 @@ impl Lexer {
     }
 
     fn read_string_literal_double(&mut self) -> Result<Token, Error> {
-        let mut s = "".to_string();
+        let mut s = StringParts::default();
         loop {
             match self.get()? {
                 '"' => break,
-                '\\' => s.push(self.read_escaped_char()?),
-                c => s.push(c),
+                c => self.read_string_char(c, &mut s)?,
             }
         }
-        Ok(self.new_stringlit(s))
+        Ok(self.new_string(s))
+    }
+
+    /// Adds `c` to a string that allows escapes and interpolation.
+    fn read_string_char(&mut self, c: char, s: &mut StringParts) -> Result<(), Error> {
+        match c {
+            '\\' => s.buf.push(self.read_escaped_char()?),
+            '#' if self.peek() == Ok('{') => {
+                self.get()?;
+                let tokens = self.read_interpolation()?;
+                s.push_code(tokens);
+            }
+            c => s.buf.push(c),
+        }
+        Ok(())
+    }
+
+    /// Reads the tokens of `#{...}` up to the matching `}`, which is
+    /// replaced by an EOF token.
+    fn read_interpolation(&mut self) -> Result<Vec<Token>, Error> {
+        let token_start_pos = self.token_start_pos;
+        let mut tokens = vec![];
+        let mut depth = 0;
+        loop {
+            while let Some(tok) = self.skip_whitespace()? {
+                tokens.push(tok);
+            }
+            self.token_start_pos = self.absolute_column_pos;
+            let ch = self.get()?;
+            let token = self.read_token(ch)?;
+            match token.kind {
+                TokenKind::Punct(Punct::LBrace) => depth += 1,
+                TokenKind::Punct(Punct::RBrace) if depth == 0 => {
+                    tokens.push(self.new_eof(self.token_start_pos));
+                    break;
+                }
+                TokenKind::Punct(Punct::RBrace) => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+        self.token_start_pos = token_start_pos;
+        Ok(tokens)
+    }
+
+    /// Whether the `<` just read begins a heredoc: `<<~ID`, `<<-ID`,
+    /// `<<ID` with an upper case `ID`, or any of them with a quoted `ID`.
+    fn is_heredoc_start(&self) -> bool {
+        let code = &self.source_info.code;
+        let pos = self.absolute_column_pos;
+        if code.get(pos) != Some(&'<') {
+            return false;
+        }
+        let pos = match code.get(pos + 1) {
+            Some('~') | Some('-') => pos + 2,
+            _ => pos + 1,
+        };
+        match code.get(pos) {
+            Some(ch) => ch.is_ascii_uppercase() || *ch == '_' || *ch == '\'' || *ch == '"',
+            None => false,
+        }
+    }
+
+    fn read_heredoc(&mut self) -> Result<Token, Error> {
+        self.get()?;
+        let squiggly = self.peek() == Ok('~');
+        let indented_end = squiggly || self.peek() == Ok('-');
+        if indented_end {
+            self.get()?;
+        }
+        let quote = match self.peek()? {
+            '\'' | '"' => Some(self.get()?),
+            _ => None,
+        };
+        let mut id = String::new();
+        match quote {
+            Some(quote) => loop {
+                match self.get()? {
+                    '\n' => return Err(Error::UnexpectedChar),
+                    c if c == quote => break,
+                    c => id.push(c),
+                }
+            },
+            None => {
+                while let Ok(ch) = self.peek() {
+                    if ch.is_ascii_alphanumeric() || ch == '_' {
+                        id.push(self.get()?);
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+        if id.is_empty() {
+            return Err(Error::UnexpectedChar);
+        }
+
+        let code = &self.source_info.code;
+        let body_start = match self.heredoc_end {
+            Some(end) => end,
+            None => match code[self.absolute_column_pos..]
+                .iter()
+                .position(|c| *c == '\n')
+            {
+                Some(i) => self.absolute_column_pos + i + 1,
+                None => return Err(Error::EOF),
+            },
+        };
+        // Each line of the body as the range of its text, without the
+        // newline.
+        let mut lines = vec![];
+        let mut start = body_start;
+        let end = loop {
+            if start >= self.len {
+                return Err(Error::EOF);
+            }
+            let line_end = code[start..]
+                .iter()
+                .position(|c| *c == '\n')
+                .map_or(self.len, |i| start + i);
+            let line: String = code[start..line_end].iter().collect();
+            let line = if indented_end {
+                line.trim_start()
+            } else {
+                &line
+            };
+            if line == id {
+                break (line_end + 1).min(self.len);
+            }
+            lines.push((start, line_end));
+            start = line_end + 1;
+        };
+        // `<<~` removes the indentation of the least indented line, not
+        // counting blank ones.
+        let indent = if squiggly {
+            lines
+                .iter()
+                .filter(|(start, end)| code[*start..*end].iter().any(|c| *c != ' '))
+                .map(|(start, end)| code[*start..*end].iter().take_while(|c| **c == ' ').count())
+                .min()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+
+        let saved = (
+            self.absolute_column_pos,
+            self.relative_column_pos,
+            self.line_start_pos,
+            self.line_pos,
+            self.token_start_pos,
+            self.source_info.coordinates.len(),
+        );
+        let mut s = StringParts::default();
+        for (start, line_end) in lines {
+            self.absolute_column_pos = start;
+            for _ in 0..indent {
+                if self.peek() != Ok(' ') {
+                    break;
+                }
+                self.get()?;
+            }
+            while self.absolute_column_pos < line_end {
+                let c = self.get()?;
+                if quote == Some('\'') {
+                    s.buf.push(c);
+                } else {
+                    self.read_string_char(c, &mut s)?;
+                }
+            }
+            s.buf.push('\n');
+        }
+        self.absolute_column_pos = saved.0;
+        self.relative_column_pos = saved.1;
+        self.line_start_pos = saved.2;
+        self.line_pos = saved.3;
+        self.token_start_pos = saved.4;
+        self.source_info.coordinates.truncate(saved.5);
+        self.heredoc_end = Some(end);
+        Ok(self.new_string(s))
     }
 
     fn read_escaped_char(&mut self) -> Result<char, Error> {
@@ -386,18 +743,76 @@ impl Lexer {
         Ok(ch)
     }
 
-    fn goto_eol(&mut self) -> Option<Error> {
-        loop {
-            match self.get() {
-                Ok('\n') => return None,
-                Err(Error::EOF) => return Some(Error::EOF),
-                _ => (),
+    /// Whether the `:` just read begins a symbol literal rather than a label
+    /// separator (`a: 1`, `"a": 1`) or a hash value separator (`a:1`).
+    fn is_symbol_start(&self) -> bool {
+        if self.token_start_pos > 0 {
+            let prev = self.source_info.code[self.token_start_pos - 1];
+            if prev.is_ascii_alphanumeric() || prev == '_' || prev == '"' || prev == '\'' {
+                return false;
+            }
+        }
+        match self.peek() {
+            Ok(ch) => {
+                ch.is_ascii_alphabetic()
+                    || ch == '_'
+                    || ch == '"'
+                    || ch == '@'
+                    || ch == '$'
+                    || "+-*/%<>=!&|^~[".contains(ch)
             }
+            Err(_) => false,
         }
     }
 
-    fn last_coordinate(&self) -> (usize, usize, usize) {
-        *self.source_info.coordinates.last().unwrap()
+    fn read_symbol_literal(&mut self) -> Result<Token, Error> {
+        let ch = self.get()?;
+        let name = if ch == '"' {
+            match self.read_string_literal_double()?.kind {
+                TokenKind::StringLit(s) => s,
+                _ => return Err(Error::UnexpectedChar),
+            }
+        } else if ch.is_ascii_alphabetic() || ch == '_' || ch == '@' || ch == '$' {
+            let mut name = ch.to_string();
+            if ch == '@' && self.peek() == Ok('@') {
+                name.push(self.get()?);
+            }
+            while let Ok(ch) = self.peek() {
+                if ch.is_ascii_alphanumeric() || ch == '_' {
+                    name.push(self.get()?);
+                } else {
+                    break;
+                }
+            }
+            match self.peek() {
+                Ok('?') | Ok('!') => name.push(self.get()?),
+                // Setter names like `:name=`, but not `:a==` or `:a=>`.
+                Ok('=')
+                    if ch != '@'
+                        && ch != '$'
+                        && !matches!(self.peek_next(), Ok('=') | Ok('>') | Ok('~')) =>
+                {
+                    name.push(self.get()?)
+                }
+                _ => {}
+            }
+            name
+        } else {
+            // Operator method names, longest match first.
+            let rest: String = self.source_info.code[self.absolute_column_pos - 1..]
+                .iter()
+                .take(3)
+                .collect();
+            let op = OPERATOR_METHODS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or(Error::UnexpectedChar)?;
+            for _ in 1..op.len() {
+                self.get()?;
+            }
+            op.to_string()
+        };
+        Ok(self.new_symbol(name))
     }
 
     pub fn tokenize(&mut self, code_text: impl Into<String>) -> Result<LexerResult, Error> {
@@ -426,38 +841,46 @@ impl Lexer {
                 tokens.push(tok);
             }
             self.token_start_pos = self.absolute_column_pos;
-            let ch: char;
-            match self.get() {
-                Ok(_ch) => ch = _ch,
+            let ch = match self.get() {
+                Ok(ch) => ch,
                 Err(Error::EOF) => {
                     self.push_last_coordinate();
                     tokens.push(self.new_eof(self.token_start_pos));
                     break;
                 }
-                Err(_) => unimplemented!(),
+                Err(err) => return Err(err),
             };
 
-            let token = if ch.is_ascii_alphabetic() || ch == '_' {
-                self.read_ascii_alphabetic(ch)?
-            } else if ch.is_numeric() {
-                self.read_number_literal(ch)?
-            } else if ch.is_ascii_punctuation() {
-                if ch == '#' {
-                    self.read_comment()
-                } else if ch == '\'' {
-                    self.read_string_literal_single()?
-                } else if ch == '"' {
-                    self.read_string_literal_double()?
-                } else {
-                    self.read_ascii_punct(ch)?
-                }
-            } else {
-                return Err(Error::UnexpectedChar);
-            };
+            let token = self.read_token(ch)?;
             tokens.push(token);
         }
         Ok(LexerResult::new(tokens))
     }
+
+    /// Reads the token that starts with `ch`.
+    fn read_token(&mut self, ch: char) -> Result<Token, Error> {
+        if ch.is_ascii_alphabetic() || ch == '_' {
+            self.read_ascii_alphabetic(ch)
+        } else if ch.is_numeric() {
+            self.read_number_literal(ch)
+        } else if ch.is_ascii_punctuation() {
+            if ch == '#' {
+                Ok(self.read_comment())
+            } else if ch == '\'' {
+                self.read_string_literal_single()
+            } else if ch == '"' {
+                self.read_string_literal_double()
+            } else if ch == ':' && self.is_symbol_start() {
+                self.read_symbol_literal()
+            } else if ch == '<' && self.is_heredoc_start() {
+                self.read_heredoc()
+            } else {
+                self.read_ascii_punct(ch)
+            }
+        } else {
+            Err(Error::UnexpectedChar)
+        }
+    }
 }
 
 #[allow(unused)]
@@ -478,10 +901,38 @@ impl Lexer {
         Annot::new(TokenKind::NumLit(num), self.cur_loc())
     }
 
+    /// An integer literal, as a `BigNumLit` when it does not fit in an i64.
+    fn new_integer(&self, num: BigInt) -> Token {
+        match num.to_i64() {
+            Some(i) => self.new_numlit(i),
+            None => Annot::new(TokenKind::BigNumLit(num), self.cur_loc()),
+        }
+    }
+
+    fn new_floatlit(&self, num: f64) -> Token {
+        Annot::new(TokenKind::FloatLit(num), self.cur_loc())
+    }
+
     fn new_stringlit(&self, s: String) -> Token {
         Annot::new(TokenKind::StringLit(s), self.cur_loc())
     }
 
+    /// A plain string literal, or an interpolated one if it has any `#{...}`.
+    fn new_string(&self, s: StringParts) -> Token {
+        let StringParts { mut parts, buf } = s;
+        if parts.is_empty() {
+            return self.new_stringlit(buf);
+        }
+        if !buf.is_empty() {
+            parts.push(StringPart::Str(buf));
+        }
+        Annot::new(TokenKind::InterpolatedString(parts), self.cur_loc())
+    }
+
+    fn new_symbol(&self, name: String) -> Token {
+        Annot::new(TokenKind::Symbol(name), self.cur_loc())
+    }
+
     fn new_punct(&self, punct: Punct) -> Token {
         Annot::new(TokenKind::Punct(punct), self.cur_loc())
     }
@@ -28,6 +28,9 @@ mod test {
     (NumLit($num:expr), ($loc_0:expr, $loc_1:expr)) => {
         Token::new_numlit($num, Loc($loc_0, $loc_1))
     };
+    (Symbol($item:expr), ($loc_0:expr, $loc_1:expr)) => {
+        Token::new_symbol($item, Loc($loc_0, $loc_1))
+    };
     (Line, ($loc_0:expr, $loc_1:expr)) => {
         Token::new_line(Loc($loc_0, $loc_1))
     };
@@ -163,4 +166,169 @@ mod test {
         ];
         assert_lexer(program, ans);
     }
+
+    #[test]
+    fn method_name_suffix() {
+        let program = "key?(a!=b)";
+        let ans = vec![
+            Token![Ident("key?".to_string()), (0, 3)],
+            Token![Punct(Punct::LParen), (4, 4)],
+            Token![Ident("a".to_string()), (5, 5)],
+            Token![Punct(Punct::NE), (6, 7)],
+            Token![Ident("b".to_string()), (8, 8)],
+            Token![Punct(Punct::RParen), (9, 9)],
+            Token![EOF, (10, 10)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn hash_label() {
+        let program = "{a: 1}";
+        let ans = vec![
+            Token![Punct(Punct::LBrace), (0, 0)],
+            Token![Ident("a".to_string()), (1, 1)],
+            Token![Punct(Punct::Colon), (2, 2)],
+            Token![Space, (3, 3)],
+            Token![NumLit(1), (4, 4)],
+            Token![Punct(Punct::RBrace), (5, 5)],
+            Token![EOF, (6, 6)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn symbol() {
+        let program = "{a: :b, :c => :\"d e\"}[:<=>]";
+        let ans = vec![
+            Token![Punct(Punct::LBrace), (0, 0)],
+            Token![Ident("a".to_string()), (1, 1)],
+            Token![Punct(Punct::Colon), (2, 2)],
+            Token![Space, (3, 3)],
+            Token![Symbol("b"), (4, 5)],
+            Token![Punct(Punct::Comma), (6, 6)],
+            Token![Space, (7, 7)],
+            Token![Symbol("c"), (8, 9)],
+            Token![Space, (10, 10)],
+            Token![Punct(Punct::FatArrow), (11, 12)],
+            Token![Space, (13, 13)],
+            Token![Symbol("d e"), (14, 19)],
+            Token![Punct(Punct::RBrace), (20, 20)],
+            Token![Punct(Punct::LBoxBrackets), (21, 21)],
+            Token![Symbol("<=>"), (22, 25)],
+            Token![Punct(Punct::RBoxBrackets), (26, 26)],
+            Token![EOF, (27, 27)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let program = "\"a#{b}c\"";
+        let ans = vec![
+            Token::new(
+                TokenKind::InterpolatedString(vec![
+                    StringPart::Str("a".to_string()),
+                    StringPart::Code(vec![
+                        Token![Ident("b".to_string()), (4, 4)],
+                        Token![EOF, (5, 5)],
+                    ]),
+                    StringPart::Str("c".to_string()),
+                ]),
+                Loc(0, 7),
+            ),
+            Token![EOF, (8, 8)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn heredoc() {
+        let program = "f(<<~A, <<-'B')\n    x\n      y\n  A\n  #{z}\n  B\n";
+        let ans = vec![
+            Token![Ident("f".to_string()), (0, 0)],
+            Token![Punct(Punct::LParen), (1, 1)],
+            Token::new(TokenKind::StringLit("x\n  y\n".to_string()), Loc(2, 5)),
+            Token![Punct(Punct::Comma), (6, 6)],
+            Token![Space, (7, 7)],
+            Token::new(TokenKind::StringLit("  #{z}\n".to_string()), Loc(8, 13)),
+            Token![Punct(Punct::RParen), (14, 14)],
+            Token![Line, (15, 15)],
+            Token![EOF, (45, 45)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn float_literal() {
+        let program = "1_0.2_5e-3 1.e 2..3";
+        let ans = vec![
+            Token::new_floatlit(10.25e-3, Loc(0, 9)),
+            Token![Space, (10, 10)],
+            Token![NumLit(1), (11, 11)],
+            Token![Punct(Punct::Dot), (12, 12)],
+            Token![Ident("e".to_string()), (13, 13)],
+            Token![Space, (14, 14)],
+            Token![NumLit(2), (15, 15)],
+            Token![Punct(Punct::Range), (16, 17)],
+            Token![NumLit(3), (18, 18)],
+            Token![EOF, (19, 19)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn radix_literal() {
+        let program = "0x1F 0o17 0b1_01 017 0d9 18446744073709551616";
+        let ans = vec![
+            Token![NumLit(31), (0, 3)],
+            Token![Space, (4, 4)],
+            Token![NumLit(15), (5, 8)],
+            Token![Space, (9, 9)],
+            Token![NumLit(5), (10, 15)],
+            Token![Space, (16, 16)],
+            Token![NumLit(15), (17, 19)],
+            Token![Space, (20, 20)],
+            Token![NumLit(9), (21, 23)],
+            Token![Space, (24, 24)],
+            Token::new_bignumlit("18446744073709551616".parse().unwrap(), Loc(25, 44)),
+            Token![EOF, (45, 45)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn operators() {
+        let program = "**<=>===<<>>^~||!|";
+        let ans = vec![
+            Token![Punct(Punct::Pow), (0, 1)],
+            Token![Punct(Punct::Cmp), (2, 4)],
+            Token![Punct(Punct::TEq), (5, 7)],
+            Token![Punct(Punct::Shl), (8, 9)],
+            Token![Punct(Punct::Shr), (10, 11)],
+            Token![Punct(Punct::Caret), (12, 12)],
+            Token![Punct(Punct::Tilde), (13, 13)],
+            Token![Punct(Punct::LOr), (14, 15)],
+            Token![Punct(Punct::Not), (16, 16)],
+            Token![Punct(Punct::Pipe), (17, 17)],
+            Token![EOF, (18, 18)],
+        ];
+        assert_lexer(program, ans);
+    }
+
+    #[test]
+    fn arrow() {
+        let program = "->x{x-1}";
+        let ans = vec![
+            Token![Punct(Punct::Arrow), (0, 1)],
+            Token![Ident("x".to_string()), (2, 2)],
+            Token![Punct(Punct::LBrace), (3, 3)],
+            Token![Ident("x".to_string()), (4, 4)],
+            Token![Punct(Punct::Minus), (5, 5)],
+            Token![NumLit(1), (6, 6)],
+            Token![Punct(Punct::RBrace), (7, 7)],
+            Token![EOF, (8, 8)],
+        ];
+        assert_lexer(program, ans);
+    }
 }
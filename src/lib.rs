@@ -1,11 +1,14 @@
 pub mod class;
 // pub mod eval;
+pub mod args;
+pub mod diagnostics;
+pub mod hash;
 pub mod instance;
 pub mod lexer;
 pub mod node;
 pub mod parser;
+pub mod proc;
 pub mod token;
 pub mod util;
-pub mod args;
 pub mod value;
 pub mod vm;
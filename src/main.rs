@@ -1,36 +1,88 @@
 use clap::{App, Arg};
+use taiko::diagnostics::diagnostics::*;
 use taiko::parser::parser::*;
+use taiko::util::util::SourceInfo;
 use taiko::vm::vm::*;
 extern crate clap;
 extern crate rustyline;
 
+/// The interpreter runs on a thread of its own with this much stack, so that
+/// deep recursion is bounded by a known size rather than the main thread's.
+const STACK_SIZE: usize = 256 << 20;
+/// The VM's `stack_limit` on that thread, leaving room for the frames below
+/// the first Ruby call and the work of the last one allowed.
+const STACK_LIMIT: usize = 240 << 20;
+
 fn main() {
+    let interpreter = std::thread::Builder::new()
+        .name("taiko".to_string())
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("cannot spawn the interpreter thread");
+    if interpreter.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+fn run() {
     let app = App::new("taiko")
         .version("0.0.1")
         .author("ysawc")
         .about("A toy ruby interpreter")
-        .arg(Arg::new("file"));
+        .arg(Arg::new("file"))
+        .arg(
+            Arg::new("dump-iseq")
+                .long("dump-iseq")
+                .help("Print the generated instruction sequences instead of running"),
+        )
+        .arg(
+            Arg::new("dump-ast")
+                .long("dump-ast")
+                .help("Print the syntax tree as an S-expression instead of running"),
+        )
+        .arg(
+            Arg::new("check")
+                .short('c')
+                .long("check")
+                .help("Check syntax only, reporting every syntax error found"),
+        );
     let app_matches = app.get_matches();
+    let mode = if app_matches.is_present("dump-iseq") {
+        Mode::DumpISeq
+    } else if app_matches.is_present("dump-ast") {
+        Mode::DumpAst
+    } else if app_matches.is_present("check") {
+        Mode::Check
+    } else {
+        Mode::Run
+    };
     match app_matches.value_of("file") {
         Some(file_name) => {
-            file_read(file_name);
-            return;
+            file_read(file_name, mode);
         }
         None => {
             repl();
-            return;
         }
     };
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Run,
+    DumpISeq,
+    DumpAst,
+    Check,
+}
+
 fn repl() {
     let mut rl = rustyline::Editor::<()>::new();
     let mut program = String::new();
     let mut parser = Parser::new();
-    let mut vm = VM::new();
+    parser.lexer.source_info.path = "(irb)".to_string();
+    let mut vm = new_vm();
     vm.repl_init_method(parser.lexer.source_info.clone(), parser.ident_table.clone());
     loop {
-        let prompt = if program.len() == 0 { ">" } else { "*" };
+        let prompt = if program.is_empty() { ">" } else { "*" };
         let readline = rl.readline(&format!("irb:{} ", prompt).to_string());
         println!("readline: {:?}", readline);
         let mut line = match readline {
@@ -53,27 +105,28 @@ fn repl() {
                         parser.ident_table = vm.ident_table.clone();
                         println!("=> {:?}", result);
                     }
-                    Err(_) => {
+                    Err(err) => {
+                        print_runtime_error(&mut vm, &err);
                         parser.lexer.source_info = source_info;
                         parser.ident_table = ident_table;
-                        println!("{}", program);
                     }
                 }
             }
             Err(err) => {
-                parser.lexer.source_info = source_info;
+                let failed_source_info =
+                    std::mem::replace(&mut parser.lexer.source_info, source_info);
                 parser.ident_table = ident_table;
-                if ParseErrorKind::EOF == err.kind {
+                if err.kind.is_incomplete() {
                     continue;
                 }
-                println!("ParseError: {:?}", err.kind);
+                print_parse_error(&failed_source_info, &err);
             }
         }
         program = String::new();
     }
 }
 
-fn file_read(file_name: impl Into<String>) {
+fn file_read(file_name: impl Into<String>, mode: Mode) {
     use std::fs::*;
     use std::io::Read;
     let file_name = file_name.into();
@@ -91,10 +144,7 @@ fn file_read(file_name: impl Into<String>) {
     let mut file_body = String::new();
 
     match OpenOptions::new().read(true).open(&absolute_path) {
-        Ok(mut ok) => ok
-            .read_to_string(&mut file_body)
-            .ok()
-            .expect("cannot read file"),
+        Ok(mut ok) => ok.read_to_string(&mut file_body).expect("cannot read file"),
         Err(ioerr) => {
             let msg = format!("{}", ioerr);
             println!("Error: Cannot find module file. '{}", &file_name);
@@ -104,17 +154,55 @@ fn file_read(file_name: impl Into<String>) {
     };
 
     let mut parser = Parser::new();
-    let res = parser.parse_program(file_body);
-    match res {
-        Ok(node) => {
-            let mut vm = VM::new();
+    parser.lexer.source_info.path = file_name;
+    let (node, errors) = parser.parse_program_with_recovery(file_body);
+    for err in &errors {
+        print_parse_error(&parser.lexer.source_info, err);
+    }
+    if mode == Mode::Check && errors.is_empty() {
+        println!("Syntax OK");
+    }
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+    match mode {
+        Mode::Check => {}
+        Mode::DumpAst => println!("{}", node.to_sexp(&parser.ident_table)),
+        Mode::DumpISeq | Mode::Run => {
+            let mut vm = new_vm();
             vm.repl_init_method(parser.lexer.source_info.clone(), parser.ident_table.clone());
             vm.init_iseq(node);
+            if mode == Mode::DumpISeq {
+                print!("{}", vm.disassemble());
+                return;
+            }
             match vm.eval() {
                 Ok(result) => println!("-> {:?}", &result),
-                Err(_) => {}
+                Err(err) => {
+                    print_runtime_error(&mut vm, &err);
+                    std::process::exit(1);
+                }
             }
         }
-        Err(err) => println!("ParseError: {:?}", err.kind),
     }
 }
+
+fn new_vm() -> VM {
+    let mut vm = VM::new();
+    vm.stack_limit = STACK_LIMIT;
+    vm
+}
+
+fn print_parse_error(source_info: &SourceInfo, err: &ParseError) {
+    Diagnostic::from_parse_error(source_info, err).emit(source_info, use_colour());
+}
+
+fn print_runtime_error(vm: &mut VM, err: &RuntimeError) {
+    Diagnostic::from_runtime_error(vm, err).emit(&vm.source_info, use_colour());
+}
+
+/// Colour is used only when stderr is a terminal and NO_COLOR is unset.
+fn use_colour() -> bool {
+    use std::io::IsTerminal;
+    std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+}
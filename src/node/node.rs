@@ -1,35 +1,95 @@
 use crate::parser::parser::*;
 use crate::util::annot::*;
 use crate::util::util::*;
+use num_bigint::BigInt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum NodeKind {
     None,
     Line,
     SelfValue,
+    Bool(bool),
     Number(i64),
+    BigNumber(BigInt),
     DecimalNumber(f64),
     String(String),
+    /// string pieces and interpolated expressions, in order
+    InterpolatedString(Vec<Node>),
     Range(Box<Node>, Box<Node>),
     Assign(Box<Node>, Box<Node>),
+    /// `lhs op= rhs`, with `||` and `&&` for `||=` and `&&=`
+    OpAssign(BinOp, Box<Node>, Box<Node>),
+    /// targets, value: `a, *b = c` or `a, b = b, a`
+    MultiAssign(Vec<Node>, Box<Node>),
+    /// parenthesized targets nested in a multiple assignment
+    MultiLhs(Vec<Node>),
+    /// `*target`, or `*` alone with a `None` target. Also `*args` in the
+    /// arguments of a call or `yield`.
+    Splat(Box<Node>),
     BinOp(BinOp, Box<Node>, Box<Node>),
+    UnOp(UnOp, Box<Node>),
     CompStmt(Vec<Node>),
     If(Box<Node>, Box<Node>, Box<Node>),
     Ident(IdentId),
+    /// A local variable assigned earlier in the scope, which is nil until
+    /// that assignment runs.
+    LocalVar(IdentId),
     TableIdent(IdentId),
     InstanceVar(IdentId),
     ClassVar(IdentId),
     GlobalIdent(IdentId),
     Const(IdentId),
     Param(IdentId),
+    /// `name = default`
+    OptionalParam(IdentId, Box<Node>),
+    /// `*name`; a bare `*` is named `*`
+    RestParam(IdentId),
+    /// `name:`, or `name: default`
+    KeywordParam(IdentId, Option<Box<Node>>),
+    /// `**name`; a bare `**` is named `**`
+    KeywordRestParam(IdentId),
+    /// `&name`
+    BlockParam(IdentId),
     FuncDecl(IdentId, Vec<Node>, Box<Node>),
     ClassDecl(IdentId, Box<Node>, Option<IdentId>),
     BlockDecl(Box<Node>),
+    /// `-> (params) { body }`, with the body as a BlockDecl.
+    Lambda(Vec<Node>, Box<Node>),
     Send(Box<Node>, Box<Node>, Box<ParsedArgs>),
     Table(Box<Node>),
+    Symbol(IdentId),
     Array(Vec<Node>),
-    ArrayIndex(Box<Node>, i64),
+    /// key and value of each entry
+    Hash(Vec<(Node, Node)>),
+    /// receiver, arguments inside `[]`
+    Index(Box<Node>, Vec<Node>),
     For(IdentId, Box<Node>, Box<Node>),
+    /// body, rescue clauses, else, ensure
+    Begin(Box<Node>, Vec<Node>, Option<Box<Node>>, Option<Box<Node>>),
+    /// exception classes, variable bound by `=> e`, body
+    Rescue(Vec<Node>, Option<IdentId>, Box<Node>),
+    Retry,
+    /// condition, body
+    While(Box<Node>, Box<Node>),
+    /// condition, body
+    Until(Box<Node>, Box<Node>),
+    /// `begin ... end while cond`, whose body runs before the first check.
+    WhilePost(Box<Node>, Box<Node>),
+    /// `begin ... end until cond`.
+    UntilPost(Box<Node>, Box<Node>),
+    Break(Option<Box<Node>>),
+    Next(Option<Box<Node>>),
+    Redo,
+    /// `return`, with several values gathered into an Array.
+    Return(Option<Box<Node>>),
+    /// `yield` with its arguments.
+    Yield(Vec<Node>),
+    /// subject, `when` or `in` clauses, else
+    Case(Option<Box<Node>>, Vec<Node>, Option<Box<Node>>),
+    /// values tested with `===`, body
+    When(Vec<Node>, Box<Node>),
+    /// alternative patterns tested with `===`, body
+    In(Vec<Node>, Box<Node>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,8 +98,17 @@ pub enum BinOp {
     Sub,
     Mul,
     Div,
+    Rem,
+    Pow,
+    Cmp,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Eq,
     Ne,
+    TEq,
     GT,
     GE,
     LT,
@@ -50,6 +119,64 @@ pub enum BinOp {
 
 pub type Node = Annot<NodeKind>;
 
+impl std::fmt::Display for BinOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::Pow => "**",
+            BinOp::Cmp => "<=>",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::TEq => "===",
+            BinOp::GT => ">",
+            BinOp::GE => ">=",
+            BinOp::LT => "<",
+            BinOp::LE => "<=",
+            BinOp::LAnd => "&&",
+            BinOp::LOr => "||",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// A prefix operator. A `-` right before a numeric literal is part of the
+/// literal instead.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnOp {
+    /// `!` and `not`
+    Not,
+    BitNot,
+    Plus,
+    Minus,
+}
+
+impl std::fmt::Display for UnOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            UnOp::Not => "!",
+            UnOp::BitNot => "~",
+            UnOp::Plus => "+",
+            UnOp::Minus => "-",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// A child of a node in the S-expression dump: either a node or a named list of nodes.
+enum SExp<'a> {
+    Node(&'a Node),
+    List(&'static str, Vec<&'a Node>),
+}
+
 impl std::fmt::Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.kind {
@@ -101,6 +228,10 @@ impl Node {
         Node::new(NodeKind::Number(num), loc)
     }
 
+    pub fn new_bignumber(num: BigInt, loc: Loc) -> Self {
+        Node::new(NodeKind::BigNumber(num), loc)
+    }
+
     pub fn new_decimal_number(decimal_num: f64, loc: Loc) -> Self {
         Node::new(NodeKind::DecimalNumber(decimal_num), loc)
     }
@@ -109,6 +240,10 @@ impl Node {
         Node::new(NodeKind::String(s), loc)
     }
 
+    pub fn new_interpolated_string(parts: Vec<Node>, loc: Loc) -> Self {
+        Node::new(NodeKind::InterpolatedString(parts), loc)
+    }
+
     pub fn new_comp_stmt() -> Self {
         Node::new(NodeKind::CompStmt(vec![]), Loc(0, 0))
     }
@@ -119,6 +254,11 @@ impl Node {
         Node::new(kind, loc)
     }
 
+    pub fn new_unop(op: UnOp, operand: Node, loc: Loc) -> Self {
+        let loc = loc.merge(operand.loc());
+        Node::new(NodeKind::UnOp(op, Box::new(operand)), loc)
+    }
+
     pub fn new_identifier(id: IdentId, loc: Loc) -> Self {
         Node::new(NodeKind::Ident(id), loc)
     }
@@ -145,30 +285,138 @@ impl Node {
         Node::new(NodeKind::Assign(Box::new(lhs), Box::new(rhs)), loc)
     }
 
+    pub fn new_op_assign(op: BinOp, lhs: Node, rhs: Node) -> Self {
+        let loc = lhs.loc().merge(rhs.loc());
+        Node::new(NodeKind::OpAssign(op, Box::new(lhs), Box::new(rhs)), loc)
+    }
+
+    pub fn new_multi_assign(targets: Vec<Node>, rhs: Node, loc: Loc) -> Self {
+        let loc = loc.merge(rhs.loc());
+        Node::new(NodeKind::MultiAssign(targets, Box::new(rhs)), loc)
+    }
+
+    pub fn new_multi_lhs(targets: Vec<Node>, loc: Loc) -> Self {
+        Node::new(NodeKind::MultiLhs(targets), loc)
+    }
+
+    pub fn new_splat(target: Node, loc: Loc) -> Self {
+        Node::new(NodeKind::Splat(Box::new(target)), loc)
+    }
+
+    /// Whether the node can be the target of an assignment: a variable, an
+    /// element `a[i]` or an attribute `obj.name`.
+    pub fn is_assignable(&self) -> bool {
+        match &self.kind {
+            NodeKind::Ident(_)
+            | NodeKind::InstanceVar(_)
+            | NodeKind::ClassVar(_)
+            | NodeKind::GlobalIdent(_)
+            | NodeKind::Index(..) => true,
+            NodeKind::Send(_, method, args) => {
+                matches!(method.kind, NodeKind::Ident(_))
+                    && args.args.is_empty()
+                    && args.node.kind == NodeKind::None
+                    && args.block_arg.is_none()
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the node is a variable, which stores a value by itself.
+    pub fn is_variable(&self) -> bool {
+        matches!(
+            self.kind,
+            NodeKind::Ident(_)
+                | NodeKind::LocalVar(_)
+                | NodeKind::InstanceVar(_)
+                | NodeKind::ClassVar(_)
+                | NodeKind::GlobalIdent(_)
+        )
+    }
+
     pub fn new_range(lhs: Node, rhs: Node) -> Self {
         let loc_merge = lhs.loc.merge(rhs.loc);
         let loc = Loc::new(loc_merge);
         Node::new(NodeKind::Range(Box::new(lhs), Box::new(rhs)), loc)
     }
 
-    pub fn new_method_decl(id: IdentId, params: Vec<Node>, body: Node) -> Self {
-        let loc = Loc::new(body.loc());
+    pub fn new_method_decl(id: IdentId, params: Vec<Node>, body: Node, loc: Loc) -> Self {
         Node::new(NodeKind::FuncDecl(id, params, Box::new(body)), loc)
     }
 
-    pub fn new_class_decl(id: IdentId, body: Node, inheritance_class_id: Option<IdentId>) -> Self {
-        let loc = Loc::new(body.loc());
+    pub fn new_class_decl(
+        id: IdentId,
+        body: Node,
+        inheritance_class_id: Option<IdentId>,
+        loc: Loc,
+    ) -> Self {
         Node::new(
             NodeKind::ClassDecl(id, Box::new(body), inheritance_class_id),
             loc,
         )
     }
 
-    pub fn new_for(id: IdentId, table: Node, body: Node) -> Self {
-        let loc = Loc::new(body.loc());
+    pub fn new_for(id: IdentId, table: Node, body: Node, loc: Loc) -> Self {
         Node::new(NodeKind::For(id, Box::new(table), Box::new(body)), loc)
     }
 
+    pub fn new_begin(
+        body: Node,
+        rescues: Vec<Node>,
+        else_: Option<Node>,
+        ensure: Option<Node>,
+        loc: Loc,
+    ) -> Self {
+        Node::new(
+            NodeKind::Begin(
+                Box::new(body),
+                rescues,
+                else_.map(Box::new),
+                ensure.map(Box::new),
+            ),
+            loc,
+        )
+    }
+
+    pub fn new_rescue(classes: Vec<Node>, var: Option<IdentId>, body: Node, loc: Loc) -> Self {
+        Node::new(NodeKind::Rescue(classes, var, Box::new(body)), loc)
+    }
+
+    pub fn new_if(cond: Node, then_: Node, else_: Node, loc: Loc) -> Self {
+        Node::new(
+            NodeKind::If(Box::new(cond), Box::new(then_), Box::new(else_)),
+            loc,
+        )
+    }
+
+    pub fn new_case(
+        subject: Option<Node>,
+        branches: Vec<Node>,
+        else_: Option<Node>,
+        loc: Loc,
+    ) -> Self {
+        Node::new(
+            NodeKind::Case(subject.map(Box::new), branches, else_.map(Box::new)),
+            loc,
+        )
+    }
+
+    pub fn new_while(cond: Node, body: Node, loc: Loc) -> Self {
+        Node::new(NodeKind::While(Box::new(cond), Box::new(body)), loc)
+    }
+
+    pub fn new_until(cond: Node, body: Node, loc: Loc) -> Self {
+        Node::new(NodeKind::Until(Box::new(cond), Box::new(body)), loc)
+    }
+
+    pub fn new_while_post(cond: Node, body: Node, loc: Loc) -> Self {
+        Node::new(NodeKind::WhilePost(Box::new(cond), Box::new(body)), loc)
+    }
+
+    pub fn new_until_post(cond: Node, body: Node, loc: Loc) -> Self {
+        Node::new(NodeKind::UntilPost(Box::new(cond), Box::new(body)), loc)
+    }
+
     pub fn new_line(loc: Loc) -> Self {
         Node::new(NodeKind::Line, loc)
     }
@@ -190,11 +438,19 @@ impl Node {
     }
 
     pub fn new_array(contents: Vec<Node>, loc: Loc) -> Self {
-        Node::new(NodeKind::Array(Box::new(contents).to_vec()), loc)
+        Node::new(NodeKind::Array(contents), loc)
+    }
+
+    pub fn new_hash(entries: Vec<(Node, Node)>, loc: Loc) -> Self {
+        Node::new(NodeKind::Hash(entries), loc)
     }
 
-    pub fn new_array_index(receiver: Node, num: i64, loc: Loc) -> Self {
-        Node::new(NodeKind::ArrayIndex(Box::new(receiver), num), loc)
+    pub fn new_symbol(id: IdentId, loc: Loc) -> Self {
+        Node::new(NodeKind::Symbol(id), loc)
+    }
+
+    pub fn new_index(receiver: Node, args: Vec<Node>, loc: Loc) -> Self {
+        Node::new(NodeKind::Index(Box::new(receiver), args), loc)
     }
 
     pub fn new_table(table: Node) -> Self {
@@ -204,10 +460,239 @@ impl Node {
 }
 
 impl Node {
-    pub fn pick_number(&self) -> i64 {
-        match self.kind {
-            NodeKind::Number(i) => i,
-            _ => unimplemented!(),
+    /// Renders the tree as an indented S-expression. Every node is printed as
+    /// `(kind atoms... @start..end children...)`, identifiers are resolved
+    /// through `ident_table`.
+    pub fn to_sexp(&self, ident_table: &IdentifierTable) -> String {
+        let mut s = String::new();
+        self.write_sexp(ident_table, 0, &mut s);
+        s
+    }
+
+    fn write_sexp(&self, ident_table: &IdentifierTable, indent: usize, s: &mut String) {
+        let name = |id: &IdentId| ident_table.get_name(*id);
+        let (head, children) = match &self.kind {
+            NodeKind::None => ("nil".to_string(), vec![]),
+            NodeKind::Line => ("line".to_string(), vec![]),
+            NodeKind::SelfValue => ("self".to_string(), vec![]),
+            NodeKind::Bool(b) => (format!("bool {}", b), vec![]),
+            NodeKind::Number(num) => (format!("number {}", num), vec![]),
+            NodeKind::BigNumber(num) => (format!("number {}", num), vec![]),
+            NodeKind::DecimalNumber(num) => (format!("decimal_number {:?}", num), vec![]),
+            NodeKind::String(string) => (format!("string {:?}", string), vec![]),
+            NodeKind::InterpolatedString(parts) => (
+                "interpolated_string".to_string(),
+                parts.iter().map(SExp::Node).collect(),
+            ),
+            NodeKind::Range(lhs, rhs) => {
+                ("range".to_string(), vec![SExp::Node(lhs), SExp::Node(rhs)])
+            }
+            NodeKind::Assign(lhs, rhs) => {
+                ("assign".to_string(), vec![SExp::Node(lhs), SExp::Node(rhs)])
+            }
+            NodeKind::OpAssign(op, lhs, rhs) => (
+                format!("op_assign {}=", op),
+                vec![SExp::Node(lhs), SExp::Node(rhs)],
+            ),
+            NodeKind::MultiAssign(targets, rhs) => (
+                "multi_assign".to_string(),
+                vec![
+                    SExp::List("targets", targets.iter().collect()),
+                    SExp::Node(rhs),
+                ],
+            ),
+            NodeKind::MultiLhs(targets) => (
+                "multi_lhs".to_string(),
+                targets.iter().map(SExp::Node).collect(),
+            ),
+            NodeKind::Splat(target) => ("splat".to_string(), vec![SExp::Node(target)]),
+            NodeKind::BinOp(op, lhs, rhs) => (
+                format!("binop {}", op),
+                vec![SExp::Node(lhs), SExp::Node(rhs)],
+            ),
+            NodeKind::UnOp(op, operand) => (format!("unop {}", op), vec![SExp::Node(operand)]),
+            NodeKind::CompStmt(nodes) => (
+                "comp_stmt".to_string(),
+                nodes.iter().map(SExp::Node).collect(),
+            ),
+            NodeKind::If(cond_, then_, else_) => (
+                "if".to_string(),
+                vec![SExp::Node(cond_), SExp::Node(then_), SExp::Node(else_)],
+            ),
+            NodeKind::Ident(id) => (format!("ident {}", name(id)), vec![]),
+            NodeKind::LocalVar(id) => (format!("lvar {}", name(id)), vec![]),
+            NodeKind::TableIdent(id) => (format!("table_ident {}", name(id)), vec![]),
+            NodeKind::InstanceVar(id) => (format!("instance_var @{}", name(id)), vec![]),
+            NodeKind::ClassVar(id) => (format!("class_var @@{}", name(id)), vec![]),
+            NodeKind::GlobalIdent(id) => (format!("global_ident ${}", name(id)), vec![]),
+            NodeKind::Const(id) => (format!("const {}", name(id)), vec![]),
+            NodeKind::Param(id) => (format!("param {}", name(id)), vec![]),
+            NodeKind::OptionalParam(id, default) => (
+                format!("optional_param {}", name(id)),
+                vec![SExp::Node(default)],
+            ),
+            NodeKind::RestParam(id) => (format!("rest_param {}", name(id)), vec![]),
+            NodeKind::KeywordParam(id, default) => (
+                format!("keyword_param {}", name(id)),
+                default.iter().map(|d| SExp::Node(d)).collect(),
+            ),
+            NodeKind::KeywordRestParam(id) => (format!("keyword_rest_param {}", name(id)), vec![]),
+            NodeKind::BlockParam(id) => (format!("block_param {}", name(id)), vec![]),
+            NodeKind::FuncDecl(id, params, body) => (
+                format!("func_decl {}", name(id)),
+                vec![
+                    SExp::List("params", params.iter().collect()),
+                    SExp::Node(body),
+                ],
+            ),
+            NodeKind::ClassDecl(id, body, inheritance_class_id) => {
+                let head = match inheritance_class_id {
+                    Some(super_id) => format!("class_decl {} < {}", name(id), name(super_id)),
+                    None => format!("class_decl {}", name(id)),
+                };
+                (head, vec![SExp::Node(body)])
+            }
+            NodeKind::BlockDecl(body) => ("block_decl".to_string(), vec![SExp::Node(body)]),
+            NodeKind::Lambda(params, body) => (
+                "lambda".to_string(),
+                vec![
+                    SExp::List("params", params.iter().collect()),
+                    SExp::Node(body),
+                ],
+            ),
+            NodeKind::Send(receiver, method, args) => {
+                let mut children = vec![
+                    SExp::Node(receiver),
+                    SExp::Node(method),
+                    SExp::List("args", args.args.iter().collect()),
+                ];
+                if !args.block_params.is_empty() {
+                    children.push(SExp::List(
+                        "block_params",
+                        args.block_params.iter().collect(),
+                    ));
+                }
+                if args.node.kind != NodeKind::None {
+                    children.push(SExp::Node(&args.node));
+                }
+                if let Some(block_arg) = &args.block_arg {
+                    children.push(SExp::List("block_arg", vec![block_arg]));
+                }
+                ("send".to_string(), children)
+            }
+            NodeKind::Table(table) => ("table".to_string(), vec![SExp::Node(table)]),
+            NodeKind::Array(nodes) => ("array".to_string(), nodes.iter().map(SExp::Node).collect()),
+            NodeKind::Symbol(id) => (format!("symbol :{}", name(id)), vec![]),
+            NodeKind::Hash(entries) => (
+                "hash".to_string(),
+                entries
+                    .iter()
+                    .map(|(key, val)| SExp::List("pair", vec![key, val]))
+                    .collect(),
+            ),
+            NodeKind::Index(receiver, args) => {
+                let mut children = vec![SExp::Node(receiver)];
+                children.extend(args.iter().map(SExp::Node));
+                ("index".to_string(), children)
+            }
+            NodeKind::For(id, table, body) => (
+                format!("for {}", name(id)),
+                vec![SExp::Node(table), SExp::Node(body)],
+            ),
+            NodeKind::Begin(body, rescues, else_, ensure) => {
+                let mut children = vec![SExp::Node(body)];
+                children.extend(rescues.iter().map(SExp::Node));
+                if let Some(else_) = else_ {
+                    children.push(SExp::List("else", vec![else_]));
+                }
+                if let Some(ensure) = ensure {
+                    children.push(SExp::List("ensure", vec![ensure]));
+                }
+                ("begin".to_string(), children)
+            }
+            NodeKind::Rescue(classes, var, body) => {
+                let head = match var {
+                    Some(id) => format!("rescue => {}", name(id)),
+                    None => "rescue".to_string(),
+                };
+                (
+                    head,
+                    vec![
+                        SExp::List("classes", classes.iter().collect()),
+                        SExp::Node(body),
+                    ],
+                )
+            }
+            NodeKind::Retry => ("retry".to_string(), vec![]),
+            NodeKind::While(cond, body) => (
+                "while".to_string(),
+                vec![SExp::Node(cond), SExp::Node(body)],
+            ),
+            NodeKind::Until(cond, body) => (
+                "until".to_string(),
+                vec![SExp::Node(cond), SExp::Node(body)],
+            ),
+            NodeKind::WhilePost(cond, body) => (
+                "while_post".to_string(),
+                vec![SExp::Node(cond), SExp::Node(body)],
+            ),
+            NodeKind::UntilPost(cond, body) => (
+                "until_post".to_string(),
+                vec![SExp::Node(cond), SExp::Node(body)],
+            ),
+            NodeKind::Break(value) => (
+                "break".to_string(),
+                value.iter().map(|v| SExp::Node(v)).collect(),
+            ),
+            NodeKind::Next(value) => (
+                "next".to_string(),
+                value.iter().map(|v| SExp::Node(v)).collect(),
+            ),
+            NodeKind::Redo => ("redo".to_string(), vec![]),
+            NodeKind::Return(value) => (
+                "return".to_string(),
+                value.iter().map(|v| SExp::Node(v)).collect(),
+            ),
+            NodeKind::Yield(args) => ("yield".to_string(), args.iter().map(SExp::Node).collect()),
+            NodeKind::Case(subject, branches, else_) => {
+                let mut children: Vec<SExp> = subject.iter().map(|s| SExp::Node(s)).collect();
+                children.extend(branches.iter().map(SExp::Node));
+                if let Some(else_) = else_ {
+                    children.push(SExp::List("else", vec![else_]));
+                }
+                ("case".to_string(), children)
+            }
+            NodeKind::When(patterns, body) => (
+                "when".to_string(),
+                vec![
+                    SExp::List("values", patterns.iter().collect()),
+                    SExp::Node(body),
+                ],
+            ),
+            NodeKind::In(patterns, body) => (
+                "in".to_string(),
+                vec![
+                    SExp::List("patterns", patterns.iter().collect()),
+                    SExp::Node(body),
+                ],
+            ),
+        };
+
+        *s += &format!("({} @{}..{}", head, self.loc.0, self.loc.1);
+        for child in children {
+            *s += &format!("\n{}", "  ".repeat(indent + 1));
+            match child {
+                SExp::Node(node) => node.write_sexp(ident_table, indent + 1, s),
+                SExp::List(list_name, nodes) => {
+                    *s += &format!("({}", list_name);
+                    for node in nodes {
+                        *s += &format!("\n{}", "  ".repeat(indent + 2));
+                        node.write_sexp(ident_table, indent + 2, s);
+                    }
+                    s.push(')');
+                }
+            }
         }
+        s.push(')');
     }
 }
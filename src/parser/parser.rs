@@ -3,6 +3,8 @@ use crate::node::node::*;
 use crate::token::token::*;
 use crate::util::annot::*;
 use crate::util::util::*;
+use num_traits::ToPrimitive;
+use rustc_hash::FxHashSet;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parser {
@@ -11,21 +13,66 @@ pub struct Parser {
     cursor: usize,
     block_context_stack: Vec<BlockContext>,
     line_context_stack: Vec<LineContext>,
+    /// Number of enclosing `rescue` clauses, where `retry` is allowed.
+    rescue_depth: usize,
+    /// Number of enclosing loops and blocks, where `break`, `next` and
+    /// `redo` are allowed.
+    loop_depth: usize,
+    /// Number of statements being parsed in the current method, class or
+    /// toplevel body.
+    stmt_depth: usize,
+    /// Jumps found outside of any loop. They are only reported once the
+    /// outermost statement is parsed, since a trailing `while` or `until`
+    /// modifier may still turn that statement into a loop.
+    pending_jumps: Vec<ParseError>,
+    /// Set by `parse_program_with_recovery`: syntax errors are collected in
+    /// `errors` and parsing resumes at the next statement.
+    recover: bool,
+    errors: Vec<ParseError>,
+    /// Closers left behind by statements that failed to parse after opening
+    /// a `def`, `do` or `{`, with the depth of the statement list they were
+    /// in. That list skips them instead of stopping there.
+    orphan_closers: Vec<(TokenKind, usize)>,
+    /// Number of statement lists being parsed.
+    comp_depth: usize,
+    /// Local variables assigned so far, innermost scope last. An identifier
+    /// that is not one of them is a method call, which may take arguments
+    /// without parentheses.
+    lvar_scopes: Vec<LvarScope>,
+    /// Set while parsing the arguments of a call without parentheses or the
+    /// condition of a loop, where `do` belongs to the outer construct.
+    no_do_block: bool,
     pub ident_table: IdentifierTable,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+struct LvarScope {
+    vars: FxHashSet<IdentId>,
+    /// A block also sees the variables of the scope it is written in.
+    block: bool,
+}
+
+impl LvarScope {
+    fn new(block: bool) -> Self {
+        Self {
+            vars: FxHashSet::default(),
+            block,
+        }
+    }
+}
+
 #[allow(unused)]
 #[derive(Debug, Clone, PartialEq)]
 enum Literal {
     String,
     Number,
-    Array,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 enum BlockContext {
     Class,
-    Method,
+    /// location of the `def` keyword
+    Method(Loc),
 }
 
 #[allow(unused)]
@@ -38,17 +85,26 @@ enum LineContext {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParsedArgs {
-    pub table: Node,
+    pub block_params: Vec<Node>,
     pub node: Node,
     pub args: Vec<Node>,
+    /// `&proc` passed as the block.
+    pub block_arg: Option<Box<Node>>,
+}
+
+impl Default for ParsedArgs {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ParsedArgs {
     pub fn new() -> Self {
         Self {
-            table: Node::new_none(),
+            block_params: vec![],
             node: Node::new_none(),
             args: vec![],
+            block_arg: None,
         }
     }
 }
@@ -63,7 +119,6 @@ impl Parser {
             true => Ok(()),
             false => {
                 let error_loc = self.tokens[self.cursor - 3].loc;
-                self.lexer.source_info.show_loc(&error_loc);
                 Err(ParseError::new(
                     ParseErrorKind::LiteralBeforeDefinition,
                     error_loc,
@@ -72,16 +127,22 @@ impl Parser {
         }
     }
 
+    /// Checks that the value just parsed was the literal `expected_context`
+    /// asks for. `loc` is the location of the token that needs it.
     fn expect_line_context_literal(
         &mut self,
         expected_context: LineContext,
+        loc: Loc,
     ) -> Result<(), ParseError> {
-        let got_literal = self.line_context_stack.last().unwrap();
-        if *got_literal == expected_context {
-            self.line_context_stack.pop().unwrap();
+        if self.line_context_stack.last() == Some(&expected_context) {
+            self.line_context_stack.pop();
             Ok(())
         } else {
-            panic!("expect {:?}, but god {:?}", expected_context, got_literal)
+            let expected = match expected_context {
+                LineContext::Literal(Literal::Number) => "a number literal before `..`",
+                _ => "a literal",
+            };
+            Err(self.error_unexpected(expected, loc))
         }
     }
 
@@ -89,21 +150,29 @@ impl Parser {
         self.line_context_stack = vec![];
     }
 
+    /// The `def` keyword of the innermost method being parsed, if any.
+    fn enclosing_method_loc(&self) -> Option<Loc> {
+        self.block_context_stack
+            .iter()
+            .rev()
+            .find_map(|context| match context {
+                BlockContext::Method(loc) => Some(*loc),
+                BlockContext::Class => None,
+            })
+    }
+
     pub fn is_out_of_method_block_context(&self) -> bool {
-        !self.block_context_stack.contains(&BlockContext::Method)
+        self.enclosing_method_loc().is_none()
     }
 
+    /// Errors at the enclosing `def` when a class is defined inside a method.
     pub fn expect_out_of_method_block_context(&self) -> Result<(), ParseError> {
-        match self.is_out_of_method_block_context() {
-            true => Ok(()),
-            false => {
-                let error_loc = self.tokens[self.cursor - 6].loc;
-                self.lexer.source_info.show_loc(&error_loc);
-                Err(ParseError::new(
-                    ParseErrorKind::InnerClassDefinitionInMethodDefinition,
-                    error_loc,
-                ))
-            }
+        match self.enclosing_method_loc() {
+            None => Ok(()),
+            Some(def_loc) => Err(ParseError::new(
+                ParseErrorKind::InnerClassDefinitionInMethodDefinition,
+                def_loc,
+            )),
         }
     }
 }
@@ -112,10 +181,45 @@ pub type ParseError = Annot<ParseErrorKind>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParseErrorKind {
-    UnexpectedToken,
+    /// `expected` describes what the parser was looking for and `found` the
+    /// token it got instead, e.g. "`end` to close `def` at line 3" and "EOF".
+    UnexpectedToken {
+        expected: String,
+        found: String,
+    },
     LiteralBeforeDefinition,
     InnerClassDefinitionInMethodDefinition,
+    RetryOutsideRescue,
+    /// `=` or `op=` after something other than a variable, an element or an
+    /// attribute.
+    InvalidAssignTarget,
+    /// A parameter after one that must come later, such as `def f(*a, b = 1)`.
+    InvalidParamOrder,
+    /// `break`, `next` or `redo` outside of a loop or block.
+    InvalidJump(Reserved),
+    /// An `in` pattern that destructures or binds, such as `in [a, b]`,
+    /// which needs pattern matching.
+    UnsupportedPattern,
     EOF,
+    Lexer(Error),
+}
+
+impl ParseErrorKind {
+    /// Whether the program only failed because it ended too early, in which
+    /// case the REPL keeps reading lines.
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            ParseErrorKind::EOF | ParseErrorKind::Lexer(Error::EOF) => true,
+            ParseErrorKind::UnexpectedToken { found, .. } => found == "EOF",
+            _ => false,
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Parser {
@@ -127,12 +231,78 @@ impl Parser {
             cursor: 0,
             block_context_stack: vec![],
             line_context_stack: vec![],
+            rescue_depth: 0,
+            loop_depth: 0,
+            stmt_depth: 0,
+            pending_jumps: vec![],
+            recover: false,
+            errors: vec![],
+            orphan_closers: vec![],
+            comp_depth: 0,
+            lvar_scopes: vec![LvarScope::new(false)],
+            no_do_block: false,
             ident_table: IdentifierTable::new(),
         }
     }
 
+    /// Parses with a new local variable scope, which is a block scope when
+    /// `block` is true.
+    fn with_lvar_scope<T>(
+        &mut self,
+        block: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.lvar_scopes.push(LvarScope::new(block));
+        let res = parse(self);
+        self.lvar_scopes.pop().unwrap();
+        res
+    }
+
+    /// Parses with `do` blocks allowed or not.
+    fn with_do_block<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let no_do_block = std::mem::replace(&mut self.no_do_block, !allowed);
+        let res = parse(self);
+        self.no_do_block = no_do_block;
+        res
+    }
+
+    fn declare_lvar(&mut self, id: IdentId) {
+        self.lvar_scopes.last_mut().unwrap().vars.insert(id);
+    }
+
+    /// Declares the local variables an assignment to `target` creates.
+    fn declare_assign_target(&mut self, target: &Node) {
+        match &target.kind {
+            NodeKind::Ident(id) => self.declare_lvar(*id),
+            NodeKind::Splat(target) => self.declare_assign_target(target),
+            NodeKind::MultiLhs(targets) => {
+                for target in targets {
+                    self.declare_assign_target(target);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_lvar(&self, id: IdentId) -> bool {
+        for scope in self.lvar_scopes.iter().rev() {
+            if scope.vars.contains(&id) {
+                return true;
+            }
+            if !scope.block {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Skips spaces and a trailing comment, but not the end of the line.
     fn skip_space(&mut self) {
-        if self.tokens[self.cursor].kind == TokenKind::Space {
+        while self.tokens[self.cursor].is_space() || self.tokens[self.cursor].is_comment() {
             self.cursor += 1;
         }
     }
@@ -146,6 +316,16 @@ impl Parser {
         self.tokens[self.cursor].loc()
     }
 
+    /// Location of the last token consumed, skipping spaces and line terminators.
+    fn prev_loc(&self) -> Loc {
+        self.tokens[..self.cursor]
+            .iter()
+            .rev()
+            .find(|tok| !tok.is_space() && !tok.is_line_term() && !tok.is_comment())
+            .map(|tok| tok.loc())
+            .unwrap_or_else(|| self.loc())
+    }
+
     pub fn get(&mut self) -> Token {
         loop {
             let token = self.tokens[self.cursor].clone();
@@ -196,13 +376,9 @@ impl Parser {
 
     fn get_if_reserved(&mut self, expect: Reserved) -> bool {
         match &self.peek().0.kind {
-            TokenKind::Reserved(reserved) => {
-                if *reserved == expect {
-                    self.get();
-                    true
-                } else {
-                    false
-                }
+            TokenKind::Reserved(reserved) if *reserved == expect => {
+                self.get();
+                true
             }
             _ => false,
         }
@@ -214,51 +390,63 @@ impl Parser {
         if tok.is_term() {
             Ok(())
         } else {
-            Err(self.error_unexpected(tok.loc()))
+            Err(self.error_unexpected("newline or `;`", tok.loc()))
         }
     }
 
     fn expect_reserved(&mut self, expect: Reserved) -> Result<(), ParseError> {
         let tok = self.get();
-        let loc = self.loc();
         match &tok.kind {
-            TokenKind::Reserved(reserved) => {
-                if *reserved == expect {
-                    Ok(())
-                } else {
-                    Err(self.error_unexpected(loc))
-                }
-            }
-            _ => Err(self.error_unexpected(loc)),
+            TokenKind::Reserved(reserved) if *reserved == expect => Ok(()),
+            _ => Err(self.error_unexpected(&format!("`{}`", expect.as_str()), tok.loc())),
         }
     }
 
-    fn expect_punct(&mut self, expect: Punct) -> Result<(), ParseError> {
+    /// Consumes the `end` closing the construct opened by `keyword` at
+    /// `open_loc`.
+    fn expect_end(&mut self, keyword: &str, open_loc: Loc) -> Result<(), ParseError> {
         let tok = self.get();
-        let loc = self.loc();
         match &tok.kind {
-            TokenKind::Punct(punct) => {
-                if *punct == expect {
-                    Ok(())
-                } else {
-                    Err(self.error_unexpected(loc))
-                }
+            TokenKind::Reserved(Reserved::End) => Ok(()),
+            _ => {
+                let line = self.lexer.source_info.line_column(open_loc.0).0;
+                let expected = format!("`end` to close `{}` at line {}", keyword, line);
+                Err(self.error_unexpected(&expected, tok.loc()))
             }
-            _ => Err(self.error_unexpected(loc)),
         }
     }
 
-    fn expect_number(&mut self) -> Result<i64, ParseError> {
+    fn expect_punct(&mut self, expect: Punct) -> Result<(), ParseError> {
         let tok = self.get();
-        match tok.kind {
-            TokenKind::NumLit(num) => Ok(num),
-            _ => panic!("expected number but god {:?}", tok),
+        match &tok.kind {
+            TokenKind::Punct(punct) if *punct == expect => Ok(()),
+            _ => Err(self.error_unexpected(&format!("`{}`", expect.as_str()), tok.loc())),
         }
     }
 
-    fn error_unexpected(&self, loc: Loc) -> ParseError {
-        self.lexer.source_info.show_loc(&loc);
-        ParseError::new(ParseErrorKind::UnexpectedToken, loc)
+    /// Describes the token at `loc` the way it is written in the source.
+    fn describe_token(&self, loc: Loc) -> String {
+        let code = &self.lexer.source_info.code;
+        if loc.0 >= code.len() {
+            return "EOF".to_string();
+        }
+        let text: String = code[loc.0..=loc.1.min(code.len() - 1)].iter().collect();
+        if text == "\n" {
+            "newline".to_string()
+        } else {
+            format!("`{}`", text)
+        }
+    }
+
+    fn error_unexpected(&self, expected: &str, loc: Loc) -> ParseError {
+        let found = self.describe_token(loc);
+        ParseError::new(
+            ParseErrorKind::UnexpectedToken {
+                expected: expected.to_string(),
+                found,
+            },
+            loc,
+        )
     }
 
     fn error_eof(&self, loc: Loc) -> ParseError {
@@ -271,13 +459,9 @@ impl Parser {
 
     fn get_if_punct(&mut self, expect: Punct) -> bool {
         match &self.peek().0.kind {
-            TokenKind::Punct(punct) => {
-                if *punct == expect {
-                    self.get();
-                    true
-                } else {
-                    false
-                }
+            TokenKind::Punct(punct) if *punct == expect => {
+                self.get();
+                true
             }
             _ => false,
         }
@@ -298,47 +482,248 @@ impl Parser {
     }
 
     pub fn parse_program(&mut self, program: String) -> Result<Node, ParseError> {
-        self.tokens = self.lexer.tokenize(program).unwrap().tokens;
-        self.cursor = 0;
-        let mut node;
+        self.recover = false;
+        self.tokenize(program)?;
+        let mut nodes = vec![];
         loop {
-            node = self.parse_comp_stmt()?;
+            let cursor = self.cursor;
+            let node = self.parse_comp_stmt()?;
+            Self::append_stmts(&mut nodes, node);
             self.skip_space();
             if self.tokens[self.cursor].is_eof() {
                 break;
             }
+            if self.cursor == cursor {
+                // A closing keyword with nothing to close.
+                let loc = self.peek().1;
+                return Err(self.error_unexpected("end-of-input", loc));
+            }
+        }
+        Ok(self.new_program_node(nodes))
+    }
+
+    /// Parses the whole program without stopping at the first syntax error.
+    /// After an error the parser skips to the next statement terminator or
+    /// closing keyword and carries on, so the returned tree holds every
+    /// statement that could be parsed along with all the errors found.
+    pub fn parse_program_with_recovery(&mut self, program: String) -> (Node, Vec<ParseError>) {
+        self.recover = true;
+        self.errors = vec![];
+        self.orphan_closers = vec![];
+        if let Err(err) = self.tokenize(program) {
+            self.recover = false;
+            return (Node::new_comp_stmt(), vec![err]);
+        }
+        let mut nodes = vec![];
+        loop {
+            match self.parse_comp_stmt() {
+                Ok(node) => Self::append_stmts(&mut nodes, node),
+                Err(err) => self.errors.push(err),
+            };
+            let (tok, loc) = self.peek();
+            if tok.is_eof() {
+                break;
+            }
+            // A closing keyword with nothing to close.
+            self.errors.push(self.error_unexpected("end-of-input", loc));
+            self.get();
+        }
+        self.recover = false;
+        let errors = std::mem::take(&mut self.errors);
+        (self.new_program_node(nodes), errors)
+    }
+
+    fn append_stmts(nodes: &mut Vec<Node>, node: Node) {
+        match node.kind {
+            NodeKind::CompStmt(mut stmts) => nodes.append(&mut stmts),
+            _ => nodes.push(node),
+        }
+    }
+
+    fn new_program_node(&self, nodes: Vec<Node>) -> Node {
+        let loc = match (nodes.first(), nodes.last()) {
+            (Some(first), Some(last)) => first.loc().merge(last.loc()),
+            _ => self.loc(),
+        };
+        Node::new(NodeKind::CompStmt(nodes), loc)
+    }
+
+    fn tokenize(&mut self, program: String) -> Result<(), ParseError> {
+        self.tokens = match self.lexer.tokenize(program) {
+            Ok(result) => result.tokens,
+            Err(err) => {
+                return Err(ParseError::new(
+                    ParseErrorKind::Lexer(err),
+                    self.lexer.error_loc(),
+                ))
+            }
+        };
+        self.cursor = 0;
+        Ok(())
+    }
+
+    /// Whether the next token ends the statement list being parsed.
+    fn is_stmt_list_end(&mut self) -> bool {
+        matches!(
+            self.peek().0.kind,
+            TokenKind::EOF
+                | TokenKind::Punct(Punct::RParen | Punct::RBrace)
+                | TokenKind::Reserved(
+                    Reserved::Else
+                        | Reserved::Elsif
+                        | Reserved::End
+                        | Reserved::Rescue
+                        | Reserved::Ensure
+                        | Reserved::When
+                        | Reserved::In
+                )
+        )
+    }
+
+    /// Skips the rest of a statement that failed to parse: everything up to
+    /// and including the next terminator, or up to a keyword that closes the
+    /// enclosing construct. `start` is where the failed attempt began.
+    fn synchronize(&mut self, err: &ParseError, start: usize) {
+        // Restart from the offending token in case it was already consumed,
+        // so that an `end` we tripped over still closes its construct. Never
+        // go back behind `start`, or the same tokens would be parsed again.
+        if let Some(pos) = self.tokens[start..self.cursor]
+            .iter()
+            .rposition(|tok| tok.loc.0 == err.loc.0)
+        {
+            self.cursor = start + pos;
+        }
+        loop {
+            match self.tokens[self.cursor].kind {
+                TokenKind::EOF => return,
+                TokenKind::Line | TokenKind::Punct(Punct::Semi) => {
+                    self.cursor += 1;
+                    self.reset_line_context();
+                    return;
+                }
+                TokenKind::Reserved(
+                    Reserved::End
+                    | Reserved::Else
+                    | Reserved::Elsif
+                    | Reserved::Rescue
+                    | Reserved::Ensure
+                    | Reserved::When
+                    | Reserved::In,
+                ) => return,
+                TokenKind::Punct(Punct::LBrace) => {
+                    self.add_orphan_closer(TokenKind::Punct(Punct::RBrace));
+                    self.cursor += 1;
+                }
+                TokenKind::Reserved(Reserved::Def | Reserved::Do) => {
+                    self.add_orphan_closer(TokenKind::Reserved(Reserved::End));
+                    self.cursor += 1;
+                }
+                TokenKind::Punct(Punct::RBrace) => {
+                    self.skip_orphan_closer();
+                    self.cursor += 1;
+                }
+                _ => self.cursor += 1,
+            }
+        }
+    }
+
+    /// Records that the closer of a construct whose opening failed to parse
+    /// belongs to the current statement list.
+    fn add_orphan_closer(&mut self, closer: TokenKind) {
+        if self.recover {
+            self.orphan_closers.push((closer, self.comp_depth));
+        }
+    }
+
+    /// Consumes the next token if it is the orphaned closer expected in
+    /// the current statement list.
+    fn skip_orphan_closer(&mut self) -> bool {
+        let kind = self.peek().0.kind;
+        match self.orphan_closers.last() {
+            Some((closer, depth)) if *closer == kind && *depth == self.comp_depth => {
+                self.orphan_closers.pop();
+                self.get();
+                true
+            }
+            _ => false,
         }
-        Ok(node)
     }
 
     pub fn parse_comp_stmt(&mut self) -> Result<Node, ParseError> {
+        self.comp_depth += 1;
+        let res = self.parse_stmt_list();
+        let depth = self.comp_depth;
+        self.orphan_closers.retain(|(_, d)| *d < depth);
+        self.comp_depth -= 1;
+        res
+    }
+
+    fn parse_stmt_list(&mut self) -> Result<Node, ParseError> {
         let mut nodes = vec![];
-        let mut loc = self.loc();
         loop {
+            if self.skip_orphan_closer() {
+                continue;
+            }
             let (tok, _) = self.peek();
             match tok.kind {
                 TokenKind::Punct(punct) => match punct {
                     Punct::Comment => continue,
-                    Punct::Semi => break,
+                    // An empty statement, as after `class Foo;`.
+                    Punct::Semi => {
+                        self.get();
+                        continue;
+                    }
+                    Punct::RBrace => break,
                     _ => {}
                 },
                 TokenKind::EOF => break,
-                TokenKind::Reserved(reserved) => match reserved {
-                    Reserved::Else | Reserved::Elsif | Reserved::End => break,
-                    _ => {}
-                },
+                TokenKind::Reserved(
+                    Reserved::Else
+                    | Reserved::Elsif
+                    | Reserved::End
+                    | Reserved::Rescue
+                    | Reserved::Ensure
+                    | Reserved::When
+                    | Reserved::In,
+                ) => break,
                 _ => {}
             };
-            let node = self.parse_expr()?;
+            let block_depth = self.block_context_stack.len();
+            let rescue_depth = self.rescue_depth;
+            let loop_depth = self.loop_depth;
+            let stmt_depth = self.stmt_depth;
+            let pending_jumps = self.pending_jumps.len();
+            let start = self.cursor;
+            let node = match self.parse_stmt() {
+                Ok(node) => node,
+                Err(err) if self.recover => {
+                    self.block_context_stack.truncate(block_depth);
+                    self.rescue_depth = rescue_depth;
+                    self.loop_depth = loop_depth;
+                    self.stmt_depth = stmt_depth;
+                    self.pending_jumps.truncate(pending_jumps);
+                    self.synchronize(&err, start);
+                    self.errors.push(err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
             nodes.push(node);
             if !self.get_if_term() {
-                break;
+                if !self.recover || self.is_stmt_list_end() {
+                    break;
+                }
+                let loc = self.peek().1;
+                let err = self.error_unexpected("newline or `;`", loc);
+                self.synchronize(&err, self.cursor);
+                self.errors.push(err);
             }
         }
 
-        if let Some(node) = nodes.last() {
-            loc = loc.merge(node.loc())
-        }
+        let loc = match (nodes.first(), nodes.last()) {
+            (Some(first), Some(last)) => first.loc().merge(last.loc()),
+            _ => self.loc(),
+        };
 
         Ok(Node {
             kind: NodeKind::CompStmt(nodes),
@@ -346,6 +731,53 @@ impl Parser {
         })
     }
 
+    /// Parses an expression followed by any `if`, `unless`, `while` or
+    /// `until` modifiers. A `begin ... end` body of `while` or `until` runs
+    /// once before the condition is checked.
+    fn parse_stmt(&mut self) -> Result<Node, ParseError> {
+        let pending_jumps = self.pending_jumps.len();
+        self.stmt_depth += 1;
+        let node = self.parse_stmt_modifiers(pending_jumps);
+        self.stmt_depth -= 1;
+        let node = node?;
+        if self.stmt_depth == 0 && self.pending_jumps.len() > pending_jumps {
+            return Err(self.pending_jumps.swap_remove(pending_jumps));
+        }
+        Ok(node)
+    }
+
+    fn parse_stmt_modifiers(&mut self, pending_jumps: usize) -> Result<Node, ParseError> {
+        let starts_with_begin = self.peek().0.kind == TokenKind::Reserved(Reserved::Begin);
+        let mut node = self.parse_stmt_expr()?;
+        let mut begin_block =
+            starts_with_begin && matches!(node.kind, NodeKind::CompStmt(_) | NodeKind::Begin(..));
+        loop {
+            self.skip_space();
+            let modifier = match self.peek_no_skip_line_term().kind {
+                TokenKind::Reserved(
+                    r @ (Reserved::If | Reserved::Unless | Reserved::While | Reserved::Until),
+                ) => r,
+                _ => return Ok(node),
+            };
+            self.cursor += 1;
+            let cond = self.parse_expr()?;
+            let loc = node.loc().merge(cond.loc());
+            node = match modifier {
+                Reserved::If => Node::new_if(cond, node, Node::new_comp_stmt(), loc),
+                Reserved::Unless => Node::new_if(cond, Node::new_comp_stmt(), node, loc),
+                Reserved::While if begin_block => Node::new_while_post(cond, node, loc),
+                Reserved::While => Node::new_while(cond, node, loc),
+                _ if begin_block => Node::new_until_post(cond, node, loc),
+                _ => Node::new_until(cond, node, loc),
+            };
+            begin_block = false;
+            // `break` and `next` are fine once the statement is a loop body.
+            if let Reserved::While | Reserved::Until = modifier {
+                self.pending_jumps.truncate(pending_jumps);
+            }
+        }
+    }
+
     pub fn parse_if_then(&mut self) -> Result<Node, ParseError> {
         let cond = self.parse_expr()?;
         // println!("if cond {}", cond);
@@ -360,13 +792,152 @@ impl Parser {
             else_ = self.parse_comp_stmt()?;
         }
         let loc = cond.loc().merge(else_.loc());
-        Ok(Node::new(
-            NodeKind::If(Box::new(cond), Box::new(then_), Box::new(else_)),
-            loc,
-        ))
+        Ok(Node::new_if(cond, then_, else_, loc))
+    }
+
+    /// Parses `unless` as an `if` with the branches swapped. Unlike `if`, it
+    /// takes no `elsif`.
+    fn parse_unless_then(&mut self) -> Result<Node, ParseError> {
+        let cond = self.parse_expr()?;
+        self.parse_then()?;
+        let then_ = self.parse_comp_stmt()?;
+        let else_ = if self.get_if_reserved(Reserved::Else) {
+            self.parse_comp_stmt()?
+        } else {
+            Node::new_comp_stmt()
+        };
+        let loc = cond.loc();
+        Ok(Node::new_if(cond, else_, then_, loc))
+    }
+
+    /// Parses an expression that may also be a multiple assignment, which is
+    /// only allowed as a statement: `a, b = b, a` or `a = 1, 2`.
+    fn parse_stmt_expr(&mut self) -> Result<Node, ParseError> {
+        if self.is_multi_assign_ahead() {
+            let loc = self.peek().1;
+            let targets = self.parse_mlhs(Punct::Assign)?;
+            self.expect_punct(Punct::Assign)?;
+            let rhs = self.parse_mrhs()?;
+            return Ok(Node::new_multi_assign(targets, rhs, loc));
+        }
+        let node = self.parse_expr()?;
+        match node.kind {
+            NodeKind::Assign(lhs, rhs)
+                if self.peek_non_space().kind == TokenKind::Punct(Punct::Comma) =>
+            {
+                let mut values = vec![*rhs];
+                while self.get_if_punct(Punct::Comma) {
+                    values.push(self.parse_arg()?);
+                }
+                let loc = values[0].loc().merge(values[values.len() - 1].loc());
+                Ok(Node::new_assign(*lhs, Node::new_array(values, loc)))
+            }
+            kind => Ok(Node { kind, ..node }),
+        }
+    }
+
+    /// Looks ahead to the end of the statement for a comma followed by `=`
+    /// outside of any brackets, or for a leading splat, which both mark the
+    /// left-hand side of a multiple assignment.
+    fn is_multi_assign_ahead(&mut self) -> bool {
+        let mut c = self.cursor;
+        while matches!(self.tokens[c].kind, TokenKind::Line | TokenKind::Space)
+            || self.tokens[c].is_comment()
+        {
+            c += 1;
+        }
+        if self.tokens[c].kind == TokenKind::Punct(Punct::Mul) {
+            return true;
+        }
+        let mut depth = 0;
+        let mut comma = false;
+        loop {
+            let tok = &self.tokens[c];
+            match &tok.kind {
+                TokenKind::EOF
+                | TokenKind::Punct(Punct::Semi)
+                | TokenKind::Reserved(Reserved::Do) => return false,
+                TokenKind::Line if depth == 0 => return false,
+                TokenKind::Punct(Punct::LParen | Punct::LBoxBrackets | Punct::LBrace) => depth += 1,
+                TokenKind::Punct(Punct::RParen | Punct::RBoxBrackets | Punct::RBrace) => {
+                    if depth == 0 {
+                        return false;
+                    }
+                    depth -= 1;
+                }
+                TokenKind::Punct(Punct::Comma) if depth == 0 => comma = true,
+                TokenKind::Punct(Punct::Assign) if depth == 0 => return comma,
+                _ => {}
+            }
+            c += 1;
+        }
+    }
+
+    /// Parses assignment targets separated by commas up to `close`. At most
+    /// one of them may be a splat.
+    fn parse_mlhs(&mut self, close: Punct) -> Result<Vec<Node>, ParseError> {
+        let mut targets = vec![];
+        let mut splat = false;
+        loop {
+            let loc = self.peek().1;
+            let target = if self.get_if_punct(Punct::LParen) {
+                let nested = self.parse_mlhs(Punct::RParen)?;
+                let end = self.peek().1;
+                self.expect_punct(Punct::RParen)?;
+                Node::new_multi_lhs(nested, loc.merge(end))
+            } else if self.get_if_punct(Punct::Mul) {
+                if splat {
+                    return Err(self.error_unexpected("an assignment target", loc));
+                }
+                splat = true;
+                match self.peek().0.kind {
+                    TokenKind::Punct(Punct::Comma) => Node::new_splat(Node::new_none(), loc),
+                    TokenKind::Punct(p) if p == close => Node::new_splat(Node::new_none(), loc),
+                    _ => {
+                        let target = self.parse_primary_ext()?;
+                        let target = self.expect_assignable(target, loc)?;
+                        let loc = loc.merge(target.loc());
+                        Node::new_splat(target, loc)
+                    }
+                }
+            } else {
+                let target = self.parse_primary_ext()?;
+                let loc = target.loc();
+                self.expect_assignable(target, loc)?
+            };
+            self.declare_assign_target(&target);
+            targets.push(target);
+            if !self.get_if_punct(Punct::Comma) {
+                return Ok(targets);
+            }
+            // A trailing comma, as in `a, = list`, takes the first element.
+            if self.peek().0.kind == TokenKind::Punct(close) {
+                return Ok(targets);
+            }
+        }
+    }
+
+    /// Parses the values of a multiple assignment. Several values are
+    /// collected into an array; a single value is destructured as it is.
+    fn parse_mrhs(&mut self) -> Result<Node, ParseError> {
+        let first = self.parse_arg()?;
+        if self.peek_non_space().kind != TokenKind::Punct(Punct::Comma) {
+            return Ok(first);
+        }
+        let mut values = vec![first];
+        while self.get_if_punct(Punct::Comma) {
+            values.push(self.parse_arg()?);
+        }
+        let loc = values[0].loc().merge(values[values.len() - 1].loc());
+        Ok(Node::new_array(values, loc))
     }
 
     pub fn parse_expr(&mut self) -> Result<Node, ParseError> {
+        let loc = self.peek().1;
+        if self.get_if_reserved(Reserved::Not) {
+            let operand = self.parse_expr()?;
+            return Ok(Node::new_unop(UnOp::Not, operand, loc));
+        }
         self.parse_arg()
     }
 
@@ -375,12 +946,20 @@ impl Parser {
     }
 
     fn parse_arg_assign(&mut self) -> Result<Node, ParseError> {
-        let lhs = self.parse_arg_logical_or()?;
+        let lhs = self.parse_arg_ternary()?;
+        let loc = self.peek().1;
         if self.get_if_punct(Punct::Assign) {
+            let lhs = self.expect_assignable(lhs, loc)?;
+            self.declare_assign_target(&lhs);
             let rhs = self.parse_arg()?;
             Ok(Node::new_assign(lhs, rhs))
+        } else if let Some(op) = self.get_if_op_assign() {
+            let lhs = self.expect_assignable(lhs, loc)?;
+            self.declare_assign_target(&lhs);
+            let rhs = self.parse_arg()?;
+            Ok(Node::new_op_assign(op, lhs, rhs))
         } else if self.get_if_punct(Punct::Range) {
-            self.expect_line_context_literal(LineContext::Literal(Literal::Number))?;
+            self.expect_line_context_literal(LineContext::Literal(Literal::Number), loc)?;
             let rhs = self.parse_arg()?;
             Ok(Node::new_range(lhs, rhs))
         } else {
@@ -388,245 +967,475 @@ impl Parser {
         }
     }
 
+    /// `cond ? then : else`, parsed as an `if`.
+    fn parse_arg_ternary(&mut self) -> Result<Node, ParseError> {
+        let cond = self.parse_arg_logical_or()?;
+        if !self.get_if_punct(Punct::Question) {
+            return Ok(cond);
+        }
+        let then_ = self.parse_arg()?;
+        self.expect_punct(Punct::Colon)?;
+        let else_ = self.parse_arg()?;
+        let loc = cond.loc().merge(else_.loc());
+        Ok(Node::new_if(cond, then_, else_, loc))
+    }
+
     fn parse_arg_logical_or(&mut self) -> Result<Node, ParseError> {
-        let lhs = self.parse_arg_logical_and()?;
-        if self.get_if_punct(Punct::LAnd) {
-            let rhs = self.parse_arg_logical_or()?;
-            Ok(Node::new_binop(BinOp::LAnd, lhs, rhs))
-        } else {
-            Ok(lhs)
+        let mut lhs = self.parse_arg_logical_and()?;
+        while let Some(op) = self.get_if_binop(&[(Punct::LOr, BinOp::LOr)]) {
+            let rhs = self.parse_arg_logical_and()?;
+            lhs = Node::new_binop(op, lhs, rhs);
         }
+        Ok(lhs)
     }
 
     fn parse_arg_logical_and(&mut self) -> Result<Node, ParseError> {
-        let lhs = self.parse_arg_eq()?;
-        if self.get_if_punct(Punct::LAnd) {
-            let rhs = self.parse_arg_logical_and()?;
-            Ok(Node::new_binop(BinOp::LAnd, lhs, rhs))
-        } else {
-            Ok(lhs)
+        let mut lhs = self.parse_arg_eq()?;
+        while let Some(op) = self.get_if_binop(&[(Punct::LAnd, BinOp::LAnd)]) {
+            let rhs = self.parse_arg_eq()?;
+            lhs = Node::new_binop(op, lhs, rhs);
         }
+        Ok(lhs)
     }
 
+    /// Equality operators do not chain: `a == b == c` is an error.
     fn parse_arg_eq(&mut self) -> Result<Node, ParseError> {
         let lhs = self.parse_arg_comp()?;
-        if self.get_if_punct(Punct::Eq) {
-            let rhs = self.parse_arg_eq()?;
-            Ok(Node::new_binop(BinOp::Eq, lhs, rhs))
-        } else if self.get_if_punct(Punct::NE) {
-            let rhs = self.parse_arg_eq()?;
-            Ok(Node::new_binop(BinOp::Ne, lhs, rhs))
-        } else {
-            Ok(lhs)
+        let ops = [
+            (Punct::Eq, BinOp::Eq),
+            (Punct::NE, BinOp::Ne),
+            (Punct::TEq, BinOp::TEq),
+            (Punct::Cmp, BinOp::Cmp),
+        ];
+        match self.get_if_binop(&ops) {
+            Some(op) => {
+                let rhs = self.parse_arg_comp()?;
+                Ok(Node::new_binop(op, lhs, rhs))
+            }
+            None => Ok(lhs),
         }
     }
 
     fn parse_arg_comp(&mut self) -> Result<Node, ParseError> {
-        let lhs = self.parse_arg_add()?;
-        if self.get_if_punct(Punct::GE) {
-            let rhs = self.parse_arg_comp()?;
-            Ok(Node::new_binop(BinOp::GE, lhs, rhs))
-        } else if self.get_if_punct(Punct::GT) {
-            let rhs = self.parse_arg_comp()?;
-            Ok(Node::new_binop(BinOp::GT, lhs, rhs))
-        } else if self.get_if_punct(Punct::LE) {
-            let rhs = self.parse_arg_comp()?;
-            Ok(Node::new_binop(BinOp::LE, lhs, rhs))
-        } else if self.get_if_punct(Punct::LT) {
-            let rhs = self.parse_arg_comp()?;
-            Ok(Node::new_binop(BinOp::LT, lhs, rhs))
-        } else {
-            Ok(lhs)
+        let mut lhs = self.parse_arg_bitor()?;
+        let ops = [
+            (Punct::GE, BinOp::GE),
+            (Punct::GT, BinOp::GT),
+            (Punct::LE, BinOp::LE),
+            (Punct::LT, BinOp::LT),
+        ];
+        while let Some(op) = self.get_if_binop(&ops) {
+            let rhs = self.parse_arg_bitor()?;
+            lhs = Node::new_binop(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_arg_bitor(&mut self) -> Result<Node, ParseError> {
+        let mut lhs = self.parse_arg_bitand()?;
+        let ops = [(Punct::Pipe, BinOp::BitOr), (Punct::Caret, BinOp::BitXor)];
+        while let Some(op) = self.get_if_binop(&ops) {
+            let rhs = self.parse_arg_bitand()?;
+            lhs = Node::new_binop(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_arg_bitand(&mut self) -> Result<Node, ParseError> {
+        let mut lhs = self.parse_arg_shift()?;
+        while let Some(op) = self.get_if_binop(&[(Punct::And, BinOp::BitAnd)]) {
+            let rhs = self.parse_arg_shift()?;
+            lhs = Node::new_binop(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_arg_shift(&mut self) -> Result<Node, ParseError> {
+        let mut lhs = self.parse_arg_add()?;
+        let ops = [(Punct::Shl, BinOp::Shl), (Punct::Shr, BinOp::Shr)];
+        while let Some(op) = self.get_if_binop(&ops) {
+            let rhs = self.parse_arg_add()?;
+            lhs = Node::new_binop(op, lhs, rhs);
         }
+        Ok(lhs)
     }
 
     fn parse_arg_add(&mut self) -> Result<Node, ParseError> {
-        let lhs = self.parse_arg_mul()?;
-        let tok = self.peek_non_space().clone();
-        match &tok.kind {
-            TokenKind::Punct(ref punct) => match punct {
-                Punct::Plus => {
-                    self.get();
-                    let rhs = self.parse_arg_add()?;
-                    let loc = lhs.loc.merge(rhs.loc);
-                    Ok(Node::new(
-                        NodeKind::BinOp(BinOp::Add, Box::new(lhs), Box::new(rhs)),
-                        loc,
-                    ))
-                }
-                Punct::Minus => {
-                    self.get();
-                    let rhs = self.parse_arg_add()?;
-                    let loc = lhs.loc.merge(rhs.loc);
-                    Ok(Node::new(
-                        NodeKind::BinOp(BinOp::Sub, Box::new(lhs), Box::new(rhs)),
-                        loc,
-                    ))
-                }
-                _ => Ok(lhs),
-            },
-            _ => Ok(lhs),
+        let mut lhs = self.parse_arg_mul()?;
+        let ops = [(Punct::Plus, BinOp::Add), (Punct::Minus, BinOp::Sub)];
+        while let Some(op) = self.get_if_binop(&ops) {
+            let rhs = self.parse_arg_mul()?;
+            lhs = Node::new_binop(op, lhs, rhs);
         }
+        Ok(lhs)
     }
 
     fn parse_arg_mul(&mut self) -> Result<Node, ParseError> {
-        let lhs = self.parse_unary_minus()?;
-        self.skip_space();
-        let tok = self.peek_non_space().clone();
-        match &tok.kind {
-            TokenKind::Punct(Punct::Mul) => {
-                self.get();
-                let rhs = self.parse_arg_mul()?;
-                let loc = lhs.loc.merge(rhs.loc);
-                Ok(Node::new(
-                    NodeKind::BinOp(BinOp::Mul, Box::new(lhs), Box::new(rhs)),
-                    loc,
-                ))
-            }
-            TokenKind::Punct(Punct::Div) => {
-                self.get();
-                let rhs = self.parse_arg_mul()?;
-                let loc = lhs.loc.merge(rhs.loc);
-                Ok(Node::new(
-                    NodeKind::BinOp(BinOp::Div, Box::new(lhs), Box::new(rhs)),
-                    loc,
-                ))
-            }
-            _ => Ok(lhs),
+        let mut lhs = self.parse_unary_minus()?;
+        let ops = [
+            (Punct::Mul, BinOp::Mul),
+            (Punct::Div, BinOp::Div),
+            (Punct::Rem, BinOp::Rem),
+        ];
+        while let Some(op) = self.get_if_binop(&ops) {
+            let rhs = self.parse_unary_minus()?;
+            lhs = Node::new_binop(op, lhs, rhs);
         }
+        Ok(lhs)
     }
 
-    fn parse_unary_minus(&mut self) -> Result<Node, ParseError> {
-        let loc = self.loc();
-        if self.get_if_punct(Punct::Minus) {
-            let lhs = self.parse_primary_ext()?;
-            let loc = loc.merge(lhs.loc());
-            let lhs = Node::new_binop(BinOp::Mul, lhs, Node::new_number(-1, loc));
+    /// Consumes a binary operator out of `ops` if one comes next on the same
+    /// line, and returns it. An operator right before `=` is left for
+    /// `parse_arg_assign`, as in `a += 1`.
+    fn get_if_binop(&mut self, ops: &[(Punct, BinOp)]) -> Option<BinOp> {
+        let kind = self.peek_non_space().kind.clone();
+        let op = ops.iter().find_map(|(punct, op)| match kind {
+            TokenKind::Punct(p) if p == *punct => Some(op.clone()),
+            _ => None,
+        })?;
+        if self.tokens[self.cursor + 1].kind == TokenKind::Punct(Punct::Assign) {
+            return None;
+        }
+        self.get();
+        Some(op)
+    }
+
+    /// Consumes `op=`, written as an operator directly followed by `=`.
+    fn get_if_op_assign(&mut self) -> Option<BinOp> {
+        let op = match self.peek_non_space().kind {
+            TokenKind::Punct(punct) => match punct {
+                Punct::Plus => BinOp::Add,
+                Punct::Minus => BinOp::Sub,
+                Punct::Mul => BinOp::Mul,
+                Punct::Div => BinOp::Div,
+                Punct::Rem => BinOp::Rem,
+                Punct::Pow => BinOp::Pow,
+                Punct::And => BinOp::BitAnd,
+                Punct::Pipe => BinOp::BitOr,
+                Punct::Caret => BinOp::BitXor,
+                Punct::Shl => BinOp::Shl,
+                Punct::Shr => BinOp::Shr,
+                Punct::LAnd => BinOp::LAnd,
+                Punct::LOr => BinOp::LOr,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if self.tokens[self.cursor + 1].kind != TokenKind::Punct(Punct::Assign) {
+            return None;
+        }
+        self.get();
+        self.get();
+        Some(op)
+    }
+
+    /// Returns `lhs` as an assignment target, where a local variable is an
+    /// identifier again. `loc` is the location of the `=`.
+    fn expect_assignable(&self, lhs: Node, loc: Loc) -> Result<Node, ParseError> {
+        if let NodeKind::LocalVar(id) = lhs.kind {
+            Ok(Node::new_identifier(id, lhs.loc()))
+        } else if lhs.is_assignable() {
             Ok(lhs)
         } else {
-            let lhs = self.parse_primary_ext()?;
-            Ok(lhs)
+            Err(ParseError::new(
+                ParseErrorKind::InvalidAssignTarget,
+                lhs.loc().merge(loc),
+            ))
         }
     }
 
-    fn parse_primary_ext(&mut self) -> Result<Node, ParseError> {
-        let loc = self.loc();
-        let mut node = self.parse_primary()?;
-        let tok = self.peek_no_skip_line_term();
-        if tok.kind == TokenKind::Punct(Punct::LParen) {
-            self.get();
-            let mut args = ParsedArgs::new();
-            args.args = self.parse_parenthesize_args()?;
-            let end_loc = self.loc();
-            // println!("node: {:?}", node);
-
-            return Ok(Node::new_send(
-                Node::new(NodeKind::SelfValue, loc),
+    /// Unary minus binds looser than `**`, so `-2 ** 2` is -4.
+    fn parse_unary_minus(&mut self) -> Result<Node, ParseError> {
+        let loc = self.peek().1;
+        if self.get_if_punct(Punct::Minus) {
+            // A minus sign right before a number is part of the literal, so
+            // that `-2.5.round` rounds -2.5.
+            let tok = self.peek_no_skip_line_term().clone();
+            let literal = match tok.kind {
+                TokenKind::NumLit(num) => Some(Token::new_numlit(-num, loc.merge(tok.loc))),
+                TokenKind::BigNumLit(num) => {
+                    let num = -num;
+                    Some(match num.to_i64() {
+                        Some(num) => Token::new_numlit(num, loc.merge(tok.loc)),
+                        None => Token::new_bignumlit(num, loc.merge(tok.loc)),
+                    })
+                }
+                TokenKind::FloatLit(num) => Some(Token::new_floatlit(-num, loc.merge(tok.loc))),
+                _ => None,
+            };
+            match literal {
+                Some(literal) if !self.is_pow_after_literal() => {
+                    self.tokens[self.cursor] = literal;
+                    self.parse_pow()
+                }
+                _ => {
+                    let operand = self.parse_unary_minus()?;
+                    Ok(Node::new_unop(UnOp::Minus, operand, loc))
+                }
+            }
+        } else {
+            self.parse_pow()
+        }
+    }
+
+    /// Whether the numeric literal at the cursor is followed by `**`.
+    fn is_pow_after_literal(&self) -> bool {
+        self.tokens[self.cursor + 1..]
+            .iter()
+            .find(|tok| !tok.is_space())
+            .is_some_and(|tok| tok.kind == TokenKind::Punct(Punct::Pow))
+    }
+
+    /// `**` is right-associative and takes a signed exponent: `2 ** -1`.
+    fn parse_pow(&mut self) -> Result<Node, ParseError> {
+        let lhs = self.parse_unary()?;
+        match self.get_if_binop(&[(Punct::Pow, BinOp::Pow)]) {
+            Some(op) => {
+                let rhs = self.parse_unary_minus()?;
+                Ok(Node::new_binop(op, lhs, rhs))
+            }
+            None => Ok(lhs),
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, ParseError> {
+        let loc = self.peek().1;
+        let op = match self.peek().0.kind {
+            TokenKind::Punct(Punct::Not) => UnOp::Not,
+            TokenKind::Punct(Punct::Tilde) => UnOp::BitNot,
+            TokenKind::Punct(Punct::Plus) => UnOp::Plus,
+            _ => return self.parse_primary_ext(),
+        };
+        self.get();
+        let operand = self.parse_unary()?;
+        Ok(Node::new_unop(op, operand, loc))
+    }
+
+    fn parse_primary_ext(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.parse_primary()?;
+        let loc = node.loc();
+        let call = match node.kind {
+            NodeKind::Ident(_) | NodeKind::Const(_)
+                if self.peek_no_skip_line_term().kind == TokenKind::Punct(Punct::LParen) =>
+            {
+                true
+            }
+            NodeKind::Ident(id) if !self.is_lvar(id) => {
+                self.is_command_arg_start() || self.is_block_start()
+            }
+            _ => false,
+        };
+        if call {
+            let args = self.parse_call_args()?;
+            node = Node::new_send(
+                Node::new(NodeKind::SelfValue, loc),
                 node,
                 args,
-                loc.merge(end_loc),
-            ));
-        } else if tok.kind == TokenKind::Punct(Punct::LBoxBrackets) {
-            self.expect_line_context_literal(LineContext::Literal(Literal::Array))?;
-            let num = self.parse_array_index()?;
-            let end_loc = self.loc();
-            let node = Node::new_array_index(node, num, loc.merge(end_loc));
-            return Ok(node);
-        };
+                loc.merge(self.prev_loc()),
+            );
+        } else if let NodeKind::Ident(id) = node.kind {
+            if self.is_lvar(id) {
+                node = Node::new(NodeKind::LocalVar(id), loc);
+            }
+        }
         loop {
             let tok = self.peek_no_skip_line_term();
             node = match tok.kind {
+                TokenKind::Punct(Punct::LBoxBrackets) => {
+                    self.get();
+                    let args =
+                        self.with_do_block(true, |parser| parser.parse_box_brackets_contents())?;
+                    Node::new_index(node, args, loc.merge(self.prev_loc()))
+                }
                 TokenKind::Punct(Punct::Dot) => {
                     self.get();
-                    let tok = self.get().clone();
-                    match &tok.kind {
-                        TokenKind::Ident(s) => {
-                            let method = s;
-                            let mut args = ParsedArgs::new();
-                            let id = self.ident_table.get_ident_id(method);
-                            self.skip_space();
-                            match self.peek_no_skip_line_term().kind {
-                                TokenKind::Punct(Punct::LParen) => {
-                                    self.get();
-                                    let param_args = self.parse_parenthesize_args()?;
-                                    args.args = param_args;
-
-                                    Node::new_send(
-                                        node,
-                                        Node::new_identifier(id, tok.loc()),
-                                        args,
-                                        loc.merge(self.loc()),
-                                    )
-                                }
-                                TokenKind::Reserved(Reserved::Do) => {
-                                    self.get();
-                                    self.skip_space();
-
-                                    let (args_node, args_table) = self.parse_do()?;
-                                    args.node = args_node;
-                                    args.table = args_table.clone();
-                                    if let NodeKind::Ident(id) = args.table.kind {
-                                        args.table.kind = NodeKind::TableIdent(id)
-                                    };
-
-                                    Node::new_send(
-                                        node,
-                                        Node::new_identifier(id, tok.loc()),
-                                        args,
-                                        loc.merge(self.loc()),
-                                    )
-                                }
-                                _ => Node::new_send(
-                                    node,
-                                    Node::new_identifier(id, tok.loc()),
-                                    args,
-                                    loc.merge(self.loc()),
-                                ),
-                            }
-                        }
-                        TokenKind::NumLit(i) => {
-                            let receive_i = node.pick_number();
-                            Node::new_decimal_number(
-                                receive_i as f64 + ((*i as f64) * 0.1),
-                                loc.merge(self.loc()),
-                            )
+                    // `proc.(args)` is `proc.call(args)`.
+                    let tok = match self.peek_no_skip_line_term() {
+                        tok if tok.kind == TokenKind::Punct(Punct::LParen) => {
+                            Token::new_ident("call".to_string(), self.prev_loc())
                         }
+                        _ => self.get().clone(),
+                    };
+                    let id = match &tok.kind {
+                        TokenKind::Ident(name) => self.ident_table.get_ident_id(name),
                         TokenKind::Reserved(Reserved::Class) => {
-                            let method = "class".to_string();
-                            let id = self.ident_table.get_ident_id(&method);
-                            let args = ParsedArgs::new();
-
-                            Node::new_send(
-                                node,
-                                Node::new_identifier(id, tok.loc()),
-                                args,
-                                loc.merge(self.loc()),
-                            )
+                            self.ident_table.get_ident_id("class")
                         }
-                        _ => panic!("method name must be an identifer."),
-                    }
+                        _ => return Err(self.error_unexpected("a method name", tok.loc())),
+                    };
+                    let args = self.parse_call_args()?;
+                    Node::new_send(
+                        node,
+                        Node::new_identifier(id, tok.loc()),
+                        args,
+                        loc.merge(self.prev_loc()),
+                    )
                 }
                 _ => return Ok(node),
             }
         }
     }
 
-    fn parse_parenthesize_args(&mut self) -> Result<Vec<Node>, ParseError> {
-        let mut args = vec![];
-        if self.get_if_punct(Punct::RParen) {
+    /// Parses the arguments and block of a method call after its name:
+    /// arguments in parentheses or, after a space, without them, followed by
+    /// an optional `do ... end` or `{ ... }` block.
+    fn parse_call_args(&mut self) -> Result<ParsedArgs, ParseError> {
+        let mut args = ParsedArgs::new();
+        let (list, block_arg, paren) = self.parse_arg_values()?;
+        args.args = list;
+        if let Some(block_arg) = block_arg {
+            args.block_arg = Some(Box::new(block_arg));
             return Ok(args);
         }
-        loop {
-            args.push(self.parse_arg()?);
-            if !self.get_if_punct(Punct::Comma) {
-                break;
+        self.skip_space();
+        match self.peek_no_skip_line_term().kind {
+            TokenKind::Reserved(Reserved::Do) if !self.no_do_block => {
+                self.get();
+                let (node, params) = self.parse_do()?;
+                args.node = node;
+                args.block_params = params;
+            }
+            TokenKind::Punct(Punct::LBrace) if paren => {
+                self.get();
+                let (node, params) = self.parse_brace_block()?;
+                args.node = node;
+                args.block_params = params;
             }
+            _ => {}
+        }
+        Ok(args)
+    }
+
+    /// Parses the arguments of a call or `yield`, in parentheses or as a
+    /// command, and returns them with any `&proc` argument and whether a
+    /// `{` block may follow.
+    fn parse_arg_values(&mut self) -> Result<(Vec<Node>, Option<Node>, bool), ParseError> {
+        if self.peek_no_skip_line_term().kind == TokenKind::Punct(Punct::LParen) {
+            self.get();
+            let (args, block_arg) = self.parse_parenthesize_args()?;
+            Ok((args, block_arg, true))
+        } else if self.is_command_arg_start() {
+            let (args, block_arg) = self.with_do_block(false, |parser| parser.parse_arg_list())?;
+            Ok((args, block_arg, false))
+        } else {
+            Ok((vec![], None, true))
         }
+    }
+
+    /// Whether a method name is followed by a space and then something
+    /// that starts an argument, as in `puts "hi"`, `foo -1` or `foo [1]`. A
+    /// sign or another unary operator only starts an argument when it is not
+    /// followed by a space, so that `foo - 1` and `foo + 1` stay binary
+    /// operations.
+    fn is_command_arg_start(&self) -> bool {
+        if !self.tokens[self.cursor].is_space() {
+            return false;
+        }
+        let mut c = self.cursor;
+        while self.tokens[c].is_space() {
+            c += 1;
+        }
+        let next_is_space = || {
+            matches!(
+                self.tokens[c + 1].kind,
+                TokenKind::Space | TokenKind::Line | TokenKind::EOF
+            )
+        };
+        match &self.tokens[c].kind {
+            TokenKind::Ident(_)
+            | TokenKind::Const(_)
+            | TokenKind::NumLit(_)
+            | TokenKind::BigNumLit(_)
+            | TokenKind::FloatLit(_)
+            | TokenKind::StringLit(_)
+            | TokenKind::InterpolatedString(_)
+            | TokenKind::Symbol(_) => true,
+            TokenKind::Reserved(reserved) => matches!(
+                reserved,
+                Reserved::True
+                    | Reserved::False
+                    | Reserved::Case
+                    | Reserved::Begin
+                    | Reserved::Def
+                    | Reserved::Yield
+            ),
+            TokenKind::Punct(Punct::At | Punct::AtAt | Punct::Dollar) => true,
+            TokenKind::Punct(Punct::LParen | Punct::LBoxBrackets | Punct::Arrow) => true,
+            TokenKind::Punct(
+                Punct::Plus | Punct::Minus | Punct::Mul | Punct::Not | Punct::Tilde | Punct::And,
+            ) => !next_is_space() && self.tokens[c + 1].kind != TokenKind::Punct(Punct::Assign),
+            _ => false,
+        }
+    }
+
+    /// Whether a block follows, after which a method name without arguments
+    /// is a call.
+    fn is_block_start(&self) -> bool {
+        let mut c = self.cursor;
+        while self.tokens[c].is_space() {
+            c += 1;
+        }
+        match self.tokens[c].kind {
+            TokenKind::Reserved(Reserved::Do) => !self.no_do_block,
+            TokenKind::Punct(Punct::LBrace) => true,
+            _ => false,
+        }
+    }
+
+    fn parse_parenthesize_args(&mut self) -> Result<(Vec<Node>, Option<Node>), ParseError> {
+        if self.get_if_punct(Punct::RParen) {
+            return Ok((vec![], None));
+        }
+        let args = self.with_do_block(true, |parser| parser.parse_arg_list())?;
         if self.get_if_punct(Punct::RParen) {
             Ok(args)
         } else {
-            Err(self.error_unexpected(self.loc()))
+            let loc = self.peek().1;
+            Err(self.error_unexpected("`,` or `)`", loc))
+        }
+    }
+
+    /// Parses call arguments separated by commas. `*args` passes the
+    /// elements of `args` as arguments. Trailing `key: value` and
+    /// `key => value` pairs are passed as one Hash, which binds to keyword
+    /// parameters. A last `&proc` argument is returned separately.
+    fn parse_arg_list(&mut self) -> Result<(Vec<Node>, Option<Node>), ParseError> {
+        let mut args = vec![];
+        let mut entries = vec![];
+        let mut block_arg = None;
+        loop {
+            if self.get_if_punct(Punct::And) {
+                block_arg = Some(self.parse_arg()?);
+                break;
+            } else if self.get_if_punct(Punct::Mul) {
+                let loc = self.prev_loc();
+                if !entries.is_empty() {
+                    return Err(self.error_unexpected("`key: value`", loc));
+                }
+                let arg = self.parse_arg()?;
+                let loc = loc.merge(arg.loc());
+                args.push(Node::new_splat(arg, loc));
+            } else if let Some(key) = self.get_if_label() {
+                entries.push((key, self.parse_arg()?));
+            } else {
+                let arg = self.parse_arg()?;
+                if self.get_if_punct(Punct::FatArrow) {
+                    entries.push((arg, self.parse_arg()?));
+                } else if entries.is_empty() {
+                    args.push(arg);
+                } else {
+                    return Err(self.error_unexpected("`key: value`", arg.loc()));
+                }
+            }
+            if !self.get_if_punct(Punct::Comma) {
+                break;
+            }
         }
+        if let (Some(first), Some(last)) = (entries.first(), entries.last()) {
+            let loc = first.0.loc().merge(last.1.loc());
+            args.push(Node::new_hash(entries, loc));
+        }
+        Ok((args, block_arg))
     }
 
     fn parse_box_brackets_contents(&mut self) -> Result<Vec<Node>, ParseError> {
@@ -641,23 +1450,75 @@ impl Parser {
             }
         }
         if self.get_if_punct(Punct::RBoxBrackets) {
-            self.line_context_stack
-                .push(LineContext::Literal(Literal::Array));
             Ok(args)
         } else {
-            Err(self.error_unexpected(self.loc()))
+            {
+                let loc = self.peek().1;
+                Err(self.error_unexpected("`,` or `]`", loc))
+            }
         }
     }
 
-    fn parse_array_index(&mut self) -> Result<i64, ParseError> {
-        self.get();
-        let num = self.expect_number()?;
+    /// Parses the entries of a hash literal after `{`: `key => value` or
+    /// `label: value`, separated by commas and closed by `}`.
+    fn parse_hash_contents(&mut self) -> Result<Vec<(Node, Node)>, ParseError> {
+        let mut entries = vec![];
+        loop {
+            if self.get_if_punct(Punct::RBrace) {
+                return Ok(entries);
+            }
+            let key = match self.get_if_label() {
+                Some(key) => key,
+                None => {
+                    let key = self.parse_arg()?;
+                    self.expect_punct(Punct::FatArrow)?;
+                    key
+                }
+            };
+            let val = self.parse_arg()?;
+            entries.push((key, val));
+            if !self.get_if_punct(Punct::Comma) {
+                self.expect_punct(Punct::RBrace)?;
+                return Ok(entries);
+            }
+        }
+    }
 
-        if self.get_if_punct(Punct::RBoxBrackets) {
-            Ok(num)
-        } else {
-            Err(self.error_unexpected(self.loc()))
+    /// Parses the tokens of a `#{...}` in a string on their own, as a
+    /// statement list.
+    fn parse_interpolation(&mut self, tokens: &[Token]) -> Result<Node, ParseError> {
+        let saved_tokens = std::mem::replace(&mut self.tokens, tokens.to_vec());
+        let saved_cursor = std::mem::replace(&mut self.cursor, 0);
+        let result = self.parse_comp_stmt().and_then(|node| {
+            let (tok, loc) = self.peek();
+            if tok.is_eof() {
+                Ok(node)
+            } else {
+                Err(self.error_unexpected("`}`", loc))
+            }
+        });
+        self.tokens = saved_tokens;
+        self.cursor = saved_cursor;
+        result
+    }
+
+    /// Consumes a `name:` or `"name":` label and returns it as a symbol.
+    /// The colon must directly follow the name, so `a ? b : c` has no label.
+    fn get_if_label(&mut self) -> Option<Node> {
+        let (tok, loc) = self.peek();
+        let name = match &tok.kind {
+            TokenKind::Ident(s) | TokenKind::Const(s) | TokenKind::StringLit(s) => s.clone(),
+            _ => return None,
+        };
+        let pos = (self.cursor..self.tokens.len()).find(|i| self.tokens[*i].loc == loc)?;
+        let colon = &self.tokens[pos + 1];
+        if colon.kind != TokenKind::Punct(Punct::Colon) || colon.loc.0 != loc.1 + 1 {
+            return None;
         }
+        self.get();
+        self.get();
+        let id = self.ident_table.get_ident_id(&name);
+        Some(Node::new_symbol(id, loc.merge(self.prev_loc())))
     }
 
     fn parse_primary(&mut self) -> Result<Node, ParseError> {
@@ -666,24 +1527,35 @@ impl Parser {
         match &tok.kind {
             TokenKind::Punct(Punct::AtAt) => {
                 let tok = self.get();
-                let loc = tok.loc();
+                let loc = loc.merge(tok.loc());
                 match &tok.kind {
                     TokenKind::Ident(name) => {
                         let id = self.ident_table.get_ident_id(name);
                         Ok(Node::new_class_var(id, loc))
                     }
-                    _ => unimplemented!(),
+                    _ => Err(self.error_unexpected("a class variable name", tok.loc())),
+                }
+            }
+            TokenKind::Punct(Punct::Dollar) => {
+                let tok = self.get();
+                let loc = loc.merge(tok.loc());
+                match &tok.kind {
+                    TokenKind::Ident(name) | TokenKind::Const(name) => {
+                        let id = self.ident_table.get_ident_id(name);
+                        Ok(Node::new_global_identifier(id, loc))
+                    }
+                    _ => Err(self.error_unexpected("a global variable name", tok.loc())),
                 }
             }
             TokenKind::Punct(Punct::At) => {
                 let tok = self.get();
-                let loc = tok.loc();
+                let loc = loc.merge(tok.loc());
                 match &tok.kind {
                     TokenKind::Ident(name) => {
                         let id = self.ident_table.get_ident_id(name);
                         Ok(Node::new_instance_var(id, loc))
                     }
-                    _ => unimplemented!(),
+                    _ => Err(self.error_unexpected("an instance variable name", tok.loc())),
                 }
             }
             TokenKind::Ident(name) => {
@@ -700,35 +1572,118 @@ impl Parser {
                 let id = self.ident_table.get_ident_id(name);
                 Ok(Node::new_const(id, loc))
             }
+            TokenKind::Reserved(Reserved::True) => Ok(Node::new(NodeKind::Bool(true), loc)),
+            TokenKind::Reserved(Reserved::False) => Ok(Node::new(NodeKind::Bool(false), loc)),
             TokenKind::NumLit(num) => {
                 self.line_context_stack
                     .push(LineContext::Literal(Literal::Number));
                 Ok(Node::new_number(*num, loc))
             }
+            TokenKind::BigNumLit(num) => {
+                self.line_context_stack
+                    .push(LineContext::Literal(Literal::Number));
+                Ok(Node::new_bignumber(num.clone(), loc))
+            }
+            TokenKind::FloatLit(num) => {
+                self.line_context_stack
+                    .push(LineContext::Literal(Literal::Number));
+                Ok(Node::new_decimal_number(*num, loc))
+            }
             TokenKind::StringLit(s) => Ok(Node::new_string(s.to_string(), loc)),
-            TokenKind::Punct(Punct::LParen) => {
-                let node = self.parse_comp_stmt()?;
-                let tok = self.get();
-                if tok.kind == TokenKind::Punct(Punct::RParen) {
-                    Ok(node)
-                } else {
-                    Err(self.error_unexpected(self.loc()))
+            TokenKind::InterpolatedString(parts) => {
+                let mut nodes = vec![];
+                for part in parts {
+                    match part {
+                        StringPart::Str(s) => nodes.push(Node::new_string(s.to_string(), loc)),
+                        StringPart::Code(tokens) => nodes.push(self.parse_interpolation(tokens)?),
+                    }
                 }
+                Ok(Node::new_interpolated_string(nodes, loc))
+            }
+            TokenKind::Symbol(name) => {
+                let id = self.ident_table.get_ident_id(name);
+                Ok(Node::new_symbol(id, loc))
+            }
+            TokenKind::Punct(Punct::LParen) => {
+                let node = self.with_do_block(true, |parser| parser.parse_comp_stmt())?;
+                self.expect_punct(Punct::RParen)?;
+                Ok(node)
             }
             TokenKind::Punct(Punct::LBoxBrackets) => {
-                if self.is_first_line_context() {
-                    let contents = self.parse_box_brackets_contents()?;
-                    let end_loc = self.loc();
-                    let node = Node::new_array(contents, loc.merge(end_loc));
-                    Ok(node)
-                } else {
-                    unimplemented!();
-                }
+                let contents =
+                    self.with_do_block(true, |parser| parser.parse_box_brackets_contents())?;
+                let end_loc = self.prev_loc();
+                Ok(Node::new_array(contents, loc.merge(end_loc)))
+            }
+            TokenKind::Punct(Punct::Arrow) => self.parse_lambda(loc),
+            TokenKind::Punct(Punct::LBrace) => {
+                let entries = self.with_do_block(true, |parser| parser.parse_hash_contents())?;
+                let end_loc = self.prev_loc();
+                Ok(Node::new_hash(entries, loc.merge(end_loc)))
             }
             TokenKind::Reserved(Reserved::If) => {
                 let node = self.parse_if_then()?;
-                self.expect_reserved(Reserved::End)?;
-                Ok(node)
+                self.expect_end("if", loc)?;
+                Ok(Node::new(node.kind, loc.merge(self.prev_loc())))
+            }
+            TokenKind::Reserved(Reserved::Unless) => {
+                let node = self.parse_unless_then()?;
+                self.expect_end("unless", loc)?;
+                Ok(Node::new(node.kind, loc.merge(self.prev_loc())))
+            }
+            TokenKind::Reserved(Reserved::Case) => self.parse_case(loc),
+            TokenKind::Reserved(Reserved::Begin) => {
+                let node = self.parse_begin_body()?;
+                self.expect_end("begin", loc)?;
+                Ok(Node::new(node.kind, loc.merge(self.prev_loc())))
+            }
+            TokenKind::Reserved(Reserved::Retry) => {
+                if self.rescue_depth == 0 {
+                    return Err(ParseError::new(ParseErrorKind::RetryOutsideRescue, loc));
+                }
+                Ok(Node::new(NodeKind::Retry, loc))
+            }
+            TokenKind::Reserved(Reserved::While) => self.parse_while(Reserved::While, loc),
+            TokenKind::Reserved(Reserved::Until) => self.parse_while(Reserved::Until, loc),
+            TokenKind::Reserved(Reserved::Break) => {
+                self.check_jump(Reserved::Break, loc);
+                let value = self.parse_jump_value()?;
+                let loc = value.as_ref().map_or(loc, |value| loc.merge(value.loc()));
+                Ok(Node::new(NodeKind::Break(value.map(Box::new)), loc))
+            }
+            TokenKind::Reserved(Reserved::Next) => {
+                self.check_jump(Reserved::Next, loc);
+                let value = self.parse_jump_value()?;
+                let loc = value.as_ref().map_or(loc, |value| loc.merge(value.loc()));
+                Ok(Node::new(NodeKind::Next(value.map(Box::new)), loc))
+            }
+            TokenKind::Reserved(Reserved::Return) => {
+                let value = match self.parse_jump_value()? {
+                    Some(first)
+                        if self.peek_no_skip_line_term().kind == TokenKind::Punct(Punct::Comma) =>
+                    {
+                        let mut values = vec![first];
+                        while self.get_if_punct(Punct::Comma) {
+                            values.push(self.parse_arg()?);
+                        }
+                        let loc = values[0].loc().merge(self.prev_loc());
+                        Some(Node::new_array(values, loc))
+                    }
+                    value => value,
+                };
+                let loc = value.as_ref().map_or(loc, |value| loc.merge(value.loc()));
+                Ok(Node::new(NodeKind::Return(value.map(Box::new)), loc))
+            }
+            TokenKind::Reserved(Reserved::Redo) => {
+                self.check_jump(Reserved::Redo, loc);
+                Ok(Node::new(NodeKind::Redo, loc))
+            }
+            TokenKind::Reserved(Reserved::Yield) => {
+                let (args, block_arg, _) = self.parse_arg_values()?;
+                if let Some(block_arg) = block_arg {
+                    return Err(self.error_unexpected("an argument", block_arg.loc()));
+                }
+                Ok(Node::new(NodeKind::Yield(args), loc.merge(self.prev_loc())))
             }
             TokenKind::Reserved(Reserved::Def) => {
                 let node = self.parse_def()?;
@@ -747,11 +1702,104 @@ impl Parser {
                 Ok(node)
             }
             TokenKind::EOF => Err(self.error_eof(loc)),
-            _ => Err(self.error_unexpected(loc)),
+            _ => Err(self.error_unexpected("an expression", loc)),
+        }
+    }
+
+    /// Parses `while cond [do] ... end` or `until cond [do] ... end`.
+    /// `loc` is the location of the keyword.
+    fn parse_while(&mut self, keyword: Reserved, loc: Loc) -> Result<Node, ParseError> {
+        let cond = self.with_do_block(false, |parser| parser.parse_expr())?;
+        if !self.get_if_term() {
+            let tok = self.get();
+            if tok.kind != TokenKind::Reserved(Reserved::Do) {
+                return Err(self.error_unexpected("`do` or newline", tok.loc()));
+            }
+        }
+        self.loop_depth += 1;
+        let body = self.parse_comp_stmt();
+        self.loop_depth -= 1;
+        let body = body?;
+        self.expect_end(keyword.as_str(), loc)?;
+        let loc = loc.merge(self.prev_loc());
+        match keyword {
+            Reserved::Until => Ok(Node::new_until(cond, body, loc)),
+            _ => Ok(Node::new_while(cond, body, loc)),
+        }
+    }
+
+    /// Records `keyword` as invalid if it is not inside a loop or block.
+    fn check_jump(&mut self, keyword: Reserved, loc: Loc) {
+        if self.loop_depth == 0 {
+            self.pending_jumps
+                .push(ParseError::new(ParseErrorKind::InvalidJump(keyword), loc));
         }
     }
 
+    /// Parses the optional value of `break` or `next`.
+    fn parse_jump_value(&mut self) -> Result<Option<Node>, ParseError> {
+        self.skip_space();
+        let tok = self.peek_no_skip_line_term();
+        if tok.is_term() {
+            return Ok(None);
+        }
+        match tok.kind {
+            TokenKind::Punct(Punct::RParen | Punct::RBrace)
+            | TokenKind::Reserved(
+                Reserved::If
+                | Reserved::Unless
+                | Reserved::While
+                | Reserved::Until
+                | Reserved::End
+                | Reserved::Else
+                | Reserved::Elsif
+                | Reserved::Rescue
+                | Reserved::Ensure
+                | Reserved::When
+                | Reserved::In,
+            ) => Ok(None),
+            _ => Ok(Some(self.parse_arg()?)),
+        }
+    }
+
+    /// Parses the body of a method or class, where loops and statements of
+    /// the enclosing code no longer apply.
+    fn parse_scope_body<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let stmt_depth = std::mem::replace(&mut self.stmt_depth, 0);
+        let rescue_depth = std::mem::replace(&mut self.rescue_depth, 0);
+        let res = parse(self);
+        self.loop_depth = loop_depth;
+        self.stmt_depth = stmt_depth;
+        self.rescue_depth = rescue_depth;
+        res
+    }
+
     fn parse_class(&mut self) -> Result<Node, ParseError> {
+        let class_loc = self.prev_loc();
+        let class = self.parse_class_header_and_body();
+        if class.is_err() {
+            self.add_orphan_closer(TokenKind::Reserved(Reserved::End));
+        }
+        let (id, inheritance_class_id, body) = class?;
+        self.expect_end("class", class_loc)?;
+        self.block_context_stack.pop().unwrap();
+        self.reset_line_context();
+
+        Ok(Node::new_class_decl(
+            id,
+            body,
+            inheritance_class_id,
+            class_loc.merge(self.prev_loc()),
+        ))
+    }
+
+    fn parse_class_header_and_body(
+        &mut self,
+    ) -> Result<(IdentId, Option<IdentId>, Node), ParseError> {
         self.expect_out_of_method_block_context()?;
         self.block_context_stack.push(BlockContext::Class);
         self.expect_first_line_context()?;
@@ -768,36 +1816,211 @@ impl Parser {
         };
         let id = self.ident_table.get_ident_id(&name);
 
-        let body = self.parse_comp_stmt()?;
-        self.expect_reserved(Reserved::End)?;
-        self.block_context_stack.pop().unwrap();
-        self.reset_line_context();
-
-        Ok(Node::new_class_decl(id, body, inheritance_class_id))
+        let body = self.with_lvar_scope(false, |parser| {
+            parser.parse_scope_body(|parser| parser.parse_comp_stmt())
+        })?;
+        Ok((id, inheritance_class_id, body))
     }
 
     pub fn parse_for(&mut self) -> Result<Node, ParseError> {
+        let for_loc = self.prev_loc();
         self.expect_first_line_context()?;
         let loc = self.loc();
         let table_ident = match &self.get().kind {
             TokenKind::Ident(s) => s.clone(),
-            _ => return Err(self.error_unexpected(loc)),
+            _ => return Err(self.error_unexpected("a loop variable", loc)),
         };
         let id = self.ident_table.get_ident_id(&table_ident);
+        self.declare_lvar(id);
         self.expect_reserved(Reserved::In)?;
         let table = self.parse_arg_add()?;
         self.skip_space();
+        self.loop_depth += 1;
+        let body = self.parse_comp_stmt();
+        self.loop_depth -= 1;
+        let body = body?;
+        self.expect_end("for", for_loc)?;
+
+        Ok(Node::new_for(
+            id,
+            table,
+            body,
+            for_loc.merge(self.prev_loc()),
+        ))
+    }
+
+    /// Parses `case [subject] (when ...)+ [else ...] end`, or the same with
+    /// `in` clauses, which need a subject. `case_loc` is the location of the
+    /// `case` keyword.
+    fn parse_case(&mut self, case_loc: Loc) -> Result<Node, ParseError> {
+        let case = self.parse_case_body(case_loc);
+        if case.is_err() {
+            self.add_orphan_closer(TokenKind::Reserved(Reserved::End));
+        }
+        case
+    }
+
+    /// Parses a `case` after the keyword, up to and including its `end`.
+    fn parse_case_body(&mut self, case_loc: Loc) -> Result<Node, ParseError> {
+        self.skip_space();
+        let subject = if self.peek_no_skip_line_term().is_term() {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
+        while let TokenKind::Line | TokenKind::Punct(Punct::Semi) =
+            self.peek_no_skip_line_term().kind
+        {
+            self.get_no_skip_line_term();
+        }
+        let loc = self.loc();
+        let keyword = match self.peek().0.kind {
+            TokenKind::Reserved(Reserved::In) if subject.is_some() => Reserved::In,
+            TokenKind::Reserved(Reserved::When) => Reserved::When,
+            TokenKind::EOF => return Err(self.error_eof(loc)),
+            _ => return Err(self.error_unexpected("`when`", loc)),
+        };
+        let mut branches = vec![];
+        loop {
+            let loc = self.peek().1;
+            if !self.get_if_reserved(keyword) {
+                break;
+            }
+            let mut patterns = vec![];
+            loop {
+                if keyword == Reserved::In {
+                    patterns.push(self.parse_pattern()?);
+                    if !self.get_if_punct(Punct::Pipe) {
+                        break;
+                    }
+                } else {
+                    patterns.push(self.parse_arg()?);
+                    if !self.get_if_punct(Punct::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.skip_space();
+            self.parse_then()?;
+            let body = self.parse_comp_stmt()?;
+            let loc = loc.merge(self.prev_loc());
+            let kind = match keyword {
+                Reserved::In => NodeKind::In(patterns, Box::new(body)),
+                _ => NodeKind::When(patterns, Box::new(body)),
+            };
+            branches.push(Node::new(kind, loc));
+        }
+        let else_ = if self.get_if_reserved(Reserved::Else) {
+            Some(self.parse_comp_stmt()?)
+        } else {
+            None
+        };
+        self.expect_end("case", case_loc)?;
+        let loc = case_loc.merge(self.prev_loc());
+        Ok(Node::new_case(subject, branches, else_, loc))
+    }
+
+    /// Parses a value pattern of an `in` clause, stopping before the `|`
+    /// that separates alternatives. Array and hash patterns and variables
+    /// would destructure or bind, and are rejected rather than compared as
+    /// values; when recovering, the error is recorded and the rest of the
+    /// `case` is parsed as usual.
+    fn parse_pattern(&mut self) -> Result<Node, ParseError> {
+        let lhs = self.parse_arg_add()?;
+        if let NodeKind::Array(_) | NodeKind::Hash(_) | NodeKind::Ident(_) | NodeKind::LocalVar(_) =
+            lhs.kind
+        {
+            let err = ParseError::new(ParseErrorKind::UnsupportedPattern, lhs.loc());
+            if !self.recover {
+                return Err(err);
+            }
+            self.errors.push(err);
+        }
+        let loc = self.peek().1;
+        if self.get_if_punct(Punct::Range) {
+            self.expect_line_context_literal(LineContext::Literal(Literal::Number), loc)?;
+            let rhs = self.parse_arg_add()?;
+            Ok(Node::new_range(lhs, rhs))
+        } else {
+            Ok(lhs)
+        }
+    }
+
+    /// Parses a body followed by optional `rescue`, `else` and `ensure`
+    /// clauses, as written in `begin ... end` and `def ... end`.
+    fn parse_begin_body(&mut self) -> Result<Node, ParseError> {
         let body = self.parse_comp_stmt()?;
-        self.expect_reserved(Reserved::End)?;
+        let mut rescues = vec![];
+        loop {
+            let loc = self.peek().1;
+            if !self.get_if_reserved(Reserved::Rescue) {
+                break;
+            }
+            rescues.push(self.parse_rescue(loc)?);
+        }
+        let else_ = if !rescues.is_empty() && self.get_if_reserved(Reserved::Else) {
+            Some(self.parse_comp_stmt()?)
+        } else {
+            None
+        };
+        let ensure = if self.get_if_reserved(Reserved::Ensure) {
+            Some(self.parse_comp_stmt()?)
+        } else {
+            None
+        };
+        if rescues.is_empty() && ensure.is_none() {
+            return Ok(body);
+        }
+        let loc = body.loc().merge(self.prev_loc());
+        Ok(Node::new_begin(body, rescues, else_, ensure, loc))
+    }
 
-        Ok(Node::new_for(id, table, body))
+    /// Parses `rescue [Class, ...] [=> var]` and its body. `rescue_loc` is the
+    /// location of the `rescue` keyword.
+    fn parse_rescue(&mut self, rescue_loc: Loc) -> Result<Node, ParseError> {
+        let mut classes = vec![];
+        let mut var = None;
+        self.skip_space();
+        if !self.peek_no_skip_line_term().is_term() {
+            match self.peek_no_skip_line_term().kind {
+                TokenKind::Punct(Punct::FatArrow) | TokenKind::Reserved(Reserved::Then) => {}
+                _ => loop {
+                    classes.push(self.parse_arg()?);
+                    if !self.get_if_punct(Punct::Comma) {
+                        break;
+                    }
+                },
+            }
+            if self.get_if_punct(Punct::FatArrow) {
+                let loc = self.loc();
+                match self.parse_ident()?.kind {
+                    NodeKind::Ident(id) => {
+                        self.declare_lvar(id);
+                        var = Some(id);
+                    }
+                    _ => return Err(self.error_unexpected("a variable name", loc)),
+                }
+            }
+        }
+        self.skip_space();
+        self.parse_then()?;
+        self.rescue_depth += 1;
+        let body = self.parse_comp_stmt();
+        self.rescue_depth -= 1;
+        let body = body?;
+        Ok(Node::new_rescue(
+            classes,
+            var,
+            body,
+            rescue_loc.merge(self.prev_loc()),
+        ))
     }
 
     pub fn parse_line(&mut self) -> Result<Node, ParseError> {
         let loc = self.loc();
         match &self.get().kind {
             TokenKind::Line => Ok(Node::new_line(loc)),
-            _ => Err(self.error_unexpected(loc)),
+            _ => Err(self.error_unexpected("newline", loc)),
         }
     }
 
@@ -805,7 +2028,7 @@ impl Parser {
         let loc = self.loc();
         match &self.get().kind {
             TokenKind::Const(s) => Ok(s.clone()),
-            _ => Err(self.error_unexpected(loc)),
+            _ => Err(self.error_unexpected("a constant name", loc)),
         }
     }
 
@@ -813,76 +2036,318 @@ impl Parser {
         if self.get_if_term() {
             return Ok(());
         }
-        self.expect_reserved(Reserved::Then)?;
-        Ok(())
+        let tok = self.get();
+        match tok.kind {
+            TokenKind::Reserved(Reserved::Then) => Ok(()),
+            _ => Err(self.error_unexpected("`then` or newline", tok.loc())),
+        }
     }
 
     fn parse_def(&mut self) -> Result<Node, ParseError> {
-        self.block_context_stack.push(BlockContext::Method);
+        let def_loc = self.prev_loc();
+        self.block_context_stack.push(BlockContext::Method(def_loc));
+        let def = self.parse_def_header_and_body();
+        if def.is_err() {
+            self.add_orphan_closer(TokenKind::Reserved(Reserved::End));
+        }
+        let (id, args, body) = def?;
+        self.expect_end("def", def_loc)?;
+        self.block_context_stack.pop().unwrap();
+        self.reset_line_context();
+
+        Ok(Node::new_method_decl(
+            id,
+            args,
+            body,
+            def_loc.merge(self.prev_loc()),
+        ))
+    }
+
+    fn parse_def_header_and_body(&mut self) -> Result<(IdentId, Vec<Node>, Node), ParseError> {
         self.expect_first_line_context()?;
-        let loc = self.loc();
-        let name = match &self.get().kind {
-            TokenKind::Ident(s) => s.clone(),
-            _ => return Err(self.error_unexpected(loc)),
-        };
+        let name = self.parse_method_name()?;
         let id = self.ident_table.get_ident_id(&name);
+        self.with_lvar_scope(false, |parser| {
+            let args = parser.parse_params()?;
+            let body = parser.parse_scope_body(|parser| parser.parse_begin_body())?;
+            Ok((id, args, body))
+        })
+    }
 
-        let args = self.parse_params()?;
-        let body = self.parse_comp_stmt()?;
-        self.expect_reserved(Reserved::End)?;
-        self.block_context_stack.pop().unwrap();
-        self.reset_line_context();
+    /// Parses the name in a `def`: an identifier, a setter such as `name=`,
+    /// or an operator such as `+`, `-@`, `<=>` or `[]=`.
+    fn parse_method_name(&mut self) -> Result<String, ParseError> {
+        let tok = self.get();
+        let loc = tok.loc();
+        let punct = match &tok.kind {
+            TokenKind::Ident(s)
+                if self.peek_no_skip_line_term().kind == TokenKind::Punct(Punct::Assign) =>
+            {
+                self.get();
+                return Ok(format!("{}=", s));
+            }
+            TokenKind::Ident(s) => return Ok(s.clone()),
+            TokenKind::Punct(punct) => *punct,
+            _ => return Err(self.error_unexpected("a method name", loc)),
+        };
+        match punct {
+            Punct::Plus | Punct::Minus | Punct::Not | Punct::Tilde => {
+                if self.peek_no_skip_line_term().kind == TokenKind::Punct(Punct::At) {
+                    self.get();
+                    if let Punct::Plus | Punct::Minus = punct {
+                        return Ok(format!("{}@", punct.as_str()));
+                    }
+                }
+                Ok(punct.as_str().to_string())
+            }
+            Punct::Mul
+            | Punct::Div
+            | Punct::Rem
+            | Punct::Pow
+            | Punct::Eq
+            | Punct::NE
+            | Punct::TEq
+            | Punct::Cmp
+            | Punct::LT
+            | Punct::LE
+            | Punct::GT
+            | Punct::GE
+            | Punct::And
+            | Punct::Pipe
+            | Punct::Caret
+            | Punct::Shl
+            | Punct::Shr => Ok(punct.as_str().to_string()),
+            Punct::LBoxBrackets => {
+                if self.peek_no_skip_line_term().kind != TokenKind::Punct(Punct::RBoxBrackets) {
+                    return Err(self.error_unexpected("a method name", loc));
+                }
+                self.get();
+                if self.peek_no_skip_line_term().kind == TokenKind::Punct(Punct::Assign) {
+                    self.get();
+                    Ok("[]=".to_string())
+                } else {
+                    Ok("[]".to_string())
+                }
+            }
+            _ => Err(self.error_unexpected("a method name", loc)),
+        }
+    }
 
-        Ok(Node::new_method_decl(id, args, body))
+    fn parse_do(&mut self) -> Result<(Node, Vec<Node>), ParseError> {
+        let do_loc = self.prev_loc();
+        let block = self.parse_block_body(|parser| parser.parse_comp_stmt());
+        if block.is_err() {
+            self.add_orphan_closer(TokenKind::Reserved(Reserved::End));
+        }
+        let block = block?;
+        self.expect_end("do", do_loc)?;
+        Ok(block)
     }
 
-    fn parse_do(&mut self) -> Result<(Node, Node), ParseError> {
-        let table = match self.peek_no_skip_line_term().kind {
+    /// Parses a `{ |params| ... }` block after the `{`.
+    fn parse_brace_block(&mut self) -> Result<(Node, Vec<Node>), ParseError> {
+        let block = self.parse_block_body(|parser| parser.parse_comp_stmt());
+        if block.is_err() {
+            self.add_orphan_closer(TokenKind::Punct(Punct::RBrace));
+        }
+        let block = block?;
+        self.expect_punct(Punct::RBrace)?;
+        Ok(block)
+    }
+
+    /// Parses the parameters and the body of a block, which sees the local
+    /// variables around it. Returns the body and the parameters.
+    fn parse_block_body(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Node, ParseError>,
+    ) -> Result<(Node, Vec<Node>), ParseError> {
+        self.parse_closure(Self::parse_block_params, parse)
+    }
+
+    /// Parses `|params|` or `||` at the start of a block, if any.
+    fn parse_block_params(&mut self) -> Result<Vec<Node>, ParseError> {
+        self.skip_space();
+        match self.peek_no_skip_line_term().kind {
             TokenKind::Punct(Punct::Pipe) => {
                 self.get();
-                let node = self.parse_ident()?;
+                let params = self.parse_param_list(Punct::Pipe)?;
                 self.expect_punct(Punct::Pipe)?;
-                node
+                Ok(params)
             }
-            _ => Node::new_none(),
-        };
+            TokenKind::Punct(Punct::LOr) => {
+                self.get();
+                Ok(vec![])
+            }
+            _ => Ok(vec![]),
+        }
+    }
 
-        self.block_context_stack.push(BlockContext::Method);
-        let body = self.parse_comp_stmt()?;
-        self.expect_reserved(Reserved::End)?;
-        self.block_context_stack.pop().unwrap();
-        self.reset_line_context();
+    /// Parses `-> (params) { ... }` or `-> params do ... end` after the `->`.
+    fn parse_lambda(&mut self, loc: Loc) -> Result<Node, ParseError> {
+        let (body, params) = self.parse_closure(
+            |parser| {
+                parser.skip_space();
+                match parser.peek_no_skip_line_term().kind {
+                    TokenKind::Punct(Punct::LParen) => {
+                        parser.get();
+                        if parser.get_if_punct(Punct::RParen) {
+                            return Ok(vec![]);
+                        }
+                        let params = parser.parse_param_list(Punct::RParen)?;
+                        parser.expect_punct(Punct::RParen)?;
+                        Ok(params)
+                    }
+                    TokenKind::Ident(_) => parser.parse_param_list(Punct::LBrace),
+                    _ => Ok(vec![]),
+                }
+            },
+            |parser| {
+                let (tok, open_loc) = parser.peek();
+                match tok.kind {
+                    TokenKind::Punct(Punct::LBrace) => {
+                        parser.get();
+                        let body = parser.parse_comp_stmt()?;
+                        parser.expect_punct(Punct::RBrace)?;
+                        Ok(body)
+                    }
+                    TokenKind::Reserved(Reserved::Do) => {
+                        parser.get();
+                        let body = parser.parse_comp_stmt()?;
+                        parser.expect_end("do", open_loc)?;
+                        Ok(body)
+                    }
+                    _ => Err(parser.error_unexpected("`{` or `do`", open_loc)),
+                }
+            },
+        )?;
+        let loc = loc.merge(self.prev_loc());
+        Ok(Node::new(NodeKind::Lambda(params, Box::new(body)), loc))
+    }
 
-        Ok((Node::new_block_decl(body), table))
+    /// Parses the parameters and then the body of a block or lambda, in a
+    /// scope that sees the local variables around it.
+    fn parse_closure(
+        &mut self,
+        parse_params: impl FnOnce(&mut Self) -> Result<Vec<Node>, ParseError>,
+        parse: impl FnOnce(&mut Self) -> Result<Node, ParseError>,
+    ) -> Result<(Node, Vec<Node>), ParseError> {
+        self.with_lvar_scope(true, |parser| {
+            let params = parse_params(parser)?;
+            let rescue_depth = std::mem::replace(&mut parser.rescue_depth, 0);
+            let loop_depth = std::mem::replace(&mut parser.loop_depth, 1);
+            let body = parser.with_do_block(true, parse);
+            parser.rescue_depth = rescue_depth;
+            parser.loop_depth = loop_depth;
+            parser.reset_line_context();
+            Ok((Node::new_block_decl(body?), params))
+        })
     }
 
+    /// Parses the parameters of a `def`, in parentheses or up to the end of
+    /// the line.
     pub fn parse_params(&mut self) -> Result<Vec<Node>, ParseError> {
-        if !self.get_if_punct(Punct::LParen) {
+        let paren = self.get_if_punct(Punct::LParen);
+        if paren && self.get_if_punct(Punct::RParen) || !paren && self.peek_non_space().is_term() {
             return Ok(vec![]);
         }
-        let mut args = vec![];
-        if self.get_if_punct(Punct::RParen) {
-            return Ok(args);
+        let params = self.parse_param_list(Punct::RParen)?;
+        if !paren || self.get_if_punct(Punct::RParen) {
+            Ok(params)
+        } else {
+            let tok = self.peek().1;
+            Err(self.error_unexpected("`,` or `)`", tok))
         }
+    }
+
+    /// Parses parameters separated by commas, ending before `close` or the
+    /// end of the line: required and optional positional parameters,
+    /// `*rest`, required parameters after it, keywords, `**opts` and
+    /// `&block`, in that order. A required parameter may be a destructuring
+    /// pattern such as `(a, b)`.
+    fn parse_param_list(&mut self, close: Punct) -> Result<Vec<Node>, ParseError> {
+        let mut params = vec![];
+        // 0: required, 1: optional, 2: after `*rest`, 3: keywords,
+        // 4: after `**opts`, 5: after `&block`.
+        let mut stage = 0;
         loop {
-            let (arg, loc) = match self.get().clone() {
-                Token {
-                    kind: TokenKind::Ident(s),
-                    loc,
-                } => (s.clone(), loc),
-                Token { loc, .. } => return Err(self.error_unexpected(loc)),
+            let loc = self.peek().1;
+            let (param, allowed, next_stage) = if self.get_if_punct(Punct::Mul) {
+                let id = self.parse_param_name_or("*")?;
+                (NodeKind::RestParam(id), stage <= 1, 2)
+            } else if self.get_if_punct(Punct::Pow) {
+                let id = self.parse_param_name_or("**")?;
+                (NodeKind::KeywordRestParam(id), stage <= 3, 4)
+            } else if self.get_if_punct(Punct::And) {
+                let id = self.parse_param_name()?;
+                (NodeKind::BlockParam(id), stage <= 4, 5)
+            } else if self.get_if_punct(Punct::LParen) {
+                let targets = self.parse_mlhs(Punct::RParen)?;
+                self.expect_punct(Punct::RParen)?;
+                let next_stage = if stage == 0 { 0 } else { 2 };
+                (NodeKind::MultiLhs(targets), stage <= 2, next_stage)
+            } else if let Some(label) = self.get_if_label() {
+                let id = match label.kind {
+                    NodeKind::Symbol(id) => id,
+                    _ => unreachable!(),
+                };
+                let next = self.peek_non_space().clone();
+                let default = match next.kind {
+                    TokenKind::Punct(Punct::Comma) => None,
+                    TokenKind::Punct(p) if p == close => None,
+                    _ if next.is_term() => None,
+                    _ => Some(Box::new(self.parse_arg()?)),
+                };
+                (NodeKind::KeywordParam(id, default), stage <= 3, 3)
+            } else {
+                let id = self.parse_param_name()?;
+                if self.get_if_punct(Punct::Assign) {
+                    let default = self.parse_arg()?;
+                    (
+                        NodeKind::OptionalParam(id, Box::new(default)),
+                        stage <= 1,
+                        1,
+                    )
+                } else {
+                    let next_stage = if stage == 0 { 0 } else { 2 };
+                    (NodeKind::TableIdent(id), stage <= 2, next_stage)
+                }
             };
-            let id = self.ident_table.get_ident_id(&arg);
-            args.push(Node::new(NodeKind::TableIdent(id), loc));
+            let loc = loc.merge(self.prev_loc());
+            if !allowed {
+                return Err(ParseError::new(ParseErrorKind::InvalidParamOrder, loc));
+            }
+            stage = next_stage;
+            if let NodeKind::TableIdent(id)
+            | NodeKind::OptionalParam(id, _)
+            | NodeKind::RestParam(id)
+            | NodeKind::KeywordParam(id, _)
+            | NodeKind::KeywordRestParam(id)
+            | NodeKind::BlockParam(id) = param
+            {
+                self.declare_lvar(id);
+            }
+            params.push(Node::new(param, loc));
             if !self.get_if_punct(Punct::Comma) {
-                break;
+                return Ok(params);
             }
         }
-        if self.get_if_punct(Punct::RParen) {
-            Ok(args)
-        } else {
-            let tok = self.peek().1;
-            Err(self.error_unexpected(tok))
+    }
+
+    fn parse_param_name(&mut self) -> Result<IdentId, ParseError> {
+        match self.get().clone() {
+            Token {
+                kind: TokenKind::Ident(name),
+                ..
+            } => Ok(self.ident_table.get_ident_id(&name)),
+            Token { loc, .. } => Err(self.error_unexpected("a parameter name", loc)),
+        }
+    }
+
+    /// The name after `*` or `**`, which may be left out.
+    fn parse_param_name_or(&mut self, anonymous: &str) -> Result<IdentId, ParseError> {
+        match self.peek_no_skip_line_term().kind {
+            TokenKind::Ident(_) => self.parse_param_name(),
+            _ => Ok(self.ident_table.get_ident_id(anonymous)),
         }
     }
 
@@ -894,7 +2359,7 @@ impl Parser {
                 let id = self.ident_table.get_ident_id(name);
                 Ok(Node::new_identifier(id, tok.loc()))
             }
-            _ => Err(self.error_unexpected(loc)),
+            _ => Err(self.error_unexpected("an identifier", loc)),
         }
     }
 }
@@ -1,8 +1,18 @@
 #[cfg(test)]
 mod test {
     use crate::parser::parser::*;
+    use crate::token::token::*;
     use crate::util::annot::*;
 
+    fn parse_expected_sexp(script: impl Into<String>, expected: &str) {
+        let mut parser = Parser::new();
+        let node = parser.parse_program(script.into()).unwrap();
+        let got = node.to_sexp(&parser.ident_table);
+        if got != expected {
+            panic!("Expected:\n{}\nGot:\n{}", expected, got);
+        }
+    }
+
     fn parse_expected_error(script: impl Into<String>, expected: ParseError) {
         let mut parser = Parser::new();
         let res = parser.parse_program(script.into()).unwrap_err();
@@ -38,4 +48,809 @@ mod test {
         );
         parse_expected_error(program, expected);
     }
+
+    #[test]
+    fn inner_class_definition_points_at_def() {
+        let expected = ParseError::new(
+            ParseErrorKind::InnerClassDefinitionInMethodDefinition,
+            Loc::new(Loc(0, 2)),
+        );
+        parse_expected_error("def f\nclass X\nend\nend", expected.clone());
+        parse_expected_error(
+            "def foo(a, b)\n  x = 1\n  class Bar\n  end\nend",
+            expected.clone(),
+        );
+        parse_expected_error("def f\n  [1].each do class K end end\nend", expected);
+        let expected = ParseError::new(
+            ParseErrorKind::InnerClassDefinitionInMethodDefinition,
+            Loc::new(Loc(12, 14)),
+        );
+        parse_expected_error("class Foo\n  def bar; class Baz; end; end\nend", expected);
+    }
+
+    #[test]
+    fn class_definition_in_block() {
+        for program in [
+            "proc do class K end end",
+            "-> do class K end end",
+            "x { class K end }",
+            "-> { class K; end }",
+            "x do class K; end; end",
+        ] {
+            let mut parser = Parser::new();
+            if let Err(err) = parser.parse_program(program.to_string()) {
+                panic!("{}: {:?}", program, err);
+            }
+        }
+    }
+
+    #[test]
+    fn sexp_method() {
+        let program = "def foo(a)
+  a.times do |i|
+    puts(i + 1.5)
+  end
+end";
+        let expected = "(comp_stmt @0..54
+  (func_decl foo @0..54
+    (params
+      (table_ident a @8..8))
+    (comp_stmt @13..50
+      (send @13..50
+        (lvar a @13..13)
+        (ident times @15..19)
+        (args)
+        (block_params
+          (table_ident i @25..25))
+        (block_decl @32..44
+          (comp_stmt @32..44
+            (send @32..44
+              (self @32..35)
+              (ident puts @32..35)
+              (args
+                (binop + @37..43
+                  (lvar i @37..37)
+                  (decimal_number 1.5 @41..43))))))))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_class() {
+        let program = "class B < A
+  @x = 'x'
+  @@y = [1, 2][0]
+end";
+        let expected = "(comp_stmt @0..43
+  (class_decl B < A @0..43
+    (comp_stmt @14..39
+      (assign @14..21
+        (instance_var @x @14..15)
+        (string \"x\" @19..21))
+      (assign @25..39
+        (class_var @@y @25..27)
+        (index @31..39
+          (array @31..36
+            (number 1 @32..32)
+            (number 2 @35..35))
+          (number 0 @38..38))))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_begin() {
+        let program = "begin
+  foo
+rescue TypeError, NameError => e
+  retry
+else
+  1
+ensure
+  2
+end";
+        let expected = "(comp_stmt @0..75
+  (begin @0..75
+    (comp_stmt @8..10
+      (ident foo @8..10))
+    (rescue => e @12..51
+      (classes
+        (const TypeError @19..27)
+        (const NameError @30..38))
+      (comp_stmt @47..51
+        (retry @47..51)))
+    (else
+      (comp_stmt @60..60
+        (number 1 @60..60)))
+    (ensure
+      (comp_stmt @71..71
+        (number 2 @71..71)))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_while() {
+        let program = "while a
+  next
+  break 1
+end
+b until c";
+        let expected = "(comp_stmt @0..37
+  (while @0..27
+    (ident a @6..6)
+    (comp_stmt @10..23
+      (next @10..13)
+      (break @17..23
+        (number 1 @23..23))))
+  (until @29..37
+    (ident c @37..37)
+    (ident b @29..29)))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_unless_and_modifier() {
+        let program = "unless a then b else c end
+d if e";
+        let expected = "(comp_stmt @0..32
+  (if @0..25
+    (ident a @7..7)
+    (comp_stmt @21..21
+      (ident c @21..21))
+    (comp_stmt @14..14
+      (ident b @14..14)))
+  (if @27..32
+    (ident e @32..32)
+    (ident d @27..27)
+    (comp_stmt @0..0)))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_begin_end_while() {
+        let program = "begin a end while b";
+        let expected = "(comp_stmt @0..18
+  (while_post @0..18
+    (ident b @18..18)
+    (comp_stmt @0..10
+      (ident a @6..6))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn elsif_after_unless_error() {
+        let program = "unless a
+  1
+elsif b
+  2
+end";
+        let expected = unexpected("`end` to close `unless` at line 1", "`elsif`", Loc(13, 17));
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn case_in_unsupported_pattern() {
+        let error = |loc| ParseError::new(ParseErrorKind::UnsupportedPattern, loc);
+        parse_expected_error("case x\nin [a, b]\nend", error(Loc(10, 15)));
+        parse_expected_error("case x\nin {k: v} then 1\nend", error(Loc(10, 15)));
+        parse_expected_error("case x\nin 1 | y\nend", error(Loc(14, 14)));
+        assert_eq!(
+            recovery_errors_found("case x\nin [a, b] then 1\nin 2 then 3\nin {k: 1}\nend\nx = )"),
+            vec!["UnsupportedPattern", "UnsupportedPattern", "`)`"]
+        );
+    }
+
+    #[test]
+    fn case_at_end_of_input() {
+        for program in ["case", "case x"].iter() {
+            let mut parser = Parser::new();
+            let err = parser.parse_program(program.to_string()).unwrap_err();
+            assert_eq!(err.kind, ParseErrorKind::EOF);
+            let mut parser = Parser::new();
+            let (_, errors) = parser.parse_program_with_recovery(program.to_string());
+            let kinds = errors.into_iter().map(|err| err.kind).collect::<Vec<_>>();
+            assert_eq!(kinds, vec![ParseErrorKind::EOF]);
+        }
+    }
+
+    #[test]
+    fn sexp_case() {
+        let program = "case a
+when 1, 2 then 'x'
+when 3..5 then 'y'
+else 'z'
+end";
+        let expected = "(comp_stmt @0..56
+  (case @0..56
+    (ident a @5..5)
+    (when @7..24
+      (values
+        (number 1 @12..12)
+        (number 2 @15..15))
+      (comp_stmt @22..24
+        (string \"x\" @22..24)))
+    (when @26..43
+      (values
+        (range @31..34
+          (number 3 @31..31)
+          (number 5 @34..34)))
+      (comp_stmt @41..43
+        (string \"y\" @41..43)))
+    (else
+      (comp_stmt @50..52
+        (string \"z\" @50..52)))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_case_in() {
+        let program = "case a
+in 1 | 2 then 'x'
+end";
+        let expected = "(comp_stmt @0..27
+  (case @0..27
+    (ident a @5..5)
+    (in @7..23
+      (patterns
+        (number 1 @10..10)
+        (number 2 @14..14))
+      (comp_stmt @21..23
+        (string \"x\" @21..23)))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn case_in_without_subject_error() {
+        let program = "case
+in 1 then 2
+end";
+        let expected = unexpected("`when`", "`in`", Loc(5, 6));
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn break_outside_loop_error() {
+        let program = "def foo
+  break
+end";
+        let expected = ParseError::new(ParseErrorKind::InvalidJump(Reserved::Break), Loc(10, 14));
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn retry_outside_rescue_error() {
+        let program = "begin
+  retry
+end";
+        let expected = ParseError::new(ParseErrorKind::RetryOutsideRescue, Loc(8, 12));
+        parse_expected_error(program, expected);
+    }
+
+    fn unexpected(expected: &str, found: &str, loc: Loc) -> ParseError {
+        ParseError::new(
+            ParseErrorKind::UnexpectedToken {
+                expected: expected.to_string(),
+                found: found.to_string(),
+            },
+            loc,
+        )
+    }
+
+    #[test]
+    fn unclosed_def_error() {
+        let program = "a = 1
+
+def foo
+  a
+";
+        let expected = unexpected("`end` to close `def` at line 3", "EOF", Loc(19, 19));
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn method_name_error() {
+        let program = "a.[1]";
+        parse_expected_error(program, unexpected("a method name", "`[`", Loc(2, 2)));
+    }
+
+    #[test]
+    fn missing_paren_error() {
+        parse_expected_error("foo(1, 2", unexpected("`,` or `)`", "EOF", Loc(8, 8)));
+        parse_expected_error("(1 2)", unexpected("`)`", "`2`", Loc(3, 3)));
+    }
+
+    #[test]
+    fn unclosed_index_error() {
+        parse_expected_error("a[1", unexpected("`,` or `]`", "EOF", Loc(3, 3)));
+    }
+
+    #[test]
+    fn sexp_index_args() {
+        let program = "pr[]\nl[1, 2]";
+        let expected = "(comp_stmt @0..11
+  (index @0..3
+    (ident pr @0..1))
+  (index @5..11
+    (ident l @5..5)
+    (number 1 @7..7)
+    (number 2 @10..10)))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_hash_and_index() {
+        let program = "h = {a: 1, 'b' => [2]}
+h['b'][0] = h.c";
+        let expected = "(comp_stmt @0..37
+  (assign @0..21
+    (ident h @0..0)
+    (hash @4..21
+      (pair
+        (symbol :a @5..6)
+        (number 1 @8..8))
+      (pair
+        (string \"b\" @11..13)
+        (array @18..20
+          (number 2 @19..19)))))
+  (assign @23..37
+    (index @23..31
+      (index @23..28
+        (lvar h @23..23)
+        (string \"b\" @25..27))
+      (number 0 @30..30))
+    (send @35..37
+      (lvar h @35..35)
+      (ident c @37..37)
+      (args))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_ternary_is_not_a_label() {
+        let program = "puts(a ? b : c)";
+        let expected = "(comp_stmt @0..14
+  (send @0..14
+    (self @0..3)
+    (ident puts @0..3)
+    (args
+      (if @5..13
+        (ident a @5..5)
+        (ident b @9..9)
+        (ident c @13..13)))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn scope_is_not_a_label() {
+        let program = "puts Float::NAN";
+        let expected = unexpected("an expression", "`::`", Loc(10, 11));
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let mut parser = Parser::new();
+        let err = parser
+            .parse_program(
+                "class Foo
+"
+                .to_string(),
+            )
+            .unwrap_err();
+        assert!(err.kind.is_incomplete());
+        let mut parser = Parser::new();
+        let err = parser.parse_program("foo(1 2)".to_string()).unwrap_err();
+        assert!(!err.kind.is_incomplete());
+    }
+
+    #[test]
+    fn recovery_reports_every_error() {
+        let program = "a = )
+def foo
+  x = (1
+  3
+end
+b = 2
+end
+c = 3 4";
+        let mut parser = Parser::new();
+        let (node, errors) = parser.parse_program_with_recovery(program.to_string());
+        let errors = errors
+            .into_iter()
+            .map(|err| (err.kind, err.loc))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (
+                ParseErrorKind::UnexpectedToken {
+                    expected: "an expression".to_string(),
+                    found: "`)`".to_string(),
+                },
+                Loc(4, 4),
+            ),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    expected: "`)`".to_string(),
+                    found: "`end`".to_string(),
+                },
+                Loc(27, 29),
+            ),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    expected: "end-of-input".to_string(),
+                    found: "`end`".to_string(),
+                },
+                Loc(37, 39),
+            ),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    expected: "newline or `;`".to_string(),
+                    found: "`4`".to_string(),
+                },
+                Loc(47, 47),
+            ),
+        ];
+        assert_eq!(errors, expected);
+        let expected = "(comp_stmt @6..45
+  (func_decl foo @6..29
+    (params)
+    (comp_stmt @27..29))
+  (assign @31..35
+    (ident b @31..31)
+    (number 2 @35..35))
+  (assign @41..45
+    (ident c @41..41)
+    (number 3 @45..45)))";
+        let got = node.to_sexp(&parser.ident_table);
+        if got != expected {
+            panic!("Expected:\n{}\nGot:\n{}", expected, got);
+        }
+    }
+
+    /// The token each error reported by the recovering parser was found at.
+    fn recovery_errors_found(program: &str) -> Vec<String> {
+        let mut parser = Parser::new();
+        let (_, errors) = parser.parse_program_with_recovery(program.to_string());
+        errors
+            .into_iter()
+            .map(|err| match err.kind {
+                ParseErrorKind::UnexpectedToken { found, .. } => found,
+                kind => format!("{:?}", kind),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recovery_terminates() {
+        assert_eq!(
+            recovery_errors_found("1 else def foo"),
+            vec!["`else`", "LiteralBeforeDefinition"]
+        );
+        assert_eq!(
+            recovery_errors_found("y += 1 ensure def"),
+            vec!["`ensure`", "LiteralBeforeDefinition"]
+        );
+    }
+
+    #[test]
+    fn recovery_consumes_inner_class_end() {
+        let error = "InnerClassDefinitionInMethodDefinition";
+        assert_eq!(
+            recovery_errors_found("def f\nclass X\nend\nend"),
+            vec![error]
+        );
+        assert_eq!(
+            recovery_errors_found("def f\n  class X < Y\n    1\n  end\nend\nx = )"),
+            vec![error, "`)`"]
+        );
+        assert_eq!(
+            recovery_errors_found("def f; class X; end; end\ndef g\n  class Z\n  end\nend"),
+            vec![error, error]
+        );
+    }
+
+    #[test]
+    fn recovery_skips_orphan_closers() {
+        let programs = [
+            "def foo(a\n a\nend\nb",
+            "class A\n  def foo(a\n    if a\n      1\n    end\n  end\nend",
+            "f { |a b\n x\n}\ny",
+            "foo(1 2) do |x|\n x\nend\nz",
+            "-> (a { \n 1\n}",
+        ];
+        for program in programs.iter() {
+            let errors = recovery_errors_found(program);
+            assert_eq!(errors.len(), 1, "{:?}: {:?}", program, errors);
+        }
+    }
+
+    #[test]
+    fn recovery_without_errors() {
+        let program = "a = 1\nb = 2";
+        let mut parser = Parser::new();
+        let (node, errors) = parser.parse_program_with_recovery(program.to_string());
+        assert!(errors.is_empty());
+        let mut parser = Parser::new();
+        let expected = parser.parse_program(program.to_string()).unwrap();
+        assert_eq!(node, expected);
+    }
+
+    #[test]
+    fn sexp_interpolated_string() {
+        let program = "\"x=#{x + 1}!\"";
+        let expected = "(comp_stmt @0..12
+  (interpolated_string @0..12
+    (string \"x=\" @0..12)
+    (comp_stmt @5..9
+      (binop + @5..9
+        (ident x @5..5)
+        (number 1 @9..9)))
+    (string \"!\" @0..12)))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn interpolation_error() {
+        let program = "\"#{1 2}\"";
+        let expected = ParseError::new(
+            ParseErrorKind::UnexpectedToken {
+                expected: "`}`".to_string(),
+                found: "`2`".to_string(),
+            },
+            Loc(5, 5),
+        );
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn sexp_operator_precedence() {
+        let program = "-2 ** 2 - 8 / 4 / 2 << 1 | ~a & 3 <=> !b";
+        let expected = "(comp_stmt @0..39
+  (binop <=> @0..39
+    (binop | @0..32
+      (binop << @0..23
+        (binop - @0..18
+          (unop - @0..6
+            (binop ** @1..6
+              (number 2 @1..1)
+              (number 2 @6..6)))
+          (binop / @10..18
+            (binop / @10..14
+              (number 8 @10..10)
+              (number 4 @14..14))
+            (number 2 @18..18)))
+        (number 1 @23..23))
+      (binop & @27..32
+        (unop ~ @27..28
+          (ident a @28..28))
+        (number 3 @32..32)))
+    (unop ! @38..39
+      (ident b @39..39))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_not() {
+        let program = "not a == b";
+        let expected = "(comp_stmt @0..9
+  (unop ! @0..9
+    (binop == @4..9
+      (ident a @4..4)
+      (ident b @9..9))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_op_assign() {
+        let program = "a[i] ||= b.c -= 1";
+        let expected = "(comp_stmt @0..16
+  (op_assign ||= @0..16
+    (index @0..3
+      (ident a @0..0)
+      (ident i @2..2))
+    (op_assign -= @9..16
+      (send @9..11
+        (ident b @9..9)
+        (ident c @11..11)
+        (args))
+      (number 1 @16..16))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn invalid_assign_target_error() {
+        let program = "a + 1 = 2";
+        let expected = ParseError::new(ParseErrorKind::InvalidAssignTarget, Loc(0, 6));
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn sexp_multi_assign() {
+        let program = "(a, *b), c = d";
+        let expected = "(comp_stmt @0..13
+  (multi_assign @0..13
+    (targets
+      (multi_lhs @0..6
+        (ident a @1..1)
+        (splat @4..5
+          (ident b @5..5)))
+      (ident c @9..9))
+    (ident d @13..13)))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_params() {
+        let program = "def f(a, b = 1, *c, d, e:, f: 2, **g, &h)\nend";
+        let expected = "(comp_stmt @0..44
+  (func_decl f @0..44
+    (params
+      (table_ident a @6..6)
+      (optional_param b @9..13
+        (number 1 @13..13))
+      (rest_param c @16..17)
+      (table_ident d @20..20)
+      (keyword_param e @23..24)
+      (keyword_param f @27..30
+        (number 2 @30..30))
+      (keyword_rest_param g @33..35)
+      (block_param h @38..39))
+    (comp_stmt @41..41)))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn invalid_param_order_error() {
+        let program = "def f(*a, b = 1)\nend";
+        let expected = ParseError::new(ParseErrorKind::InvalidParamOrder, Loc(10, 14));
+        parse_expected_error(program, expected);
+    }
+
+    #[test]
+    fn sexp_command_call() {
+        let program = "x = 1\nfoo x -1, k: 2\nx -1";
+        let expected = "(comp_stmt @0..24
+  (assign @0..4
+    (ident x @0..0)
+    (number 1 @4..4))
+  (send @6..19
+    (self @6..8)
+    (ident foo @6..8)
+    (args
+      (binop - @10..13
+        (lvar x @10..10)
+        (number 1 @13..13))
+      (hash @16..19
+        (pair
+          (symbol :k @16..17)
+          (number 2 @19..19)))))
+  (binop - @21..24
+    (lvar x @21..21)
+    (number 1 @24..24)))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_command_call_signs() {
+        let program = "foo +5\nfoo + 5\nfoo [1]";
+        let expected = "(comp_stmt @0..21
+  (send @0..5
+    (self @0..2)
+    (ident foo @0..2)
+    (args
+      (unop + @4..5
+        (number 5 @5..5))))
+  (binop + @7..13
+    (ident foo @7..9)
+    (number 5 @13..13))
+  (send @15..21
+    (self @15..17)
+    (ident foo @15..17)
+    (args
+      (array @19..21
+        (number 1 @20..20)))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_splat_args() {
+        let program = "foo *a\nfoo * a\nfoo(1, *a)";
+        let expected = "(comp_stmt @0..24
+  (send @0..5
+    (self @0..2)
+    (ident foo @0..2)
+    (args
+      (splat @4..5
+        (ident a @5..5))))
+  (binop * @7..13
+    (ident foo @7..9)
+    (ident a @13..13))
+  (send @15..24
+    (self @15..17)
+    (ident foo @15..17)
+    (args
+      (number 1 @19..19)
+      (splat @22..23
+        (ident a @23..23)))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_command_call_with_block() {
+        let program = "foo bar { 1 } do 2 end";
+        let expected = "(comp_stmt @0..21
+  (send @0..21
+    (self @0..2)
+    (ident foo @0..2)
+    (args
+      (send @4..12
+        (self @4..6)
+        (ident bar @4..6)
+        (args)
+        (block_decl @10..10
+          (comp_stmt @10..10
+            (number 1 @10..10)))))
+    (block_decl @17..17
+      (comp_stmt @17..17
+        (number 2 @17..17)))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_brace_block_params_and_yield() {
+        let program = "f { |a, (b, *c)| yield a, b }";
+        let expected = "(comp_stmt @0..28
+  (send @0..28
+    (self @0..0)
+    (ident f @0..0)
+    (args)
+    (block_params
+      (table_ident a @5..5)
+      (multi_lhs @8..14
+        (ident b @9..9)
+        (splat @12..13
+          (ident c @13..13))))
+    (block_decl @17..26
+      (comp_stmt @17..26
+        (yield @17..26
+          (lvar a @23..23)
+          (lvar b @26..26))))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_lambda_and_block_arg() {
+        let program = "f(&->(x) { x })";
+        let expected = "(comp_stmt @0..14
+  (send @0..14
+    (self @0..0)
+    (ident f @0..0)
+    (args)
+    (block_arg
+      (lambda @3..13
+        (params
+          (table_ident x @6..6))
+        (block_decl @11..11
+          (comp_stmt @11..11
+            (lvar x @11..11)))))))";
+        parse_expected_sexp(program, expected);
+    }
+
+    #[test]
+    fn sexp_return() {
+        let program = "return a, 1\n[1].each { |x| return }";
+        let expected = "(comp_stmt @0..34
+  (return @0..10
+    (array @7..10
+      (ident a @7..7)
+      (number 1 @10..10)))
+  (send @12..34
+    (array @12..14
+      (number 1 @13..13))
+    (ident each @16..19)
+    (args)
+    (block_params
+      (table_ident x @24..24))
+    (block_decl @27..32
+      (comp_stmt @27..32
+        (return @27..32)))))";
+        parse_expected_sexp(program, expected);
+    }
 }
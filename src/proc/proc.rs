@@ -0,0 +1,81 @@
+use crate::args::args::*;
+use crate::util::util::*;
+use crate::vm::iseq::*;
+use crate::vm::vm::*;
+use rustc_hash::FxHashMap;
+
+/// What a `Proc` runs when it is called.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcKind {
+    /// `:name.to_proc`, which sends `name` to its first argument.
+    Symbol(IdentId),
+    /// `proc { }` or a block taken by a `&block` parameter. Missing
+    /// arguments are nil and extra ones are dropped.
+    Block(CapturedBlock),
+    /// `lambda { }` or `-> { }`, which checks its arguments like a method.
+    Lambda(CapturedBlock),
+}
+
+/// A block together with what it closes over: the local scope it is written
+/// in, `self` and the block of the method it is written in, for `yield`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedBlock {
+    pub iseq: ISeqRef,
+    pub scope: ScopeRef,
+    pub env: Env,
+    pub block: Option<Box<Block>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcInfo {
+    pub kind: ProcKind,
+}
+
+impl ProcInfo {
+    pub fn new(kind: ProcKind) -> Self {
+        Self { kind }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcRef(pub usize);
+
+impl std::hash::Hash for ProcRef {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalProcTable {
+    table: FxHashMap<ProcRef, ProcInfo>,
+    proc_id: usize,
+}
+
+impl Default for GlobalProcTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlobalProcTable {
+    pub fn new() -> Self {
+        Self {
+            table: FxHashMap::default(),
+            proc_id: 0,
+        }
+    }
+
+    pub fn new_proc(&mut self, info: ProcInfo) -> ProcRef {
+        let new_proc = ProcRef(self.proc_id);
+        self.proc_id += 1;
+        self.table.insert(new_proc, info);
+        new_proc
+    }
+
+    pub fn get(&self, proc_ref: ProcRef) -> &ProcInfo {
+        self.table
+            .get(&proc_ref)
+            .expect("GlobalProcTable#get(): ProcRef is not valid.")
+    }
+}
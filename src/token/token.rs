@@ -1,11 +1,18 @@
 use crate::util::annot::*;
+use num_bigint::BigInt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     Ident(String),
     Const(String),
     NumLit(i64),
+    /// An integer literal too large for `NumLit`.
+    BigNumLit(BigInt),
+    FloatLit(f64),
     StringLit(String),
+    /// A double-quoted string or heredoc with `#{...}` in it.
+    InterpolatedString(Vec<StringPart>),
+    Symbol(String),
     Reserved(Reserved),
     Punct(Punct),
     Space,
@@ -14,6 +21,15 @@ pub enum TokenKind {
     String,
 }
 
+/// A piece of an interpolated string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Str(String),
+    /// The tokens between `#{` and `}`, terminated by an EOF token in place
+    /// of the closing brace.
+    Code(Vec<Token>),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Reserved {
     BEGIN,
@@ -64,17 +80,29 @@ pub enum Punct {
     Minus,
     Mul,
     Div,
+    Rem,
+    Pow,
     And,
-    Or,
+    Caret,
+    Tilde,
+    Not,
+    Shl,
+    Shr,
     LParen,
     RParen,
     LBoxBrackets,
     RBoxBrackets,
+    LBrace,
+    RBrace,
     Semi,
     Colon,
+    Scope,
+    Question,
     Comma,
     Eq,
     NE,
+    TEq,
+    Cmp,
     GT,
     GE,
     LT,
@@ -82,12 +110,113 @@ pub enum Punct {
     LAnd,
     LOr,
     Assign,
+    FatArrow,
+    Arrow,
     Comment,
     Dot,
     Range,
     Pipe,
     AtAt,
     At,
+    Dollar,
+}
+
+impl Reserved {
+    /// The keyword as written in source code.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reserved::BEGIN => "BEGIN",
+            Reserved::END => "END",
+            Reserved::Alias => "alias",
+            Reserved::And => "and",
+            Reserved::Begin => "begin",
+            Reserved::Break => "break",
+            Reserved::Case => "case",
+            Reserved::Class => "class",
+            Reserved::Def => "def",
+            Reserved::Defined => "defined?",
+            Reserved::Do => "do",
+            Reserved::Else => "else",
+            Reserved::Elsif => "elsif",
+            Reserved::End => "end",
+            Reserved::Ensure => "ensure",
+            Reserved::False => "false",
+            Reserved::For => "for",
+            Reserved::If => "if",
+            Reserved::In => "in",
+            Reserved::Module => "module",
+            Reserved::Next => "next",
+            Reserved::Nil => "nil",
+            Reserved::Not => "not",
+            Reserved::Or => "or",
+            Reserved::Redo => "redo",
+            Reserved::Rescue => "rescue",
+            Reserved::Retry => "retry",
+            Reserved::Return => "return",
+            Reserved::Super => "super",
+            Reserved::Then => "then",
+            Reserved::True => "true",
+            Reserved::Undef => "undef",
+            Reserved::Unless => "unless",
+            Reserved::Until => "until",
+            Reserved::When => "when",
+            Reserved::While => "while",
+            Reserved::Yield => "yield",
+            Reserved::__LINE__ => "__LINE__",
+            Reserved::__FILE__ => "__FILE__",
+        }
+    }
+}
+
+impl Punct {
+    /// The punctuation as written in source code.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Punct::Plus => "+",
+            Punct::Minus => "-",
+            Punct::Mul => "*",
+            Punct::Div => "/",
+            Punct::Rem => "%",
+            Punct::Pow => "**",
+            Punct::And => "&",
+            Punct::Caret => "^",
+            Punct::Tilde => "~",
+            Punct::Not => "!",
+            Punct::Shl => "<<",
+            Punct::Shr => ">>",
+            Punct::LParen => "(",
+            Punct::RParen => ")",
+            Punct::LBoxBrackets => "[",
+            Punct::RBoxBrackets => "]",
+            Punct::LBrace => "{",
+            Punct::RBrace => "}",
+            Punct::Semi => ";",
+            Punct::Colon => ":",
+            Punct::Scope => "::",
+            Punct::Question => "?",
+            Punct::Comma => ",",
+            Punct::Eq => "==",
+            Punct::NE => "!=",
+            Punct::TEq => "===",
+            Punct::Cmp => "<=>",
+            Punct::GT => ">",
+            Punct::GE => ">=",
+            Punct::LT => "<",
+            Punct::LE => "<=",
+            Punct::LAnd => "&&",
+            Punct::LOr => "||",
+            Punct::Assign => "=",
+            Punct::FatArrow => "=>",
+            Punct::Arrow => "->",
+            Punct::Comment => "#",
+            Punct::Dot => ".",
+            Punct::Range => "..",
+            Punct::Pipe => "|",
+            Punct::AtAt => "@@",
+            Punct::At => "@",
+            Punct::Dollar => "$",
+        }
+    }
 }
 
 pub type Token = Annot<TokenKind>;
@@ -109,6 +238,18 @@ impl Token {
         Annot::new(TokenKind::NumLit(num), loc)
     }
 
+    pub fn new_bignumlit(num: BigInt, loc: Loc) -> Self {
+        Annot::new(TokenKind::BigNumLit(num), loc)
+    }
+
+    pub fn new_floatlit(num: f64, loc: Loc) -> Self {
+        Annot::new(TokenKind::FloatLit(num), loc)
+    }
+
+    pub fn new_symbol(name: impl Into<String>, loc: Loc) -> Self {
+        Annot::new(TokenKind::Symbol(name.into()), loc)
+    }
+
     pub fn new_comment(loc: Loc) -> Self {
         Annot::new(TokenKind::Punct(Punct::Comment), loc)
     }
@@ -3,18 +3,40 @@ use rustc_hash::FxHashMap;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SourceInfo {
+    /// File name shown in backtraces.
+    pub path: String,
     pub code: Vec<char>,
     pub coordinates: Vec<(usize, usize, usize)>,
 }
 
+impl Default for SourceInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SourceInfo {
     pub fn new() -> SourceInfo {
         Self {
+            path: "-".to_string(),
             code: vec![],
             coordinates: vec![],
         }
     }
 
+    /// Returns the 1-origin line and column of the character at `pos`.
+    pub fn line_column(&self, pos: usize) -> (usize, usize) {
+        for line in &self.coordinates {
+            if line.0 <= pos && pos <= line.1 {
+                return (line.2 + 1, pos - line.0 + 1);
+            }
+        }
+        match self.coordinates.last() {
+            Some(line) => (line.2 + 1, pos.saturating_sub(line.0) + 1),
+            None => (1, pos + 1),
+        }
+    }
+
     pub fn show_loc(&self, loc: &Loc) {
         for line in &self.coordinates {
             if line.1 < loc.0 || line.0 > loc.1 {
@@ -25,7 +47,7 @@ impl SourceInfo {
                 self.code[(line.0)..(line.1)].iter().collect::<String>()
             );
             use std::cmp::*;
-            let read = if loc.0 < line.0 { 0 } else { loc.0 - line.0 };
+            let read = loc.0.saturating_sub(line.0);
             let length = min(loc.1, line.1) + 1 - max(loc.0, line.0);
             println!("{}{}", " ".repeat(read), "^".repeat(length));
         }
@@ -48,6 +70,13 @@ impl std::hash::Hash for IdentId {
     }
 }
 
+/// Names of operator methods, longest first so that they can be matched
+/// against source text.
+pub const OPERATOR_METHODS: [&str; 26] = [
+    "[]=", "===", "<=>", "[]", "**", "==", "!=", "=~", "<=", ">=", "<<", ">>", "+@", "-@", "+",
+    "-", "*", "/", "%", "<", ">", "!", "&", "|", "^", "~",
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct IdentifierTable {
     pub table: FxHashMap<String, usize>,
@@ -80,7 +109,7 @@ impl IdentifierTable {
         }
     }
 
-    pub fn get_name(&mut self, id: IdentId) -> String {
+    pub fn get_name(&self, id: IdentId) -> String {
         for (k, v) in self.table.iter() {
             if *v == id.0 {
                 return k.to_string();
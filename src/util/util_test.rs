@@ -29,4 +29,14 @@ c = 3
         ];
         eval_script(prog, expected);
     }
+
+    #[test]
+    fn coordinates_heredoc() {
+        let prog = "a = <<~X # doc
+  b
+X
+c = 1";
+        let expected = vec![(0, 14, 0), (15, 18, 1), (19, 20, 2), (21, 26, 3)];
+        eval_script(prog, expected);
+    }
 }
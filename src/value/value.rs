@@ -1,19 +1,29 @@
 use crate::class::class::*;
+use crate::hash::hash::*;
 use crate::instance::instance::*;
+use crate::proc::proc::*;
 use crate::util::util::*;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Nil,
     Bool(bool),
     FixNum(i64),
+    /// An Integer outside the range of `FixNum`. Never holds a value that
+    /// fits in an i64, so that equal Integers have the same representation.
+    BigNum(BigInt),
     FixDecimalNum(f64),
     String(String),
+    Symbol(IdentId),
     Class(ClassRef),
     Instance(InstanceRef),
     SelfClass(Class),
     Array(Vec<Value>),
     Range(Box<Value>, Box<Value>),
+    Hash(HashRef),
+    Proc(ProcRef),
 }
 
 impl Value {
@@ -65,29 +75,121 @@ impl Value {
         }
     }
 
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
     pub fn to_b(self) -> bool {
         match self {
             Value::Nil => false,
             Value::Bool(b) => b,
-            Value::FixNum(n) => {
-                if n > 0 {
-                    true
-                } else {
-                    false
-                }
-            }
+            Value::FixNum(n) => n > 0,
             Value::String(_) => true,
             _ => unimplemented!(),
         }
     }
 
-    pub fn to_i(self) -> i64 {
+    /// The value of a number as a float.
+    pub fn to_f64(&self) -> Option<f64> {
         match self {
-            Value::String(s) => match s.parse() {
-                Ok(i) => i,
-                _ => unimplemented!(),
-            },
-            _ => unimplemented!(),
+            Value::FixNum(i) => Some(*i as f64),
+            Value::BigNum(i) => Some(i.to_f64().unwrap_or(f64::INFINITY)),
+            Value::FixDecimalNum(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// The Integer `num`, demoted to a `FixNum` when it fits.
+    pub fn integer(num: BigInt) -> Value {
+        match num.to_i64() {
+            Some(i) => Value::FixNum(i),
+            None => Value::BigNum(num),
+        }
+    }
+
+    /// Parses the leading integer of `s` the way `String#to_i` does,
+    /// returning 0 when there is none.
+    pub fn str_to_i(s: &str) -> Value {
+        let s = s.trim_start();
+        let (sign, digits) = match s.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let mut i = BigInt::from(0);
+        for c in digits.chars() {
+            match c.to_digit(10) {
+                Some(d) => i = i * 10 + d,
+                None if c == '_' => {}
+                None => break,
+            }
+        }
+        Value::integer(sign * i)
+    }
+
+    /// Parses the leading float of `s` the way `String#to_f` does,
+    /// returning 0.0 when there is none.
+    pub fn str_to_f(s: &str) -> f64 {
+        let s = s.trim_start();
+        let mut end = 0;
+        let mut seen_dot = false;
+        let mut seen_exp = false;
+        let chars: Vec<char> = s.chars().collect();
+        let mut text = String::new();
+        while end < chars.len() {
+            let c = chars[end];
+            let next_is_digit = chars.get(end + 1).is_some_and(|c| c.is_ascii_digit());
+            match c {
+                '0'..='9' => text.push(c),
+                '_' if next_is_digit && !text.is_empty() => {}
+                '+' | '-' if text.is_empty() || text.ends_with('e') => text.push(c),
+                '.' if !seen_dot && !seen_exp && next_is_digit => {
+                    seen_dot = true;
+                    text.push(c);
+                }
+                'e' | 'E' if !seen_exp && text.chars().any(|c| c.is_ascii_digit()) => {
+                    seen_exp = true;
+                    text.push('e');
+                }
+                _ => break,
+            }
+            end += 1;
+        }
+        // Drop an incomplete exponent such as the `e` of `"1e"`.
+        let text = text.trim_end_matches(['e', '+', '-']);
+        text.parse::<f64>().unwrap_or(0.0)
+    }
+
+    /// Formats `f` the way `Float#to_s` does: always with a fractional
+    /// part, and in exponent notation outside 1e-4 <= |f| < 1e16.
+    pub fn float_to_s(f: f64) -> String {
+        if f.is_nan() {
+            return "NaN".to_string();
+        }
+        if f.is_infinite() {
+            return if f > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+        }
+        if f == 0.0 {
+            return if f.is_sign_negative() { "-0.0" } else { "0.0" }.to_string();
+        }
+        // The shortest digits that round-trip, e.g. "1.5e-3".
+        let exp_form = format!("{:e}", f);
+        let (mantissa, exp) = exp_form.split_at(exp_form.find('e').unwrap());
+        let exp: i32 = exp[1..].parse().unwrap();
+        if (-4..16).contains(&exp) {
+            let s = f.to_string();
+            if s.contains('.') {
+                s
+            } else {
+                s + ".0"
+            }
+        } else {
+            let mantissa = if mantissa.contains('.') {
+                mantissa.to_string()
+            } else {
+                format!("{}.0", mantissa)
+            };
+            let sign = if exp < 0 { '-' } else { '+' };
+            format!("{}e{}{:02}", mantissa, sign, exp.abs())
         }
     }
 
@@ -95,12 +197,17 @@ impl Value {
         match self {
             Value::Nil => Class::Nil,
             Value::Bool(_) => Class::Bool,
-            Value::FixNum(_) => Class::FixNum,
+            Value::FixNum(_) | Value::BigNum(_) => Class::FixNum,
             Value::FixDecimalNum(_) => Class::FixDecimalNum,
             Value::String(_) => Class::String,
+            Value::Symbol(_) => Class::Symbol,
             Value::Class(_) => Class::Class,
             Value::Instance(_) => Class::Instance,
-            _ => unimplemented!(),
+            Value::SelfClass(_) => Class::Class,
+            Value::Array(_) => Class::Array,
+            Value::Range(_, _) => Class::Range,
+            Value::Hash(_) => Class::Hash,
+            Value::Proc(_) => Class::Proc,
         }
     }
 }
@@ -0,0 +1,132 @@
+use crate::util::util::*;
+use crate::value::value::*;
+use crate::vm::inst::*;
+use crate::vm::iseq::*;
+use crate::vm::vm::*;
+
+impl VM {
+    /// Renders every instruction sequence generated so far.
+    pub fn disassemble(&self) -> String {
+        (0..self.stack.iseqs.len())
+            .map(|i| self.disassemble_iseq(ISeqRef(i)))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Renders a signature such as `a, [b], *c, d, e:, [f:], **g, &h`, with
+    /// optional parameters in brackets.
+    fn params_to_s(&self, params: &Params) -> String {
+        let name = |id: &IdentId| self.ident_table.get_name(*id);
+        let mut names: Vec<String> = params.required.iter().map(name).collect();
+        names.extend(params.optional.iter().map(|id| format!("[{}]", name(id))));
+        names.extend(params.rest.iter().map(|id| match name(id).as_str() {
+            "*" => "*".to_string(),
+            rest => format!("*{}", rest),
+        }));
+        names.extend(params.post.iter().map(name));
+        names.extend(params.keyword.iter().map(|(id, required)| match required {
+            true => format!("{}:", name(id)),
+            false => format!("[{}:]", name(id)),
+        }));
+        names.extend(
+            params
+                .keyword_rest
+                .iter()
+                .map(|id| match name(id).as_str() {
+                    "**" => "**".to_string(),
+                    rest => format!("**{}", rest),
+                }),
+        );
+        names.extend(params.block.iter().map(|id| format!("&{}", name(id))));
+        names.join(", ")
+    }
+
+    /// Renders one instruction sequence, one instruction per line with its
+    /// position, opcode name, decoded operands and source line:column.
+    pub fn disassemble_iseq(&self, iseq: ISeqRef) -> String {
+        let info = &self.stack.iseqs[*iseq];
+        let mut s = format!("== {} (iseq {})", self.iseq_name(iseq), *iseq);
+        if !info.params.is_empty() {
+            s += &format!(" params: {}", self.params_to_s(&info.params));
+        }
+        s.push('\n');
+
+        let mut pos = 0;
+        while pos < info.body.len() {
+            let inst = info.body[pos];
+            let mut operand_pos = pos + 1;
+            let mut operands = vec![];
+            for operand in Inst::operands(inst) {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&info.body[operand_pos..operand_pos + 8]);
+                operands.push(self.operand_to_s(*operand, u64::from_be_bytes(bytes)));
+                operand_pos += 8;
+            }
+            let mut line = format!(
+                "{:04} {:<20}{:<24}",
+                pos,
+                Inst::name(inst),
+                operands.join(", ")
+            );
+            if let Some(loc) = info.loc(pos) {
+                let (line_no, column) = self.source_info.line_column(loc.0);
+                line += &format!("({}:{})", line_no, column);
+            }
+            s += line.trim_end();
+            s.push('\n');
+            pos = operand_pos;
+        }
+        for (i, handler) in info.handlers.iter().enumerate() {
+            let kind = match handler.kind {
+                HandlerKind::Rescue => "rescue",
+                HandlerKind::Ensure => "ensure",
+                HandlerKind::Loop => "loop",
+            };
+            s += &format!(
+                "handler {} {:<7}{:04}..{:04} -> {:04}\n",
+                i, kind, handler.start, handler.end, handler.dest
+            );
+        }
+        for (i, table) in info.case_tables.iter().enumerate() {
+            let mut entries = table.iter().collect::<Vec<_>>();
+            entries.sort();
+            let entries = entries
+                .iter()
+                .map(|(val, dest)| format!("{} -> {:04}", val, dest))
+                .collect::<Vec<String>>();
+            s += &format!("case {} {{{}}}\n", i, entries.join(", "));
+        }
+        s
+    }
+
+    pub fn iseq_name(&self, iseq: ISeqRef) -> String {
+        match self.stack.iseqs[*iseq].kind {
+            ISeqKind::Top => "<main>".to_string(),
+            ISeqKind::Method(id) => self.ident_table.get_name(id),
+            ISeqKind::Class(id) => format!("<class:{}>", self.ident_table.get_name(id)),
+            ISeqKind::Block(parent) => format!("block in {}", self.iseq_name(parent)),
+        }
+    }
+
+    fn operand_to_s(&self, operand: Operand, num: u64) -> String {
+        match operand {
+            Operand::FixNum => (num as i64).to_string(),
+            Operand::DecimalNum => Value::float_to_s(f64::from_bits(num)),
+            Operand::Ident => self.ident_table.get_name(IdentId(num as usize)),
+            Operand::String => {
+                format!(
+                    "{:?}",
+                    self.stack.ident_table.get_name(IdentId(num as usize))
+                )
+            }
+            Operand::Len => num.to_string(),
+            Operand::ISeq => match num {
+                0 => "-".to_string(),
+                num => format!("<iseq {}>", num),
+            },
+            Operand::Dest => format!("{:04}", num),
+            Operand::Handler => format!("handler {}", num),
+            Operand::CaseTable => format!("case {}", num),
+        }
+    }
+}
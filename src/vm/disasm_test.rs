@@ -0,0 +1,157 @@
+#[cfg(test)]
+mod test {
+    use crate::parser::parser::*;
+    use crate::vm::vm::*;
+
+    fn assert_disasm(script: impl Into<String>, expected: &str) {
+        let mut parser = Parser::new();
+        let node = parser.parse_program(script.into()).unwrap();
+
+        let mut vm = VM::new();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        let got = vm.disassemble();
+        if got != expected {
+            panic!("Expected:\n{}\nGot:\n{}", expected, got);
+        }
+    }
+
+    #[test]
+    fn disasm_method() {
+        let program = "def foo(a)
+  a.times do |i|
+    puts(i)
+  end
+end";
+        let expected = "== <main> (iseq 0)
+0000 FUNC_DECL           foo, <iseq 1>           (1:1)
+0017 LEAVE                                       (1:1)
+
+== foo (iseq 1) params: a
+0000 IDENT_OR_NIL        a                       (2:3)
+0009 SEND                times, 0, <iseq 2>      (2:3)
+0034 LEAVE                                       (2:3)
+
+== block in foo (iseq 2) params: i
+0000 SELF_VALUE                                  (3:5)
+0001 IDENT_OR_NIL        i                       (3:10)
+0010 SEND                puts, 1, -              (3:5)
+0035 LEAVE                                       (3:5)
+";
+        assert_disasm(program, expected);
+    }
+
+    #[test]
+    fn disasm_if() {
+        let program = "a = 1
+if a == 1 then 'one' else 2 end";
+        let expected = "== <main> (iseq 0)
+0000 FIXNUM              1                       (1:5)
+0009 IDENT_ASSIGN        a                       (1:1)
+0018 POP                                         (1:1)
+0019 IDENT_OR_NIL        a                       (2:4)
+0028 FIXNUM              1                       (2:9)
+0037 EQ                                          (2:4)
+0038 JUMP_IF_FALSE       0065                    (2:1)
+0047 STRING              \"one\"                   (2:16)
+0056 JUMP                0074                    (2:1)
+0065 FIXNUM              2                       (2:27)
+0074 LEAVE                                       (1:1)
+";
+        assert_disasm(program, expected);
+    }
+
+    #[test]
+    fn disasm_case_dispatch() {
+        let program = "case 2
+when 1 then 'one'
+when 2, 3 then 'few'
+end";
+        let expected = "== <main> (iseq 0)
+0000 FIXNUM              2                       (1:6)
+0009 CASE_DISPATCH       case 0, 0083            (1:1)
+0026 FIXNUM              1                       (2:6)
+0035 CASE_EQ                                     (1:1)
+0036 JUMP_IF_TRUE        0094                    (1:1)
+0045 FIXNUM              2                       (3:6)
+0054 CASE_EQ                                     (1:1)
+0055 JUMP_IF_TRUE        0113                    (1:1)
+0064 FIXNUM              3                       (3:9)
+0073 CASE_EQ                                     (1:1)
+0074 JUMP_IF_TRUE        0113                    (1:1)
+0083 POP                                         (1:1)
+0084 NIL                                         (1:1)
+0085 JUMP                0132                    (1:1)
+0094 POP                                         (1:1)
+0095 STRING              \"one\"                   (2:13)
+0104 JUMP                0132                    (1:1)
+0113 POP                                         (1:1)
+0114 STRING              \"few\"                   (3:16)
+0123 JUMP                0132                    (1:1)
+0132 LEAVE                                       (1:1)
+case 0 {1 -> 0094, 2 -> 0113, 3 -> 0113}
+";
+        assert_disasm(program, expected);
+    }
+
+    #[test]
+    fn disasm_rescue() {
+        let program = "begin
+  1
+rescue
+  2
+end";
+        let expected = "== <main> (iseq 0)
+0000 SET_HANDLER         handler 0               (1:1)
+0009 FIXNUM              1                       (2:3)
+0018 JUMP                0077                    (1:1)
+0027 DUP                                         (3:1)
+0028 CONST               StandardError           (3:1)
+0037 RESCUE_MATCH                                (3:1)
+0038 JUMP_IF_TRUE        0056                    (3:1)
+0047 JUMP                0076                    (3:1)
+0056 ENTER_RESCUE                                (3:1)
+0057 FIXNUM              2                       (4:3)
+0066 LEAVE_RESCUE                                (3:1)
+0067 JUMP                0077                    (3:1)
+0076 RERAISE                                     (1:1)
+0077 LEAVE                                       (1:1)
+handler 0 rescue 0000..0018 -> 0027
+";
+        assert_disasm(program, expected);
+    }
+
+    #[test]
+    fn disasm_param_defaults() {
+        let program = "def f(a, b = a, *c, d:, e: 1, **f, &g)
+end";
+        let expected = "== <main> (iseq 0)
+0000 FUNC_DECL           f, <iseq 1>             (1:1)
+0017 LEAVE                                       (1:1)
+
+== f (iseq 1) params: a, [b], *c, d:, [e:], **f, &g
+0000 JUMP_IF_BOUND       b, 0036                 (1:1)
+0017 IDENT_OR_NIL        a                       (1:14)
+0026 IDENT_ASSIGN        b                       (1:1)
+0035 POP                                         (1:1)
+0036 JUMP_IF_BOUND       e, 0072                 (1:1)
+0053 FIXNUM              1                       (1:28)
+0062 IDENT_ASSIGN        e                       (1:1)
+0071 POP                                         (1:1)
+0072 NIL                                         (1:39)
+0073 LEAVE                                       (1:39)
+";
+        assert_disasm(program, expected);
+    }
+
+    #[test]
+    fn disasm_float() {
+        let program = "2.0 + 1.5";
+        let expected = "== <main> (iseq 0)
+0000 DECIMALNUM          2.0                     (1:1)
+0009 DECIMALNUM          1.5                     (1:7)
+0018 ADD                                         (1:1)
+0019 LEAVE                                       (1:1)
+";
+        assert_disasm(program, expected);
+    }
+}
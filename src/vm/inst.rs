@@ -1,30 +1,76 @@
 #[derive(Debug, Clone)]
 pub struct Inst;
 
+/// Opcodes of the flat instruction stream. Operands are stored inline
+/// right after the opcode as 8-byte big-endian words.
 impl Inst {
     pub const NIL: u8 = 1;
     pub const SELF_VALUE: u8 = 2;
-    pub const IDENT_ASSIGN: u8 = 3;
-    pub const FIXNUM: u8 = 4;
-    pub const DECIMALNUM: u8 = 5;
-    pub const BOOL: u8 = 6;
-    pub const STRING: u8 = 7;
-    pub const ARRAY: u8 = 8;
-    pub const ARRAY_INDEX: u8 = 9;
-    pub const SEND: u8 = 10;
-    pub const IDENT: u8 = 11;
-    pub const TABLE_IDENT: u8 = 12;
-    pub const IF: u8 = 13;
-    pub const INIT_FUNC: u8 = 14;
-    pub const FUNC_DECL: u8 = 15;
-    pub const CLASS_DECL: u8 = 16;
-    pub const CONST: u8 = 17;
-    pub const INSTANCE_VAR: u8 = 18;
-    pub const INSTANCE_VAR_ASSIGN: u8 = 19;
-    pub const CLASS_VAR: u8 = 20;
-    pub const CLASS_VAR_ASSIGN: u8 = 21;
-    pub const RANGE: u8 = 22;
-    pub const FOR: u8 = 23;
+    /// operand: i64
+    pub const FIXNUM: u8 = 3;
+    /// operand: f64 bits
+    pub const DECIMALNUM: u8 = 4;
+    /// operand: string id
+    pub const STRING: u8 = 5;
+    /// operand: number of elements
+    pub const ARRAY: u8 = 6;
+    /// operand: number of index arguments. Pops them and a receiver, pushes
+    /// the element.
+    pub const INDEX: u8 = 7;
+    pub const RANGE: u8 = 8;
+    /// operand: IdentId
+    pub const SYMBOL: u8 = 9;
+
+    /// operand: IdentId
+    pub const IDENT: u8 = 10;
+    /// operand: IdentId
+    pub const IDENT_ASSIGN: u8 = 11;
+    /// operand: IdentId
+    pub const CONST: u8 = 12;
+    /// operand: IdentId
+    pub const INSTANCE_VAR: u8 = 13;
+    /// operand: IdentId
+    pub const INSTANCE_VAR_ASSIGN: u8 = 14;
+    /// operand: IdentId
+    pub const CLASS_VAR: u8 = 15;
+    /// operand: IdentId
+    pub const CLASS_VAR_ASSIGN: u8 = 16;
+    /// operand: number of index arguments. Pops a value, the index arguments
+    /// and a receiver, stores the value at the index and pushes the value and
+    /// then the receiver, which is a new Array for an Array receiver.
+    pub const INDEX_ASSIGN: u8 = 17;
+    /// operand: number of entries. Pops a key and a value for each.
+    pub const HASH: u8 = 18;
+    /// operand: number of parts. Pops the parts, converts them with `to_s`
+    /// and pushes their concatenation.
+    pub const INTERPOLATE: u8 = 19;
+
+    /// operands: method IdentId, number of args, block ISeqRef (0 for none)
+    pub const SEND: u8 = 20;
+    /// operands: method IdentId, body ISeqRef
+    pub const FUNC_DECL: u8 = 21;
+    /// operands: class IdentId, body ISeqRef
+    pub const CLASS_DECL: u8 = 22;
+
+    pub const POP: u8 = 30;
+    pub const DUP: u8 = 31;
+    /// operand: destination
+    pub const JUMP: u8 = 32;
+    /// operand: destination
+    pub const JUMP_IF_FALSE: u8 = 33;
+    /// operand: destination
+    pub const JUMP_IF_TRUE: u8 = 34;
+    /// operands: loop variable IdentId, destination when exhausted
+    pub const FOR_NEXT: u8 = 35;
+
+    /// operand: handler index
+    pub const SET_HANDLER: u8 = 36;
+    /// operand: handler index
+    pub const RETRY: u8 = 37;
+    /// Pops a class and an exception, pushes whether the exception is a kind of the class.
+    pub const RESCUE_MATCH: u8 = 38;
+    /// Pops an exception and raises it again.
+    pub const RERAISE: u8 = 39;
 
     pub const ADD: u8 = 40;
     pub const SUB: u8 = 41;
@@ -36,8 +82,304 @@ impl Inst {
     pub const GE: u8 = 47;
     pub const LT: u8 = 48;
     pub const LE: u8 = 49;
-    pub const LAND: u8 = 50;
-    pub const LOR: u8 = 51;
 
-    pub const END: u8 = 255;
+    /// operand: loop handler index. Cuts the exec stack back to the depth
+    /// saved by its `SET_HANDLER`.
+    pub const UNWIND: u8 = 50;
+    /// Pops a value and leaves the running block and the method that
+    /// yielded to it, which returns the value.
+    pub const BREAK: u8 = 51;
+    /// Pops a pattern and pushes whether `pattern === subject`, leaving the
+    /// `case` subject below it on the stack.
+    pub const CASE_EQ: u8 = 52;
+    /// operands: case table index, destination when not found. Jumps
+    /// straight to the `when` clause for an Integer subject; any other
+    /// subject falls through to the `CASE_EQ` tests.
+    pub const CASE_DISPATCH: u8 = 53;
+    /// Pops the `case` subject that no `in` clause matched and raises
+    /// NoMatchingPatternError.
+    pub const NO_MATCHING_PATTERN: u8 = 54;
+    pub const REM: u8 = 55;
+    /// operand: string id of the decimal digits
+    pub const BIGNUM: u8 = 56;
+    pub const TRUE: u8 = 57;
+    pub const FALSE: u8 = 58;
+    pub const POW: u8 = 59;
+    /// `<=>`
+    pub const CMP: u8 = 60;
+    /// `===`
+    pub const TEQ: u8 = 61;
+    pub const BIT_AND: u8 = 62;
+    pub const BIT_OR: u8 = 63;
+    pub const BIT_XOR: u8 = 64;
+    pub const SHL: u8 = 65;
+    pub const SHR: u8 = 66;
+    /// Pops a value and pushes `!value`.
+    pub const NOT: u8 = 67;
+    pub const BIT_NOT: u8 = 68;
+    /// Unary `+`.
+    pub const POS: u8 = 69;
+    /// Unary `-`.
+    pub const NEG: u8 = 70;
+    /// operand: IdentId
+    pub const GLOBAL_VAR: u8 = 71;
+    /// operand: IdentId
+    pub const GLOBAL_VAR_ASSIGN: u8 = 72;
+    /// operand: IdentId. Like `IDENT`, but pushes nil for a variable that
+    /// was never assigned, as the target of `op=` is.
+    pub const IDENT_OR_NIL: u8 = 73;
+    /// operand: setter IdentId. Pops a value and a receiver, calls the
+    /// setter with the value and pushes the value.
+    pub const ATTR_ASSIGN: u8 = 74;
+    /// Swaps the top two values.
+    pub const SWAP: u8 = 75;
+    /// operand: count. Pushes copies of the top `count` values.
+    pub const DUPN: u8 = 76;
+    /// operands: leading count, splat flag, trailing count. Pops a value and
+    /// pushes the values for the targets of a multiple assignment, the first
+    /// target's on top. A value other than an Array is taken as `[value]`.
+    pub const EXPAND_ARRAY: u8 = 77;
+    /// operand: depth. Pushes a copy of the value `depth` places below the top.
+    pub const TOPN: u8 = 78;
+    /// operands: IdentId, dest. Jumps when the caller passed the optional
+    /// parameter, skipping the code that assigns its default.
+    pub const JUMP_IF_BOUND: u8 = 79;
+    /// operand: argument count. Pops the arguments, calls the block of the
+    /// running method with them and pushes its value.
+    pub const YIELD: u8 = 80;
+    /// operand: block ISeq. Pushes a lambda of the block, closing over the
+    /// current scope.
+    pub const LAMBDA: u8 = 81;
+    /// operands: IdentId, argument count. Like SEND, with the block given
+    /// by a value popped first: a Proc, a Symbol or nil.
+    pub const SEND_BLOCK_ARG: u8 = 82;
+    /// Pops a value and leaves the running block and the method it was
+    /// written in (or its lambda), which returns the value.
+    pub const RETURN: u8 = 83;
+    /// Pops a value and pushes the Array of arguments `*value` passes: an
+    /// Array itself, no arguments for nil, the pairs of a Hash, the
+    /// Integers of a Range and `[value]` for anything else.
+    pub const SPLAT: u8 = 84;
+    /// operand: number of Arrays. Pops them and pushes their concatenation.
+    pub const CONCAT_ARRAYS: u8 = 85;
+    /// operands: IdentId, block ISeqRef (0 for none), 1 when a block
+    /// argument is on top. Like SEND and SEND_BLOCK_ARG, with the arguments
+    /// popped as one Array.
+    pub const SEND_SPLAT: u8 = 86;
+    /// Like YIELD, with the arguments popped as one Array.
+    pub const YIELD_SPLAT: u8 = 87;
+    /// operand: depth. Replaces the value `depth` places below the top with
+    /// a copy of the top.
+    pub const SETN: u8 = 88;
+    /// operand: IdentId. Like `CLASS_VAR`, but pushes nil for a class
+    /// variable that was never assigned, as the target of `||=` is.
+    pub const CLASS_VAR_OR_NIL: u8 = 89;
+    /// Makes the exception on top of the stack, which stays there, the one a
+    /// bare `raise` raises again while its `rescue` clause runs.
+    pub const ENTER_RESCUE: u8 = 90;
+    /// Pops the value of a `rescue` clause and the exception below it, which
+    /// stops being handled, and pushes the value back.
+    pub const LEAVE_RESCUE: u8 = 91;
+
+    pub const LEAVE: u8 = 255;
+}
+
+/// Kind of an inline operand, used to decode instruction sequences.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    FixNum,
+    DecimalNum,
+    Ident,
+    String,
+    Len,
+    ISeq,
+    Dest,
+    Handler,
+    CaseTable,
+}
+
+impl Inst {
+    pub fn name(inst: u8) -> &'static str {
+        match inst {
+            Inst::NIL => "NIL",
+            Inst::SELF_VALUE => "SELF_VALUE",
+            Inst::FIXNUM => "FIXNUM",
+            Inst::DECIMALNUM => "DECIMALNUM",
+            Inst::STRING => "STRING",
+            Inst::ARRAY => "ARRAY",
+            Inst::INDEX => "INDEX",
+            Inst::RANGE => "RANGE",
+            Inst::SYMBOL => "SYMBOL",
+            Inst::IDENT => "IDENT",
+            Inst::IDENT_ASSIGN => "IDENT_ASSIGN",
+            Inst::CONST => "CONST",
+            Inst::INSTANCE_VAR => "INSTANCE_VAR",
+            Inst::INSTANCE_VAR_ASSIGN => "INSTANCE_VAR_ASSIGN",
+            Inst::CLASS_VAR => "CLASS_VAR",
+            Inst::CLASS_VAR_ASSIGN => "CLASS_VAR_ASSIGN",
+            Inst::INDEX_ASSIGN => "INDEX_ASSIGN",
+            Inst::HASH => "HASH",
+            Inst::INTERPOLATE => "INTERPOLATE",
+            Inst::SEND => "SEND",
+            Inst::FUNC_DECL => "FUNC_DECL",
+            Inst::CLASS_DECL => "CLASS_DECL",
+            Inst::POP => "POP",
+            Inst::DUP => "DUP",
+            Inst::JUMP => "JUMP",
+            Inst::JUMP_IF_FALSE => "JUMP_IF_FALSE",
+            Inst::JUMP_IF_TRUE => "JUMP_IF_TRUE",
+            Inst::FOR_NEXT => "FOR_NEXT",
+            Inst::SET_HANDLER => "SET_HANDLER",
+            Inst::RETRY => "RETRY",
+            Inst::RESCUE_MATCH => "RESCUE_MATCH",
+            Inst::RERAISE => "RERAISE",
+            Inst::ADD => "ADD",
+            Inst::SUB => "SUB",
+            Inst::MUL => "MUL",
+            Inst::DIV => "DIV",
+            Inst::EQ => "EQ",
+            Inst::NE => "NE",
+            Inst::GT => "GT",
+            Inst::GE => "GE",
+            Inst::LT => "LT",
+            Inst::LE => "LE",
+            Inst::UNWIND => "UNWIND",
+            Inst::BREAK => "BREAK",
+            Inst::CASE_EQ => "CASE_EQ",
+            Inst::CASE_DISPATCH => "CASE_DISPATCH",
+            Inst::NO_MATCHING_PATTERN => "NO_MATCHING_PATTERN",
+            Inst::REM => "REM",
+            Inst::BIGNUM => "BIGNUM",
+            Inst::TRUE => "TRUE",
+            Inst::FALSE => "FALSE",
+            Inst::POW => "POW",
+            Inst::CMP => "CMP",
+            Inst::TEQ => "TEQ",
+            Inst::BIT_AND => "BIT_AND",
+            Inst::BIT_OR => "BIT_OR",
+            Inst::BIT_XOR => "BIT_XOR",
+            Inst::SHL => "SHL",
+            Inst::SHR => "SHR",
+            Inst::NOT => "NOT",
+            Inst::BIT_NOT => "BIT_NOT",
+            Inst::POS => "POS",
+            Inst::NEG => "NEG",
+            Inst::GLOBAL_VAR => "GLOBAL_VAR",
+            Inst::GLOBAL_VAR_ASSIGN => "GLOBAL_VAR_ASSIGN",
+            Inst::IDENT_OR_NIL => "IDENT_OR_NIL",
+            Inst::ATTR_ASSIGN => "ATTR_ASSIGN",
+            Inst::SWAP => "SWAP",
+            Inst::DUPN => "DUPN",
+            Inst::EXPAND_ARRAY => "EXPAND_ARRAY",
+            Inst::TOPN => "TOPN",
+            Inst::JUMP_IF_BOUND => "JUMP_IF_BOUND",
+            Inst::YIELD => "YIELD",
+            Inst::LAMBDA => "LAMBDA",
+            Inst::SEND_BLOCK_ARG => "SEND_BLOCK_ARG",
+            Inst::RETURN => "RETURN",
+            Inst::SPLAT => "SPLAT",
+            Inst::CONCAT_ARRAYS => "CONCAT_ARRAYS",
+            Inst::SEND_SPLAT => "SEND_SPLAT",
+            Inst::YIELD_SPLAT => "YIELD_SPLAT",
+            Inst::SETN => "SETN",
+            Inst::CLASS_VAR_OR_NIL => "CLASS_VAR_OR_NIL",
+            Inst::ENTER_RESCUE => "ENTER_RESCUE",
+            Inst::LEAVE_RESCUE => "LEAVE_RESCUE",
+            Inst::LEAVE => "LEAVE",
+            _ => "UNKNOWN",
+        }
+    }
+
+    pub fn operands(inst: u8) -> &'static [Operand] {
+        match inst {
+            Inst::FIXNUM => &[Operand::FixNum],
+            Inst::DECIMALNUM => &[Operand::DecimalNum],
+            Inst::STRING | Inst::BIGNUM => &[Operand::String],
+            Inst::ARRAY
+            | Inst::HASH
+            | Inst::INTERPOLATE
+            | Inst::DUPN
+            | Inst::TOPN
+            | Inst::YIELD
+            | Inst::INDEX
+            | Inst::INDEX_ASSIGN
+            | Inst::CONCAT_ARRAYS
+            | Inst::SETN => &[Operand::Len],
+            Inst::EXPAND_ARRAY => &[Operand::Len, Operand::Len, Operand::Len],
+            Inst::IDENT
+            | Inst::SYMBOL
+            | Inst::IDENT_ASSIGN
+            | Inst::CONST
+            | Inst::INSTANCE_VAR
+            | Inst::INSTANCE_VAR_ASSIGN
+            | Inst::CLASS_VAR
+            | Inst::CLASS_VAR_ASSIGN
+            | Inst::GLOBAL_VAR
+            | Inst::GLOBAL_VAR_ASSIGN
+            | Inst::IDENT_OR_NIL
+            | Inst::CLASS_VAR_OR_NIL
+            | Inst::ATTR_ASSIGN => &[Operand::Ident],
+            Inst::SEND => &[Operand::Ident, Operand::Len, Operand::ISeq],
+            Inst::SEND_BLOCK_ARG => &[Operand::Ident, Operand::Len],
+            Inst::SEND_SPLAT => &[Operand::Ident, Operand::ISeq, Operand::Len],
+            Inst::LAMBDA => &[Operand::ISeq],
+            Inst::FUNC_DECL | Inst::CLASS_DECL => &[Operand::Ident, Operand::ISeq],
+            Inst::JUMP | Inst::JUMP_IF_FALSE | Inst::JUMP_IF_TRUE => &[Operand::Dest],
+            Inst::FOR_NEXT | Inst::JUMP_IF_BOUND => &[Operand::Ident, Operand::Dest],
+            Inst::SET_HANDLER | Inst::RETRY | Inst::UNWIND => &[Operand::Handler],
+            Inst::CASE_DISPATCH => &[Operand::CaseTable, Operand::Dest],
+            _ => &[],
+        }
+    }
+}
+
+/// Operator instructions and the names of the methods they stand for, which
+/// an instance may define to overload them.
+const OPERATORS: [(u8, &str); 23] = [
+    (Inst::ADD, "+"),
+    (Inst::SUB, "-"),
+    (Inst::MUL, "*"),
+    (Inst::DIV, "/"),
+    (Inst::REM, "%"),
+    (Inst::POW, "**"),
+    (Inst::EQ, "=="),
+    (Inst::NE, "!="),
+    (Inst::TEQ, "==="),
+    (Inst::CMP, "<=>"),
+    (Inst::GT, ">"),
+    (Inst::GE, ">="),
+    (Inst::LT, "<"),
+    (Inst::LE, "<="),
+    (Inst::BIT_AND, "&"),
+    (Inst::BIT_OR, "|"),
+    (Inst::BIT_XOR, "^"),
+    (Inst::SHL, "<<"),
+    (Inst::SHR, ">>"),
+    (Inst::NOT, "!"),
+    (Inst::BIT_NOT, "~"),
+    (Inst::POS, "+@"),
+    (Inst::NEG, "-@"),
+];
+
+impl Inst {
+    /// The method name of an operator instruction.
+    pub fn operator_name(inst: u8) -> Option<&'static str> {
+        OPERATORS
+            .iter()
+            .find(|(op, _)| *op == inst)
+            .map(|(_, name)| *name)
+    }
+
+    /// The operator instruction for a method name.
+    pub fn from_operator_name(name: &str) -> Option<u8> {
+        OPERATORS
+            .iter()
+            .find(|(_, op_name)| *op_name == name)
+            .map(|(inst, _)| *inst)
+    }
+
+    /// Whether an operator instruction takes one operand instead of two.
+    pub fn is_unary(inst: u8) -> bool {
+        matches!(inst, Inst::NOT | Inst::BIT_NOT | Inst::POS | Inst::NEG)
+    }
 }
@@ -0,0 +1,153 @@
+use crate::util::annot::*;
+use crate::util::util::*;
+use crate::vm::vm::*;
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ISeqRef(pub usize);
+
+impl std::ops::Deref for ISeqRef {
+    type Target = usize;
+    fn deref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl std::hash::Hash for ISeqRef {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ISeqKind {
+    Top,
+    Method(IdentId),
+    Class(IdentId),
+    /// A block, with the sequence it is written in.
+    Block(ISeqRef),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandlerKind {
+    Rescue,
+    Ensure,
+    /// Not an error handler: only records the exec stack depth that
+    /// `break`, `next` and `redo` cut back to with `UNWIND`.
+    Loop,
+}
+
+/// Instructions in `start..end` are protected by the handler code at `dest`.
+/// When one of them fails, the exec stack is cut back to the depth saved by
+/// `SET_HANDLER`, the exception is pushed and control moves to `dest`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handler {
+    pub kind: HandlerKind,
+    pub start: usize,
+    pub end: usize,
+    pub dest: usize,
+}
+
+/// The parameter signature of a method or block. Anonymous `*` and `**`
+/// are named `*` and `**`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params {
+    pub required: Vec<IdentId>,
+    /// Bound from the arguments when given; their defaults are evaluated at
+    /// the start of the body otherwise.
+    pub optional: Vec<IdentId>,
+    pub rest: Option<IdentId>,
+    /// Required parameters after `*rest`, bound from the last arguments.
+    pub post: Vec<IdentId>,
+    /// Keyword parameters, with whether they are required.
+    pub keyword: Vec<(IdentId, bool)>,
+    pub keyword_rest: Option<IdentId>,
+    pub block: Option<IdentId>,
+    /// Variables assigned by destructuring patterns such as `(a, b)`, which
+    /// are bound through hidden parameters named after the pattern.
+    pub destructured: Vec<IdentId>,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Params::default()
+    }
+
+    /// Every parameter name, in declaration order.
+    pub fn names(&self) -> Vec<IdentId> {
+        let mut names = self.required.clone();
+        names.extend(&self.optional);
+        names.extend(self.rest);
+        names.extend(&self.post);
+        names.extend(self.keyword.iter().map(|(id, _)| *id));
+        names.extend(self.keyword_rest);
+        names.extend(self.block);
+        names.extend(&self.destructured);
+        names
+    }
+
+    pub fn takes_keywords(&self) -> bool {
+        !self.keyword.is_empty() || self.keyword_rest.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ISeqInfo {
+    pub kind: ISeqKind,
+    pub params: Params,
+    pub body: Vec<ISeq>,
+    /// Source location of the instructions starting at each position,
+    /// recorded only where the location changes.
+    pub locs: Vec<(usize, Loc)>,
+    pub handlers: Vec<Handler>,
+    /// Jump tables of `CASE_DISPATCH`, from an Integer `when` value to the
+    /// position of its clause.
+    pub case_tables: Vec<FxHashMap<i64, usize>>,
+}
+
+impl ISeqInfo {
+    pub fn new(kind: ISeqKind, params: Params) -> Self {
+        Self {
+            kind,
+            params,
+            body: vec![],
+            locs: vec![],
+            handlers: vec![],
+            case_tables: vec![],
+        }
+    }
+
+    pub fn loc(&self, pos: usize) -> Option<Loc> {
+        self.locs
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= pos)
+            .map(|(_, loc)| *loc)
+    }
+}
+
+impl ISeqInfo {
+    /// Index of the innermost handler protecting the instruction at `pos`.
+    pub fn handler(&self, pos: usize) -> Option<usize> {
+        self.innermost(pos, |kind| kind != HandlerKind::Loop)
+    }
+
+    /// Index of the innermost `ensure` handler protecting the instruction
+    /// at `pos`, which `break` and `return` out of a block pass through.
+    pub fn ensure_handler(&self, pos: usize) -> Option<usize> {
+        self.innermost(pos, |kind| kind == HandlerKind::Ensure)
+    }
+
+    fn innermost(&self, pos: usize, accepts: impl Fn(HandlerKind) -> bool) -> Option<usize> {
+        self.handlers
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| accepts(h.kind) && h.start <= pos && pos < h.end)
+            .min_by_key(|(_, h)| h.end - h.start)
+            .map(|(i, _)| i)
+    }
+}
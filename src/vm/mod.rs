@@ -1,4 +1,7 @@
+pub mod disasm;
+pub mod disasm_test;
 pub mod inst;
+pub mod iseq;
 pub mod stack;
 #[allow(clippy::module_inception)]
 pub mod vm;
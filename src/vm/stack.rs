@@ -1,25 +1,76 @@
+use crate::node::node::*;
+use crate::util::annot::*;
 use crate::util::util::*;
 use crate::value::value::*;
+use crate::vm::iseq::*;
 
-use rustc_hash::FxHashMap;
+/// A method, block or class body being executed, as listed in backtraces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    /// `<main>`, `fact`, `Foo#bar`, `block in fact`, `<class:Foo>` ...
+    pub label: String,
+    /// Index in `stack_poses` of the frame running the code, or None for
+    /// builtin methods.
+    pub exec_frame: Option<usize>,
+}
+
+/// A `while`, `until` or `for` loop being generated, the target of `break`,
+/// `next` and `redo` inside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopInfo {
+    /// Loop handler whose saved depth is the exec stack on entry to the loop.
+    pub break_handler: usize,
+    /// Loop handler whose saved depth is the exec stack at the start of the body.
+    pub next_handler: usize,
+    pub next_dest: usize,
+    pub redo_dest: usize,
+    /// Operands of the `break` jumps, patched to the end of the loop.
+    pub breaks: Vec<usize>,
+    /// Length of `ensure_stack` outside the loop.
+    pub ensure_depth: usize,
+}
 
 #[derive(Debug, Clone)]
 pub struct Stack {
-    pub iseqs: FxHashMap<usize, Vec<u8>>,
+    pub iseqs: Vec<ISeqInfo>,
     pub ident_table: IdentifierTable,
     pub stack_poses: Vec<usize>,
     pub iseq_poses: Vec<usize>,
     pub exec_stack: Vec<Vec<Value>>,
+    /// Exec stack depth saved by each `SET_HANDLER` of the frame.
+    pub handler_depths: Vec<Vec<usize>>,
+    pub call_frames: Vec<CallFrame>,
+    pub loc_stack: Vec<Loc>,
+    /// Rescue handlers enclosing the code being generated, targets of `retry`.
+    pub retry_stack: Vec<usize>,
+    /// Loops enclosing the code being generated in the current sequence.
+    pub loop_stack: Vec<LoopInfo>,
+    /// `ensure` clauses enclosing the code being generated in the current
+    /// sequence, emitted again in front of jumps that leave them.
+    pub ensure_stack: Vec<Node>,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Stack {
     pub fn new() -> Self {
         Self {
-            iseqs: FxHashMap::default(),
+            // ISeqRef(0) is reserved for the toplevel sequence.
+            iseqs: vec![ISeqInfo::new(ISeqKind::Top, Params::new())],
             ident_table: IdentifierTable::default(),
             stack_poses: vec![],
             iseq_poses: vec![],
             exec_stack: vec![],
+            handler_depths: vec![],
+            call_frames: vec![],
+            loc_stack: vec![],
+            retry_stack: vec![],
+            loop_stack: vec![],
+            ensure_stack: vec![],
         }
     }
 }
@@ -1,17 +1,21 @@
 use crate::args::args::*;
 use crate::class::class::*;
+use crate::hash::hash::*;
 
 use crate::instance::instance::*;
 use crate::node::node::*;
+use crate::proc::proc::*;
 use crate::util::annot::*;
 use crate::util::util::*;
 use crate::value::value::*;
 use crate::vm::inst::*;
+use crate::vm::iseq::*;
 use crate::vm::stack::*;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, Pow, Signed, ToPrimitive, Zero};
 use rustc_hash::FxHashMap;
 
-use std::ops::Deref;
-
 pub type ISeq = u8;
 
 #[derive(Debug, Clone)]
@@ -21,18 +25,49 @@ pub struct VM {
     pub ident_table: IdentifierTable,
     pub class_table: GlobalClassTable,
     pub instance_table: GlobalInstanceTable,
+    pub hash_table: GlobalHashTable,
+    pub proc_table: GlobalProcTable,
     pub method_table: MethodTable,
     pub const_table: ValueTable,
     pub class_stack: Vec<ClassRef>,
-    pub scope_stack: Vec<LocalScope>,
+    pub scope_table: LocalScopeTable,
+    /// The local scope of each running method, class body and block.
+    pub scope_stack: Vec<ScopeRef>,
     pub global_stack: Vec<GlobalScope>,
     pub env: Vec<Env>,
+    /// The block passed to each running Ruby method, innermost last.
+    pub block_stack: Vec<Option<Block>>,
+    /// `break` and `return` signals held while the `ensure` clauses they
+    /// pass through run, innermost last.
+    pub pending_signals: Vec<RuntimeError>,
+    /// The exceptions being handled by running `rescue` clauses, innermost
+    /// last, with the frame and stack slot each is held in. A bare `raise`
+    /// raises the innermost one again.
+    pub rescued_exceptions: Vec<(usize, usize, Value)>,
+    /// The first node that could not be compiled, reported by `eval`.
+    compile_error: Option<RuntimeError>,
+    /// Ruby calls running, and the native stack address where the outermost
+    /// one started.
+    call_depth: usize,
+    stack_base: usize,
+    /// Past this many nested calls, or this many bytes of native stack used
+    /// by them, a call raises SystemStackError instead of overflowing the
+    /// host stack. The default `stack_limit` fits the 2 MiB stack of a
+    /// spawned thread; raise it when running the VM on a larger stack, as
+    /// the `taiko` binary does.
+    pub max_call_depth: usize,
+    pub stack_limit: usize,
 }
 
+const MAX_CALL_DEPTH: usize = 10_000;
+/// Half the stack Rust gives a spawned thread, leaving the rest for the
+/// frames below the first call and the work of the last one allowed.
+const STACK_LIMIT: usize = 1 << 20;
+
 pub type ValueTable = FxHashMap<IdentId, Value>;
-pub type BuiltinFunc = fn(eval: &mut VM, receiver: Value, args: Args) -> Value;
+pub type BuiltinFunc = fn(eval: &mut VM, receiver: Value, args: Args) -> EvalResult;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Env {
     ClassRef(ClassRef),
     InstanceRef(InstanceRef),
@@ -41,8 +76,8 @@ pub enum Env {
 #[derive(Clone)]
 pub enum MethodInfo {
     RubyFunc {
-        params: Vec<IdentId>,
-        ptr: usize,
+        params: Box<Params>,
+        iseq: ISeqRef,
         local_scope: LocalScope,
     },
     BuiltinFunc {
@@ -56,10 +91,10 @@ impl std::fmt::Debug for MethodInfo {
         match self {
             MethodInfo::RubyFunc {
                 params,
-                ptr,
+                iseq,
                 local_scope,
             } => {
-                write!(f, "RubyFunc {:?} {:?} {:?}", params, ptr, local_scope)
+                write!(f, "RubyFunc {:?} {:?} {:?}", params, iseq, local_scope)
             }
             MethodInfo::BuiltinFunc { name, .. } => write!(f, "BuiltinFunc {:?}", name),
         }
@@ -68,18 +103,163 @@ impl std::fmt::Debug for MethodInfo {
 
 pub type MethodTable = FxHashMap<IdentId, MethodInfo>;
 
+/// Local variables of a method, class body or block call. A block call
+/// shares the variables of the scope the block is written in through
+/// `outer`, so a block sees and updates them by reference.
 #[derive(Debug, Clone, PartialEq)]
 pub struct LocalScope {
     lvar_table: ValueTable,
-    propagated_table: ValueTable,
+    outer: Option<ScopeRef>,
+    /// Optional parameters the caller left out, which get their defaults
+    /// at the start of the body.
+    unbound: Vec<IdentId>,
+    /// Whether a Proc closes over the scope, which then outlives its frame.
+    captured: bool,
+    /// Whether the scope is a lambda call, which `return` leaves.
+    lambda: bool,
+}
+
+impl Default for LocalScope {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LocalScope {
     pub fn new() -> Self {
         Self {
             lvar_table: FxHashMap::default(),
-            propagated_table: FxHashMap::default(),
+            outer: None,
+            unbound: vec![],
+            captured: false,
+            lambda: false,
+        }
+    }
+
+    /// Defines the parameters of a call. Those bound to None are left for
+    /// their defaults.
+    fn bind(&mut self, bindings: Vec<(IdentId, Option<Value>)>) {
+        for (param, arg) in bindings {
+            if arg.is_none() {
+                self.unbound.push(param);
+            }
+            self.lvar_table.insert(param, arg.unwrap_or(Value::Nil));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeRef(pub usize);
+
+/// Every live local scope. The scope of a finished call is reused unless a
+/// Proc captured it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalScopeTable {
+    table: Vec<LocalScope>,
+    free: Vec<ScopeRef>,
+}
+
+impl Default for LocalScopeTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalScopeTable {
+    pub fn new() -> Self {
+        Self {
+            table: vec![],
+            free: vec![],
+        }
+    }
+
+    pub fn new_scope(&mut self, scope: LocalScope) -> ScopeRef {
+        match self.free.pop() {
+            Some(scope_ref) => {
+                self.table[scope_ref.0] = scope;
+                scope_ref
+            }
+            None => {
+                self.table.push(scope);
+                ScopeRef(self.table.len() - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, scope_ref: ScopeRef) -> &LocalScope {
+        &self.table[scope_ref.0]
+    }
+
+    pub fn get_mut(&mut self, scope_ref: ScopeRef) -> &mut LocalScope {
+        &mut self.table[scope_ref.0]
+    }
+
+    /// Frees the scope of a finished call for reuse, unless it is captured.
+    pub fn release(&mut self, scope_ref: ScopeRef) {
+        let scope = &mut self.table[scope_ref.0];
+        if !scope.captured {
+            *scope = LocalScope::new();
+            self.free.push(scope_ref);
+        }
+    }
+
+    /// Keeps `scope_ref` and the scopes it sees alive for a Proc.
+    pub fn capture(&mut self, scope_ref: ScopeRef) {
+        let mut scope_ref = Some(scope_ref);
+        while let Some(r) = scope_ref {
+            let scope = &mut self.table[r.0];
+            scope.captured = true;
+            scope_ref = scope.outer;
+        }
+    }
+
+    /// The scope of the method or lambda that `return` in `scope_ref`
+    /// leaves.
+    pub fn return_target(&self, scope_ref: ScopeRef) -> ScopeRef {
+        let scope = self.get(scope_ref);
+        match scope.outer {
+            Some(outer) if !scope.lambda => self.return_target(outer),
+            _ => scope_ref,
+        }
+    }
+
+    /// The variable `id` as seen from `scope_ref`.
+    pub fn get_lvar(&self, scope_ref: ScopeRef, id: IdentId) -> Option<&Value> {
+        let scope = self.get(scope_ref);
+        match scope.lvar_table.get(&id) {
+            Some(val) => Some(val),
+            None => scope.outer.and_then(|outer| self.get_lvar(outer, id)),
+        }
+    }
+
+    /// Assigns the variable `id` where it is visible from `scope_ref`, or
+    /// defines it in `scope_ref`.
+    pub fn set_lvar(&mut self, scope_ref: ScopeRef, id: IdentId, val: Value) {
+        let mut r = scope_ref;
+        loop {
+            let scope = self.get_mut(r);
+            if let Some(var) = scope.lvar_table.get_mut(&id) {
+                *var = val;
+                return;
+            }
+            match scope.outer {
+                Some(outer) => r = outer,
+                None => break,
+            }
         }
+        self.get_mut(scope_ref).lvar_table.insert(id, val);
+    }
+
+    /// Every variable visible from `scope_ref`, with inner ones shadowing
+    /// outer ones.
+    pub fn visible_lvars(&self, scope_ref: ScopeRef) -> ValueTable {
+        let scope = self.get(scope_ref);
+        let mut lvars = match scope.outer {
+            Some(outer) => self.visible_lvars(outer),
+            None => FxHashMap::default(),
+        };
+        lvars.extend(scope.lvar_table.clone());
+        lvars
     }
 }
 
@@ -88,6 +268,12 @@ pub struct GlobalScope {
     gvar_table: ValueTable,
 }
 
+impl Default for GlobalScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GlobalScope {
     pub fn new() -> Self {
         Self {
@@ -98,7 +284,23 @@ impl GlobalScope {
 
 pub type EvalResult = Result<Value, RuntimeError>;
 
-pub type RuntimeError = Annot<RuntimeErrorKind>;
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub loc: Loc,
+    /// Innermost call first, formatted as `file.rb:12:in 'fact'`.
+    pub backtrace: Vec<String>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, loc: Loc, backtrace: Vec<String>) -> Self {
+        Self {
+            kind,
+            loc,
+            backtrace,
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RuntimeErrorKind {
@@ -106,22 +308,59 @@ pub enum RuntimeErrorKind {
     Unreachable(String),
     Name(String),
     NoMethod(String),
+    Type(String),
+    Index(String),
+    Key(String),
+    Argument(String),
+    ZeroDivision(String),
+    FloatDomain(String),
+    Runtime(String),
+    NoMatchingPattern(String),
+    LocalJump(String),
+    SystemStack(String),
+    /// An exception object raised by `raise`.
+    Exception(Value),
+    /// `break` out of the block, with the value for the method call it was
+    /// passed to. Not an exception: `rescue` never sees it.
+    Break(ISeqRef, Value),
+    /// `return` out of a block, with the scope of the method or lambda it
+    /// leaves and the value. Like Break, `rescue` never sees it.
+    Return(ScopeRef, Value),
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
     pub fn new() -> Self {
+        let mut scope_table = LocalScopeTable::new();
+        let top_scope = scope_table.new_scope(LocalScope::new());
         Self {
             stack: Stack::new(),
             source_info: SourceInfo::new(),
             ident_table: IdentifierTable::new(),
             class_table: GlobalClassTable::new(),
             instance_table: GlobalInstanceTable::new(),
+            hash_table: GlobalHashTable::new(),
+            proc_table: GlobalProcTable::new(),
             method_table: FxHashMap::default(),
             const_table: FxHashMap::default(),
             class_stack: vec![],
-            scope_stack: vec![LocalScope::new()],
+            scope_table,
+            scope_stack: vec![top_scope],
             global_stack: vec![GlobalScope::new()],
             env: vec![],
+            block_stack: vec![],
+            pending_signals: vec![],
+            rescued_exceptions: vec![],
+            compile_error: None,
+            call_depth: 0,
+            stack_base: 0,
+            max_call_depth: MAX_CALL_DEPTH,
+            stack_limit: STACK_LIMIT,
         }
     }
 
@@ -138,7 +377,7 @@ impl VM {
         self.source_info = source_info;
         self.ident_table = ident_table;
 
-        let id = self.ident_table.get_ident_id(&"top".to_string());
+        let id = self.ident_table.get_ident_id("top");
         let classref = self.new_class(id, 0);
         self.env.push(Env::ClassRef(classref));
         self.class_stack.push(classref);
@@ -146,7 +385,7 @@ impl VM {
         macro_rules! reg_method_table {
                      ( $($id:expr => $func:path),+ ) => {
                          $(
-                             let id = self.ident_table.get_ident_id(&$id.to_string());
+                             let id = self.ident_table.get_ident_id($id);
                              let info = MethodInfo::BuiltinFunc {
                                  name: $id.to_string(),
                                  func: $func
@@ -166,7 +405,132 @@ impl VM {
             "times" => VM::builtin_times,
             "len" => VM::builtin_len,
             "each" => VM::builtin_each,
-            "instance_variables" => VM::builtin_instance_variables
+            "instance_variables" => VM::builtin_instance_variables,
+            "raise" => VM::builtin_raise,
+            "each_pair" => VM::builtin_each,
+            "fetch" => VM::builtin_fetch,
+            "key?" => VM::builtin_key_p,
+            "has_key?" => VM::builtin_key_p,
+            "include?" => VM::builtin_include_p,
+            "delete" => VM::builtin_delete,
+            "keys" => VM::builtin_keys,
+            "values" => VM::builtin_values,
+            "map" => VM::builtin_map,
+            "select" => VM::builtin_select,
+            "to_a" => VM::builtin_to_a,
+            "merge" => VM::builtin_merge,
+            "size" => VM::builtin_size,
+            "length" => VM::builtin_size,
+            "inspect" => VM::builtin_inspect,
+            "to_sym" => VM::builtin_to_sym,
+            "to_proc" => VM::builtin_to_proc,
+            "call" => VM::builtin_call,
+            "send" => VM::builtin_send,
+            "__send__" => VM::builtin_send,
+            "public_send" => VM::builtin_send,
+            "to_f" => VM::builtin_to_f,
+            "round" => VM::builtin_round,
+            "floor" => VM::builtin_floor,
+            "ceil" => VM::builtin_ceil,
+            "divmod" => VM::builtin_divmod,
+            "modulo" => VM::builtin_modulo,
+            "nan?" => VM::builtin_nan_p,
+            "infinite?" => VM::builtin_infinite_p,
+            "finite?" => VM::builtin_finite_p,
+            "block_given?" => VM::builtin_block_given_p,
+            "proc" => VM::builtin_proc,
+            "lambda" => VM::builtin_lambda,
+            "lambda?" => VM::builtin_lambda_p
+        }
+
+        self.init_value_classes();
+        self.init_exception_classes();
+    }
+
+    /// Defines the classes of builtin values, so that they can be named in
+    /// `case`/`when` and `rescue`.
+    fn init_value_classes(&mut self) {
+        self.define_builtin_classes(&[
+            ("Class", None),
+            ("NilClass", None),
+            ("TrueClass", None),
+            ("FalseClass", None),
+            ("Numeric", None),
+            ("Integer", Some("Numeric")),
+            ("Float", Some("Numeric")),
+            ("String", None),
+            ("Array", None),
+            ("Range", None),
+            ("Symbol", None),
+            ("Hash", None),
+            ("Proc", None),
+        ]);
+    }
+
+    /// Defines the builtin exception classes, unless they already exist.
+    fn init_exception_classes(&mut self) {
+        self.define_builtin_classes(&[
+            ("Exception", None),
+            ("ScriptError", Some("Exception")),
+            ("NotImplementedError", Some("ScriptError")),
+            ("StandardError", Some("Exception")),
+            ("RuntimeError", Some("StandardError")),
+            ("NameError", Some("StandardError")),
+            ("NoMethodError", Some("NameError")),
+            ("ZeroDivisionError", Some("StandardError")),
+            ("RangeError", Some("StandardError")),
+            ("FloatDomainError", Some("RangeError")),
+            ("TypeError", Some("StandardError")),
+            ("ArgumentError", Some("StandardError")),
+            ("IndexError", Some("StandardError")),
+            ("KeyError", Some("IndexError")),
+            ("LocalJumpError", Some("StandardError")),
+            ("NoMatchingPatternError", Some("StandardError")),
+            ("SystemStackError", Some("Exception")),
+        ]);
+
+        let exception = self.builtin_class_ref("Exception");
+        for (name, func) in [
+            ("new", VM::builtin_exception_new as BuiltinFunc),
+            ("message", VM::builtin_exception_message),
+            ("to_s", VM::builtin_exception_message),
+            ("backtrace", VM::builtin_exception_backtrace),
+        ]
+        .iter()
+        {
+            let id = self.ident_table.get_ident_id(name);
+            let info = MethodInfo::BuiltinFunc {
+                name: name.to_string(),
+                func: *func,
+            };
+            self.class_info_with_ref(exception)
+                .method_table
+                .insert(id, info);
+        }
+    }
+
+    /// Defines each class as a constant, after its superclass, unless it
+    /// already exists.
+    fn define_builtin_classes(&mut self, classes: &[(&str, Option<&str>)]) {
+        for (name, superclass) in classes.iter() {
+            let id = self.ident_table.get_ident_id(name);
+            if self.const_table.contains_key(&id) {
+                continue;
+            }
+            let class_ref = self.new_class(id, 0);
+            self.const_table.insert(id, Value::Class(class_ref));
+            if let Some(superclass) = superclass {
+                let superclass_ref = self.builtin_class_ref(superclass);
+                self.add_subclass(class_ref, superclass_ref);
+            }
+        }
+    }
+
+    fn builtin_class_ref(&mut self, name: &str) -> ClassRef {
+        let id = self.ident_table.get_ident_id(name);
+        match self.const_table.get(&id) {
+            Some(Value::Class(class_ref)) => *class_ref,
+            _ => unreachable!("builtin class {} is not defined.", name),
         }
     }
 
@@ -183,484 +547,1779 @@ impl VM {
         self.class_info_with_ref(env)
     }
 
-    pub fn update_propagated_var(&mut self) {
-        let lvar_table = self.local_scope().clone().lvar_table;
-        let propagated_table = self.local_scope().clone().propagated_table;
-        self.scope_stack.pop();
-        for (id, _) in propagated_table.into_iter() {
-            *self.local_scope().lvar_table.get_mut(&id).unwrap() =
-                lvar_table.get(&id).unwrap().clone();
+    pub fn builtin_puts(&mut self, _receiver: Value, args: Args) -> EvalResult {
+        let args = args.args;
+        for arg in args {
+            self.puts_value(&arg);
         }
+        Ok(Value::Nil)
     }
 
-    pub fn builtin_puts(&mut self, _receiver: Value, args: Args) -> Value {
-        let args = args.args;
-        for arg in args {
-            println!("{}", self.val_to_s(&arg));
+    /// Prints `val` on its own line, or each element of an array on its own.
+    fn puts_value(&mut self, val: &Value) {
+        match val {
+            Value::Array(vals) if vals.is_empty() => println!(),
+            Value::Array(vals) => {
+                for val in vals {
+                    self.puts_value(val);
+                }
+            }
+            val => {
+                // A line that already ends in a newline is not given another.
+                let s = self.val_to_s(val);
+                match s.strip_suffix('\n') {
+                    Some(s) => println!("{}", s),
+                    None => println!("{}", s),
+                }
+            }
         }
-        Value::Nil
     }
 
-    pub fn builtin_new(&mut self, receiver: Value, _args: Args) -> Value {
+    pub fn builtin_new(&mut self, receiver: Value, _args: Args) -> EvalResult {
         match receiver {
             Value::Class(class_ref) => {
                 let instance = self.new_instance(class_ref);
-                Value::Instance(instance)
+                Ok(Value::Instance(instance))
             }
-            _ => unimplemented!(),
+            _ => Err(self.error_nomethod_for("new", &receiver)),
         }
     }
-    pub fn builtin_to_i(&mut self, receiver: Value, _args: Args) -> Value {
-        let i = receiver.to_i();
-        Value::FixNum(i)
-    }
 
-    pub fn builtin_to_s(&mut self, receiver: Value, _args: Args) -> Value {
-        let s = self.val_to_s(&receiver);
-        Value::String(s)
+    pub fn builtin_to_i(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::FixNum(_) | Value::BigNum(_) => Ok(receiver),
+            Value::FixDecimalNum(f) => self.float_to_i(f),
+            Value::String(s) => Ok(Value::str_to_i(&s)),
+            _ => Err(self.error_nomethod_for("to_i", &receiver)),
+        }
     }
 
-    pub fn builtin_assert(&mut self, _receiver: Value, args: Args) -> Value {
-        let args = args.args;
-        if args.len() != 2 {
-            unimplemented!();
-        };
-        let god = &args[0];
-        let expected = &args[1];
-        if god == expected {
-            Value::Nil
+    /// The integer part of `f`, raising FloatDomainError for NaN and the
+    /// infinities.
+    fn float_to_i(&mut self, f: f64) -> EvalResult {
+        if f.is_finite() {
+            Ok(Value::integer(BigInt::from_f64(f.trunc()).unwrap()))
         } else {
-            panic!("assertion fail! Expected {:?}, bad god {:?}", god, expected)
+            Err(self.error_float_domain(f))
         }
     }
 
-    pub fn builtin_class(&mut self, receiver: Value, _args: Args) -> Value {
-        let class = receiver.to_class();
-        Value::SelfClass(class)
-    }
-
-    pub fn builtin_times(&mut self, receiver: Value, args: Args) -> Value {
+    pub fn builtin_to_f(&mut self, receiver: Value, _args: Args) -> EvalResult {
         match receiver {
-            Value::FixNum(n) => {
-                for i in 0..n {
-                    self.new_propagated_local_var_stack();
-
-                    let imm_value = Value::FixNum(i);
-                    let val = args.table as usize;
-                    self.lvar_table_as_mut().insert(IdentId(val), imm_value);
-
-                    self.eval_seq().unwrap_or_else(|err| {
-                        panic!("Builtin#times: error occured while eval_node. {:?};", err)
-                    });
-                    self.init_eval_stack();
-                    let local_scope = self.local_scope().clone();
-                    self.scope_stack.pop();
-                    for (id, n) in local_scope.lvar_table.into_iter() {
-                        if self.local_scope().lvar_table.contains_key(&id) {
-                            *self.local_scope().lvar_table.get_mut(&id).unwrap() = n;
-                        }
-                    }
-                }
-            }
-            _ => panic!(
-                "Builtin#times : must has array reciver, bud god {:?}.",
-                receiver
-            ),
+            Value::FixNum(i) => Ok(Value::FixDecimalNum(i as f64)),
+            Value::BigNum(i) => Ok(Value::FixDecimalNum(i.to_f64().unwrap_or(f64::INFINITY))),
+            Value::FixDecimalNum(_) => Ok(receiver),
+            Value::String(s) => Ok(Value::FixDecimalNum(Value::str_to_f(&s))),
+            _ => Err(self.error_nomethod_for("to_f", &receiver)),
         }
-        Value::Nil
     }
 
-    pub fn builtin_len(&mut self, receiver: Value, _args: Args) -> Value {
-        match receiver {
-            Value::Array(contents) => {
-                let i = contents.len() as i64;
-                Value::FixNum(i)
-            }
-            _ => unimplemented!(),
-        }
+    pub fn builtin_round(&mut self, receiver: Value, args: Args) -> EvalResult {
+        self.eval_rounding(receiver, args, "round", Rounding::Round)
+    }
+
+    pub fn builtin_floor(&mut self, receiver: Value, args: Args) -> EvalResult {
+        self.eval_rounding(receiver, args, "floor", Rounding::Floor)
+    }
+
+    pub fn builtin_ceil(&mut self, receiver: Value, args: Args) -> EvalResult {
+        self.eval_rounding(receiver, args, "ceil", Rounding::Ceil)
     }
 
-    pub fn builtin_each(&mut self, receiver: Value, args: Args) -> Value {
+    /// `round`, `floor` and `ceil` with an optional number of decimal
+    /// digits. A Float rounded to a positive number of digits stays a
+    /// Float; anything else becomes an Integer.
+    fn eval_rounding(
+        &mut self,
+        receiver: Value,
+        args: Args,
+        method: &str,
+        rounding: Rounding,
+    ) -> EvalResult {
+        let digits = match args.args.as_slice() {
+            [] => 0,
+            [Value::FixNum(digits)] => *digits,
+            [arg] => {
+                let name = self.val_class_name(arg);
+                return Err(
+                    self.error_type(format!("no implicit conversion of {} into Integer", name))
+                );
+            }
+            args => {
+                return Err(self.error_argument(format!(
+                    "wrong number of arguments (given {}, expected 0..1)",
+                    args.len()
+                )))
+            }
+        };
         match receiver {
-            Value::Array(contents) => {
-                for c in contents {
-                    self.set_stack_pos(0);
-                    self.new_propagated_local_var_stack();
-                    self.lvar_table_as_mut()
-                        .insert(IdentId(args.table.into()), c);
-                    self.eval_seq().unwrap_or_else(|err| {
-                        panic!("Builtin#each: error occured while eval_node. {:?}.", err)
-                    });
+            Value::FixNum(_) | Value::BigNum(_) if digits >= 0 => Ok(receiver),
+            Value::FixNum(_) | Value::BigNum(_) => {
+                let i = match &receiver {
+                    Value::FixNum(i) => BigInt::from(*i),
+                    Value::BigNum(i) => i.clone(),
+                    _ => unreachable!(),
+                };
+                // Rounding to more digits than the number has gives 0.
+                let digits = (-digits) as usize;
+                if digits > i.to_string().len() {
+                    return Ok(Value::FixNum(0));
                 }
+                let unit = BigInt::from(10).pow(digits as u32);
+                let rem = i.mod_floor(&unit);
+                let floor = &i - &rem;
+                let twice: BigInt = &rem * 2;
+                let rounded = match rounding {
+                    Rounding::Floor => floor,
+                    Rounding::Ceil if rem.is_zero() => floor,
+                    Rounding::Ceil => floor + unit,
+                    // Halves round away from zero.
+                    Rounding::Round if twice > unit || (twice == unit && i.is_positive()) => {
+                        floor + unit
+                    }
+                    Rounding::Round => floor,
+                };
+                Ok(Value::integer(rounded))
             }
-            Value::Range(mut rhs, mut lhs) => {
-                for c in lhs.value()..=rhs.value() {
-                    self.set_stack_pos(0);
-                    self.new_propagated_local_var_stack();
-                    self.lvar_table_as_mut()
-                        .insert(IdentId(args.table.into()), Value::FixNum(c));
-                    self.eval_seq().unwrap_or_else(|err| {
-                        panic!("Builtin#each: error occured while eval_node. {:?}.", err)
-                    });
+            Value::FixDecimalNum(f) => {
+                let round = |f: f64| match rounding {
+                    Rounding::Round => f.round(),
+                    Rounding::Floor => f.floor(),
+                    Rounding::Ceil => f.ceil(),
+                };
+                if digits > 0 {
+                    let unit = 10f64.powi(digits as i32);
+                    Ok(Value::FixDecimalNum(round(f * unit) / unit))
+                } else {
+                    let unit = 10f64.powi(-digits as i32);
+                    self.float_to_i(round(f / unit) * unit)
                 }
             }
-            _ => panic!(
-                "Builtin#each: must has array reciver, bud god {:?}.",
-                receiver
-            ),
+            _ => Err(self.error_nomethod_for(method, &receiver)),
         }
-        Value::Nil
     }
 
-    pub fn builtin_instance_variables(&mut self, receiver: Value, _args: Args) -> Value {
-        match receiver {
-            Value::Instance(instance_ref) => {
-                let mut names = vec![];
-                for key in self.instance_ref(instance_ref).instance_var.clone().keys() {
-                    names.push(Value::String(format!(
-                        "@{}",
-                        self.ident_table.get_name(*key)
-                    )));
-                }
-                Value::Array(names)
+    /// `divmod(other)`: the floored quotient as an Integer and the modulo.
+    pub fn builtin_divmod(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let rhs = self.expect_arg(&args, 0)?;
+        let (receiver, rhs) = coerce_bignum(receiver, rhs);
+        let integers = match (&receiver, &rhs) {
+            (Value::FixNum(lhs), Value::FixNum(rhs)) => {
+                Some((BigInt::from(*lhs), BigInt::from(*rhs)))
+            }
+            (lhs, rhs) => bignum_operands(lhs, rhs),
+        };
+        if let Some((lhs, rhs)) = integers {
+            if rhs.is_zero() {
+                return Err(self.error_zero_division());
             }
-            _ => panic!(
-                "Builtin#instance_variables: must has array reciver, bud god {:?}.",
-                receiver
-            ),
+            let (q, m) = lhs.div_mod_floor(&rhs);
+            return Ok(Value::Array(vec![Value::integer(q), Value::integer(m)]));
         }
+        let (lhs, rhs) = match (receiver, rhs) {
+            (Value::FixNum(lhs), Value::FixDecimalNum(rhs)) => (lhs as f64, rhs),
+            (Value::FixDecimalNum(lhs), Value::FixNum(rhs)) => (lhs, rhs as f64),
+            (Value::FixDecimalNum(lhs), Value::FixDecimalNum(rhs)) => (lhs, rhs),
+            (lhs, rhs) => return Err(self.error_binop("divmod", &lhs, &rhs)),
+        };
+        if rhs == 0.0 {
+            return Err(self.error_zero_division());
+        }
+        let q = self.float_to_i((lhs / rhs).floor())?;
+        Ok(Value::Array(vec![
+            q,
+            Value::FixDecimalNum(float_mod(lhs, rhs)),
+        ]))
     }
-}
 
-impl VM {
-    pub fn gen_array_with_len(&mut self, array: &Vec<Node>) {
-        self.gen_nodes(array.to_vec());
-        let len = array.len();
-        self.gen_comp_usize(len);
+    pub fn builtin_modulo(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let rhs = self.expect_arg(&args, 0)?;
+        self.eval_rem(receiver, rhs)
     }
 
-    pub fn gen_num_option(&mut self, id_option: Option<IdentId>) {
-        match id_option {
-            Some(id) => self.gen_comp_usize(*id),
-            None => self.push_iseq(Inst::NIL),
+    pub fn builtin_nan_p(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::FixDecimalNum(f) => Ok(Value::Bool(f.is_nan())),
+            _ => Err(self.error_nomethod_for("nan?", &receiver)),
         }
     }
 
-    pub fn gen(&mut self, node: &Node) {
-        // println!("&node.kind: {:?}", &node.kind);
-        match &node.kind {
-            NodeKind::None => self.push_iseq(Inst::NIL),
-            NodeKind::SelfValue => self.push_iseq(Inst::SELF_VALUE),
-            NodeKind::Number(num) => self.gen_comp_fixnum(*num),
-            NodeKind::String(s) => {
-                let id = self.stack.ident_table.get_ident_id(s);
-                self.gen_comp_usize(*id);
-                self.push_iseq(Inst::STRING);
-            }
-            NodeKind::BinOp(op, lhs, rhs) => match op {
-                BinOp::Add => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::ADD);
-                }
-                BinOp::Sub => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::SUB);
-                }
-                BinOp::Mul => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::MUL);
-                }
-                BinOp::Div => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::DIV);
-                }
-                BinOp::Eq => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::EQ);
-                }
-                BinOp::Ne => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::NE);
-                }
-                BinOp::GT => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::GT);
-                }
-                BinOp::GE => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::GE);
-                }
-                BinOp::LT => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::LT);
-                }
-                BinOp::LE => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::LE);
-                }
-                BinOp::LAnd => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::LAND);
-                }
-                BinOp::LOr => {
-                    self.gen(lhs);
-                    self.gen(rhs);
-                    self.push_iseq(Inst::LOR);
-                }
-            },
-            NodeKind::Ident(id) => {
-                let num = id.deref();
-                self.gen_ident(*num);
-            }
-            NodeKind::Const(id) => {
-                let num = id.deref();
-                self.gen_const(*num);
-            }
-            NodeKind::TableIdent(id) => {
-                let num = id.deref();
-                self.gen_table_ident(*num);
-            }
-            NodeKind::CompStmt(nodes) => {
-                self.gen_nodes(nodes.to_vec());
-            }
-            NodeKind::BlockDecl(body) => self.gen(body),
-            NodeKind::Array(nodes) => {
-                self.gen_array_with_len(nodes);
-                self.push_iseq(Inst::ARRAY);
-            }
-            NodeKind::ArrayIndex(nodes, num) => {
-                self.gen(nodes);
-                self.gen_comp_fixnum(*num);
-                self.push_iseq(Inst::ARRAY_INDEX);
-            }
-            NodeKind::If(cond_, then_, else_) => {
-                self.gen_body(else_);
-                self.gen_body(then_);
-                self.gen_body(cond_);
-                self.push_iseq(Inst::IF);
-            }
-            NodeKind::FuncDecl(id, params, body) => {
-                let num = id.deref();
-                self.gen_comp_usize(*num);
-                self.gen_array_with_len(params);
-                self.push_iseq(Inst::INIT_FUNC);
-                self.gen_comp_usize(*num);
-                self.gen_body(body);
-                self.push_iseq(Inst::FUNC_DECL);
-            }
-            NodeKind::ClassDecl(id, body, inheritence_class_id) => {
-                let num = id.deref();
-                self.gen_comp_usize(*num);
-                self.gen_body(body);
-                self.gen_num_option(*inheritence_class_id);
-                self.push_iseq(Inst::CLASS_DECL);
-            }
-            NodeKind::Assign(lhs, rhs) => {
-                self.gen(rhs);
-                match lhs.kind {
-                    NodeKind::Ident(id) => {
-                        self.gen_comp_fixnum(*id as i64);
-                        self.push_iseq(Inst::IDENT_ASSIGN);
-                    }
-                    NodeKind::InstanceVar(id) => {
-                        self.gen_comp_fixnum(*id as i64);
-                        self.push_iseq(Inst::INSTANCE_VAR_ASSIGN);
-                    }
-                    NodeKind::ClassVar(id) => {
-                        self.gen_comp_fixnum(*id as i64);
-                        self.push_iseq(Inst::CLASS_VAR_ASSIGN);
-                    }
-                    _ => unimplemented!(),
-                }
-            }
-            NodeKind::Range(lhs, rhs) => {
-                self.gen(lhs);
-                self.gen(rhs);
-                self.push_iseq(Inst::RANGE);
-            }
-            NodeKind::Send(receiver, method, args) => {
-                self.gen(receiver);
-                let id = match method.kind {
-                    NodeKind::Ident(id) => id,
-                    _ => unimplemented!(),
-                };
-                self.gen_comp_usize(*id);
-                self.gen(&args.table);
-                self.gen_body(&args.node);
-                self.gen_nodes_with_len(args.args.to_owned());
-                self.push_iseq(Inst::SEND);
-            }
-            NodeKind::InstanceVar(id) => {
-                self.gen_comp_usize(**id);
-                self.push_iseq(Inst::INSTANCE_VAR);
-            }
-            NodeKind::ClassVar(id) => {
-                self.gen_comp_usize(**id);
-                self.push_iseq(Inst::CLASS_VAR);
-            }
-            NodeKind::For(id, table, body) => {
-                let num = id.deref();
-                self.gen_comp_usize(*num);
-                self.gen(table);
-                self.gen_body(body);
-                self.push_iseq(Inst::FOR);
-            }
-            _ => {
-                println!("&node.kind: {:?}", &node.kind);
-                println!("{:?}", self.ident_table);
-                unimplemented!();
+    /// `infinite?`: 1 or -1 for an infinity, nil otherwise.
+    pub fn builtin_infinite_p(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::FixNum(_) | Value::BigNum(_) => Ok(Value::Nil),
+            Value::FixDecimalNum(f) if f.is_infinite() => {
+                Ok(Value::FixNum(if f > 0.0 { 1 } else { -1 }))
             }
+            Value::FixDecimalNum(_) => Ok(Value::Nil),
+            _ => Err(self.error_nomethod_for("infinite?", &receiver)),
         }
     }
 
-    fn gen_nodes(&mut self, nodes: Vec<Node>) {
-        for node in nodes.clone() {
-            self.gen(&node);
+    pub fn builtin_finite_p(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::FixNum(_) | Value::BigNum(_) => Ok(Value::Bool(true)),
+            Value::FixDecimalNum(f) => Ok(Value::Bool(f.is_finite())),
+            _ => Err(self.error_nomethod_for("finite?", &receiver)),
         }
     }
 
-    fn gen_nodes_with_len(&mut self, nodes: Vec<Node>) {
-        self.gen_nodes(nodes.to_vec());
-        let len = nodes.len();
-        self.gen_comp_usize(len);
+    pub fn builtin_to_s(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        let s = self.val_to_s(&receiver);
+        Ok(Value::String(s))
     }
 
-    fn gen_comp_fixnum(&mut self, num: i64) {
-        self.push_iseq(Inst::FIXNUM);
-        self.push_iseq((num >> 56) as u8);
-        self.push_iseq((num >> 48) as u8);
-        self.push_iseq((num >> 40) as u8);
-        self.push_iseq((num >> 32) as u8);
-        self.push_iseq((num >> 24) as u8);
-        self.push_iseq((num >> 16) as u8);
-        self.push_iseq((num >> 8) as u8);
-        self.push_iseq(num as u8);
+    pub fn builtin_inspect(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        let s = self.val_inspect(&receiver);
+        Ok(Value::String(s))
     }
 
-    pub fn gen_ident(&mut self, num: usize) {
-        self.push_iseq(Inst::IDENT);
-        self.push_iseq((num >> 56) as u8);
-        self.push_iseq((num >> 48) as u8);
-        self.push_iseq((num >> 40) as u8);
-        self.push_iseq((num >> 32) as u8);
-        self.push_iseq((num >> 24) as u8);
-        self.push_iseq((num >> 16) as u8);
-        self.push_iseq((num >> 8) as u8);
-        self.push_iseq(num as u8);
+    pub fn builtin_to_sym(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Symbol(_) => Ok(receiver),
+            Value::String(s) => Ok(Value::Symbol(self.ident_table.get_ident_id(&s))),
+            _ => Err(self.error_nomethod_for("to_sym", &receiver)),
+        }
     }
 
-    pub fn gen_const(&mut self, num: usize) {
-        self.push_iseq(Inst::CONST);
-        self.push_iseq((num >> 56) as u8);
-        self.push_iseq((num >> 48) as u8);
-        self.push_iseq((num >> 40) as u8);
-        self.push_iseq((num >> 32) as u8);
-        self.push_iseq((num >> 24) as u8);
-        self.push_iseq((num >> 16) as u8);
-        self.push_iseq((num >> 8) as u8);
-        self.push_iseq(num as u8);
+    pub fn builtin_to_proc(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Proc(_) => Ok(receiver),
+            Value::Symbol(id) => {
+                let info = ProcInfo::new(ProcKind::Symbol(id));
+                Ok(Value::Proc(self.proc_table.new_proc(info)))
+            }
+            _ => Err(self.error_nomethod_for("to_proc", &receiver)),
+        }
     }
 
-    pub fn gen_table_ident(&mut self, num: usize) {
-        self.push_iseq(Inst::TABLE_IDENT);
-        self.push_iseq((num >> 56) as u8);
-        self.push_iseq((num >> 48) as u8);
-        self.push_iseq((num >> 40) as u8);
-        self.push_iseq((num >> 32) as u8);
-        self.push_iseq((num >> 24) as u8);
-        self.push_iseq((num >> 16) as u8);
-        self.push_iseq((num >> 8) as u8);
-        self.push_iseq(num as u8);
+    pub fn builtin_call(&mut self, receiver: Value, args: Args) -> EvalResult {
+        match receiver {
+            Value::Proc(proc_ref) => self.call_proc(proc_ref, args),
+            _ => Err(self.error_nomethod_for("call", &receiver)),
+        }
     }
 
-    pub fn gen_comp_usize(&mut self, num: usize) {
-        let num = num as i64;
-        self.gen_comp_fixnum(num);
+    /// `proc { ... }`: the block as a Proc.
+    pub fn builtin_proc(&mut self, _receiver: Value, args: Args) -> EvalResult {
+        match self.expect_block(&args)? {
+            Block::Captured(block) => Ok(self.new_block_proc(block, false)),
+            Block::Proc(proc_ref) => Ok(Value::Proc(proc_ref)),
+        }
     }
 
-    pub fn lvar_table_as_mut(&mut self) -> &mut ValueTable {
-        &mut self.scope_stack.last_mut().unwrap().lvar_table
+    /// `lambda { ... }`: the block as a lambda. A Proc passed with `&`
+    /// stays as it is.
+    pub fn builtin_lambda(&mut self, _receiver: Value, args: Args) -> EvalResult {
+        match self.expect_block(&args)? {
+            Block::Captured(block) => Ok(self.new_block_proc(block, true)),
+            Block::Proc(proc_ref) => Ok(Value::Proc(proc_ref)),
+        }
     }
 
-    pub fn local_scope(&mut self) -> &mut LocalScope {
-        self.scope_stack.last_mut().unwrap()
+    pub fn builtin_lambda_p(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Proc(proc_ref) => {
+                let kind = &self.proc_table.get(proc_ref).kind;
+                Ok(Value::Bool(matches!(kind, ProcKind::Lambda(_))))
+            }
+            _ => Err(self.error_nomethod_for("lambda?", &receiver)),
+        }
     }
 
-    fn new_class_info(&mut self, id: IdentId, ptr: usize) -> ClassRef {
-        let name = self.ident_table.get_name(id);
-        self.class_table.new_class(id, name, ptr)
+    /// Whether the method this is called in was given a block.
+    pub fn builtin_block_given_p(&mut self, _receiver: Value, _args: Args) -> EvalResult {
+        Ok(Value::Bool(matches!(
+            self.block_stack.last(),
+            Some(Some(_))
+        )))
     }
 
-    fn new_propagated_local_var_stack(&mut self) {
-        let mut last_scope_stack = self.scope_stack.last_mut().unwrap().to_owned();
-        last_scope_stack.propagated_table = last_scope_stack.clone().lvar_table;
-        self.scope_stack.push(last_scope_stack);
+    /// `send(name, *args)`: calls the method named by a symbol or string,
+    /// passing the rest of the arguments and the block along.
+    pub fn builtin_send(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let mut args = args;
+        let id = match args.args.first() {
+            Some(Value::Symbol(id)) => *id,
+            Some(Value::String(name)) => self.ident_table.get_ident_id(name),
+            Some(name) => {
+                let name = self.val_inspect(name);
+                return Err(self.error_type(format!("{} is not a symbol nor a string", name)));
+            }
+            None => return Err(self.error_argument("no method name given")),
+        };
+        args.args.remove(0);
+        self.send(receiver, id, args)
     }
 
-    pub fn init_iseq(&mut self, node: Node) {
-        self.stack.stack_poses.push(0);
-        self.stack.iseq_poses.push(0);
-        self.stack.iseqs.insert(0, vec![]);
-        self.stack.exec_stack.push(vec![]);
-        self.gen(&node);
-        self.push_iseq(Inst::END);
-        // println!("{:?}", self.stack.iseqs);
-        // println!("{:?}", self.env_info().method_table);
-    }
-
-    fn get_val(&mut self) -> usize {
-        match self.iseq() {
-            Inst::FIXNUM
-            | Inst::IDENT
-            | Inst::TABLE_IDENT
-            | Inst::CONST
-            | Inst::CLASS_VAR
-            | Inst::INSTANCE_VAR => self.plus_stack_pos(1),
-            _ => unimplemented!(),
-        }
-        let mut num = 0;
-        num += (self.iseq() as usize) << 56;
-        num += (self.iseq_idx(1) as usize) << 48;
-        num += (self.iseq_idx(2) as usize) << 40;
-        num += (self.iseq_idx(3) as usize) << 32;
-        num += (self.iseq_idx(4) as usize) << 24;
-        num += (self.iseq_idx(5) as usize) << 16;
-        num += (self.iseq_idx(6) as usize) << 8;
-        num += self.iseq_idx(7) as usize;
-        self.plus_stack_pos(8);
-        num as usize
+    pub fn builtin_assert(&mut self, _receiver: Value, args: Args) -> EvalResult {
+        let args = args.args;
+        if args.len() != 2 {
+            return Err(self.error_argument(format!(
+                "wrong number of arguments (given {}, expected 2)",
+                args.len()
+            )));
+        };
+        let expected = &args[0];
+        let got = &args[1];
+        if expected == got {
+            Ok(Value::Nil)
+        } else {
+            Err(self.error_runtime(format!(
+                "Assertion failed. Expected {:?}, but got {:?}",
+                expected, got
+            )))
+        }
     }
 
-    pub fn insert_num(&mut self, num: usize) {
-        let pos = self.stack_pos();
-        self.current_iseq().insert(pos, Inst::FIXNUM as u8);
-        self.current_iseq().insert(pos + 1, (num >> 56) as u8);
-        self.current_iseq().insert(pos + 2, (num >> 48) as u8);
-        self.current_iseq().insert(pos + 3, (num >> 40) as u8);
-        self.current_iseq().insert(pos + 4, (num >> 32) as u8);
-        self.current_iseq().insert(pos + 5, (num >> 24) as u8);
-        self.current_iseq().insert(pos + 6, (num >> 16) as u8);
-        self.current_iseq().insert(pos + 7, (num >> 8) as u8);
-        self.current_iseq().insert(pos + 8, num as u8);
+    pub fn builtin_class(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Instance(r) => Ok(Value::Class(self.class_ref_with_instance(r))),
+            _ => Ok(Value::SelfClass(receiver.to_class())),
+        }
     }
 
-    pub fn insert_iseq(&mut self, inst: u8) {
-        let pos = self.stack_pos();
-        self.current_iseq().insert(pos, inst);
+    pub fn builtin_times(&mut self, receiver: Value, args: Args) -> EvalResult {
+        match receiver {
+            Value::FixNum(n) => {
+                if let Some(block) = args.block {
+                    for i in 0..n {
+                        self.eval_block(&block, vec![Value::FixNum(i)])?;
+                    }
+                }
+                Ok(Value::Nil)
+            }
+            _ => Err(self.error_nomethod_for("times", &receiver)),
+        }
+    }
+
+    pub fn builtin_len(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Array(contents) => Ok(Value::FixNum(contents.len() as i64)),
+            Value::String(s) => Ok(Value::FixNum(s.chars().count() as i64)),
+            _ => Err(self.error_nomethod_for("len", &receiver)),
+        }
+    }
+
+    pub fn builtin_each(&mut self, receiver: Value, args: Args) -> EvalResult {
+        if let Value::Hash(_) = receiver {
+            let block = self.expect_block(&args)?;
+            for pair in self.hash_pairs(&receiver, "each")? {
+                self.eval_block(&block, vec![pair])?;
+            }
+            return Ok(receiver);
+        }
+        let contents = match &receiver {
+            Value::Array(contents) => contents.clone(),
+            Value::Range(e, s) => match (&**s, &**e) {
+                (Value::FixNum(s), Value::FixNum(e)) => (*s..=*e).map(Value::FixNum).collect(),
+                _ => {
+                    return Err(self.error_type("can't iterate from a Range of non-Integer values"))
+                }
+            },
+            _ => return Err(self.error_nomethod_for("each", &receiver)),
+        };
+        if let Some(block) = args.block {
+            for c in contents {
+                self.eval_block(&block, vec![c])?;
+            }
+        }
+        Ok(Value::Nil)
+    }
+
+    /// `fetch(key)`, `fetch(key, default)` and `fetch(key) { |key| ... }`:
+    /// raises KeyError for a missing key unless a default or a block is
+    /// given. The block wins over the default.
+    pub fn builtin_fetch(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let hash = self.expect_hash(&receiver, "fetch")?;
+        let (key, default) = match args.args.as_slice() {
+            [key] => (key.clone(), None),
+            [key, default] => (key.clone(), Some(default.clone())),
+            args => {
+                return Err(self.error_argument(format!(
+                    "wrong number of arguments (given {}, expected 1..2)",
+                    args.len()
+                )))
+            }
+        };
+        let hash_key = self.hash_table.key(&key);
+        match (self.hash_table.get(hash).get(&hash_key).cloned(), default) {
+            (Some(val), _) => Ok(val),
+            (None, _) if args.block.is_some() => {
+                let block = self.expect_block(&args)?;
+                self.eval_block(&block, vec![key])
+            }
+            (None, Some(val)) => Ok(val),
+            (None, None) => {
+                let key = self.val_inspect(&key);
+                Err(self.error(RuntimeErrorKind::Key(format!("key not found: {}", key))))
+            }
+        }
+    }
+
+    pub fn builtin_key_p(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let hash = self.expect_hash(&receiver, "key?")?;
+        let key = self.expect_arg(&args, 0)?;
+        let key = self.hash_table.key(&key);
+        Ok(Value::Bool(self.hash_table.get(hash).contains_key(&key)))
+    }
+
+    /// `include?`: element membership by `==` for an array, substring search
+    /// for a string and `key?` for a hash.
+    pub fn builtin_include_p(&mut self, receiver: Value, args: Args) -> EvalResult {
+        match &receiver {
+            Value::Array(contents) => {
+                let arg = self.expect_arg(&args, 0)?;
+                for val in contents {
+                    if self.eval_eq(val.clone(), arg.clone())?.is_truthy() {
+                        return Ok(Value::Bool(true));
+                    }
+                }
+                Ok(Value::Bool(false))
+            }
+            Value::String(s) => match self.expect_arg(&args, 0)? {
+                Value::String(sub) => Ok(Value::Bool(s.contains(sub.as_str()))),
+                arg => {
+                    let name = self.val_class_name(&arg);
+                    Err(self.error_type(format!("no implicit conversion of {} into String", name)))
+                }
+            },
+            _ => {
+                let hash = self.expect_hash(&receiver, "include?")?;
+                let key = self.expect_arg(&args, 0)?;
+                let key = self.hash_table.key(&key);
+                Ok(Value::Bool(self.hash_table.get(hash).contains_key(&key)))
+            }
+        }
+    }
+
+    /// Removes `key` and returns its value, or nil when it is not present.
+    pub fn builtin_delete(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let hash = self.expect_hash(&receiver, "delete")?;
+        let key = self.expect_arg(&args, 0)?;
+        let key = self.hash_table.key(&key);
+        let val = self.hash_table.get_mut(hash).remove(&key);
+        Ok(val.unwrap_or(Value::Nil))
+    }
+
+    pub fn builtin_keys(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        let hash = self.expect_hash(&receiver, "keys")?;
+        Ok(Value::Array(self.hash_table.get(hash).keys()))
+    }
+
+    pub fn builtin_values(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        let hash = self.expect_hash(&receiver, "values")?;
+        Ok(Value::Array(self.hash_table.get(hash).values()))
+    }
+
+    /// `map` over an array, or over the `[key, value]` pairs of a hash.
+    pub fn builtin_map(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let block = self.expect_block(&args)?;
+        let contents = match receiver {
+            Value::Array(contents) => contents,
+            _ => self.hash_pairs(&receiver, "map")?,
+        };
+        let mut vals = vec![];
+        for val in contents {
+            vals.push(self.eval_block(&block, vec![val])?);
+        }
+        Ok(Value::Array(vals))
+    }
+
+    /// `select` on an array returns an array; on a hash it yields each
+    /// `[key, value]` pair and returns a new hash.
+    pub fn builtin_select(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let block = self.expect_block(&args)?;
+        match receiver {
+            Value::Array(contents) => {
+                let mut vals = vec![];
+                for val in contents {
+                    if self.eval_block(&block, vec![val.clone()])?.is_truthy() {
+                        vals.push(val);
+                    }
+                }
+                Ok(Value::Array(vals))
+            }
+            _ => {
+                let hash = self.expect_hash(&receiver, "select")?;
+                let mut info = HashInfo::new();
+                for (key, val) in self.hash_table.get(hash).entries().to_vec() {
+                    let pair = Value::Array(vec![key.clone(), val.clone()]);
+                    if self.eval_block(&block, vec![pair])?.is_truthy() {
+                        info.insert(self.hash_table.key(&key), val);
+                    }
+                }
+                Ok(Value::Hash(self.hash_table.new_hash(info)))
+            }
+        }
+    }
+
+    pub fn builtin_to_a(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Array(_) => Ok(receiver),
+            _ => Ok(Value::Array(self.hash_pairs(&receiver, "to_a")?)),
+        }
+    }
+
+    /// Returns a new hash with the entries of `other` added, replacing the
+    /// values of keys present in both.
+    pub fn builtin_merge(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let hash = self.expect_hash(&receiver, "merge")?;
+        let mut info = self.hash_table.get(hash).clone();
+        for other in args.args {
+            let other = match other {
+                Value::Hash(other) => other,
+                other => {
+                    let name = self.val_class_name(&other);
+                    return Err(
+                        self.error_type(format!("no implicit conversion of {} into Hash", name))
+                    );
+                }
+            };
+            for (key, val) in self.hash_table.get(other).entries() {
+                info.insert(self.hash_table.key(key), val.clone());
+            }
+        }
+        Ok(Value::Hash(self.hash_table.new_hash(info)))
+    }
+
+    pub fn builtin_size(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        let size = match &receiver {
+            Value::Array(contents) => contents.len(),
+            Value::String(s) => s.chars().count(),
+            Value::Hash(hash) => self.hash_table.get(*hash).len(),
+            _ => return Err(self.error_nomethod_for("size", &receiver)),
+        };
+        Ok(Value::FixNum(size as i64))
+    }
+
+    fn expect_hash(&mut self, receiver: &Value, method: &str) -> Result<HashRef, RuntimeError> {
+        match receiver {
+            Value::Hash(hash) => Ok(*hash),
+            _ => Err(self.error_nomethod_for(method, receiver)),
+        }
+    }
+
+    /// The entries of a hash as `[key, value]` arrays, the way they are
+    /// yielded to blocks.
+    fn hash_pairs(&mut self, receiver: &Value, method: &str) -> Result<Vec<Value>, RuntimeError> {
+        let hash = self.expect_hash(receiver, method)?;
+        let pairs = self
+            .hash_table
+            .get(hash)
+            .entries()
+            .iter()
+            .map(|(key, val)| Value::Array(vec![key.clone(), val.clone()]))
+            .collect();
+        Ok(pairs)
+    }
+
+    fn expect_block(&mut self, args: &Args) -> Result<Block, RuntimeError> {
+        match &args.block {
+            Some(block) => Ok(block.clone()),
+            None => Err(self.error_argument("no block given")),
+        }
+    }
+
+    fn expect_arg(&mut self, args: &Args, index: usize) -> Result<Value, RuntimeError> {
+        match args.args.get(index) {
+            Some(arg) => Ok(arg.clone()),
+            None => Err(self.error_argument(format!(
+                "wrong number of arguments (given {}, expected {})",
+                args.args.len(),
+                index + 1
+            ))),
+        }
+    }
+
+    fn expect_arg_count(&mut self, args: &Args, count: usize) -> Result<(), RuntimeError> {
+        if args.args.len() == count {
+            Ok(())
+        } else {
+            Err(self.error_argument(format!(
+                "wrong number of arguments (given {}, expected {})",
+                args.args.len(),
+                count
+            )))
+        }
+    }
+
+    pub fn builtin_instance_variables(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        let mut names = vec![];
+        if let Value::Instance(instance_ref) = receiver {
+            for key in self.instance_ref(instance_ref).instance_var.clone().keys() {
+                names.push(Value::String(format!(
+                    "@{}",
+                    self.ident_table.get_name(*key)
+                )));
+            }
+        }
+        Ok(Value::Array(names))
+    }
+
+    /// `raise`, `raise "message"`, `raise Class[, "message"]` or
+    /// `raise exception[, "message"]`.
+    pub fn builtin_raise(&mut self, _receiver: Value, args: Args) -> EvalResult {
+        let args = args.args;
+        if args.len() > 2 {
+            return Err(self.error_argument(format!(
+                "wrong number of arguments (given {}, expected 0..2)",
+                args.len()
+            )));
+        }
+        let exception_class = self.builtin_class_ref("Exception");
+        let exception = match args.first() {
+            None => match self.rescued_exceptions.last() {
+                Some((_, _, exception)) => exception.clone(),
+                None => self.new_exception("RuntimeError", "unhandled exception"),
+            },
+            Some(Value::String(message)) if args.len() == 1 => {
+                self.new_exception("RuntimeError", message)
+            }
+            Some(Value::Class(r)) if self.is_subclass_of(*r, exception_class) => {
+                let args = Args {
+                    block: None,
+                    args: args[1..].to_vec(),
+                };
+                self.builtin_exception_new(Value::Class(*r), args)?
+            }
+            Some(Value::Instance(r))
+                if self.is_subclass_of(self.instance_table.get(*r).class_id, exception_class) =>
+            {
+                if let Some(message) = args.get(1) {
+                    let id = self.ident_table.get_ident_id("message");
+                    self.instance_ref(*r)
+                        .instance_var
+                        .insert(id, message.clone());
+                }
+                Value::Instance(*r)
+            }
+            _ => return Err(self.error_type("exception class/object expected")),
+        };
+        Err(self.error_exception(exception))
+    }
+
+    pub fn builtin_exception_new(&mut self, receiver: Value, args: Args) -> EvalResult {
+        let class_ref = match receiver {
+            Value::Class(class_ref) => class_ref,
+            _ => return Err(self.error_nomethod_for("new", &receiver)),
+        };
+        let message = match args.args.first() {
+            Some(message) => message.clone(),
+            None => Value::String(self.class_table.get(class_ref).name.clone()),
+        };
+        let instance = self.new_instance(class_ref);
+        let id = self.ident_table.get_ident_id("message");
+        self.instance_ref(instance).instance_var.insert(id, message);
+        Ok(Value::Instance(instance))
+    }
+
+    pub fn builtin_exception_backtrace(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Instance(r) => {
+                let info = self.instance_ref(r);
+                match info.raised_loc {
+                    Some(_) => Ok(Value::Array(
+                        info.backtrace.iter().cloned().map(Value::String).collect(),
+                    )),
+                    None => Ok(Value::Nil),
+                }
+            }
+            _ => Err(self.error_nomethod_for("backtrace", &receiver)),
+        }
+    }
+
+    pub fn builtin_exception_message(&mut self, receiver: Value, _args: Args) -> EvalResult {
+        match receiver {
+            Value::Instance(r) => {
+                let id = self.ident_table.get_ident_id("message");
+                let message = self.instance_ref(r).instance_var.get(&id).cloned();
+                let message = message.unwrap_or(Value::Nil);
+                Ok(Value::String(self.val_to_s(&message)))
+            }
+            _ => Err(self.error_nomethod_for("message", &receiver)),
+        }
+    }
+}
+
+impl VM {
+    pub fn gen(&mut self, node: &Node) {
+        self.stack.loc_stack.push(node.loc());
+        self.gen_node(node);
+        self.stack.loc_stack.pop().unwrap();
+    }
+
+    fn gen_node(&mut self, node: &Node) {
+        match &node.kind {
+            NodeKind::None => self.push_iseq(Inst::NIL),
+            NodeKind::SelfValue => self.push_iseq(Inst::SELF_VALUE),
+            NodeKind::Bool(true) => self.push_iseq(Inst::TRUE),
+            NodeKind::Bool(false) => self.push_iseq(Inst::FALSE),
+            NodeKind::Number(num) => self.gen_with_operand(Inst::FIXNUM, *num as usize),
+            NodeKind::BigNumber(num) => {
+                let id = self.stack.ident_table.get_ident_id(&num.to_string());
+                self.gen_with_operand(Inst::BIGNUM, *id);
+            }
+            NodeKind::DecimalNumber(num) => {
+                self.gen_with_operand(Inst::DECIMALNUM, num.to_bits() as usize)
+            }
+            NodeKind::String(s) => {
+                let id = self.stack.ident_table.get_ident_id(s);
+                self.gen_with_operand(Inst::STRING, *id);
+            }
+            NodeKind::BinOp(op, lhs, rhs) => match op {
+                BinOp::LAnd => {
+                    self.gen(lhs);
+                    self.push_iseq(Inst::DUP);
+                    let pos = self.gen_jump(Inst::JUMP_IF_FALSE);
+                    self.push_iseq(Inst::POP);
+                    self.gen(rhs);
+                    self.patch_jump(pos);
+                }
+                BinOp::LOr => {
+                    self.gen(lhs);
+                    self.push_iseq(Inst::DUP);
+                    let pos = self.gen_jump(Inst::JUMP_IF_TRUE);
+                    self.push_iseq(Inst::POP);
+                    self.gen(rhs);
+                    self.patch_jump(pos);
+                }
+                _ => {
+                    self.gen(lhs);
+                    self.gen(rhs);
+                    let inst = binop_inst(op);
+                    self.push_iseq(inst);
+                }
+            },
+            NodeKind::UnOp(op, operand) => {
+                self.gen(operand);
+                let inst = match op {
+                    UnOp::Not => Inst::NOT,
+                    UnOp::BitNot => Inst::BIT_NOT,
+                    UnOp::Plus => Inst::POS,
+                    UnOp::Minus => Inst::NEG,
+                };
+                self.push_iseq(inst);
+            }
+            NodeKind::Ident(id) | NodeKind::TableIdent(id) => {
+                self.gen_with_operand(Inst::IDENT, **id)
+            }
+            NodeKind::LocalVar(id) => self.gen_with_operand(Inst::IDENT_OR_NIL, **id),
+            NodeKind::Const(id) => self.gen_with_operand(Inst::CONST, **id),
+            NodeKind::CompStmt(nodes) => self.gen_comp_stmt(nodes),
+            NodeKind::BlockDecl(body) => self.gen(body),
+            NodeKind::Array(nodes) => {
+                self.gen_nodes(nodes);
+                self.gen_with_operand(Inst::ARRAY, nodes.len());
+            }
+            NodeKind::InterpolatedString(parts) => {
+                self.gen_nodes(parts);
+                self.gen_with_operand(Inst::INTERPOLATE, parts.len());
+            }
+            NodeKind::Symbol(id) => self.gen_with_operand(Inst::SYMBOL, **id),
+            NodeKind::Hash(entries) => {
+                for (key, val) in entries {
+                    self.gen(key);
+                    self.gen(val);
+                }
+                self.gen_with_operand(Inst::HASH, entries.len());
+            }
+            NodeKind::Index(receiver, args) => {
+                self.gen(receiver);
+                for arg in args {
+                    self.gen(arg);
+                }
+                self.gen_with_operand(Inst::INDEX, args.len());
+            }
+            NodeKind::If(cond_, then_, else_) => {
+                self.gen(cond_);
+                let else_pos = self.gen_jump(Inst::JUMP_IF_FALSE);
+                self.gen(then_);
+                let end_pos = self.gen_jump(Inst::JUMP);
+                self.patch_jump(else_pos);
+                self.gen(else_);
+                self.patch_jump(end_pos);
+            }
+            NodeKind::FuncDecl(id, params, body) => {
+                let iseq = self.gen_iseq(ISeqKind::Method(*id), params, body);
+                self.gen_with_operands(Inst::FUNC_DECL, &[**id, *iseq]);
+            }
+            NodeKind::ClassDecl(id, body, inheritence_class_id) => {
+                match inheritence_class_id {
+                    Some(class_id) => self.gen_with_operand(Inst::CONST, **class_id),
+                    None => self.push_iseq(Inst::NIL),
+                }
+                let iseq = self.gen_iseq(ISeqKind::Class(*id), &[], body);
+                self.gen_with_operands(Inst::CLASS_DECL, &[**id, *iseq]);
+            }
+            NodeKind::Assign(lhs, rhs) => self.gen_assign(lhs, rhs),
+            NodeKind::MultiAssign(targets, rhs) => {
+                self.gen(rhs);
+                self.push_iseq(Inst::DUP);
+                self.gen_multi_assign(targets);
+            }
+            NodeKind::OpAssign(op, lhs, rhs) => self.gen_op_assign(op, lhs, rhs),
+            NodeKind::GlobalIdent(id) => self.gen_with_operand(Inst::GLOBAL_VAR, **id),
+            NodeKind::Range(lhs, rhs) => {
+                self.gen(lhs);
+                self.gen(rhs);
+                self.push_iseq(Inst::RANGE);
+            }
+            NodeKind::Send(receiver, method, args) => {
+                let id = match method.kind {
+                    NodeKind::Ident(id) => id,
+                    _ => return self.gen_unsupported(method),
+                };
+                self.gen(receiver);
+                let args_len = self.gen_args(&args.args);
+                if let Some(block_arg) = &args.block_arg {
+                    self.gen(block_arg);
+                    match args_len {
+                        Some(len) => self.gen_with_operands(Inst::SEND_BLOCK_ARG, &[*id, len]),
+                        None => self.gen_with_operands(Inst::SEND_SPLAT, &[*id, 0, 1]),
+                    }
+                    return;
+                }
+                let block = match args.node.kind {
+                    NodeKind::None => 0,
+                    _ => {
+                        let kind = ISeqKind::Block(ISeqRef(self.iseq_pos()));
+                        *self.gen_iseq(kind, &args.block_params, &args.node)
+                    }
+                };
+                match args_len {
+                    Some(len) => self.gen_with_operands(Inst::SEND, &[*id, len, block]),
+                    None => self.gen_with_operands(Inst::SEND_SPLAT, &[*id, block, 0]),
+                }
+            }
+            NodeKind::InstanceVar(id) => self.gen_with_operand(Inst::INSTANCE_VAR, **id),
+            NodeKind::ClassVar(id) => self.gen_with_operand(Inst::CLASS_VAR, **id),
+            NodeKind::For(id, table, body) => {
+                let break_handler = self.new_loop_handler();
+                self.gen(table);
+                self.gen_with_operand(Inst::FIXNUM, 0);
+                let loop_pos = self.current_iseq().len();
+                self.gen_with_operands(Inst::FOR_NEXT, &[**id, 0]);
+                let exit_pos = self.current_iseq().len() - 8;
+                let next_handler = self.new_loop_handler();
+                let body_pos = self.current_iseq().len();
+                self.push_loop(break_handler, next_handler, loop_pos, body_pos);
+                self.gen(body);
+                self.push_iseq(Inst::POP);
+                self.gen_with_operand(Inst::JUMP, loop_pos);
+                self.patch_jump(exit_pos);
+                self.pop_loop();
+            }
+            NodeKind::While(cond, body) => self.gen_while(cond, body, Inst::JUMP_IF_FALSE, false),
+            NodeKind::Until(cond, body) => self.gen_while(cond, body, Inst::JUMP_IF_TRUE, false),
+            NodeKind::WhilePost(cond, body) => {
+                self.gen_while(cond, body, Inst::JUMP_IF_FALSE, true)
+            }
+            NodeKind::UntilPost(cond, body) => self.gen_while(cond, body, Inst::JUMP_IF_TRUE, true),
+            NodeKind::Case(subject, branches, else_) => self.gen_case(subject, branches, else_),
+            NodeKind::Break(value) => self.gen_break(value),
+            NodeKind::Next(value) => self.gen_next(value),
+            NodeKind::Return(value) => self.gen_return(value),
+            NodeKind::Redo => self.gen_redo(),
+            NodeKind::Lambda(params, body) => {
+                let kind = ISeqKind::Block(ISeqRef(self.iseq_pos()));
+                let iseq = self.gen_iseq(kind, params, body);
+                self.gen_with_operand(Inst::LAMBDA, *iseq);
+            }
+            NodeKind::Yield(args) => match self.gen_args(args) {
+                Some(len) => self.gen_with_operand(Inst::YIELD, len),
+                None => self.push_iseq(Inst::YIELD_SPLAT),
+            },
+            NodeKind::Begin(body, rescues, else_, ensure) => {
+                self.gen_begin(body, rescues, else_, ensure);
+            }
+            NodeKind::Retry => match self.stack.retry_stack.last().copied() {
+                Some(handler) => self.gen_with_operand(Inst::RETRY, handler),
+                None => {
+                    let msg = "retry outside of rescue clause".to_string();
+                    self.gen_error(RuntimeErrorKind::LocalJump(msg));
+                }
+            },
+            _ => self.gen_unsupported(node),
+        }
+    }
+
+    fn gen_unsupported(&mut self, node: &Node) {
+        let sexp = node.to_sexp(&self.ident_table);
+        let name = sexp.trim_start_matches('(').split_whitespace().next();
+        let msg = format!("cannot compile `{}`", name.unwrap_or_default());
+        self.gen_error(RuntimeErrorKind::Unimplemented(msg));
+    }
+
+    /// Records an error in code that cannot be compiled, which `eval` then
+    /// returns, and compiles `nil` in its place.
+    fn gen_error(&mut self, kind: RuntimeErrorKind) {
+        if self.compile_error.is_none() {
+            let loc = self.stack.loc_stack.last().copied().unwrap_or(Loc(0, 0));
+            self.compile_error = Some(RuntimeError::new(kind, loc, vec![]));
+        }
+        self.push_iseq(Inst::NIL);
+    }
+
+    /// Compiles `begin`/`rescue`/`else`/`ensure`. The ensure clause is emitted
+    /// twice: once on the normal path, and once in its handler where the
+    /// pending exception is raised again afterwards.
+    fn gen_begin(
+        &mut self,
+        body: &Node,
+        rescues: &[Node],
+        else_: &Option<Box<Node>>,
+        ensure: &Option<Box<Node>>,
+    ) {
+        let start = self.current_iseq().len();
+        let ensure_handler = ensure
+            .as_ref()
+            .map(|_| self.new_handler(HandlerKind::Ensure, start));
+        let rescue_handler = match rescues.is_empty() {
+            true => None,
+            false => Some(self.new_handler(HandlerKind::Rescue, start)),
+        };
+        for handler in ensure_handler.iter().chain(rescue_handler.iter()) {
+            self.gen_with_operand(Inst::SET_HANDLER, *handler);
+        }
+        if let Some(ensure) = ensure {
+            self.stack.ensure_stack.push((**ensure).clone());
+        }
+        self.gen(body);
+        if let Some(handler) = rescue_handler {
+            self.handler_mut(handler).end = self.current_iseq().len();
+        }
+        if let Some(else_) = else_ {
+            self.push_iseq(Inst::POP);
+            self.gen(else_);
+        }
+        if let Some(handler) = rescue_handler {
+            let mut exits = vec![self.gen_jump(Inst::JUMP)];
+            self.handler_mut(handler).dest = self.current_iseq().len();
+            for rescue in rescues {
+                let (classes, var, rescue_body) = match &rescue.kind {
+                    NodeKind::Rescue(classes, var, body) => (classes, var, body),
+                    _ => unreachable!(),
+                };
+                self.stack.loc_stack.push(rescue.loc());
+                let mut matched = vec![];
+                if classes.is_empty() {
+                    let id = self.ident_table.get_ident_id("StandardError");
+                    self.push_iseq(Inst::DUP);
+                    self.gen_with_operand(Inst::CONST, *id);
+                    self.push_iseq(Inst::RESCUE_MATCH);
+                    matched.push(self.gen_jump(Inst::JUMP_IF_TRUE));
+                }
+                for class in classes {
+                    self.push_iseq(Inst::DUP);
+                    self.gen(class);
+                    self.push_iseq(Inst::RESCUE_MATCH);
+                    matched.push(self.gen_jump(Inst::JUMP_IF_TRUE));
+                }
+                let next = self.gen_jump(Inst::JUMP);
+                for pos in matched {
+                    self.patch_jump(pos);
+                }
+                if let Some(id) = var {
+                    self.gen_with_operand(Inst::IDENT_ASSIGN, **id);
+                }
+                self.push_iseq(Inst::ENTER_RESCUE);
+                self.stack.retry_stack.push(handler);
+                self.gen(rescue_body);
+                self.stack.retry_stack.pop().unwrap();
+                self.push_iseq(Inst::LEAVE_RESCUE);
+                exits.push(self.gen_jump(Inst::JUMP));
+                self.patch_jump(next);
+                self.stack.loc_stack.pop().unwrap();
+            }
+            self.push_iseq(Inst::RERAISE);
+            for pos in exits {
+                self.patch_jump(pos);
+            }
+        }
+        if let (Some(handler), Some(ensure)) = (ensure_handler, ensure) {
+            self.stack.ensure_stack.pop().unwrap();
+            self.handler_mut(handler).end = self.current_iseq().len();
+            self.gen(ensure);
+            self.push_iseq(Inst::POP);
+            let exit = self.gen_jump(Inst::JUMP);
+            self.handler_mut(handler).dest = self.current_iseq().len();
+            self.gen(ensure);
+            self.push_iseq(Inst::POP);
+            self.push_iseq(Inst::RERAISE);
+            self.patch_jump(exit);
+        }
+    }
+
+    /// Compiles `case`. With a subject, it stays on the stack while each
+    /// pattern is tested with `CASE_EQ` and is popped on entering a clause.
+    /// Without one, each value is tested for truth.
+    fn gen_case(
+        &mut self,
+        subject: &Option<Box<Node>>,
+        branches: &[Node],
+        else_: &Option<Box<Node>>,
+    ) {
+        let clauses = branches
+            .iter()
+            .map(|branch| match &branch.kind {
+                NodeKind::When(patterns, body) | NodeKind::In(patterns, body) => {
+                    (patterns.as_slice(), body.as_ref())
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let has_subject = subject.is_some();
+        let mut dispatch = None;
+        if let Some(subject) = subject {
+            self.gen(subject);
+            dispatch = self.gen_case_dispatch(&clauses);
+        }
+        let mut clause_jumps = vec![];
+        for (patterns, _) in &clauses {
+            let mut jumps = vec![];
+            for pattern in patterns.iter() {
+                self.gen(pattern);
+                if has_subject {
+                    self.push_iseq(Inst::CASE_EQ);
+                }
+                jumps.push(self.gen_jump(Inst::JUMP_IF_TRUE));
+            }
+            clause_jumps.push(jumps);
+        }
+
+        if let Some((_, else_pos)) = dispatch {
+            self.patch_jump(else_pos);
+        }
+        let is_pattern_match = matches!(
+            branches.first(),
+            Some(Node {
+                kind: NodeKind::In(..),
+                ..
+            })
+        );
+        if has_subject && else_.is_none() && is_pattern_match {
+            self.push_iseq(Inst::NO_MATCHING_PATTERN);
+        } else {
+            if has_subject {
+                self.push_iseq(Inst::POP);
+            }
+            match else_ {
+                Some(else_) => self.gen(else_),
+                None => self.push_iseq(Inst::NIL),
+            }
+        }
+        let mut end_jumps = vec![self.gen_jump(Inst::JUMP)];
+
+        for (i, ((_, body), jumps)) in clauses.iter().zip(clause_jumps).enumerate() {
+            let body_pos = self.current_iseq().len();
+            for pos in jumps {
+                self.patch_jump(pos);
+            }
+            if let Some((table, _)) = dispatch {
+                let iseq_pos = self.iseq_pos();
+                for dest in self.stack.iseqs[iseq_pos].case_tables[table].values_mut() {
+                    if *dest == i {
+                        *dest = body_pos;
+                    }
+                }
+            }
+            if has_subject {
+                self.push_iseq(Inst::POP);
+            }
+            self.gen(body);
+            end_jumps.push(self.gen_jump(Inst::JUMP));
+        }
+        for pos in end_jumps {
+            self.patch_jump(pos);
+        }
+    }
+
+    /// Emits `CASE_DISPATCH` when every `when` value is an Integer literal.
+    /// Returns the case table, which maps each value to its clause index
+    /// until the clauses are generated, and the position of the operand for
+    /// the not-found destination.
+    fn gen_case_dispatch(&mut self, clauses: &[(&[Node], &Node)]) -> Option<(usize, usize)> {
+        let mut table = FxHashMap::default();
+        for (i, (patterns, _)) in clauses.iter().enumerate() {
+            for pattern in patterns.iter() {
+                match pattern.kind {
+                    NodeKind::Number(num) => {
+                        table.entry(num).or_insert(i);
+                    }
+                    _ => return None,
+                }
+            }
+        }
+        if table.is_empty() {
+            return None;
+        }
+        let iseq_pos = self.iseq_pos();
+        let case_tables = &mut self.stack.iseqs[iseq_pos].case_tables;
+        case_tables.push(table);
+        let index = case_tables.len() - 1;
+        self.gen_with_operands(Inst::CASE_DISPATCH, &[index, 0]);
+        Some((index, self.current_iseq().len() - 8))
+    }
+
+    fn gen_assign(&mut self, lhs: &Node, rhs: &Node) {
+        match &lhs.kind {
+            NodeKind::Index(receiver, args) => {
+                let below = self.gen_index_receiver(receiver);
+                for arg in args {
+                    self.gen(arg);
+                }
+                self.gen(rhs);
+                self.gen_index_assign(receiver, args.len(), below);
+            }
+            NodeKind::Send(receiver, method, _) => {
+                self.gen(receiver);
+                self.gen(rhs);
+                self.gen_attr_assign(method);
+            }
+            _ => {
+                self.gen(rhs);
+                self.gen_store(lhs);
+            }
+        }
+    }
+
+    /// `lhs op= rhs`. The receiver and index of an element or attribute
+    /// target are evaluated once, and `||=` and `&&=` only evaluate `rhs`
+    /// and assign when needed.
+    fn gen_op_assign(&mut self, op: &BinOp, lhs: &Node, rhs: &Node) {
+        // Number of values below the current value that locate the target.
+        let mut below = 0;
+        let operands = match &lhs.kind {
+            NodeKind::Index(receiver, args) => {
+                below = self.gen_index_receiver(receiver);
+                for arg in args {
+                    self.gen(arg);
+                }
+                self.gen_with_operand(Inst::DUPN, args.len() + 1);
+                self.gen_with_operand(Inst::INDEX, args.len());
+                below + args.len() + 1
+            }
+            NodeKind::Send(receiver, method, _) => {
+                self.gen(receiver);
+                self.push_iseq(Inst::DUP);
+                let id = match method.kind {
+                    NodeKind::Ident(id) => id,
+                    _ => unreachable!(),
+                };
+                self.gen_with_operands(Inst::SEND, &[*id, 0, 0]);
+                1
+            }
+            NodeKind::Ident(id) => {
+                self.gen_with_operand(Inst::IDENT_OR_NIL, **id);
+                0
+            }
+            NodeKind::ClassVar(id) if *op == BinOp::LOr => {
+                self.gen_with_operand(Inst::CLASS_VAR_OR_NIL, **id);
+                0
+            }
+            _ => {
+                self.gen(lhs);
+                0
+            }
+        };
+        let store = |vm: &mut VM| match &lhs.kind {
+            NodeKind::Index(receiver, args) => vm.gen_index_assign(receiver, args.len(), below),
+            NodeKind::Send(_, method, _) => vm.gen_attr_assign(method),
+            _ => vm.gen_store(lhs),
+        };
+        match op {
+            BinOp::LOr | BinOp::LAnd => {
+                self.push_iseq(Inst::DUP);
+                let keep = match op {
+                    BinOp::LOr => self.gen_jump(Inst::JUMP_IF_TRUE),
+                    _ => self.gen_jump(Inst::JUMP_IF_FALSE),
+                };
+                self.push_iseq(Inst::POP);
+                self.gen(rhs);
+                store(self);
+                let end = self.gen_jump(Inst::JUMP);
+                self.patch_jump(keep);
+                for _ in 0..operands {
+                    self.push_iseq(Inst::SWAP);
+                    self.push_iseq(Inst::POP);
+                }
+                self.patch_jump(end);
+            }
+            _ => {
+                self.gen(rhs);
+                self.push_iseq(binop_inst(op));
+                store(self);
+            }
+        }
+    }
+
+    /// Stores the value on top of the stack in a variable, leaving it there.
+    fn gen_store(&mut self, lhs: &Node) {
+        match lhs.kind {
+            NodeKind::Ident(id) | NodeKind::LocalVar(id) => {
+                self.gen_with_operand(Inst::IDENT_ASSIGN, *id)
+            }
+            NodeKind::InstanceVar(id) => self.gen_with_operand(Inst::INSTANCE_VAR_ASSIGN, *id),
+            NodeKind::ClassVar(id) => self.gen_with_operand(Inst::CLASS_VAR_ASSIGN, *id),
+            NodeKind::GlobalIdent(id) => self.gen_with_operand(Inst::GLOBAL_VAR_ASSIGN, *id),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Pushes the receiver of an element assignment and returns the number
+    /// of values left below it. When the receiver is itself an element, as
+    /// in `a[1][0] = v`, these are a slot for the result and the receivers
+    /// and index arguments of the chain, each evaluated once, that
+    /// `gen_index_assign` stores the updated elements back through.
+    fn gen_index_receiver(&mut self, receiver: &Node) -> usize {
+        if !matches!(receiver.kind, NodeKind::Index(..)) {
+            self.gen(receiver);
+            return 0;
+        }
+        self.push_iseq(Inst::NIL);
+        1 + self.gen_index_chain(receiver)
+    }
+
+    fn gen_index_chain(&mut self, receiver: &Node) -> usize {
+        match &receiver.kind {
+            NodeKind::Index(outer, args) => {
+                let below = self.gen_index_chain(outer);
+                for arg in args {
+                    self.gen(arg);
+                }
+                self.gen_with_operand(Inst::DUPN, args.len() + 1);
+                self.gen_with_operand(Inst::INDEX, args.len());
+                below + args.len() + 1
+            }
+            _ => {
+                self.gen(receiver);
+                0
+            }
+        }
+    }
+
+    /// Emits `INDEX_ASSIGN` for the receiver, `len` index arguments and the
+    /// value on the stack, with `below` values under the receiver as pushed
+    /// by `gen_index_receiver`. An Array comes back as a new value, which is
+    /// stored in the receiver when it is a variable or element.
+    fn gen_index_assign(&mut self, receiver: &Node, len: usize, below: usize) {
+        if below > 0 {
+            self.gen_with_operand(Inst::SETN, below + len + 1);
+        }
+        self.gen_with_operand(Inst::INDEX_ASSIGN, len);
+        self.gen_index_write_back(receiver);
+        if below > 0 {
+            self.push_iseq(Inst::POP);
+        }
+    }
+
+    /// Stores the new receiver `INDEX_ASSIGN` pushed back where it came
+    /// from, leaving the assigned value.
+    fn gen_index_write_back(&mut self, receiver: &Node) {
+        match &receiver.kind {
+            NodeKind::Index(outer, args) => {
+                self.push_iseq(Inst::SWAP);
+                self.push_iseq(Inst::POP);
+                self.gen_with_operand(Inst::INDEX_ASSIGN, args.len());
+                self.gen_index_write_back(outer);
+            }
+            _ => {
+                if receiver.is_variable() {
+                    self.gen_store(receiver);
+                }
+                self.push_iseq(Inst::POP);
+            }
+        }
+    }
+
+    fn gen_attr_assign(&mut self, method: &Node) {
+        let name = match method.kind {
+            NodeKind::Ident(id) => self.ident_table.get_name(id),
+            _ => unreachable!(),
+        };
+        let id = self.ident_table.get_ident_id(&format!("{}=", name));
+        self.gen_with_operand(Inst::ATTR_ASSIGN, *id);
+    }
+
+    /// Assigns the value on top of the stack, which is popped, to the targets
+    /// of a multiple assignment. All values are evaluated before the first
+    /// target is assigned, so `a, b = b, a` swaps.
+    fn gen_multi_assign(&mut self, targets: &[Node]) {
+        let splat = targets
+            .iter()
+            .position(|target| matches!(target.kind, NodeKind::Splat(_)));
+        let (pre, post) = match splat {
+            Some(pos) => (pos, targets.len() - pos - 1),
+            None => (targets.len(), 0),
+        };
+        self.gen_with_operands(Inst::EXPAND_ARRAY, &[pre, splat.is_some() as usize, post]);
+        for target in targets {
+            self.gen_multi_assign_target(target);
+        }
+    }
+
+    fn gen_multi_assign_target(&mut self, target: &Node) {
+        match &target.kind {
+            NodeKind::Splat(target) if target.kind == NodeKind::None => {
+                self.push_iseq(Inst::POP);
+            }
+            NodeKind::Splat(target) => self.gen_multi_assign_target(target),
+            NodeKind::MultiLhs(targets) => self.gen_multi_assign(targets),
+            NodeKind::Index(receiver, args) => {
+                let below = self.gen_index_receiver(receiver);
+                for arg in args {
+                    self.gen(arg);
+                }
+                self.gen_with_operand(Inst::TOPN, below + args.len() + 1);
+                self.gen_index_assign(receiver, args.len(), below);
+                self.push_iseq(Inst::POP);
+                self.push_iseq(Inst::POP);
+            }
+            NodeKind::Send(receiver, method, _) => {
+                self.gen(receiver);
+                self.push_iseq(Inst::SWAP);
+                self.gen_attr_assign(method);
+                self.push_iseq(Inst::POP);
+            }
+            _ => {
+                self.gen_store(target);
+                self.push_iseq(Inst::POP);
+            }
+        }
+    }
+
+    /// Compiles `while` and `until`; `exit` is the jump that leaves the loop
+    /// on the result of the condition. A `post` loop runs its body once
+    /// before the first check. The loop evaluates to nil unless it is left
+    /// by `break`.
+    fn gen_while(&mut self, cond: &Node, body: &Node, exit: u8, post: bool) {
+        let handler = self.new_loop_handler();
+        let skip_cond = if post {
+            Some(self.gen_jump(Inst::JUMP))
+        } else {
+            None
+        };
+        let cond_pos = self.current_iseq().len();
+        self.gen(cond);
+        let exit_pos = self.gen_jump(exit);
+        if let Some(skip_cond) = skip_cond {
+            self.patch_jump(skip_cond);
+        }
+        let body_pos = self.current_iseq().len();
+        self.push_loop(handler, handler, cond_pos, body_pos);
+        self.gen(body);
+        self.push_iseq(Inst::POP);
+        self.gen_with_operand(Inst::JUMP, cond_pos);
+        self.patch_jump(exit_pos);
+        self.push_iseq(Inst::NIL);
+        self.pop_loop();
+    }
+
+    /// Registers a loop handler and saves the current exec stack depth in it.
+    fn new_loop_handler(&mut self) -> usize {
+        let start = self.current_iseq().len();
+        let handler = self.new_handler(HandlerKind::Loop, start);
+        self.gen_with_operand(Inst::SET_HANDLER, handler);
+        handler
+    }
+
+    fn push_loop(
+        &mut self,
+        break_handler: usize,
+        next_handler: usize,
+        next_dest: usize,
+        redo_dest: usize,
+    ) {
+        let ensure_depth = self.stack.ensure_stack.len();
+        self.stack.loop_stack.push(LoopInfo {
+            break_handler,
+            next_handler,
+            next_dest,
+            redo_dest,
+            breaks: vec![],
+            ensure_depth,
+        });
+    }
+
+    /// Ends the innermost loop at the current position, where `break` jumps to.
+    fn pop_loop(&mut self) {
+        let info = self.stack.loop_stack.pop().unwrap();
+        let end = self.current_iseq().len();
+        for pos in info.breaks {
+            self.patch_jump(pos);
+        }
+        self.handler_mut(info.break_handler).end = end;
+        self.handler_mut(info.break_handler).dest = end;
+        if info.next_handler != info.break_handler {
+            self.handler_mut(info.next_handler).end = end;
+            self.handler_mut(info.next_handler).dest = info.next_dest;
+        }
+    }
+
+    /// `break` leaves the innermost loop, or else the block and the method
+    /// call it was given to.
+    fn gen_break(&mut self, value: &Option<Box<Node>>) {
+        match self.stack.loop_stack.last().cloned() {
+            Some(info) => {
+                self.gen_with_operand(Inst::UNWIND, info.break_handler);
+                self.gen_jump_value(value);
+                self.gen_ensures(info.ensure_depth);
+                let pos = self.gen_jump(Inst::JUMP);
+                self.stack.loop_stack.last_mut().unwrap().breaks.push(pos);
+            }
+            None => {
+                self.gen_jump_value(value);
+                self.push_iseq(Inst::BREAK);
+            }
+        }
+    }
+
+    /// `next` starts the next iteration of the innermost loop, or else
+    /// returns from the block.
+    fn gen_next(&mut self, value: &Option<Box<Node>>) {
+        match self.stack.loop_stack.last().cloned() {
+            Some(info) => {
+                self.gen_with_operand(Inst::UNWIND, info.next_handler);
+                if let Some(value) = value {
+                    self.gen(value);
+                    self.push_iseq(Inst::POP);
+                }
+                self.gen_ensures(info.ensure_depth);
+                self.gen_with_operand(Inst::JUMP, info.next_dest);
+            }
+            None => {
+                self.gen_jump_value(value);
+                self.gen_ensures(0);
+                self.push_iseq(Inst::LEAVE);
+            }
+        }
+    }
+
+    /// `return` leaves the method, out of any loops. In a block it leaves
+    /// the method the block was written in, or the lambda it belongs to,
+    /// with the `ensure` clauses on the way run as the signal unwinds.
+    fn gen_return(&mut self, value: &Option<Box<Node>>) {
+        self.gen_jump_value(value);
+        let pos = self.iseq_pos();
+        match self.stack.iseqs[pos].kind {
+            ISeqKind::Block(_) => self.push_iseq(Inst::RETURN),
+            _ => {
+                self.gen_ensures(0);
+                self.push_iseq(Inst::LEAVE);
+            }
+        }
+    }
+
+    /// `redo` restarts the body of the innermost loop or block without
+    /// checking the condition or taking the next element.
+    fn gen_redo(&mut self) {
+        match self.stack.loop_stack.last().cloned() {
+            Some(info) => {
+                self.gen_with_operand(Inst::UNWIND, info.next_handler);
+                self.gen_ensures(info.ensure_depth);
+                self.gen_with_operand(Inst::JUMP, info.redo_dest);
+            }
+            None => {
+                self.gen_ensures(0);
+                self.gen_with_operand(Inst::JUMP, 0);
+            }
+        }
+    }
+
+    fn gen_jump_value(&mut self, value: &Option<Box<Node>>) {
+        match value {
+            Some(value) => self.gen(value),
+            None => self.push_iseq(Inst::NIL),
+        }
+    }
+
+    /// Emits the `ensure` clauses entered after the first `depth` ones,
+    /// innermost first, for a jump that leaves them.
+    fn gen_ensures(&mut self, depth: usize) {
+        for i in (depth..self.stack.ensure_stack.len()).rev() {
+            let outer = self.stack.ensure_stack.split_off(i);
+            self.gen(&outer[0]);
+            self.push_iseq(Inst::POP);
+            self.stack.ensure_stack.extend(outer);
+        }
+    }
+
+    fn new_handler(&mut self, kind: HandlerKind, start: usize) -> usize {
+        let pos = self.iseq_pos();
+        let handlers = &mut self.stack.iseqs[pos].handlers;
+        handlers.push(Handler {
+            kind,
+            start,
+            end: start,
+            dest: start,
+        });
+        handlers.len() - 1
+    }
+
+    fn handler_mut(&mut self, handler: usize) -> &mut Handler {
+        let pos = self.iseq_pos();
+        &mut self.stack.iseqs[pos].handlers[handler]
+    }
+
+    fn gen_nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.gen(node);
+        }
+    }
+
+    /// Pushes the arguments of a call or `yield` and returns their count.
+    /// With a `*splat` among them they are gathered into one Array instead,
+    /// and None is returned.
+    fn gen_args(&mut self, args: &[Node]) -> Option<usize> {
+        if !args
+            .iter()
+            .any(|arg| matches!(arg.kind, NodeKind::Splat(_)))
+        {
+            self.gen_nodes(args);
+            return Some(args.len());
+        }
+        let mut arrays = 0;
+        let mut pending = 0;
+        for arg in args {
+            match &arg.kind {
+                NodeKind::Splat(arg) => {
+                    if pending > 0 {
+                        self.gen_with_operand(Inst::ARRAY, pending);
+                        arrays += 1;
+                        pending = 0;
+                    }
+                    self.gen(arg);
+                    self.push_iseq(Inst::SPLAT);
+                    arrays += 1;
+                }
+                _ => {
+                    self.gen(arg);
+                    pending += 1;
+                }
+            }
+        }
+        if pending > 0 {
+            self.gen_with_operand(Inst::ARRAY, pending);
+            arrays += 1;
+        }
+        self.gen_with_operand(Inst::CONCAT_ARRAYS, arrays);
+        None
+    }
+
+    fn gen_comp_stmt(&mut self, nodes: &[Node]) {
+        match nodes.split_last() {
+            Some((last, nodes)) => {
+                for node in nodes {
+                    self.gen(node);
+                    self.push_iseq(Inst::POP);
+                }
+                self.gen(last);
+            }
+            None => self.push_iseq(Inst::NIL),
+        }
+    }
+
+    /// Compiles `body` into a new instruction sequence and returns its reference.
+    fn gen_iseq(&mut self, kind: ISeqKind, params: &[Node], body: &Node) -> ISeqRef {
+        let iseq = ISeqRef(self.stack.iseqs.len());
+        let params_signature = self.params_signature(params);
+        self.stack.iseqs.push(ISeqInfo::new(kind, params_signature));
+        self.stack.iseq_poses.push(*iseq);
+        let retry_stack = std::mem::take(&mut self.stack.retry_stack);
+        let loop_stack = std::mem::take(&mut self.stack.loop_stack);
+        let ensure_stack = std::mem::take(&mut self.stack.ensure_stack);
+        self.gen_param_prologue(params);
+        self.gen_body(body);
+        self.stack.retry_stack = retry_stack;
+        self.stack.loop_stack = loop_stack;
+        self.stack.ensure_stack = ensure_stack;
+        self.stack.iseq_poses.pop().unwrap();
+        iseq
+    }
+
+    fn params_signature(&mut self, params: &[Node]) -> Params {
+        let mut signature = Params::new();
+        for param in params {
+            let id = match &param.kind {
+                NodeKind::TableIdent(id) | NodeKind::Ident(id) => Some(*id),
+                NodeKind::MultiLhs(targets) => {
+                    collect_target_names(targets, &mut signature.destructured);
+                    Some(self.destructured_param_id(targets))
+                }
+                _ => None,
+            };
+            match &param.kind {
+                NodeKind::TableIdent(_) | NodeKind::Ident(_) | NodeKind::MultiLhs(_) => {
+                    let id = id.unwrap();
+                    if signature.optional.is_empty() && signature.rest.is_none() {
+                        signature.required.push(id);
+                    } else {
+                        signature.post.push(id);
+                    }
+                }
+                NodeKind::OptionalParam(id, _) => signature.optional.push(*id),
+                NodeKind::RestParam(id) => signature.rest = Some(*id),
+                NodeKind::KeywordParam(id, default) => {
+                    signature.keyword.push((*id, default.is_none()));
+                }
+                NodeKind::KeywordRestParam(id) => signature.keyword_rest = Some(*id),
+                NodeKind::BlockParam(id) => signature.block = Some(*id),
+                _ => unreachable!(),
+            }
+        }
+        signature
+    }
+
+    /// The hidden parameter holding the value a pattern such as `(a, b)` is
+    /// destructured from, named after the pattern.
+    fn destructured_param_id(&mut self, targets: &[Node]) -> IdentId {
+        let name = format!("({})", self.targets_to_s(targets));
+        self.ident_table.get_ident_id(&name)
+    }
+
+    fn targets_to_s(&self, targets: &[Node]) -> String {
+        targets
+            .iter()
+            .map(|target| match &target.kind {
+                NodeKind::Ident(id) => self.ident_table.get_name(*id),
+                NodeKind::Splat(target) => match target.kind {
+                    NodeKind::Ident(id) => format!("*{}", self.ident_table.get_name(id)),
+                    _ => "*".to_string(),
+                },
+                NodeKind::MultiLhs(targets) => format!("({})", self.targets_to_s(targets)),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Destructures the patterns among the parameters and assigns the
+    /// default of each optional parameter the caller left out.
+    fn gen_param_prologue(&mut self, params: &[Node]) {
+        for param in params {
+            let (id, default) = match &param.kind {
+                NodeKind::MultiLhs(targets) => {
+                    let id = self.destructured_param_id(targets);
+                    self.gen_with_operand(Inst::IDENT, *id);
+                    self.gen_multi_assign(targets);
+                    continue;
+                }
+                NodeKind::OptionalParam(id, default) => (id, default),
+                NodeKind::KeywordParam(id, Some(default)) => (id, default),
+                _ => continue,
+            };
+            self.gen_with_operands(Inst::JUMP_IF_BOUND, &[**id, 0]);
+            let bound = self.current_iseq().len() - 8;
+            self.gen(default);
+            self.gen_with_operand(Inst::IDENT_ASSIGN, **id);
+            self.push_iseq(Inst::POP);
+            self.patch_jump(bound);
+        }
+    }
+
+    /// Compiles the whole body of a sequence, terminated by LEAVE.
+    fn gen_body(&mut self, body: &Node) {
+        self.stack.loc_stack.push(body.loc());
+        self.gen_node(body);
+        self.push_iseq(Inst::LEAVE);
+        self.stack.loc_stack.pop().unwrap();
+    }
+
+    fn push_operand(&mut self, num: usize) {
+        self.current_iseq()
+            .extend_from_slice(&(num as u64).to_be_bytes());
+    }
+
+    pub fn gen_with_operand(&mut self, inst: u8, num: usize) {
+        self.push_iseq(inst);
+        self.push_operand(num);
+    }
+
+    pub fn gen_with_operands(&mut self, inst: u8, nums: &[usize]) {
+        self.push_iseq(inst);
+        for num in nums {
+            self.push_operand(*num);
+        }
+    }
+
+    /// Emits a jump with a placeholder destination and returns the position
+    /// of the operand, to be filled in by `patch_jump`.
+    fn gen_jump(&mut self, inst: u8) -> usize {
+        self.gen_with_operand(inst, 0);
+        self.current_iseq().len() - 8
+    }
+
+    fn patch_jump(&mut self, pos: usize) {
+        let dest = self.current_iseq().len() as u64;
+        self.current_iseq()[pos..pos + 8].copy_from_slice(&dest.to_be_bytes());
+    }
+
+    fn current_scope(&self) -> ScopeRef {
+        *self.scope_stack.last().unwrap()
+    }
+
+    fn get_lvar(&self, id: IdentId) -> Option<Value> {
+        self.scope_table.get_lvar(self.current_scope(), id).cloned()
+    }
+
+    fn set_lvar(&mut self, id: IdentId, val: Value) {
+        let scope = self.current_scope();
+        self.scope_table.set_lvar(scope, id, val);
+    }
+
+    fn gvar_table(&mut self) -> &mut ValueTable {
+        &mut self.global_stack.last_mut().unwrap().gvar_table
+    }
+
+    fn new_class_info(&mut self, id: IdentId, ptr: usize) -> ClassRef {
+        let name = self.ident_table.get_name(id);
+        self.class_table.new_class(id, name, ptr)
+    }
+
+    /// Runs `iseq` in a new local scope, released when it finishes.
+    fn eval_iseq_in_scope(
+        &mut self,
+        iseq: ISeqRef,
+        label: String,
+        scope: LocalScope,
+    ) -> EvalResult {
+        let scope = self.scope_table.new_scope(scope);
+        self.scope_stack.push(scope);
+        let val = match self.eval_iseq(iseq, label) {
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::Return(target, val),
+                ..
+            }) if target == scope => Ok(val),
+            res => res,
+        };
+        self.scope_stack.pop().unwrap();
+        self.scope_table.release(scope);
+        val
+    }
+
+    pub fn init_iseq(&mut self, node: Node) {
+        self.stack.iseqs[0] = ISeqInfo::new(ISeqKind::Top, Params::new());
+        self.stack.stack_poses = vec![0];
+        self.stack.iseq_poses = vec![0];
+        self.stack.exec_stack = vec![vec![]];
+        self.stack.handler_depths = vec![vec![]];
+        self.rescued_exceptions.clear();
+        self.stack.call_frames = vec![CallFrame {
+            label: "<main>".to_string(),
+            exec_frame: Some(0),
+        }];
+        self.gen_body(&node);
     }
 
     pub fn push_iseq(&mut self, inst: u8) {
+        if let Some(loc) = self.stack.loc_stack.last().copied() {
+            let pos = self.iseq_pos();
+            let iseq = &mut self.stack.iseqs[pos];
+            if iseq.locs.last().map(|(_, last)| *last) != Some(loc) {
+                iseq.locs.push((iseq.body.len(), loc));
+            }
+        }
         self.current_iseq().push(inst);
     }
 
@@ -672,10 +2331,6 @@ impl VM {
         *self.stack.stack_poses.last_mut().unwrap() = num;
     }
 
-    pub fn stack_pos_as_mut(&mut self) -> usize {
-        *self.stack.stack_poses.last_mut().unwrap()
-    }
-
     pub fn stack_pos(&mut self) -> usize {
         *self.stack.stack_poses.last().unwrap()
     }
@@ -684,607 +2339,1095 @@ impl VM {
         *self.stack.iseq_poses.last().unwrap()
     }
 
-    pub fn iseq_pos_mut(&mut self) -> &mut usize {
-        &mut *self.stack.iseq_poses.last_mut().unwrap()
-    }
-
     pub fn current_iseq(&mut self) -> &mut Vec<ISeq> {
         let pos = self.iseq_pos();
-        self.stack.iseqs.get_mut(&pos).unwrap()
+        &mut self.stack.iseqs[pos].body
     }
 
-    pub fn gen_body(&mut self, node: &Node) {
-        match node.kind {
-            NodeKind::None => self.push_iseq(Inst::NIL),
-            _ => {
-                let ptr = node as *const _ as usize;
-                self.stack.iseq_poses.push(ptr);
-                let iseq_pos = self.iseq_pos();
-                self.stack.iseqs.insert(iseq_pos, vec![]);
-                self.gen(node);
-                self.current_iseq().push(Inst::END);
-                self.stack.iseq_poses.pop().unwrap();
-                self.gen_comp_usize(ptr);
+    pub fn copy_exec_stack(&mut self) -> Value {
+        self.exec_stack().last().unwrap().to_owned()
+    }
+
+    pub fn pop_value(&mut self) -> Value {
+        self.exec_stack().pop().unwrap()
+    }
+
+    fn pop_values(&mut self, len: usize) -> Vec<Value> {
+        let pos = self.exec_stack().len() - len;
+        self.exec_stack().split_off(pos)
+    }
+
+    /// Pops the Array that SEND_SPLAT and YIELD_SPLAT take their arguments
+    /// from.
+    fn pop_args_array(&mut self) -> Vec<Value> {
+        match self.pop_value() {
+            Value::Array(args) => args,
+            val => vec![val],
+        }
+    }
+
+    /// Pops a `&block` argument and converts it into the Block to pass.
+    fn pop_block_arg(&mut self) -> Result<Option<Block>, RuntimeError> {
+        match self.pop_value() {
+            Value::Nil => Ok(None),
+            Value::Proc(proc_ref) => Ok(Some(Block::Proc(proc_ref))),
+            Value::Symbol(method) => {
+                let info = ProcInfo::new(ProcKind::Symbol(method));
+                Ok(Some(Block::Proc(self.proc_table.new_proc(info))))
             }
+            val => Err(self.error_type(format!(
+                "wrong argument type {} (expected Proc)",
+                self.val_class_name(&val)
+            ))),
         }
     }
 
-    fn copy_ptr(&mut self) -> usize {
-        match self.copy_exec_stack() {
-            Value::FixNum(n) => n as usize,
-            Value::Nil => 0,
-            _ => unimplemented!(),
+    /// The arguments `*val` expands to.
+    fn splat_values(&mut self, val: Value) -> Result<Vec<Value>, RuntimeError> {
+        match val {
+            Value::Array(vals) => Ok(vals),
+            Value::Nil => Ok(vec![]),
+            Value::Hash(_) => self.hash_pairs(&val, "to_a"),
+            Value::Range(e, s) => match (*s, *e) {
+                (Value::FixNum(s), Value::FixNum(e)) => Ok((s..=e).map(Value::FixNum).collect()),
+                _ => Err(self.error_type("can't iterate from a Range of non-Integer values")),
+            },
+            val => Ok(vec![val]),
         }
     }
 
-    fn get_ptr(&mut self) -> usize {
-        match self.pop_value() {
-            Value::FixNum(n) => n as usize,
-            Value::Nil => 0,
-            _ => unimplemented!(),
+    /// Sends a method call, returning the value of a `break` out of `iseq`,
+    /// the block literal given to the call.
+    fn send_with_block(
+        &mut self,
+        receiver: Value,
+        id: IdentId,
+        args: Args,
+        iseq: Option<ISeqRef>,
+    ) -> EvalResult {
+        match self.send(receiver, id, args) {
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::Break(break_iseq, val),
+                ..
+            }) if Some(break_iseq) == iseq => Ok(val),
+            res => res,
         }
     }
 
-    fn push_fixnum(&mut self) -> Value {
-        let val = self.get_val();
-        Value::FixNum(val as i64)
+    fn yield_block(&mut self, args: Vec<Value>) -> EvalResult {
+        let block = match self.block_stack.last() {
+            Some(Some(block)) => block.clone(),
+            _ => {
+                return Err(self.error(RuntimeErrorKind::LocalJump(
+                    "no block given (yield)".to_string(),
+                )))
+            }
+        };
+        self.eval_block(&block, args)
     }
 
-    pub fn copy_exec_stack(&mut self) -> Value {
-        self.exec_stack().last().unwrap().to_owned()
+    fn self_value(&mut self) -> Value {
+        match self.env() {
+            Env::ClassRef(r) => Value::Class(r),
+            Env::InstanceRef(r) => Value::Instance(r),
+        }
     }
 
-    pub fn pop_value(&mut self) -> Value {
-        self.exec_stack().pop().unwrap()
+    fn push_frame(&mut self, iseq: ISeqRef) {
+        self.stack.stack_poses.push(0);
+        self.stack.iseq_poses.push(*iseq);
+        self.stack.exec_stack.push(vec![]);
+        self.stack.handler_depths.push(vec![]);
+    }
+
+    fn pop_frame(&mut self) -> Value {
+        self.stack.stack_poses.pop().unwrap();
+        self.stack.iseq_poses.pop().unwrap();
+        self.stack.handler_depths.pop().unwrap();
+        let mut exec_stack = self.stack.exec_stack.pop().unwrap();
+        let frames = self.stack.exec_stack.len();
+        self.rescued_exceptions
+            .retain(|(frame, ..)| *frame < frames);
+        exec_stack.pop().unwrap_or(Value::Nil)
+    }
+
+    /// Cuts the current frame's stack back to `depth`, ending the `rescue`
+    /// clauses whose exceptions were held above it.
+    fn truncate_exec_stack(&mut self, depth: usize) {
+        self.exec_stack().truncate(depth);
+        let frame = self.stack.exec_stack.len() - 1;
+        self.rescued_exceptions
+            .retain(|(f, d, _)| *f < frame || *d < depth);
     }
 
-    fn class_stack(&mut self) -> Value {
-        let classref = self.class_stack.last().unwrap_or_else(|| {
-            panic!("Evaluator#eval: class stack is empty");
+    /// Runs `iseq` in a new frame and returns the value it left.
+    fn eval_iseq(&mut self, iseq: ISeqRef, label: String) -> EvalResult {
+        self.enter_call()?;
+        self.stack.call_frames.push(CallFrame {
+            label,
+            exec_frame: Some(self.stack.stack_poses.len()),
         });
-        Value::Class(*classref)
+        self.push_frame(iseq);
+        let res = self.eval_seq();
+        let val = self.pop_frame();
+        self.stack.call_frames.pop().unwrap();
+        self.call_depth -= 1;
+        res.map(|_| val)
     }
 
-    fn get_params(&mut self) -> Vec<IdentId> {
-        let mut arr: Vec<IdentId> = vec![];
-        let len = self.pop_value().value();
-        for _ in 0..len {
-            arr.push(self.pop_value().ident());
+    /// Counts a new Ruby call, raising SystemStackError past the limits.
+    fn enter_call(&mut self) -> Result<(), RuntimeError> {
+        let marker = 0u8;
+        let here = &marker as *const u8 as usize;
+        if self.call_depth == 0 {
+            self.stack_base = here;
+        }
+        if self.call_depth >= self.max_call_depth
+            || self.stack_base.abs_diff(here) > self.stack_limit
+        {
+            let msg = "stack level too deep".to_string();
+            return Err(self.error(RuntimeErrorKind::SystemStack(msg)));
         }
-        arr.reverse();
-        arr
+        self.call_depth += 1;
+        Ok(())
     }
 
-    fn get_array(&mut self) -> Vec<Value> {
-        let mut arr: Vec<Value> = vec![];
-        let len = self.pop_value().value();
-        for _ in 0..len {
-            arr.push(self.pop_value());
+    /// Runs the block passed to a method with `args`.
+    pub fn eval_block(&mut self, block: &Block, args: Vec<Value>) -> EvalResult {
+        let args = Args { block: None, args };
+        match block {
+            Block::Captured(block) => self.eval_captured_block(block, args),
+            Block::Proc(proc_ref) => self.call_proc(*proc_ref, args),
         }
-        arr.reverse();
-        arr
     }
 
-    fn save_exec_stack(&mut self) {
-        self.stack.exec_stack.push(vec![]);
+    /// Captures the current scope, `self` and block for `iseq`.
+    fn capture_block(&self, iseq: ISeqRef) -> CapturedBlock {
+        CapturedBlock {
+            iseq,
+            scope: self.current_scope(),
+            env: self.env.last().unwrap().clone(),
+            block: self.block_stack.last().cloned().flatten().map(Box::new),
+        }
     }
 
-    fn save_eval_info(&mut self) {
-        self.stack.stack_poses.push(0);
-        self.stack.iseq_poses.push(0);
+    /// Makes a Proc of `block`, keeping the scopes it closes over alive.
+    fn new_block_proc(&mut self, block: CapturedBlock, lambda: bool) -> Value {
+        self.capture_scopes(&block);
+        let kind = if lambda {
+            ProcKind::Lambda(block)
+        } else {
+            ProcKind::Block(block)
+        };
+        Value::Proc(self.proc_table.new_proc(ProcInfo::new(kind)))
     }
 
-    fn eval_body(&mut self) -> Value {
-        let ptr = self.get_ptr();
-        if ptr == 0 {
-            Value::Nil
-        } else {
-            self.save_eval_info();
-            *self.iseq_pos_mut() = ptr;
-            self.eval_seq().unwrap();
-            self.return_stack();
-            self.pop_value()
+    fn capture_scopes(&mut self, block: &CapturedBlock) {
+        self.scope_table.capture(block.scope);
+        if let Some(Block::Captured(outer)) = block.block.as_deref() {
+            self.capture_scopes(outer);
         }
     }
 
-    fn eval_body_with_args_ptr(&mut self, ptr: usize) {
-        if ptr == 0 {
-            unimplemented!();
-        } else {
-            self.save_eval_info();
-            *self.iseq_pos_mut() = ptr;
-            self.eval_seq().unwrap();
-            self.return_stack();
+    /// Calls a Proc with `args`.
+    fn call_proc(&mut self, proc_ref: ProcRef, args: Args) -> EvalResult {
+        match self.proc_table.get(proc_ref).kind.clone() {
+            ProcKind::Symbol(id) => {
+                let mut args = args;
+                if args.args.is_empty() {
+                    return Err(self.error_argument("no receiver given"));
+                }
+                let receiver = args.args.remove(0);
+                self.send(receiver, id, args)
+            }
+            ProcKind::Block(block) => self.eval_captured_block(&block, args),
+            ProcKind::Lambda(block) => self.eval_lambda(&block, args),
         }
     }
 
-    fn get_body(&mut self) -> Vec<ISeq> {
-        let ptr = self.get_ptr();
-        if ptr == 0 {
-            vec![]
-        } else {
-            *self.iseq_pos_mut() = ptr;
-            self.current_iseq().clone()
+    /// Runs a block or a Proc made from one. Missing arguments are nil and
+    /// extra ones are dropped.
+    pub fn eval_captured_block(&mut self, block: &CapturedBlock, args: Args) -> EvalResult {
+        let params = &self.stack.iseqs[*block.iseq].params;
+        let args = Args {
+            args: block_args(params, args.args),
+            ..args
+        };
+        self.run_block(block, args, false)
+    }
+
+    /// Runs a lambda, which takes its arguments like a method and which
+    /// `break` and `return` leave.
+    fn eval_lambda(&mut self, block: &CapturedBlock, args: Args) -> EvalResult {
+        match self.run_block(block, args, true) {
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::Break(iseq, val),
+                ..
+            }) if iseq == block.iseq => Ok(val),
+            res => res,
         }
     }
 
+    /// Runs a block in a new scope that sees the variables of the scope it
+    /// was written in, with `self` and the block it was written with.
+    fn run_block(&mut self, block: &CapturedBlock, args: Args, lambda: bool) -> EvalResult {
+        let params = self.stack.iseqs[*block.iseq].params.clone();
+        let bindings = self.bind_params(&params, args)?;
+        let mut scope = LocalScope::new();
+        scope.outer = Some(block.scope);
+        scope.lambda = lambda;
+        scope.bind(bindings);
+        self.env.push(block.env.clone());
+        self.block_stack.push(block.block.as_deref().cloned());
+        let label = format!("block in {}", self.block_owner_label(block.iseq));
+        let val = self.eval_iseq_in_scope(block.iseq, label, scope);
+        self.block_stack.pop().unwrap();
+        self.env.pop().unwrap();
+        val
+    }
+
+    /// Label of the innermost running frame that the block is written in.
+    fn block_owner_label(&self, block: ISeqRef) -> String {
+        let parent = match self.stack.iseqs[*block].kind {
+            ISeqKind::Block(parent) => parent,
+            _ => return self.iseq_name(block),
+        };
+        self.stack
+            .call_frames
+            .iter()
+            .rev()
+            .find(|frame| match frame.exec_frame {
+                Some(i) => self.stack.iseq_poses[i] == *parent,
+                None => false,
+            })
+            .map(|frame| frame.label.clone())
+            .unwrap_or_else(|| self.iseq_name(parent))
+    }
+
     pub fn iseq(&mut self) -> u8 {
         let iseq_pos = self.iseq_pos();
         let stack_pos = self.stack_pos();
-        self.stack.iseqs[&iseq_pos][stack_pos]
+        self.stack.iseqs[iseq_pos].body[stack_pos]
     }
 
-    pub fn iseq_idx(&mut self, num: i64) -> u8 {
+    /// Reads an 8-byte operand at the current position and advances past it.
+    fn read_operand(&mut self) -> usize {
         let iseq_pos = self.iseq_pos();
         let stack_pos = self.stack_pos();
-        self.stack.iseqs[&iseq_pos][(stack_pos as i64 + num) as usize]
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.stack.iseqs[iseq_pos].body[stack_pos..stack_pos + 8]);
+        self.plus_stack_pos(8);
+        u64::from_be_bytes(bytes) as usize
     }
 
-    pub fn init_eval_stack(&mut self) {
-        self.set_stack_pos(0);
+    fn read_ident(&mut self) -> IdentId {
+        IdentId(self.read_operand())
     }
 
-    pub fn clear_last_exec_stack(&mut self) {
-        self.stack.exec_stack.pop().unwrap();
+    pub fn exec_stack(&mut self) -> &mut Vec<Value> {
+        &mut *self.stack.exec_stack.last_mut().unwrap()
     }
 
-    pub fn push_last_eval_stack(&mut self) {
-        let val = self.exec_stack().last().unwrap().clone();
-        self.clear_last_exec_stack();
-        self.exec_stack().push(val);
+    /// Runs the current frame until LEAVE. Errors raised inside a region
+    /// protected by `rescue` or `ensure` transfer control to its handler.
+    pub fn eval_seq(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            match self.eval_insts() {
+                Ok(()) => return Ok(()),
+                Err(err) => self.handle_error(err)?,
+            }
+        }
     }
 
-    pub fn return_stack(&mut self) {
-        self.stack.stack_poses.pop().unwrap();
-        self.stack.iseq_poses.pop().unwrap();
+    fn handle_error(&mut self, err: RuntimeError) -> Result<(), RuntimeError> {
+        let signal = match err.kind {
+            RuntimeErrorKind::Unreachable(_) => return Err(err),
+            RuntimeErrorKind::Break(..) | RuntimeErrorKind::Return(..) => true,
+            _ => false,
+        };
+        let iseq_pos = self.iseq_pos();
+        let pos = self.stack_pos().saturating_sub(1);
+        let iseq = &self.stack.iseqs[iseq_pos];
+        let handler = match if signal {
+            iseq.ensure_handler(pos)
+        } else {
+            iseq.handler(pos)
+        } {
+            Some(handler) => handler,
+            None => return Err(err),
+        };
+        let dest = self.stack.iseqs[iseq_pos].handlers[handler].dest;
+        let depth = self.handler_depth(handler);
+        // The `ensure` clause re-raises a nil left in place of a signal.
+        let exception = if signal {
+            self.pending_signals.push(err);
+            Value::Nil
+        } else {
+            self.error_to_exception(err)
+        };
+        self.truncate_exec_stack(depth);
+        self.exec_stack().push(exception);
+        self.set_stack_pos(dest);
+        Ok(())
     }
 
-    pub fn exec_stack(&mut self) -> &mut Vec<Value> {
-        &mut *self.stack.exec_stack.last_mut().unwrap()
+    fn handler_depth(&mut self, handler: usize) -> usize {
+        let depths = self.stack.handler_depths.last().unwrap();
+        depths.get(handler).copied().unwrap_or(0)
     }
 
-    pub fn eval_seq(&mut self) -> Result<(), RuntimeError> {
-        // println!("self.stack.iseqs: {:?}", self.stack.iseqs);
+    fn eval_insts(&mut self) -> Result<(), RuntimeError> {
         loop {
-            // println!(
-            //     "self.iseq(): {:?}, self.iseq_pos(): {:?}, self.stack.iseq_poses: {:?}",
-            //     self.iseq(),
-            //     self.iseq_pos(),
-            //     self.stack.iseq_poses
-            // );
-            // println!("self.stack.exec_stack: {:?}", self.stack.exec_stack);
-            match self.iseq() {
+            let inst = self.iseq();
+            self.plus_stack_pos(1);
+            match inst {
                 Inst::NIL => {
-                    self.plus_stack_pos(1);
                     self.exec_stack().push(Value::Nil);
                 }
                 Inst::SELF_VALUE => {
-                    self.plus_stack_pos(1);
-                    let val = self.class_stack();
+                    let val = self.self_value();
                     self.exec_stack().push(val);
                 }
+                Inst::FIXNUM => {
+                    let num = self.read_operand() as i64;
+                    self.exec_stack().push(Value::FixNum(num));
+                }
+                Inst::DECIMALNUM => {
+                    let num = f64::from_bits(self.read_operand() as u64);
+                    self.exec_stack().push(Value::FixDecimalNum(num));
+                }
+                Inst::STRING => {
+                    let id = self.read_ident();
+                    let name = self.stack.ident_table.get_name(id);
+                    self.exec_stack().push(Value::String(name));
+                }
+                Inst::TRUE => {
+                    self.exec_stack().push(Value::Bool(true));
+                }
+                Inst::FALSE => {
+                    self.exec_stack().push(Value::Bool(false));
+                }
+                Inst::BIGNUM => {
+                    let id = self.read_ident();
+                    let digits = self.stack.ident_table.get_name(id);
+                    let num = digits.parse::<BigInt>().unwrap();
+                    self.exec_stack().push(Value::BigNum(num));
+                }
                 Inst::IDENT_ASSIGN => {
-                    self.plus_stack_pos(1);
-                    let id = self.pop_value().ident();
-                    let rhs = self.pop_value();
-                    match self.lvar_table_as_mut().get_mut(&id) {
-                        Some(val) => {
-                            *val = rhs.clone();
-                        }
-                        None => {
-                            self.lvar_table_as_mut().insert(id, rhs.clone());
-                        }
-                    }
-                    self.exec_stack().push(rhs);
+                    let id = self.read_ident();
+                    let rhs = self.copy_exec_stack();
+                    self.set_lvar(id, rhs);
                 }
                 Inst::INSTANCE_VAR_ASSIGN => {
-                    self.plus_stack_pos(1);
-                    let id = self.pop_value().ident();
-                    let rhs = self.pop_value();
-
+                    let id = self.read_ident();
+                    let rhs = self.copy_exec_stack();
                     match self.env() {
                         Env::ClassRef(r) => {
-                            let instance_var =
-                                self.class_info_with_ref(r).instance_var.get_mut(&id);
-                            match instance_var {
-                                Some(val) => {
-                                    *val = rhs.clone();
-                                }
-                                None => {
-                                    self.class_info_with_ref(r)
-                                        .instance_var
-                                        .insert(id, rhs.clone());
-                                }
-                            }
+                            self.class_info_with_ref(r).instance_var.insert(id, rhs);
                         }
                         Env::InstanceRef(r) => {
-                            match self.instance_ref(r).instance_var.get_mut(&id) {
-                                Some(val) => {
-                                    *val = rhs.clone();
-                                }
-                                None => {
-                                    self.instance_ref(r).instance_var.insert(id, rhs.clone());
-                                }
-                            }
+                            self.instance_ref(r).instance_var.insert(id, rhs);
                         }
                     };
                 }
                 Inst::CLASS_VAR_ASSIGN => {
-                    self.plus_stack_pos(1);
-                    let id = self.pop_value().ident();
-                    let rhs = self.pop_value();
+                    let id = self.read_ident();
+                    let rhs = self.copy_exec_stack();
                     match self.env() {
                         Env::ClassRef(r) => {
-                            let class_var = self.class_info_with_ref(r).class_var.get_mut(&id);
-                            match class_var {
-                                Some(val) => {
-                                    *val = rhs.clone();
-                                }
-                                None => {
-                                    self.class_info_with_ref(r)
-                                        .class_var
-                                        .insert(id, rhs.clone());
-                                }
-                            }
+                            self.class_info_with_ref(r).class_var.insert(id, rhs);
                         }
                         Env::InstanceRef(r) => {
-                            let class_info = self.class_info_with_instance(r);
-                            let class_var = class_info.class_var.get_mut(&id);
-                            match class_var {
-                                Some(val) => {
-                                    *val = rhs.clone();
-                                }
-                                None => {
-                                    panic!("This class variable not defined. {:?}", id);
-                                }
-                            }
+                            self.class_info_with_instance(r).class_var.insert(id, rhs);
                         }
                     }
                 }
-                Inst::END => {
-                    self.plus_stack_pos(1);
-                    if let Some(val) = self.exec_stack().pop() {
-                        self.exec_stack().push(val);
-                    } else {
+                Inst::LEAVE => {
+                    if self.exec_stack().is_empty() {
                         self.exec_stack().push(Value::Nil);
                     }
                     return Ok(());
                 }
-                Inst::FIXNUM => {
-                    let val = self.push_fixnum();
-                    self.exec_stack().push(val);
-                }
-                Inst::STRING => {
-                    self.plus_stack_pos(1);
-                    let id = self.pop_value().ident();
-                    let name = self.stack.ident_table.get_name(IdentId(*id));
-                    self.env_info().ident_table.get_ident_id(&name);
-                    let val = Value::String(name);
-                    self.exec_stack().push(val);
-                }
-                Inst::ADD => {
-                    self.plus_stack_pos(1);
+                Inst::ADD
+                | Inst::SUB
+                | Inst::MUL
+                | Inst::DIV
+                | Inst::REM
+                | Inst::POW
+                | Inst::EQ
+                | Inst::NE
+                | Inst::TEQ
+                | Inst::CMP
+                | Inst::GT
+                | Inst::GE
+                | Inst::LT
+                | Inst::LE
+                | Inst::BIT_AND
+                | Inst::BIT_OR
+                | Inst::BIT_XOR
+                | Inst::SHL
+                | Inst::SHR => {
                     let rhs = self.pop_value();
                     let lhs = self.pop_value();
-                    let val = self.eval_add(lhs, rhs)?;
+                    let val = self.eval_binop(inst, lhs, rhs)?;
                     self.exec_stack().push(val);
                 }
-                Inst::SUB => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_sub(lhs, rhs)?;
+                Inst::NOT | Inst::BIT_NOT | Inst::POS | Inst::NEG => {
+                    let operand = self.pop_value();
+                    let val = self.eval_unop(inst, operand)?;
                     self.exec_stack().push(val);
                 }
-                Inst::MUL => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_mul(lhs, rhs)?;
+                Inst::SEND => {
+                    let id = self.read_ident();
+                    let args_len = self.read_operand();
+                    let iseq = match self.read_operand() {
+                        0 => None,
+                        iseq => Some(ISeqRef(iseq)),
+                    };
+                    let block = iseq.map(|iseq| Block::Captured(self.capture_block(iseq)));
+                    let args = self.pop_values(args_len);
+                    let receiver = self.pop_value();
+                    let val = self.send_with_block(receiver, id, Args { block, args }, iseq)?;
                     self.exec_stack().push(val);
                 }
-                Inst::DIV => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_div(lhs, rhs)?;
+                Inst::SEND_SPLAT => {
+                    let id = self.read_ident();
+                    let iseq = match self.read_operand() {
+                        0 => None,
+                        iseq => Some(ISeqRef(iseq)),
+                    };
+                    let block = match self.read_operand() {
+                        0 => iseq.map(|iseq| Block::Captured(self.capture_block(iseq))),
+                        _ => self.pop_block_arg()?,
+                    };
+                    let args = self.pop_args_array();
+                    let receiver = self.pop_value();
+                    let val = self.send_with_block(receiver, id, Args { block, args }, iseq)?;
                     self.exec_stack().push(val);
                 }
-                Inst::EQ => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_eq(lhs, rhs)?;
-                    self.exec_stack().push(val);
+                Inst::SPLAT => {
+                    let val = self.pop_value();
+                    let vals = self.splat_values(val)?;
+                    self.exec_stack().push(Value::Array(vals));
                 }
-                Inst::NE => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_neq(lhs, rhs)?;
-                    self.exec_stack().push(val);
+                Inst::CONCAT_ARRAYS => {
+                    let len = self.read_operand();
+                    let mut vals = vec![];
+                    for arr in self.pop_values(len) {
+                        match arr {
+                            Value::Array(arr) => vals.extend(arr),
+                            val => vals.push(val),
+                        }
+                    }
+                    self.exec_stack().push(Value::Array(vals));
                 }
-                Inst::GT => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_gt(lhs, rhs)?;
-                    self.exec_stack().push(val);
+                Inst::ARRAY => {
+                    let len = self.read_operand();
+                    let arr = self.pop_values(len);
+                    self.exec_stack().push(Value::Array(arr));
                 }
-                Inst::GE => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_ge(lhs, rhs)?;
+                Inst::INDEX => {
+                    let len = self.read_operand();
+                    let args = self.pop_values(len);
+                    let receiver = self.pop_value();
+                    let val = self.eval_index(receiver, args)?;
                     self.exec_stack().push(val);
                 }
-                Inst::LT => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_lt(lhs, rhs)?;
+                Inst::INDEX_ASSIGN => {
+                    let len = self.read_operand();
+                    let val = self.pop_value();
+                    let args = self.pop_values(len);
+                    let receiver = self.pop_value();
+                    let receiver = self.eval_index_assign(receiver, args, val.clone())?;
                     self.exec_stack().push(val);
+                    self.exec_stack().push(receiver);
                 }
-                Inst::LE => {
-                    self.plus_stack_pos(1);
-                    let rhs = self.pop_value();
-                    let lhs = self.pop_value();
-                    let val = self.eval_le(lhs, rhs)?;
+                Inst::ATTR_ASSIGN => {
+                    let id = self.read_ident();
+                    let val = self.pop_value();
+                    let receiver = self.pop_value();
+                    let args = Args {
+                        block: None,
+                        args: vec![val.clone()],
+                    };
+                    self.send(receiver, id, args)?;
                     self.exec_stack().push(val);
                 }
-                Inst::SEND => {
-                    self.plus_stack_pos(1);
-                    self.save_eval_info();
-                    let args = self.get_array();
-                    let body = self.get_body();
-                    let table = self.pop_value().value() as u8;
-                    let id = self.pop_value().ident();
-                    let info = self.get_method_info(id);
-                    let receiver = self.pop_value();
-                    let f = self.push_env(receiver.clone());
-                    self.push_env(receiver.clone());
-
-                    match info {
-                        MethodInfo::RubyFunc {
-                            params,
-                            ptr,
-                            local_scope,
-                        } => {
-                            let args_len = args.len();
-                            self.scope_stack.push(local_scope);
-                            for (i, param) in params.iter().enumerate() {
-                                let arg = if args_len > i {
-                                    args[i].clone()
-                                } else {
-                                    Value::Nil
-                                };
-                                self.lvar_table_as_mut().insert(*param, arg);
-                            }
-                            self.save_exec_stack();
-                            self.eval_body_with_args_ptr(ptr);
-                            self.push_last_eval_stack();
-                            self.pop_env_if_true(f);
-                            self.scope_stack.pop();
-                        }
-                        MethodInfo::BuiltinFunc { func, .. } => {
-                            let args = Args { body, args, table };
-                            self.pop_env_if_true(f);
-                            let val = func(self, receiver, args);
-                            self.exec_stack().push(val);
-                        }
-                    }
-                    self.return_stack();
+                Inst::GLOBAL_VAR => {
+                    let id = self.read_ident();
+                    let val = self.gvar_table().get(&id).cloned();
+                    self.exec_stack().push(val.unwrap_or(Value::Nil));
                 }
-                Inst::ARRAY => {
-                    self.plus_stack_pos(1);
-                    let arr = self.get_array();
-                    self.exec_stack().push(Value::Array(arr));
+                Inst::GLOBAL_VAR_ASSIGN => {
+                    let id = self.read_ident();
+                    let rhs = self.copy_exec_stack();
+                    self.gvar_table().insert(id, rhs);
                 }
-                Inst::ARRAY_INDEX => {
-                    self.plus_stack_pos(1);
-                    let idx = self.pop_value().value() as usize;
-                    match self.pop_value() {
-                        Value::Array(arr) => {
-                            let val = arr[idx].clone();
-                            self.exec_stack().push(val);
-                        }
-                        _ => unreachable!(),
+                Inst::IDENT_OR_NIL => {
+                    let id = self.read_ident();
+                    let val = self.get_lvar(id);
+                    self.exec_stack().push(val.unwrap_or(Value::Nil));
+                }
+                Inst::SYMBOL => {
+                    let id = self.read_ident();
+                    self.exec_stack().push(Value::Symbol(id));
+                }
+                Inst::HASH => {
+                    let len = self.read_operand();
+                    let vals = self.pop_values(len * 2);
+                    let mut info = HashInfo::new();
+                    for pair in vals.chunks(2) {
+                        info.insert(self.hash_table.key(&pair[0]), pair[1].clone());
                     }
+                    let hash = self.hash_table.new_hash(info);
+                    self.exec_stack().push(Value::Hash(hash));
                 }
-                Inst::IF => {
-                    self.plus_stack_pos(1);
-                    let val = if self.eval_body().bool() {
-                        let val = self.eval_body();
-                        self.pop_value();
-                        val
-                    } else {
-                        self.pop_value();
-                        self.eval_body()
-                    };
+                Inst::INTERPOLATE => {
+                    let len = self.read_operand();
+                    let parts = self.pop_values(len);
+                    let mut s = String::new();
+                    for part in parts {
+                        s += &self.interpolated_to_s(part)?;
+                    }
+                    self.exec_stack().push(Value::String(s));
+                }
+                Inst::POP => {
+                    self.pop_value();
+                }
+                Inst::DUP => {
+                    let val = self.copy_exec_stack();
+                    self.exec_stack().push(val);
+                }
+                Inst::SWAP => {
+                    let len = self.exec_stack().len();
+                    self.exec_stack().swap(len - 1, len - 2);
+                }
+                Inst::DUPN => {
+                    let count = self.read_operand();
+                    let len = self.exec_stack().len();
+                    let top = self.exec_stack()[len - count..].to_vec();
+                    self.exec_stack().extend(top);
+                }
+                Inst::SETN => {
+                    let depth = self.read_operand();
+                    let val = self.copy_exec_stack();
+                    let len = self.exec_stack().len();
+                    self.exec_stack()[len - 1 - depth] = val;
+                }
+                Inst::TOPN => {
+                    let depth = self.read_operand();
+                    let len = self.exec_stack().len();
+                    let val = self.exec_stack()[len - 1 - depth].clone();
                     self.exec_stack().push(val);
                 }
-                Inst::INIT_FUNC => {
-                    self.plus_stack_pos(1);
-                    let params = self.get_params();
-                    let id = self.pop_value().ident();
-                    let mut local_scope = LocalScope {
-                        lvar_table: FxHashMap::default(),
-                        propagated_table: FxHashMap::default(),
+                Inst::EXPAND_ARRAY => {
+                    let pre = self.read_operand();
+                    let splat = self.read_operand() != 0;
+                    let post = self.read_operand();
+                    let values = match self.pop_value() {
+                        Value::Array(values) => values,
+                        val => vec![val],
                     };
-
-                    self.new_propagated_local_var_stack();
-                    let last_scope_stack = self.scope_stack.last_mut().unwrap().to_owned();
-                    local_scope.lvar_table = last_scope_stack.lvar_table;
-                    local_scope.propagated_table = last_scope_stack.propagated_table;
-
-                    for n in &params {
-                        self.lvar_table_as_mut()
-                            .insert(IdentId(**n), Value::FixNum(0));
-                        local_scope.lvar_table.insert(*n, Value::FixNum(0));
+                    let targets = expand_array(values, pre, splat, post);
+                    self.exec_stack().extend(targets.into_iter().rev());
+                }
+                Inst::JUMP => {
+                    let dest = self.read_operand();
+                    self.set_stack_pos(dest);
+                }
+                Inst::JUMP_IF_FALSE => {
+                    let dest = self.read_operand();
+                    if !self.pop_value().is_truthy() {
+                        self.set_stack_pos(dest);
+                    }
+                }
+                Inst::JUMP_IF_BOUND => {
+                    let id = self.read_ident();
+                    let dest = self.read_operand();
+                    let scope = self.current_scope();
+                    if !self.scope_table.get(scope).unbound.contains(&id) {
+                        self.set_stack_pos(dest);
+                    }
+                }
+                Inst::JUMP_IF_TRUE => {
+                    let dest = self.read_operand();
+                    if self.pop_value().is_truthy() {
+                        self.set_stack_pos(dest);
+                    }
+                }
+                Inst::FUNC_DECL => {
+                    let id = self.read_ident();
+                    let iseq = ISeqRef(self.read_operand());
+                    let params = self.stack.iseqs[*iseq].params.clone();
+                    let mut local_scope = LocalScope::new();
+                    local_scope.lvar_table = self.scope_table.visible_lvars(self.current_scope());
+                    for param in &params.names() {
+                        local_scope.lvar_table.insert(*param, Value::Nil);
                     }
                     self.env_info().method_table.insert(
                         id,
                         MethodInfo::RubyFunc {
-                            params,
-                            ptr: 0,
+                            params: Box::new(params),
+                            iseq,
                             local_scope,
                         },
                     );
+                    self.exec_stack().push(Value::Nil);
                 }
-                Inst::FUNC_DECL => {
-                    self.plus_stack_pos(1);
-                    self.save_eval_info();
-                    let ptr_ = self.copy_ptr();
-                    self.get_body();
-                    self.return_stack();
-                    let id = self.pop_value().ident();
-
-                    match self.env_info().method_table.get_mut(&id).unwrap() {
-                        MethodInfo::RubyFunc { ptr, .. } => {
-                            *ptr = ptr_;
+                Inst::CLASS_DECL => {
+                    let id = self.read_ident();
+                    let iseq = ISeqRef(self.read_operand());
+                    let inheritence_class = self.pop_value();
+                    let class_ref = match self.const_table.get(&id) {
+                        Some(Value::Class(class_ref)) => *class_ref,
+                        _ => {
+                            let class_ref = self.new_class_info(id, *iseq);
+                            self.const_table.insert(id, Value::Class(class_ref));
+                            class_ref
+                        }
+                    };
+                    if let Value::Class(inheritence_class_ref) = inheritence_class {
+                        if self.is_subclass_of(inheritence_class_ref, class_ref) {
+                            return Err(self.error_type(format!(
+                                "superclass mismatch for class {}",
+                                self.ident_table.get_name(id)
+                            )));
                         }
-                        _ => unreachable!(),
+                        self.add_subclass(class_ref, inheritence_class_ref);
                     }
-                    self.scope_stack.pop();
-                }
-                Inst::CLASS_DECL => {
-                    self.plus_stack_pos(1);
-                    let inheritence_class_id = self.pop_value().option_ident();
-                    self.save_eval_info();
-                    let ptr = self.copy_ptr();
-                    self.get_body();
-                    self.return_stack();
-                    let id = self.pop_value().ident();
-                    let mut info = self.new_class_info(id, ptr);
-                    let class_ref = self.class_ref_with_id(id);
                     self.env.push(Env::ClassRef(class_ref));
-                    let val = info.to_val();
-                    self.add_subclass(info, inheritence_class_id);
-                    self.const_table.insert(id, val);
-                    self.new_propagated_local_var_stack();
-                    self.eval_body_with_args_ptr(ptr);
+                    let mut local_scope = LocalScope::new();
+                    local_scope.lvar_table = self.scope_table.visible_lvars(self.current_scope());
+                    let label = format!("<class:{}>", self.ident_table.get_name(id));
+                    let val = self.eval_iseq_in_scope(iseq, label, local_scope);
                     self.env.pop().unwrap();
-                    self.scope_stack.pop();
+                    self.exec_stack().push(val?);
                 }
                 Inst::IDENT => {
-                    let mut id = self.push_fixnum();
-                    match self.lvar_table_as_mut().clone().get(&id.ident()) {
-                        Some(val) => self.exec_stack().push(val.to_owned()),
+                    let id = self.read_ident();
+                    match self.get_lvar(id) {
+                        Some(val) => self.exec_stack().push(val),
+                        // An identifier that is not a local variable calls
+                        // a method without arguments.
+                        None if self.responds_to_self(id) => {
+                            let receiver = self.self_value();
+                            let val = self.send(receiver, id, Args::new())?;
+                            self.exec_stack().push(val);
+                        }
                         None => {
-                            println!("self.lvar_table_as_mut(): {:?}", self.lvar_table_as_mut());
-                            println!("self.ident_table: {:?}", self.ident_table);
-                            println!("id: {:?}", id);
-                            panic!("undefined local variable.")
+                            let name = self.ident_table.get_name(id);
+                            return Err(self.error_name(format!(
+                                "undefined local variable or method `{}'",
+                                name
+                            )));
                         }
                     }
                 }
                 Inst::CONST => {
-                    let id = self.push_fixnum().ident();
-                    match self.const_table.clone().get(&id) {
-                        Some(val) => self.exec_stack().push(val.to_owned()),
+                    let id = self.read_ident();
+                    match self.const_table.get(&id) {
+                        Some(val) => {
+                            let val = val.to_owned();
+                            self.exec_stack().push(val)
+                        }
                         None => {
-                            println!("self.lvar_table_as_mut(): {:?}", self.lvar_table_as_mut());
-                            println!("self.ident_table: {:?}", self.ident_table);
-                            println!("id: {:?}", id);
-                            panic!("undefined local variable.")
+                            let name = self.ident_table.get_name(id);
+                            return Err(self.error_name(format!("uninitialized constant {}", name)));
                         }
                     }
                 }
-                Inst::TABLE_IDENT => {
-                    let val = self.push_fixnum();
+                Inst::INSTANCE_VAR => {
+                    let id = self.read_ident();
+                    let val = match self.env() {
+                        Env::ClassRef(r) => self.class_info_with_ref(r).instance_var.get(&id),
+                        Env::InstanceRef(r) => self.instance_ref(r).instance_var.get(&id),
+                    };
+                    let val = val.cloned().unwrap_or(Value::Nil);
                     self.exec_stack().push(val);
                 }
-                Inst::INSTANCE_VAR => {
-                    self.plus_stack_pos(1);
-                    let id = self.pop_value().ident();
-                    match self.env() {
-                        Env::InstanceRef(r) => {
-                            let val = self.instance_value(r, id);
+                Inst::CLASS_VAR | Inst::CLASS_VAR_OR_NIL => {
+                    let id = self.read_ident();
+                    let class_ref = match self.env() {
+                        Env::ClassRef(r) => r,
+                        Env::InstanceRef(r) => self.class_ref_with_instance(r),
+                    };
+                    match self.class_info_with_ref(class_ref).class_var.get(&id) {
+                        Some(val) => {
+                            let val = val.to_owned();
                             self.exec_stack().push(val);
                         }
-                        _ => unimplemented!(),
-                    }
-                }
-                Inst::CLASS_VAR => {
-                    self.plus_stack_pos(1);
-                    let id = self.pop_value().ident();
-                    match self.env() {
-                        Env::ClassRef(r) => {
-                            let val = self.class_value(r, id);
-                            self.exec_stack().push(val);
+                        None if inst == Inst::CLASS_VAR_OR_NIL => {
+                            self.exec_stack().push(Value::Nil)
                         }
-                        Env::InstanceRef(r) => {
-                            let val = self.class_value_with_instance(r, id);
-                            self.exec_stack().push(val);
+                        None => {
+                            let name = self.ident_table.get_name(id);
+                            let class_name = self.class_table.get(class_ref).name.clone();
+                            return Err(self.error_name(format!(
+                                "uninitialized class variable @@{} in {}",
+                                name, class_name
+                            )));
                         }
                     }
                 }
                 Inst::RANGE => {
-                    self.plus_stack_pos(1);
                     let rhs = self.pop_value();
                     let lhs = self.pop_value();
                     let val = Value::Range(Box::new(rhs), Box::new(lhs));
                     self.exec_stack().push(val);
                 }
-                Inst::FOR => {
-                    self.plus_stack_pos(1);
-                    let ptr = self.get_ptr();
-                    let (s, e) = self.pop_value().range();
-                    let id = self.pop_value().ident();
-                    self.save_eval_info();
-                    *self.iseq_pos_mut() = ptr;
-
-                    for i in s..=e {
-                        self.new_propagated_local_var_stack();
-
-                        let imm_value = Value::FixNum(i);
-                        self.lvar_table_as_mut().insert(id, imm_value);
-
-                        self.eval_seq().unwrap_or_else(|err| {
-                            panic!("for: error occured while eval_node. {:?};", err)
-                        });
-                        self.init_eval_stack();
-                        let local_scope = self.local_scope().clone();
-                        self.scope_stack.pop();
-                        for (id, n) in local_scope.lvar_table.into_iter() {
-                            if self.local_scope().lvar_table.contains_key(&id) {
-                                *self.local_scope().lvar_table.get_mut(&id).unwrap() = n;
+                Inst::FOR_NEXT => {
+                    let id = self.read_ident();
+                    let dest = self.read_operand();
+                    let idx = self.pop_value().value();
+                    let next = match self.copy_exec_stack() {
+                        Value::Range(e, s) => match (*s, *e) {
+                            (Value::FixNum(s), Value::FixNum(e)) => {
+                                let i = s + idx;
+                                if i <= e {
+                                    Some(Value::FixNum(i))
+                                } else {
+                                    None
+                                }
                             }
+                            _ => {
+                                return Err(self.error_type(
+                                    "can't iterate from a Range of non-Integer values",
+                                ))
+                            }
+                        },
+                        Value::Array(arr) => arr.get(idx as usize).cloned(),
+                        iter => return Err(self.error_nomethod_for("each", &iter)),
+                    };
+                    match next {
+                        Some(val) => {
+                            self.set_lvar(id, val);
+                            self.exec_stack().push(Value::FixNum(idx + 1));
                         }
+                        None => self.set_stack_pos(dest),
                     }
-                    self.exec_stack().push(Value::Nil);
-                    self.return_stack();
                 }
-                _ => unimplemented!(),
+                Inst::SET_HANDLER => {
+                    let handler = self.read_operand();
+                    let depth = self.exec_stack().len();
+                    let depths = self.stack.handler_depths.last_mut().unwrap();
+                    if depths.len() <= handler {
+                        depths.resize(handler + 1, 0);
+                    }
+                    depths[handler] = depth;
+                }
+                Inst::RETRY => {
+                    let handler = self.read_operand();
+                    let depth = self.handler_depth(handler);
+                    let iseq_pos = self.iseq_pos();
+                    let start = self.stack.iseqs[iseq_pos].handlers[handler].start;
+                    self.truncate_exec_stack(depth);
+                    self.set_stack_pos(start);
+                }
+                Inst::RESCUE_MATCH => {
+                    let class = self.pop_value();
+                    let exception = self.pop_value();
+                    let matched = match class {
+                        Value::Class(class_ref) => match exception {
+                            Value::Instance(r) => {
+                                let exception_class = self.class_ref_with_instance(r);
+                                self.is_subclass_of(exception_class, class_ref)
+                            }
+                            _ => false,
+                        },
+                        _ => {
+                            return Err(
+                                self.error_type("class or module required for rescue clause")
+                            )
+                        }
+                    };
+                    self.exec_stack().push(Value::Bool(matched));
+                }
+                Inst::ENTER_RESCUE => {
+                    let frame = self.stack.exec_stack.len() - 1;
+                    let depth = self.exec_stack().len() - 1;
+                    let exception = self.copy_exec_stack();
+                    self.rescued_exceptions.push((frame, depth, exception));
+                }
+                Inst::LEAVE_RESCUE => {
+                    self.rescued_exceptions.pop().unwrap();
+                    let val = self.pop_value();
+                    self.pop_value();
+                    self.exec_stack().push(val);
+                }
+                Inst::RERAISE => {
+                    let exception = self.pop_value();
+                    if exception == Value::Nil {
+                        return Err(self.pending_signals.pop().unwrap());
+                    }
+                    return Err(self.error_exception(exception));
+                }
+                Inst::CASE_EQ => {
+                    let pattern = self.pop_value();
+                    let subject = self.copy_exec_stack();
+                    let matched = self.eval_case_eq(pattern, subject)?;
+                    self.exec_stack().push(Value::Bool(matched));
+                }
+                Inst::CASE_DISPATCH => {
+                    let table = self.read_operand();
+                    let else_dest = self.read_operand();
+                    if let Value::FixNum(num) = self.copy_exec_stack() {
+                        let iseq_pos = self.iseq_pos();
+                        let dest = self.stack.iseqs[iseq_pos].case_tables[table]
+                            .get(&num)
+                            .copied();
+                        self.set_stack_pos(dest.unwrap_or(else_dest));
+                    }
+                }
+                Inst::NO_MATCHING_PATTERN => {
+                    let subject = self.pop_value();
+                    let msg = self.val_to_s(&subject);
+                    return Err(self.error(RuntimeErrorKind::NoMatchingPattern(msg)));
+                }
+                Inst::UNWIND => {
+                    let handler = self.read_operand();
+                    let depth = self.handler_depth(handler);
+                    self.truncate_exec_stack(depth);
+                }
+                Inst::SEND_BLOCK_ARG => {
+                    let id = self.read_ident();
+                    let args_len = self.read_operand();
+                    let block = self.pop_block_arg()?;
+                    let args = self.pop_values(args_len);
+                    let receiver = self.pop_value();
+                    let val = self.send(receiver, id, Args { block, args })?;
+                    self.exec_stack().push(val);
+                }
+                Inst::LAMBDA => {
+                    let iseq = ISeqRef(self.read_operand());
+                    let block = self.capture_block(iseq);
+                    let val = self.new_block_proc(block, true);
+                    self.exec_stack().push(val);
+                }
+                Inst::YIELD => {
+                    let len = self.read_operand();
+                    let args = self.pop_values(len);
+                    let val = self.yield_block(args)?;
+                    self.exec_stack().push(val);
+                }
+                Inst::YIELD_SPLAT => {
+                    let args = self.pop_args_array();
+                    let val = self.yield_block(args)?;
+                    self.exec_stack().push(val);
+                }
+                Inst::BREAK => {
+                    let val = self.pop_value();
+                    let iseq = ISeqRef(self.iseq_pos());
+                    return Err(self.error(RuntimeErrorKind::Break(iseq, val)));
+                }
+                Inst::RETURN => {
+                    let val = self.pop_value();
+                    let target = self.scope_table.return_target(self.current_scope());
+                    if !self.scope_stack.contains(&target) {
+                        let msg = "unexpected return".to_string();
+                        return Err(self.error(RuntimeErrorKind::LocalJump(msg)));
+                    }
+                    return Err(self.error(RuntimeErrorKind::Return(target, val)));
+                }
+                _ => {
+                    return Err(self.error_unreachable(format!("unknown instruction {}", inst)));
+                }
             }
         }
     }
 
-    pub fn eval(&mut self) -> EvalResult {
-        self.eval_seq()?;
-        Ok(self.pop_value())
+    fn send(&mut self, receiver: Value, id: IdentId, args: Args) -> EvalResult {
+        let block = args.block.clone();
+        let f = self.push_env(receiver.clone());
+        let (owner, info) = match self.get_method_info(id) {
+            Some(found) => found,
+            None => {
+                self.pop_env_if_true(f);
+                let name = self.ident_table.get_name(id);
+                if let Some(inst) = Inst::from_operator_name(&name) {
+                    return self.eval_operator_method(inst, receiver, args);
+                }
+                return Err(self.error_nomethod_for(&name, &receiver));
+            }
+        };
+        let label = match info {
+            MethodInfo::RubyFunc { .. } if self.class_stack.contains(&owner) => {
+                self.ident_table.get_name(id)
+            }
+            _ => self.method_label(&receiver, id),
+        };
+        match info {
+            MethodInfo::RubyFunc {
+                params,
+                iseq,
+                local_scope,
+            } => {
+                let bindings = match self.bind_params(&params, args) {
+                    Ok(bindings) => bindings,
+                    Err(err) => {
+                        self.pop_env_if_true(f);
+                        return Err(err);
+                    }
+                };
+                let mut local_scope = local_scope;
+                local_scope.bind(bindings);
+                self.block_stack.push(block);
+                let val = self.eval_iseq_in_scope(iseq, label, local_scope);
+                self.block_stack.pop().unwrap();
+                self.pop_env_if_true(f);
+                val
+            }
+            MethodInfo::BuiltinFunc { func, .. } => {
+                self.pop_env_if_true(f);
+                self.stack.call_frames.push(CallFrame {
+                    label,
+                    exec_frame: None,
+                });
+                let val = func(self, receiver, args);
+                self.stack.call_frames.pop().unwrap();
+                val
+            }
+        }
     }
 
-    fn class_info_with_ref(&mut self, class_ref: ClassRef) -> &mut ClassInfo {
-        self.class_table.get_mut(class_ref)
-    }
+    /// Matches `args` to the parameters of a method. A parameter bound to
+    /// None was left out and gets its default in the method body. A trailing
+    /// Hash is taken as keyword arguments when the method has keyword
+    /// parameters, the other arguments still cover the required parameters,
+    /// and its keys are Symbols or the method has a `**` parameter.
+    fn bind_params(
+        &mut self,
+        params: &Params,
+        args: Args,
+    ) -> Result<Vec<(IdentId, Option<Value>)>, RuntimeError> {
+        let mut args = args;
+        let required = params.required.len() + params.post.len();
+        let kwargs = match args.args.last() {
+            Some(Value::Hash(hash))
+                if params.takes_keywords()
+                    && args.args.len() > required
+                    && (params.keyword_rest.is_some()
+                        || self
+                            .hash_table
+                            .get(*hash)
+                            .keys()
+                            .iter()
+                            .all(|key| matches!(key, Value::Symbol(_)))) =>
+            {
+                let hash = *hash;
+                args.args.pop();
+                Some(self.hash_table.get(hash).clone())
+            }
+            _ => None,
+        };
+        let mut positional = args.args;
+        let max = required + params.optional.len();
+        let given = positional.len();
+        if given < required || params.rest.is_none() && given > max {
+            let expected = if params.rest.is_some() {
+                format!("{}+", required)
+            } else if max > required {
+                format!("{}..{}", required, max)
+            } else {
+                required.to_string()
+            };
+            return Err(self.error_argument(format!(
+                "wrong number of arguments (given {}, expected {})",
+                given, expected
+            )));
+        }
+
+        let mut bindings = vec![];
+        let post = positional.split_off(given - params.post.len());
+        let mut positional = positional.into_iter();
+        for param in &params.required {
+            bindings.push((*param, positional.next()));
+        }
+        for param in &params.optional {
+            bindings.push((*param, positional.next()));
+        }
+        if let Some(rest) = params.rest {
+            bindings.push((rest, Some(Value::Array(positional.collect()))));
+        }
+        bindings.extend(params.post.iter().copied().zip(post.into_iter().map(Some)));
 
-    fn class_info_with_id(&mut self, ident_id: IdentId) -> ClassInfo {
-        for t in self.class_table.table.to_owned() {
-            if t.1.id == ident_id {
-                return t.1;
+        let mut kwargs = kwargs.unwrap_or_default();
+        let mut missing = vec![];
+        for (param, required) in &params.keyword {
+            let arg = kwargs.remove(&self.hash_table.key(&Value::Symbol(*param)));
+            if arg.is_none() && *required {
+                missing.push(Value::Symbol(*param));
+            }
+            bindings.push((*param, arg));
+        }
+        if !missing.is_empty() {
+            return Err(self.error_keywords("missing", &missing));
+        }
+        match params.keyword_rest {
+            Some(keyword_rest) => {
+                let hash = self.hash_table.new_hash(kwargs);
+                bindings.push((keyword_rest, Some(Value::Hash(hash))));
+            }
+            None if !kwargs.is_empty() => {
+                return Err(self.error_keywords("unknown", &kwargs.keys()));
             }
+            None => {}
+        }
+        if let Some(param) = params.block {
+            let arg = match args.block {
+                Some(Block::Captured(block)) => self.new_block_proc(block, false),
+                Some(Block::Proc(proc_ref)) => Value::Proc(proc_ref),
+                None => Value::Nil,
+            };
+            bindings.push((param, Some(arg)));
         }
-        unimplemented!()
+        Ok(bindings)
     }
 
-    fn class_ref_with_id(&mut self, ident_id: IdentId) -> ClassRef {
-        for t in self.class_table.table.to_owned() {
-            if t.1.id == ident_id {
-                return t.0;
-            }
+    /// ArgumentError such as `missing keywords: :a, :b`.
+    fn error_keywords(&mut self, problem: &str, keywords: &[Value]) -> RuntimeError {
+        let names = keywords
+            .iter()
+            .map(|key| self.val_inspect(key))
+            .collect::<Vec<String>>();
+        let plural = if names.len() == 1 { "" } else { "s" };
+        self.error_argument(format!(
+            "{} keyword{}: {}",
+            problem,
+            plural,
+            names.join(", ")
+        ))
+    }
+
+    /// Whether `self` has a method `id`.
+    fn responds_to_self(&mut self, id: IdentId) -> bool {
+        let receiver = self.self_value();
+        let f = self.push_env(receiver);
+        let found = self.get_method_info(id).is_some();
+        self.pop_env_if_true(f);
+        found
+    }
+
+    /// `puts` for methods called on main, `Foo.bar` on a class and
+    /// `Foo#bar` on an instance.
+    fn method_label(&self, receiver: &Value, id: IdentId) -> String {
+        let name = self.ident_table.get_name(id);
+        match receiver {
+            Value::Class(r) if self.class_stack.contains(r) => name,
+            Value::Class(r) => format!("{}.{}", self.class_table.get(*r).name, name),
+            _ => format!("{}#{}", self.val_class_name(receiver), name),
+        }
+    }
+
+    pub fn eval(&mut self) -> EvalResult {
+        if let Some(err) = self.compile_error.take() {
+            return Err(err);
         }
-        unimplemented!()
+        self.eval_seq()?;
+        Ok(self.pop_value())
+    }
+
+    fn class_info_with_ref(&mut self, class_ref: ClassRef) -> &mut ClassInfo {
+        self.class_table.get_mut(class_ref)
     }
 
     fn class_info_with_instance(&mut self, instance_ref: InstanceRef) -> &mut ClassInfo {
@@ -1300,64 +3443,48 @@ impl VM {
         self.instance_table.get_mut(instance_ref)
     }
 
-    fn instance_value(&mut self, instance_ref: InstanceRef, id: IdentId) -> Value {
-        self.instance_table
-            .get_mut(instance_ref)
-            .instance_var
-            .get(&id)
-            .unwrap()
-            .to_owned()
-    }
-
-    fn class_value(&mut self, class_ref: ClassRef, id: IdentId) -> Value {
-        self.class_info_with_ref(class_ref)
-            .class_var
-            .get_mut(&id)
-            .unwrap()
-            .to_owned()
-    }
-
-    fn class_value_with_instance(&mut self, instance_ref: InstanceRef, id: IdentId) -> Value {
-        self.class_info_with_instance(instance_ref)
-            .class_var
-            .get_mut(&id)
-            .unwrap()
-            .to_owned()
-    }
-
-    fn add_subclass(&mut self, info: ClassRef, inheritence_class_id: Option<IdentId>) {
-        if let Some(inheritence_class_id) = inheritence_class_id {
-            let inheritence_class_ref = self.class_ref_with_id(inheritence_class_id);
-            let class = self.class_info_with_ref(info);
-            class
-                .subclass
-                .insert(inheritence_class_id, inheritence_class_ref);
-        }
+    fn add_subclass(&mut self, info: ClassRef, inheritence_class_ref: ClassRef) {
+        let inheritence_class_id = self.class_info_with_ref(inheritence_class_ref).id;
+        let class = self.class_info_with_ref(info);
+        class
+            .subclass
+            .insert(inheritence_class_id, inheritence_class_ref);
     }
 
-    fn get_method_info(&mut self, id: IdentId) -> MethodInfo {
-        for env in self.env.clone().iter_mut().rev() {
-            let r = match env {
-                Env::ClassRef(ClassRef(r)) => *self.class_info_with_ref(ClassRef(*r)).id,
-                Env::InstanceRef(InstanceRef(r)) => {
-                    *self.class_info_with_instance(InstanceRef(*r)).id
-                }
+    /// Finds method `id` along the env stack, with the class defining it.
+    fn get_method_info(&mut self, id: IdentId) -> Option<(ClassRef, MethodInfo)> {
+        for env in self.env.clone().iter().rev() {
+            let class_ref = match env {
+                Env::ClassRef(r) => *r,
+                Env::InstanceRef(r) => self.class_ref_with_instance(*r),
             };
-            let class_ref = self.class_info_with_id(IdentId(r)).clone();
-            // println!("id: {:?}", id);
-            // println!("class_ref.method_table: {:?}", class_ref.method_table);
-            match class_ref.method_table.get(&id) {
-                Some(info) => return info.to_owned(),
-                None => {
-                    for r in class_ref.subclass.values() {
-                        if let Some(info) = self.class_info_with_ref(*r).method_table.get(&id) {
-                            return info.to_owned();
-                        }
-                    }
-                }
+            if let Some(info) = self.find_method(class_ref, id) {
+                return Some(info);
             }
         }
-        unimplemented!("undefined function.");
+        None
+    }
+
+    /// Looks `id` up in `class_ref` and then in its superclasses.
+    fn find_method(&self, class_ref: ClassRef, id: IdentId) -> Option<(ClassRef, MethodInfo)> {
+        let class_info = self.class_table.get(class_ref);
+        match class_info.method_table.get(&id) {
+            Some(info) => Some((class_ref, info.to_owned())),
+            None => class_info
+                .subclass
+                .values()
+                .find_map(|r| self.find_method(*r, id)),
+        }
+    }
+
+    fn is_subclass_of(&self, class_ref: ClassRef, superclass_ref: ClassRef) -> bool {
+        class_ref == superclass_ref
+            || self
+                .class_table
+                .get(class_ref)
+                .subclass
+                .values()
+                .any(|r| self.is_subclass_of(*r, superclass_ref))
     }
 
     fn push_env(&mut self, val: Value) -> bool {
@@ -1380,9 +3507,106 @@ impl VM {
         }
     }
 
+    /// Runs a binary operator. An instance's own method for the operator,
+    /// such as `def +(other)`, takes precedence.
+    fn eval_binop(&mut self, inst: u8, lhs: Value, rhs: Value) -> EvalResult {
+        if let Some(val) = self.send_operator(inst, &lhs, vec![rhs.clone()])? {
+            return Ok(val);
+        }
+        match inst {
+            Inst::ADD => self.eval_add(lhs, rhs),
+            Inst::SUB => self.eval_sub(lhs, rhs),
+            Inst::MUL => self.eval_mul(lhs, rhs),
+            Inst::DIV => self.eval_div(lhs, rhs),
+            Inst::REM => self.eval_rem(lhs, rhs),
+            Inst::POW => self.eval_pow(lhs, rhs),
+            Inst::EQ => self.eval_eq(lhs, rhs),
+            // `!=` is the negation of `==`, which may be user-defined.
+            Inst::NE => {
+                let eq = self.eval_binop(Inst::EQ, lhs, rhs)?;
+                Ok(Value::Bool(!eq.is_truthy()))
+            }
+            Inst::TEQ => Ok(Value::Bool(self.eval_case_eq(lhs, rhs)?)),
+            Inst::CMP => self.eval_cmp(lhs, rhs),
+            Inst::GT => self.eval_gt(lhs, rhs),
+            Inst::GE => self.eval_ge(lhs, rhs),
+            Inst::LT => self.eval_lt(lhs, rhs),
+            Inst::LE => self.eval_le(lhs, rhs),
+            Inst::BIT_AND | Inst::BIT_OR | Inst::BIT_XOR => self.eval_bitwise(inst, lhs, rhs),
+            Inst::SHL | Inst::SHR => self.eval_shift(inst, lhs, rhs),
+            _ => unreachable!(),
+        }
+    }
+
+    fn eval_unop(&mut self, inst: u8, operand: Value) -> EvalResult {
+        if let Some(val) = self.send_operator(inst, &operand, vec![])? {
+            return Ok(val);
+        }
+        match (inst, operand) {
+            (Inst::NOT, operand) => Ok(Value::Bool(!operand.is_truthy())),
+            (Inst::BIT_NOT, Value::FixNum(i)) => Ok(Value::FixNum(!i)),
+            (Inst::BIT_NOT, Value::BigNum(i)) => Ok(Value::integer(!i)),
+            (
+                Inst::POS,
+                operand @ (Value::FixNum(_) | Value::BigNum(_) | Value::FixDecimalNum(_)),
+            ) => Ok(operand),
+            (Inst::NEG, Value::FixNum(i)) => match i.checked_neg() {
+                Some(i) => Ok(Value::FixNum(i)),
+                None => Ok(Value::integer(-BigInt::from(i))),
+            },
+            (Inst::NEG, Value::BigNum(i)) => Ok(Value::integer(-i)),
+            (Inst::NEG, Value::FixDecimalNum(f)) => Ok(Value::FixDecimalNum(-f)),
+            (inst, operand) => {
+                let name = Inst::operator_name(inst).unwrap();
+                Err(self.error_nomethod_for(name, &operand))
+            }
+        }
+    }
+
+    /// Calls the method an instance defines for operator `inst`, if any.
+    fn send_operator(
+        &mut self,
+        inst: u8,
+        receiver: &Value,
+        args: Vec<Value>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let class_ref = match receiver {
+            Value::Instance(r) => self.class_ref_with_instance(*r),
+            _ => return Ok(None),
+        };
+        let id = self
+            .ident_table
+            .get_ident_id(Inst::operator_name(inst).unwrap());
+        if self.find_method(class_ref, id).is_none() {
+            return Ok(None);
+        }
+        let args = Args { block: None, args };
+        self.send(receiver.clone(), id, args).map(Some)
+    }
+
+    /// An operator called by name, as in `1.send(:+, 2)`, on a receiver
+    /// without a method of that name.
+    fn eval_operator_method(&mut self, inst: u8, receiver: Value, args: Args) -> EvalResult {
+        if Inst::is_unary(inst) {
+            self.expect_arg_count(&args, 0)?;
+            self.eval_unop(inst, receiver)
+        } else {
+            self.expect_arg_count(&args, 1)?;
+            let rhs = args.args.into_iter().next().unwrap();
+            self.eval_binop(inst, receiver, rhs)
+        }
+    }
+
     fn eval_add(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
+        if let Some((lhs, rhs)) = bignum_operands(&lhs, &rhs) {
+            return Ok(Value::integer(lhs + rhs));
+        }
         match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::FixNum(lhs + rhs)),
+            (Value::FixNum(lhs), Value::FixNum(rhs)) => match lhs.checked_add(rhs) {
+                Some(i) => Ok(Value::FixNum(i)),
+                None => Ok(Value::integer(BigInt::from(lhs) + rhs)),
+            },
             (Value::FixDecimalNum(lhs), Value::FixNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs + (rhs as f64)))
             }
@@ -1392,13 +3616,21 @@ impl VM {
             (Value::FixDecimalNum(lhs), Value::FixDecimalNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs + rhs))
             }
-            (_, _) => unimplemented!(),
+            (Value::String(lhs), Value::String(rhs)) => Ok(Value::String(lhs + &rhs)),
+            (lhs, rhs) => Err(self.error_binop("+", &lhs, &rhs)),
         }
     }
 
     fn eval_sub(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
+        if let Some((lhs, rhs)) = bignum_operands(&lhs, &rhs) {
+            return Ok(Value::integer(lhs - rhs));
+        }
         match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::FixNum(lhs - rhs)),
+            (Value::FixNum(lhs), Value::FixNum(rhs)) => match lhs.checked_sub(rhs) {
+                Some(i) => Ok(Value::FixNum(i)),
+                None => Ok(Value::integer(BigInt::from(lhs) - rhs)),
+            },
             (Value::FixDecimalNum(lhs), Value::FixNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs - (rhs as f64)))
             }
@@ -1408,13 +3640,20 @@ impl VM {
             (Value::FixDecimalNum(lhs), Value::FixDecimalNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs - rhs))
             }
-            (_, _) => unimplemented!(),
+            (lhs, rhs) => Err(self.error_binop("-", &lhs, &rhs)),
         }
     }
 
     fn eval_mul(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
+        if let Some((lhs, rhs)) = bignum_operands(&lhs, &rhs) {
+            return Ok(Value::integer(lhs * rhs));
+        }
         match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::FixNum(lhs * rhs)),
+            (Value::FixNum(lhs), Value::FixNum(rhs)) => match lhs.checked_mul(rhs) {
+                Some(i) => Ok(Value::FixNum(i)),
+                None => Ok(Value::integer(BigInt::from(lhs) * rhs)),
+            },
             (Value::FixDecimalNum(lhs), Value::FixNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs * (rhs as f64)))
             }
@@ -1424,13 +3663,24 @@ impl VM {
             (Value::FixDecimalNum(lhs), Value::FixDecimalNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs * rhs))
             }
-            (_, _) => unimplemented!(),
+            (lhs, rhs) => Err(self.error_binop("*", &lhs, &rhs)),
         }
     }
 
     fn eval_div(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
+        if let Some((lhs, rhs)) = bignum_operands(&lhs, &rhs) {
+            if rhs.is_zero() {
+                return Err(self.error_zero_division());
+            }
+            return Ok(Value::integer(lhs.div_floor(&rhs)));
+        }
         match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::FixNum(lhs / rhs)),
+            (Value::FixNum(_), Value::FixNum(0)) => Err(self.error_zero_division()),
+            (Value::FixNum(lhs), Value::FixNum(rhs)) => match floor_div(lhs, rhs) {
+                Some(i) => Ok(Value::FixNum(i)),
+                None => Ok(Value::integer(-BigInt::from(lhs))),
+            },
             (Value::FixDecimalNum(lhs), Value::FixNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs / (rhs as f64)))
             }
@@ -1440,51 +3690,578 @@ impl VM {
             (Value::FixDecimalNum(lhs), Value::FixDecimalNum(rhs)) => {
                 Ok(Value::FixDecimalNum(lhs / rhs))
             }
-            (_, _) => unimplemented!(),
+            (lhs, rhs) => Err(self.error_binop("/", &lhs, &rhs)),
         }
     }
 
-    fn eval_eq(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+    /// `%`, whose result takes the sign of the divisor.
+    fn eval_rem(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
+        if let Some((lhs, rhs)) = bignum_operands(&lhs, &rhs) {
+            if rhs.is_zero() {
+                return Err(self.error_zero_division());
+            }
+            return Ok(Value::integer(lhs.mod_floor(&rhs)));
+        }
         match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::Bool(lhs == rhs)),
-            (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(lhs == rhs)),
-            (_, _) => unimplemented!(),
+            (Value::FixNum(_), Value::FixNum(0)) => Err(self.error_zero_division()),
+            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::FixNum(int_mod(lhs, rhs))),
+            (Value::FixDecimalNum(lhs), Value::FixNum(rhs)) => {
+                Ok(Value::FixDecimalNum(float_mod(lhs, rhs as f64)))
+            }
+            (Value::FixNum(lhs), Value::FixDecimalNum(rhs)) => {
+                Ok(Value::FixDecimalNum(float_mod(lhs as f64, rhs)))
+            }
+            (Value::FixDecimalNum(lhs), Value::FixDecimalNum(rhs)) => {
+                Ok(Value::FixDecimalNum(float_mod(lhs, rhs)))
+            }
+            (lhs, rhs) => Err(self.error_binop("%", &lhs, &rhs)),
         }
     }
 
-    fn eval_neq(&mut self, lhs: Value, rhs: Value) -> EvalResult {
-        match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::Bool(lhs != rhs)),
-            (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(lhs != rhs)),
-            (_, _) => unimplemented!("NoMethodError: '!='"),
+    /// `**`. A negative Integer exponent gives a Float rather than a
+    /// Rational.
+    fn eval_pow(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        match (&lhs, &rhs) {
+            (Value::FixNum(_) | Value::BigNum(_), Value::FixNum(exp)) if *exp >= 0 => {
+                let base = match lhs {
+                    Value::FixNum(i) => BigInt::from(i),
+                    Value::BigNum(i) => i,
+                    _ => unreachable!(),
+                };
+                match exp.to_u32() {
+                    Some(exp) => Ok(Value::integer(base.pow(exp))),
+                    None => Err(self.error_argument("exponent is too large")),
+                }
+            }
+            _ => match (lhs.to_f64(), rhs.to_f64()) {
+                (Some(base), Some(exp)) => Ok(Value::FixDecimalNum(base.powf(exp))),
+                _ => Err(self.error_binop("**", &lhs, &rhs)),
+            },
         }
     }
 
-    fn eval_ge(&mut self, lhs: Value, rhs: Value) -> EvalResult {
-        match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::Bool(lhs >= rhs)),
-            (_, _) => unimplemented!("NoMethodError: '>='"),
+    /// `<=>`, which is nil for values that cannot be compared.
+    fn eval_cmp(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        match self.eval_compare("<=>", lhs.clone(), rhs.clone()) {
+            Ok(ord) => Ok(Value::FixNum(ord as i64)),
+            Err(_) if lhs == rhs => Ok(Value::FixNum(0)),
+            Err(_) => Ok(Value::Nil),
         }
     }
 
-    fn eval_gt(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+    /// `&`, `|` and `^` on Integers and booleans, and `&` and `|` as set
+    /// operations on Arrays.
+    fn eval_bitwise(&mut self, inst: u8, lhs: Value, rhs: Value) -> EvalResult {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
+        if let Some((lhs, rhs)) = bignum_operands(&lhs, &rhs) {
+            return Ok(Value::integer(match inst {
+                Inst::BIT_AND => lhs & rhs,
+                Inst::BIT_OR => lhs | rhs,
+                _ => lhs ^ rhs,
+            }));
+        }
         match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::Bool(lhs > rhs)),
-            (_, _) => unimplemented!("NoMethodError: '>'"),
+            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::FixNum(match inst {
+                Inst::BIT_AND => lhs & rhs,
+                Inst::BIT_OR => lhs | rhs,
+                _ => lhs ^ rhs,
+            })),
+            (lhs @ (Value::Nil | Value::Bool(_)), rhs) => {
+                let (lhs, rhs) = (lhs.is_truthy(), rhs.is_truthy());
+                Ok(Value::Bool(match inst {
+                    Inst::BIT_AND => lhs && rhs,
+                    Inst::BIT_OR => lhs || rhs,
+                    _ => lhs != rhs,
+                }))
+            }
+            (Value::Array(lhs), Value::Array(rhs)) if inst != Inst::BIT_XOR => {
+                let mut elems: Vec<Value> = vec![];
+                let candidates = match inst {
+                    Inst::BIT_AND => lhs.iter().filter(|v| rhs.contains(v)).collect::<Vec<_>>(),
+                    _ => lhs.iter().chain(rhs.iter()).collect(),
+                };
+                for val in candidates {
+                    if !elems.contains(val) {
+                        elems.push(val.clone());
+                    }
+                }
+                Ok(Value::Array(elems))
+            }
+            (lhs, rhs) => {
+                let name = Inst::operator_name(inst).unwrap();
+                Err(self.error_binop(name, &lhs, &rhs))
+            }
         }
     }
 
-    fn eval_le(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+    /// `<<` and `>>` on Integers. A negative shift goes the other way.
+    fn eval_shift(&mut self, inst: u8, lhs: Value, rhs: Value) -> EvalResult {
+        let name = Inst::operator_name(inst).unwrap();
+        let lhs = match lhs {
+            Value::FixNum(i) => BigInt::from(i),
+            Value::BigNum(i) => i,
+            lhs => return Err(self.error_binop(name, &lhs, &rhs)),
+        };
+        let shift = match rhs {
+            Value::FixNum(shift) if inst == Inst::SHL => shift,
+            Value::FixNum(shift) => -shift,
+            rhs => return Err(self.error_binop(name, &Value::integer(lhs), &rhs)),
+        };
+        let magnitude = shift.unsigned_abs() as usize;
+        if shift >= 0 {
+            Ok(Value::integer(lhs << magnitude))
+        } else {
+            Ok(Value::integer(lhs >> magnitude))
+        }
+    }
+
+    fn eval_eq(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
         match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::Bool(lhs <= rhs)),
-            (_, _) => unimplemented!("NoMethodError: '<='"),
+            (Value::FixNum(lhs), Value::FixDecimalNum(rhs)) => Ok(Value::Bool(lhs as f64 == rhs)),
+            (Value::FixDecimalNum(lhs), Value::FixNum(rhs)) => Ok(Value::Bool(lhs == rhs as f64)),
+            (Value::Hash(lhs), Value::Hash(rhs)) => {
+                let lhs = self.hash_table.get(lhs);
+                let rhs = self.hash_table.get(rhs);
+                let eq = lhs.len() == rhs.len()
+                    && lhs
+                        .entries()
+                        .iter()
+                        .all(|(key, val)| rhs.get(&self.hash_table.key(key)) == Some(val));
+                Ok(Value::Bool(eq))
+            }
+            (lhs, rhs) => Ok(Value::Bool(lhs == rhs)),
+        }
+    }
+
+    /// `pattern === subject`: class membership for a class, inclusion for
+    /// a range, a user-defined `===` for an instance, and equality otherwise.
+    fn eval_case_eq(&mut self, pattern: Value, subject: Value) -> Result<bool, RuntimeError> {
+        match pattern {
+            Value::Class(class_ref) => {
+                let subject_class = match subject {
+                    Value::Instance(r) => self.class_ref_with_instance(r),
+                    _ => {
+                        let name = self.val_class_name(&subject);
+                        self.builtin_class_ref(&name)
+                    }
+                };
+                Ok(self.is_subclass_of(subject_class, class_ref))
+            }
+            Value::Range(end, start) => {
+                let in_range = |vm: &mut VM, lhs: Value, rhs: Value| {
+                    vm.eval_compare("<=", lhs, rhs)
+                        .map(|ord| ord != std::cmp::Ordering::Greater)
+                        .unwrap_or(false)
+                };
+                Ok(in_range(self, *start, subject.clone()) && in_range(self, subject, *end))
+            }
+            Value::Instance(r) => {
+                let class_ref = self.class_ref_with_instance(r);
+                let id = self.ident_table.get_ident_id("===");
+                if self.find_method(class_ref, id).is_none() {
+                    return Ok(pattern == subject);
+                }
+                let args = Args {
+                    block: None,
+                    args: vec![subject],
+                };
+                Ok(self.send(pattern, id, args)?.is_truthy())
+            }
+            pattern => Ok(self.eval_eq(pattern, subject)?.is_truthy()),
         }
     }
 
+    fn eval_ge(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let ord = self.eval_compare(">=", lhs, rhs)?;
+        Ok(Value::Bool(ord != std::cmp::Ordering::Less))
+    }
+
+    fn eval_gt(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let ord = self.eval_compare(">", lhs, rhs)?;
+        Ok(Value::Bool(ord == std::cmp::Ordering::Greater))
+    }
+
+    fn eval_le(&mut self, lhs: Value, rhs: Value) -> EvalResult {
+        let ord = self.eval_compare("<=", lhs, rhs)?;
+        Ok(Value::Bool(ord != std::cmp::Ordering::Greater))
+    }
+
     fn eval_lt(&mut self, lhs: Value, rhs: Value) -> EvalResult {
-        match (lhs, rhs) {
-            (Value::FixNum(lhs), Value::FixNum(rhs)) => Ok(Value::Bool(lhs < rhs)),
-            (_, _) => unimplemented!("NoMethodError: '<'"),
+        let ord = self.eval_compare("<", lhs, rhs)?;
+        Ok(Value::Bool(ord == std::cmp::Ordering::Less))
+    }
+
+    fn eval_compare(
+        &mut self,
+        op: &str,
+        lhs: Value,
+        rhs: Value,
+    ) -> Result<std::cmp::Ordering, RuntimeError> {
+        let (lhs, rhs) = coerce_bignum(lhs, rhs);
+        if let Some((lhs, rhs)) = bignum_operands(&lhs, &rhs) {
+            return Ok(lhs.cmp(&rhs));
+        }
+        let ord = match (&lhs, &rhs) {
+            (Value::FixNum(l), Value::FixNum(r)) => Some(l.cmp(r)),
+            (Value::FixNum(l), Value::FixDecimalNum(r)) => (*l as f64).partial_cmp(r),
+            (Value::FixDecimalNum(l), Value::FixNum(r)) => l.partial_cmp(&(*r as f64)),
+            (Value::FixDecimalNum(l), Value::FixDecimalNum(r)) => l.partial_cmp(r),
+            (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+            (Value::FixNum(_), _)
+            | (Value::BigNum(_), _)
+            | (Value::FixDecimalNum(_), _)
+            | (Value::String(_), _) => None,
+            _ => return Err(self.error_nomethod_for(op, &lhs)),
+        };
+        match ord {
+            Some(ord) => Ok(ord),
+            None => {
+                let lhs = self.val_class_name(&lhs);
+                let rhs = self.val_class_name(&rhs);
+                Err(self.error_argument(format!("comparison of {} with {} failed", lhs, rhs)))
+            }
+        }
+    }
+
+    /// `receiver[args]`. An Array takes an index or a start and a length.
+    fn eval_index(&mut self, receiver: Value, args: Vec<Value>) -> EvalResult {
+        match &receiver {
+            Value::Proc(proc_ref) => {
+                let args = Args { block: None, args };
+                self.call_proc(*proc_ref, args)
+            }
+            Value::Hash(hash) => {
+                let key = self.expect_index_args(args, 1)?.remove(0);
+                let key = self.hash_table.key(&key);
+                let val = self.hash_table.get(*hash).get(&key).cloned();
+                Ok(val.unwrap_or(Value::Nil))
+            }
+            Value::Array(arr) => match self.expect_array_index(args)? {
+                (i, None) => {
+                    let len = arr.len() as i64;
+                    let pos = if i < 0 { len + i } else { i };
+                    if 0 <= pos && pos < len {
+                        Ok(arr[pos as usize].clone())
+                    } else {
+                        Err(self.error_index(format!(
+                            "index {} outside of array bounds: {}...{}",
+                            i, -len, len
+                        )))
+                    }
+                }
+                (start, Some(count)) => {
+                    let len = arr.len() as i64;
+                    let start = if start < 0 { len + start } else { start };
+                    if start < 0 || start > len || count < 0 {
+                        return Ok(Value::Nil);
+                    }
+                    let end = len.min(start.saturating_add(count));
+                    Ok(Value::Array(arr[start as usize..end as usize].to_vec()))
+                }
+            },
+            Value::Instance(_) => {
+                let id = self.ident_table.get_ident_id("[]");
+                let args = Args { block: None, args };
+                self.send(receiver.clone(), id, args)
+            }
+            _ => Err(self.error_nomethod_for("[]", &receiver)),
+        }
+    }
+
+    /// Stores `val` at `args` and returns the receiver, which is a new value
+    /// for an Array. `array[start, length] = val` replaces that many elements
+    /// with the elements of `val`, or with `val` itself when it is not an
+    /// Array.
+    fn eval_index_assign(&mut self, receiver: Value, args: Vec<Value>, val: Value) -> EvalResult {
+        match receiver {
+            Value::Hash(hash) => {
+                let key = self.expect_index_args(args, 1)?.remove(0);
+                let key = self.hash_table.key(&key);
+                self.hash_table.get_mut(hash).insert(key, val);
+                Ok(receiver)
+            }
+            Value::Array(mut arr) => {
+                let (i, count) = self.expect_array_index(args)?;
+                let len = arr.len() as i64;
+                let pos = if i < 0 { len + i } else { i };
+                if pos < 0 {
+                    return Err(self.error_index(format!(
+                        "index {} too small for array; minimum: -{}",
+                        i, len
+                    )));
+                }
+                let pos = pos as usize;
+                match count {
+                    None => {
+                        if pos >= arr.len() {
+                            arr.resize(pos + 1, Value::Nil);
+                        }
+                        arr[pos] = val;
+                    }
+                    Some(count) if count < 0 => {
+                        return Err(self.error_index(format!("negative length ({})", count)));
+                    }
+                    Some(count) => {
+                        if pos > arr.len() {
+                            arr.resize(pos, Value::Nil);
+                        }
+                        let end = arr.len().min(pos.saturating_add(count as usize));
+                        let vals = match val {
+                            Value::Array(vals) => vals,
+                            val => vec![val],
+                        };
+                        arr.splice(pos..end, vals);
+                    }
+                }
+                Ok(Value::Array(arr))
+            }
+            Value::Instance(_) => {
+                let id = self.ident_table.get_ident_id("[]=");
+                let mut args = args;
+                args.push(val);
+                let args = Args { block: None, args };
+                self.send(receiver.clone(), id, args)?;
+                Ok(receiver)
+            }
+            _ => Err(self.error_nomethod_for("[]=", &receiver)),
+        }
+    }
+
+    fn expect_index_args(
+        &mut self,
+        args: Vec<Value>,
+        count: usize,
+    ) -> Result<Vec<Value>, RuntimeError> {
+        if args.len() == count {
+            Ok(args)
+        } else {
+            Err(self.error_argument(format!(
+                "wrong number of arguments (given {}, expected {})",
+                args.len(),
+                count
+            )))
+        }
+    }
+
+    /// The index, and the length when one is given, of an Array element
+    /// reference.
+    fn expect_array_index(&mut self, args: Vec<Value>) -> Result<(i64, Option<i64>), RuntimeError> {
+        let mut nums = vec![];
+        for arg in &args {
+            match arg {
+                Value::FixNum(i) => nums.push(*i),
+                arg => {
+                    let name = self.val_class_name(arg);
+                    return Err(
+                        self.error_type(format!("no implicit conversion of {} into Integer", name))
+                    );
+                }
+            }
+        }
+        match nums.as_slice() {
+            [i] => Ok((*i, None)),
+            [start, count] => Ok((*start, Some(*count))),
+            _ => Err(self.error_argument(format!(
+                "wrong number of arguments (given {}, expected 1..2)",
+                args.len()
+            ))),
+        }
+    }
+}
+
+impl VM {
+    fn new_exception(&mut self, class_name: &str, message: &str) -> Value {
+        let class_ref = self.builtin_class_ref(class_name);
+        let instance = self.new_instance(class_ref);
+        let id = self.ident_table.get_ident_id("message");
+        self.instance_ref(instance)
+            .instance_var
+            .insert(id, Value::String(message.to_string()));
+        Value::Instance(instance)
+    }
+
+    /// Raises `exception`, which keeps the location and backtrace of the
+    /// point it was first raised at. The frame of `raise` itself is omitted.
+    pub fn error_exception(&mut self, exception: Value) -> RuntimeError {
+        let loc = self.cur_loc();
+        let mut backtrace = self.backtrace();
+        if let Some(CallFrame {
+            exec_frame: None, ..
+        }) = self.stack.call_frames.last()
+        {
+            backtrace.remove(0);
+        }
+        let (loc, backtrace) = match exception {
+            Value::Instance(r) => {
+                let info = self.instance_ref(r);
+                if info.raised_loc.is_none() {
+                    info.raised_loc = Some(loc);
+                    info.backtrace = backtrace;
+                }
+                (info.raised_loc.unwrap(), info.backtrace.clone())
+            }
+            _ => (loc, backtrace),
+        };
+        RuntimeError::new(RuntimeErrorKind::Exception(exception), loc, backtrace)
+    }
+
+    /// The exception object for `err`, as seen by `rescue`.
+    fn error_to_exception(&mut self, err: RuntimeError) -> Value {
+        if let RuntimeErrorKind::Exception(exception) = err.kind {
+            return exception;
+        }
+        let exception = self.new_exception(error_class_name(&err.kind), &error_message(&err.kind));
+        if let Value::Instance(r) = exception {
+            let info = self.instance_ref(r);
+            info.raised_loc = Some(err.loc);
+            info.backtrace = err.backtrace;
+        }
+        exception
+    }
+
+    /// `message (ClassName)`, as printed for an uncaught error.
+    pub fn error_to_s(&mut self, err: &RuntimeError) -> String {
+        match &err.kind {
+            RuntimeErrorKind::Exception(Value::Instance(r)) => {
+                let class_name = self.instance_table.get(*r).class_name.clone();
+                let message = self
+                    .builtin_exception_message(Value::Instance(*r), Args::new())
+                    .map(|message| self.val_to_s(&message))
+                    .unwrap_or_default();
+                format!("{} ({})", message, class_name)
+            }
+            kind => format!("{} ({})", error_message(kind), error_class_name(kind)),
+        }
+    }
+
+    /// Location of the instruction being executed in the current frame.
+    fn cur_loc(&mut self) -> Loc {
+        let iseq_pos = self.iseq_pos();
+        let stack_pos = self.stack_pos();
+        self.stack.iseqs[iseq_pos]
+            .loc(stack_pos.saturating_sub(1))
+            .unwrap_or(Loc(0, 0))
+    }
+
+    fn error(&mut self, kind: RuntimeErrorKind) -> RuntimeError {
+        let loc = self.cur_loc();
+        RuntimeError::new(kind, loc, self.backtrace())
+    }
+
+    /// Ruby-formatted backtrace of the current call frames, innermost first.
+    /// Builtin methods are reported at the line of their caller.
+    pub fn backtrace(&self) -> Vec<String> {
+        let mut backtrace = vec![];
+        let mut line = 0;
+        for frame in self.stack.call_frames.iter() {
+            if let Some(i) = frame.exec_frame {
+                let iseq = &self.stack.iseqs[self.stack.iseq_poses[i]];
+                if let Some(loc) = iseq.loc(self.stack.stack_poses[i].saturating_sub(1)) {
+                    line = self.source_info.line_column(loc.0).0;
+                }
+            }
+            backtrace.push(format!(
+                "{}:{}:in '{}'",
+                self.source_info.path, line, frame.label
+            ));
+        }
+        backtrace.reverse();
+        backtrace
+    }
+
+    pub fn error_unimplemented(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::Unimplemented(msg.into()))
+    }
+
+    pub fn error_unreachable(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::Unreachable(msg.into()))
+    }
+
+    pub fn error_name(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::Name(msg.into()))
+    }
+
+    pub fn error_nomethod(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::NoMethod(msg.into()))
+    }
+
+    pub fn error_type(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::Type(msg.into()))
+    }
+
+    pub fn error_index(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::Index(msg.into()))
+    }
+
+    pub fn error_argument(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::Argument(msg.into()))
+    }
+
+    pub fn error_zero_division(&mut self) -> RuntimeError {
+        self.error(RuntimeErrorKind::ZeroDivision("divided by 0".to_string()))
+    }
+
+    /// FloatDomainError for converting NaN or an infinity to an Integer.
+    pub fn error_float_domain(&mut self, f: f64) -> RuntimeError {
+        self.error(RuntimeErrorKind::FloatDomain(Value::float_to_s(f)))
+    }
+
+    pub fn error_runtime(&mut self, msg: impl Into<String>) -> RuntimeError {
+        self.error(RuntimeErrorKind::Runtime(msg.into()))
+    }
+
+    /// NoMethodError for calling `method` on `receiver`.
+    pub fn error_nomethod_for(&mut self, method: &str, receiver: &Value) -> RuntimeError {
+        let receiver_name = match receiver {
+            Value::Class(r) if self.class_stack.contains(r) => "main:Object".to_string(),
+            Value::Class(r) => format!("{}:Class", self.class_table.get(*r).name),
+            _ => self.val_class_name(receiver),
+        };
+        self.error_nomethod(format!(
+            "undefined method `{}' for {}",
+            method, receiver_name
+        ))
+    }
+
+    /// Error for a binary operator whose operands do not match: TypeError
+    /// when `lhs` has the operator but `rhs` is of the wrong type, and
+    /// NoMethodError when `lhs` does not have it.
+    fn error_binop(&mut self, op: &str, lhs: &Value, rhs: &Value) -> RuntimeError {
+        let rhs_name = self.val_class_name(rhs);
+        let arithmetic = matches!(op, "+" | "-" | "*" | "/" | "%" | "**" | "divmod");
+        match lhs {
+            Value::FixNum(_) | Value::BigNum(_) => {
+                self.error_type(format!("{} can't be coerced into Integer", rhs_name))
+            }
+            Value::FixDecimalNum(_) if arithmetic => {
+                self.error_type(format!("{} can't be coerced into Float", rhs_name))
+            }
+            Value::String(_) if op == "+" => self.error_type(format!(
+                "no implicit conversion of {} into String",
+                rhs_name
+            )),
+            _ => self.error_nomethod_for(op, lhs),
+        }
+    }
+
+    /// Ruby class name of `val`, as shown in error messages.
+    pub fn val_class_name(&self, val: &Value) -> String {
+        match val {
+            Value::Nil => "NilClass".to_string(),
+            Value::Bool(true) => "TrueClass".to_string(),
+            Value::Bool(false) => "FalseClass".to_string(),
+            Value::FixNum(_) | Value::BigNum(_) => "Integer".to_string(),
+            Value::FixDecimalNum(_) => "Float".to_string(),
+            Value::String(_) => "String".to_string(),
+            Value::Array(_) => "Array".to_string(),
+            Value::Range(_, _) => "Range".to_string(),
+            Value::Symbol(_) => "Symbol".to_string(),
+            Value::Hash(_) => "Hash".to_string(),
+            Value::Proc(_) => "Proc".to_string(),
+            Value::Class(_) | Value::SelfClass(_) => "Class".to_string(),
+            Value::Instance(r) => self.instance_table.get(*r).class_name.clone(),
         }
     }
 }
@@ -1493,7 +4270,7 @@ impl VM {
     pub fn new_class(&mut self, id: IdentId, ptr: usize) -> ClassRef {
         let name = self.ident_table.get_name(id);
         let class_ref = self.class_table.new_class(id, name, ptr);
-        let id = self.ident_table.get_ident_id(&"new".to_string());
+        let id = self.ident_table.get_ident_id("new");
         let info = MethodInfo::BuiltinFunc {
             name: "new".to_string(),
             func: VM::builtin_new,
@@ -1527,12 +4304,10 @@ impl VM {
                 false => "false".to_string(),
             },
             Value::FixNum(i) => i.to_string(),
-            Value::FixDecimalNum(f) => f.to_string(),
+            Value::BigNum(i) => i.to_string(),
+            Value::FixDecimalNum(f) => Value::float_to_s(*f),
             Value::String(s) => s.clone(),
-            Value::Class(class) => {
-                let class_info = self.class_table.get(*class);
-                format!("#<{}:{:?}>", class_info.name, class)
-            }
+            Value::Class(class) => self.class_table.get(*class).name.clone(),
             Value::Instance(instance) => {
                 let info = self.instance_table.get(*instance);
                 format!("#<{}:{:?}>", info.class_name, instance)
@@ -1540,12 +4315,283 @@ impl VM {
             Value::SelfClass(c) => {
                 format!("{:?}", c)
             }
-            Value::Array(v) => {
-                format!("{:?}", v)
+            Value::Array(_) => self.val_inspect(val),
+            Value::Range(end, start) => {
+                format!("{}..{}", self.val_to_s(start), self.val_to_s(end))
+            }
+            Value::Symbol(id) => self.ident_table.get_name(*id),
+            Value::Hash(_) => self.val_inspect(val),
+            Value::Proc(proc_ref) => match self.proc_table.get(*proc_ref).kind {
+                ProcKind::Symbol(id) => {
+                    format!(
+                        "#<Proc:{:?}(&{})>",
+                        proc_ref,
+                        self.val_inspect(&Value::Symbol(id))
+                    )
+                }
+                ProcKind::Block(_) => format!("#<Proc:{:?}>", proc_ref),
+                ProcKind::Lambda(_) => format!("#<Proc:{:?} (lambda)>", proc_ref),
+            },
+        }
+    }
+
+    /// `val.to_s` for string interpolation, which calls a user-defined
+    /// `to_s` of an instance.
+    fn interpolated_to_s(&mut self, val: Value) -> Result<String, RuntimeError> {
+        if let Value::Instance(r) = val {
+            let class_ref = self.class_ref_with_instance(r);
+            let id = self.ident_table.get_ident_id("to_s");
+            if let Some((_, MethodInfo::RubyFunc { .. })) = self.find_method(class_ref, id) {
+                if let Value::String(s) = self.send(val.clone(), id, Args::new())? {
+                    return Ok(s);
+                }
+            }
+        }
+        Ok(self.val_to_s(&val))
+    }
+
+    /// `inspect` of `val`, as shown inside hashes.
+    pub fn val_inspect(&mut self, val: &Value) -> String {
+        match val {
+            Value::Nil => "nil".to_string(),
+            Value::String(s) => format!("{:?}", s),
+            Value::Symbol(id) => {
+                let name = self.ident_table.get_name(*id);
+                if is_plain_symbol_name(&name) {
+                    format!(":{}", name)
+                } else {
+                    format!(":{:?}", name)
+                }
+            }
+            Value::Array(vals) => {
+                let vals = vals
+                    .iter()
+                    .map(|val| self.val_inspect(val))
+                    .collect::<Vec<String>>();
+                format!("[{}]", vals.join(", "))
             }
-            Value::Range(s, e) => {
-                format!("{:?}..{:?}", s, e)
+            Value::Hash(hash) => {
+                let entries = self.hash_table.get(*hash).entries().to_vec();
+                if entries.is_empty() {
+                    return "{}".to_string();
+                }
+                let entries = entries
+                    .iter()
+                    .map(|(key, val)| match key {
+                        Value::Symbol(id) => {
+                            format!(
+                                "{}: {}",
+                                self.ident_table.get_name(*id),
+                                self.val_inspect(val)
+                            )
+                        }
+                        key => format!("{} => {}", self.val_inspect(key), self.val_inspect(val)),
+                    })
+                    .collect::<Vec<String>>();
+                format!("{{{}}}", entries.join(", "))
             }
+            val => self.val_to_s(val),
+        }
+    }
+}
+
+/// The instruction for a binary operator other than `&&` and `||`.
+/// Distributes `values` over `pre` leading targets, an optional splat that
+/// collects the rest as an Array, and `post` trailing targets. Missing values
+/// are nil.
+/// Arguments of a block call adjusted to the block's parameters the way
+/// procs take them: a single Array is spread over several parameters,
+/// missing arguments are nil and extra ones are dropped.
+fn block_args(params: &Params, args: Vec<Value>) -> Vec<Value> {
+    let required = params.required.len() + params.post.len();
+    let positional = required + params.optional.len();
+    let mut args = match args.as_slice() {
+        [Value::Array(values)] if positional > 1 || positional > 0 && params.rest.is_some() => {
+            values.clone()
+        }
+        _ => args,
+    };
+    if args.len() < required {
+        args.resize(required, Value::Nil);
+    } else if params.rest.is_none() && !params.takes_keywords() {
+        args.truncate(positional);
+    }
+    args
+}
+
+/// Names of the variables assigned by the targets of a pattern.
+fn collect_target_names(targets: &[Node], names: &mut Vec<IdentId>) {
+    for target in targets {
+        match &target.kind {
+            NodeKind::Ident(id) => names.push(*id),
+            NodeKind::Splat(target) => collect_target_names(std::slice::from_ref(target), names),
+            NodeKind::MultiLhs(targets) => collect_target_names(targets, names),
+            _ => {}
+        }
+    }
+}
+
+fn expand_array(mut values: Vec<Value>, pre: usize, splat: bool, post: usize) -> Vec<Value> {
+    let split = if splat {
+        values.len().saturating_sub(post).max(pre)
+    } else {
+        pre
+    };
+    let mut trailing = values.split_off(split.min(values.len()));
+    values.resize(pre.max(values.len()), Value::Nil);
+    let rest = values.split_off(pre);
+    let mut targets = values;
+    if splat {
+        targets.push(Value::Array(rest));
+    }
+    trailing.resize(post, Value::Nil);
+    targets.extend(trailing);
+    targets
+}
+
+fn binop_inst(op: &BinOp) -> u8 {
+    match op {
+        BinOp::Add => Inst::ADD,
+        BinOp::Sub => Inst::SUB,
+        BinOp::Mul => Inst::MUL,
+        BinOp::Div => Inst::DIV,
+        BinOp::Rem => Inst::REM,
+        BinOp::Pow => Inst::POW,
+        BinOp::Cmp => Inst::CMP,
+        BinOp::BitAnd => Inst::BIT_AND,
+        BinOp::BitOr => Inst::BIT_OR,
+        BinOp::BitXor => Inst::BIT_XOR,
+        BinOp::Shl => Inst::SHL,
+        BinOp::Shr => Inst::SHR,
+        BinOp::Eq => Inst::EQ,
+        BinOp::Ne => Inst::NE,
+        BinOp::TEq => Inst::TEQ,
+        BinOp::GT => Inst::GT,
+        BinOp::GE => Inst::GE,
+        BinOp::LT => Inst::LT,
+        BinOp::LE => Inst::LE,
+        BinOp::LAnd | BinOp::LOr => unreachable!(),
+    }
+}
+
+/// How `round`, `floor` and `ceil` pick between neighbouring values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rounding {
+    Round,
+    Floor,
+    Ceil,
+}
+
+/// Both operands as BigInts, when they are Integers and at least one of
+/// them is a Bignum.
+fn bignum_operands(lhs: &Value, rhs: &Value) -> Option<(BigInt, BigInt)> {
+    match (lhs, rhs) {
+        (Value::BigNum(lhs), Value::BigNum(rhs)) => Some((lhs.clone(), rhs.clone())),
+        (Value::BigNum(lhs), Value::FixNum(rhs)) => Some((lhs.clone(), BigInt::from(*rhs))),
+        (Value::FixNum(lhs), Value::BigNum(rhs)) => Some((BigInt::from(*lhs), rhs.clone())),
+        _ => None,
+    }
+}
+
+/// Converts a Bignum operand to a Float when the other one is a Float.
+fn coerce_bignum(lhs: Value, rhs: Value) -> (Value, Value) {
+    let to_f = |i: &BigInt| Value::FixDecimalNum(i.to_f64().unwrap_or(f64::NAN));
+    match (&lhs, &rhs) {
+        (Value::BigNum(i), Value::FixDecimalNum(_)) => (to_f(i), rhs),
+        (Value::FixDecimalNum(_), Value::BigNum(i)) => (lhs, to_f(i)),
+        _ => (lhs, rhs),
+    }
+}
+
+/// Integer division rounding towards negative infinity, or None on overflow.
+fn floor_div(lhs: i64, rhs: i64) -> Option<i64> {
+    let q = lhs.checked_div(rhs)?;
+    if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+        Some(q - 1)
+    } else {
+        Some(q)
+    }
+}
+
+/// Integer modulo whose result takes the sign of the divisor.
+fn int_mod(lhs: i64, rhs: i64) -> i64 {
+    // Only `i64::MIN % -1` overflows, and its remainder is 0.
+    let m = lhs.checked_rem(rhs).unwrap_or(0);
+    if m != 0 && (m < 0) != (rhs < 0) {
+        m + rhs
+    } else {
+        m
+    }
+}
+
+/// Float modulo whose result takes the sign of the divisor.
+fn float_mod(lhs: f64, rhs: f64) -> f64 {
+    let m = lhs % rhs;
+    if m != 0.0 && (m < 0.0) != (rhs < 0.0) {
+        m + rhs
+    } else {
+        m
+    }
+}
+
+/// Whether a symbol named `name` can be written without quotes.
+fn is_plain_symbol_name(name: &str) -> bool {
+    if OPERATOR_METHODS.contains(&name) {
+        return true;
+    }
+    let ident = name.trim_start_matches('@').trim_start_matches('$');
+    let ident = ident
+        .strip_suffix(|c| c == '?' || c == '!' || c == '=')
+        .unwrap_or(ident);
+    let mut chars = ident.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
         }
+        _ => false,
+    }
+}
+
+/// Name of the exception class that `rescue` sees for an error raised by the VM.
+fn error_class_name(kind: &RuntimeErrorKind) -> &'static str {
+    match kind {
+        RuntimeErrorKind::Unimplemented(_) => "NotImplementedError",
+        RuntimeErrorKind::Unreachable(_) => "Exception",
+        RuntimeErrorKind::Name(_) => "NameError",
+        RuntimeErrorKind::NoMethod(_) => "NoMethodError",
+        RuntimeErrorKind::Type(_) => "TypeError",
+        RuntimeErrorKind::Index(_) => "IndexError",
+        RuntimeErrorKind::Key(_) => "KeyError",
+        RuntimeErrorKind::Argument(_) => "ArgumentError",
+        RuntimeErrorKind::ZeroDivision(_) => "ZeroDivisionError",
+        RuntimeErrorKind::FloatDomain(_) => "FloatDomainError",
+        RuntimeErrorKind::Runtime(_) => "RuntimeError",
+        RuntimeErrorKind::NoMatchingPattern(_) => "NoMatchingPatternError",
+        RuntimeErrorKind::LocalJump(_) => "LocalJumpError",
+        RuntimeErrorKind::SystemStack(_) => "SystemStackError",
+        RuntimeErrorKind::Exception(_) => "Exception",
+        RuntimeErrorKind::Break(..) | RuntimeErrorKind::Return(..) => "LocalJumpError",
+    }
+}
+
+fn error_message(kind: &RuntimeErrorKind) -> String {
+    match kind {
+        RuntimeErrorKind::Unimplemented(msg)
+        | RuntimeErrorKind::Unreachable(msg)
+        | RuntimeErrorKind::Name(msg)
+        | RuntimeErrorKind::NoMethod(msg)
+        | RuntimeErrorKind::Type(msg)
+        | RuntimeErrorKind::Index(msg)
+        | RuntimeErrorKind::Key(msg)
+        | RuntimeErrorKind::Argument(msg)
+        | RuntimeErrorKind::ZeroDivision(msg)
+        | RuntimeErrorKind::FloatDomain(msg)
+        | RuntimeErrorKind::Runtime(msg)
+        | RuntimeErrorKind::NoMatchingPattern(msg)
+        | RuntimeErrorKind::LocalJump(msg)
+        | RuntimeErrorKind::SystemStack(msg) => msg.clone(),
+        RuntimeErrorKind::Exception(_) => "exception".to_string(),
+        RuntimeErrorKind::Break(..) => "break from proc-closure".to_string(),
+        RuntimeErrorKind::Return(..) => "unexpected return".to_string(),
     }
 }
@@ -13,13 +13,41 @@ mod test {
 
         let mut vm = VM::new();
         vm.init(parser.lexer.source_info, parser.ident_table, node);
-        let _ = vm.eval_seq();
-        let val = vm.pop_value();
+        let val = vm
+            .eval()
+            .unwrap_or_else(|err| panic!("Got runtime error: {:?}", err));
         if val != expected {
             panic!("Expected:{:?} Got:{:?}", expected, val);
         }
     }
 
+    /// Evaluates `script` and checks that it fails with `expected`, raised at
+    /// the source text `expected_src`.
+    fn eval_script_error(
+        script: impl Into<String>,
+        expected: RuntimeErrorKind,
+        expected_src: &str,
+    ) {
+        let script = script.into();
+        let mut parser = Parser::new();
+        let node = parser.parse_program(script.clone()).unwrap();
+
+        let mut vm = VM::new();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        match vm.eval() {
+            Ok(val) => panic!("Expected:{:?} Got:{:?}", expected, val),
+            Err(err) => {
+                if err.kind != expected {
+                    panic!("Expected:{:?} Got:{:?}", expected, err.kind);
+                }
+                let src = &script[err.loc.0..=err.loc.1];
+                if src != expected_src {
+                    panic!("Expected:{:?} Got:{:?}", expected_src, src);
+                }
+            }
+        }
+    }
+
     #[test]
     fn func1() {
         let program = "
@@ -180,23 +208,85 @@ mod test {
         eval_script(program, expected);
     }
 
-    // #[test]
-    // fn decimal_number1() {
-    //     let program = "
-    //         123.4;
-    //     ";
-    //     let expected = Value::FixDecimalNum(123.4);
-    //     eval_script(program, expected);
-    // }
+    #[test]
+    fn unless1() {
+        let program = "
+            a = unless 3 == 4 then 1 else 2 end
+            b = unless 3 == 3
+              1
+            else
+              2
+            end
+            c = unless 3 == 3 then 1 end
+            [a, b, c]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(1), Value::FixNum(2), Value::Nil]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn if_modifier() {
+        let program = "
+            a = 1
+            b = 1
+            a = 2 if a == 1
+            b = 2 unless b == 1
+            c = 0
+            c = c + 1 while c < 5 if a == 2
+            [a, b, c]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(2), Value::FixNum(1), Value::FixNum(5)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn unassigned_local_is_nil() {
+        let program = "
+            def z
+              5
+            end
+            y = 1 if false
+            z = 2 if false
+            [y, z]
+        ";
+        let expected = Value::Array(vec![Value::Nil, Value::Nil]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn jump_with_if_modifier() {
+        let program = "
+            r = 0
+            i = 0
+            while i < 10
+              i = i + 1
+              next if i == 2
+              break if i == 5
+              r = r + i
+            end
+            r
+        ";
+        let expected = Value::FixNum(8);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn decimal_number1() {
+        let program = "
+            123.4;
+        ";
+        let expected = Value::FixDecimalNum(123.4);
+        eval_script(program, expected);
+    }
 
-    // #[test]
-    // fn decimal_number2() {
-    //     let program = "
-    //         12.3 + 4 - 5.6 * 7.8 / 9;
-    //     ";
-    //     let expected = Value::FixDecimalNum(11.446666666666667);
-    //     eval_script(program, expected);
-    // }
+    #[test]
+    fn decimal_number2() {
+        let program = "
+            12.3 + 4 - 5.6 * 7.8 / 9;
+        ";
+        let expected = Value::FixDecimalNum(11.446666666666667);
+        eval_script(program, expected);
+    }
 
     #[test]
     fn local_var1() {
@@ -307,6 +397,45 @@ mod test {
         eval_script(program, expected);
     }
 
+    #[test]
+    fn index_args() {
+        let program = "
+            pr = proc { 7 }
+            pair = proc { |a, b| a + b }
+            l = [1, 2, 3, 4]
+            a = [pr[], pair[1, 2], l[1, 2], l[3, 9], l[4, 1], l[5, 1]]
+            l[1, 2] = [8, 9, 10]
+            x, l[0, 2] = 1, 6
+            m = [1, 2]
+            m[4, 1] ||= 7
+            a[a.size] = l
+            a[a.size] = m
+            a
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(7),
+            Value::FixNum(3),
+            Value::Array(vec![Value::FixNum(2), Value::FixNum(3)]),
+            Value::Array(vec![Value::FixNum(4)]),
+            Value::Array(vec![]),
+            Value::Nil,
+            Value::Array(vec![
+                Value::FixNum(6),
+                Value::FixNum(9),
+                Value::FixNum(10),
+                Value::FixNum(4),
+            ]),
+            Value::Array(vec![
+                Value::FixNum(1),
+                Value::FixNum(2),
+                Value::Nil,
+                Value::Nil,
+                Value::FixNum(7),
+            ]),
+        ]);
+        eval_script(program, expected);
+    }
+
     #[test]
     fn each1() {
         let program = "
@@ -346,6 +475,32 @@ mod test {
         eval_script(program, expected);
     }
 
+    #[test]
+    fn for2() {
+        let program = "
+            r = 0
+            for n in [1, 2, 3]
+              r = r + n
+            end
+            r + n
+        ";
+        let expected = Value::FixNum(9);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn logical_and1() {
+        let program = "
+            a = 0
+            if 1 == 2 && foo()
+              a = 1
+            end
+            a
+        ";
+        let expected = Value::FixNum(0);
+        eval_script(program, expected);
+    }
+
     #[test]
     fn class1() {
         let program = "
@@ -404,6 +559,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn instance_variables() {
         let program = "
             class Car
@@ -484,4 +640,1912 @@ mod test {
         let expected = Value::Nil;
         eval_script(program, expected);
     }
+
+    #[test]
+    fn error_undefined_local_var() {
+        let program = "
+            a = 1
+            a + b
+        ";
+        let expected = RuntimeErrorKind::Name("undefined local variable or method `b'".to_string());
+        eval_script_error(program, expected, "b");
+    }
+
+    #[test]
+    fn error_uninitialized_const() {
+        let program = "Foo.new";
+        let expected = RuntimeErrorKind::Name("uninitialized constant Foo".to_string());
+        eval_script_error(program, expected, "Foo");
+    }
+
+    #[test]
+    fn error_undefined_method() {
+        let program = "
+            def foo
+              bar(1)
+            end
+            foo()
+        ";
+        let expected =
+            RuntimeErrorKind::NoMethod("undefined method `bar' for main:Object".to_string());
+        eval_script_error(program, expected, "bar(1)");
+    }
+
+    #[test]
+    fn error_undefined_method_in_block() {
+        let program = "
+            3.times do |n|
+              n.foo
+            end
+        ";
+        let expected = RuntimeErrorKind::NoMethod("undefined method `foo' for Integer".to_string());
+        eval_script_error(program, expected, "n.foo");
+    }
+
+    #[test]
+    fn error_type() {
+        let program = "1 + 'a'";
+        let expected = RuntimeErrorKind::Type("String can't be coerced into Integer".to_string());
+        eval_script_error(program, expected, "1 + 'a'");
+    }
+
+    #[test]
+    fn error_binop_type() {
+        let expected =
+            RuntimeErrorKind::Type("no implicit conversion of Integer into String".to_string());
+        eval_script_error("'a' + 1", expected, "'a' + 1");
+        let expected = RuntimeErrorKind::Type("String can't be coerced into Float".to_string());
+        eval_script_error("1.5 * 'a'", expected, "1.5 * 'a'");
+    }
+
+    #[test]
+    fn error_binop_undefined() {
+        let expected = RuntimeErrorKind::NoMethod("undefined method `-' for String".to_string());
+        eval_script_error("'a' - 'b'", expected, "'a' - 'b'");
+        let expected = RuntimeErrorKind::NoMethod("undefined method `/' for String".to_string());
+        eval_script_error("'a' / 2", expected, "'a' / 2");
+        let expected = RuntimeErrorKind::NoMethod("undefined method `&' for Float".to_string());
+        eval_script_error("1.5 & 1", expected, "1.5 & 1");
+    }
+
+    #[test]
+    fn error_zero_division() {
+        let program = "
+            a = 0
+            10 / a
+        ";
+        let expected = RuntimeErrorKind::ZeroDivision("divided by 0".to_string());
+        eval_script_error(program, expected, "10 / a");
+    }
+
+    #[test]
+    fn error_index() {
+        let program = "[1, 2, 3][5]";
+        let expected =
+            RuntimeErrorKind::Index("index 5 outside of array bounds: -3...3".to_string());
+        eval_script_error(program, expected, "[1, 2, 3][5]");
+    }
+
+    #[test]
+    fn error_comparison() {
+        let program = "1 < 'a'";
+        let expected =
+            RuntimeErrorKind::Argument("comparison of Integer with String failed".to_string());
+        eval_script_error(program, expected, "1 < 'a'");
+    }
+
+    #[test]
+    fn error_assert() {
+        let program = "assert(1, 2)";
+        let expected = RuntimeErrorKind::Runtime(
+            "Assertion failed. Expected FixNum(1), but got FixNum(2)".to_string(),
+        );
+        eval_script_error(program, expected, "assert(1, 2)");
+    }
+
+    #[test]
+    fn vm_is_reusable_after_error() {
+        let mut vm = VM::new();
+        let mut parser = Parser::new();
+        let node = parser.parse_program("1 + nil".to_string()).unwrap();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        assert!(vm.eval().is_err());
+
+        let mut parser = Parser::new();
+        let node = parser.parse_program("a = 1\na + 1".to_string()).unwrap();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        assert_eq!(vm.eval(), Ok(Value::FixNum(2)));
+    }
+
+    #[test]
+    fn string_add() {
+        let program = "'foo' + 'bar'";
+        let expected = Value::String("foobar".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn rescue_vm_error() {
+        let program = "
+            def div(a, b)
+              a / b
+            rescue ZeroDivisionError => e
+              e.message
+            end
+            div(1, 0)
+        ";
+        let expected = Value::String("divided by 0".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn rescue_by_class() {
+        let program = "
+            x = begin
+              raise(ArgumentError, 'bad')
+            rescue TypeError, NameError
+              1
+            rescue StandardError => e
+              assert(ArgumentError, e.class)
+              assert('bad', e.message)
+              2
+            end
+            x
+        ";
+        let expected = Value::FixNum(2);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn rescue_subclass() {
+        let program = "
+            class MyError < RuntimeError
+            end
+            begin
+              begin
+                raise(MyError.new('mine'))
+              rescue NameError
+                1
+              end
+            rescue StandardError => e
+              e.message
+            end
+        ";
+        let expected = Value::String("mine".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn rescue_else_ensure() {
+        let program = "
+            log = 0
+            x = begin
+              1
+            rescue
+              2
+            else
+              3
+            ensure
+              log = log + 10
+            end
+            y = begin
+              raise('boom')
+            rescue
+              4
+            ensure
+              log = log + 100
+            end
+            [x, y, log]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(3), Value::FixNum(4), Value::FixNum(110)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn ensure_runs_when_propagating() {
+        let program = "
+            def foo
+              @log = 'ensure'
+              raise('boom')
+            ensure
+              @log = @log + ' done'
+            end
+            begin
+              foo()
+            rescue RuntimeError => e
+              [e.message, @log]
+            end
+        ";
+        let expected = Value::Array(vec![
+            Value::String("boom".to_string()),
+            Value::String("ensure done".to_string()),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn rescue_unwinds_stack() {
+        let program = "
+            def raise_in_block
+              3.times do |i|
+                if i == 1
+                  raise('in block')
+                end
+              end
+            end
+            [1, 2, begin
+              raise_in_block()
+            rescue
+              3
+            end, 4]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(1),
+            Value::FixNum(2),
+            Value::FixNum(3),
+            Value::FixNum(4),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn bare_raise_reraises() {
+        let program = "
+            begin
+              begin
+                raise 'x'
+              rescue
+                raise
+              end
+            rescue => e
+              assert('x', e.message)
+            end
+            def reraise
+              raise
+            end
+            begin
+              begin
+                raise ArgumentError, 'inner'
+              rescue
+                begin
+                  raise 'other'
+                rescue
+                end
+                reraise
+              end
+            rescue ArgumentError => e
+              assert('inner', e.message)
+            end
+            begin
+              raise
+            rescue RuntimeError => e
+              e.message
+            end
+        ";
+        let expected = Value::String("unhandled exception".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn retry1() {
+        let program = "
+            count = 0
+            begin
+              count = count + 1
+              if count < 3
+                raise(TypeError)
+              end
+              count
+            rescue TypeError
+              retry
+            end
+        ";
+        let expected = Value::FixNum(3);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn unrescued_exception() {
+        let program = "
+            begin
+              raise('boom')
+            rescue TypeError
+              1
+            end
+        ";
+        let mut parser = Parser::new();
+        let node = parser.parse_program(program.to_string()).unwrap();
+        let mut vm = VM::new();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        let err = vm.eval().unwrap_err();
+        assert_eq!(vm.error_to_s(&err), "boom (RuntimeError)");
+        assert_eq!(&program[err.loc.0..=err.loc.1], "raise('boom')");
+    }
+
+    fn eval_script_backtrace(script: impl Into<String>, expected: Vec<&str>) {
+        let mut parser = Parser::new();
+        parser.lexer.source_info.path = "test.rb".to_string();
+        let node = parser.parse_program(script.into()).unwrap();
+
+        let mut vm = VM::new();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        let err = vm.eval().unwrap_err();
+        if err.backtrace != expected {
+            panic!("Expected:{:?} Got:{:?}", expected, err.backtrace);
+        }
+    }
+
+    #[test]
+    fn backtrace_nested_calls() {
+        let program = "class Foo
+  def bar(n)
+    n.times do |i|
+      fact(i)
+    end
+  end
+end
+def fact(n)
+  if n == 0
+    1 / n
+  else
+    n * fact(n - 1)
+  end
+end
+Foo.new.bar(2)";
+        let expected = vec![
+            "test.rb:10:in 'fact'",
+            "test.rb:4:in 'block in Foo#bar'",
+            "test.rb:3:in 'Integer#times'",
+            "test.rb:3:in 'Foo#bar'",
+            "test.rb:15:in '<main>'",
+        ];
+        eval_script_backtrace(program, expected);
+    }
+
+    #[test]
+    fn backtrace_of_reraised_exception() {
+        let program = "def foo
+  raise('boom')
+end
+begin
+  foo()
+ensure
+  1
+end";
+        let expected = vec!["test.rb:2:in 'foo'", "test.rb:5:in '<main>'"];
+        eval_script_backtrace(program, expected);
+    }
+
+    #[test]
+    fn exception_backtrace() {
+        let program = "
+            class Foo
+              def self_error
+                self.undefined
+              end
+            end
+            begin
+              Foo.new.self_error
+            rescue NoMethodError => e
+              e.backtrace
+            end
+        ";
+        let expected = Value::Array(vec![
+            Value::String("-:4:in 'Foo#self_error'".to_string()),
+            Value::String("-:8:in '<main>'".to_string()),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn while1() {
+        let program = "
+            i = 0
+            r = 0
+            while i < 5
+              i = i + 1
+              if i == 2
+                next
+              end
+              r = r + i
+            end
+            r
+        ";
+        let expected = Value::FixNum(13);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn until_modifier() {
+        let program = "
+            a = 0
+            a = a + 3 until a > 10
+            b = 0
+            b = b + 1 while b < a
+            [a, b, while a < 0 do end]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(12), Value::FixNum(12), Value::Nil]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn begin_end_while_runs_body_first() {
+        let program = "
+            k = 0
+            begin k += 1 end while false
+            j = 0
+            begin
+              j += 1
+              next if j == 2
+            end until j >= 3
+            m = 0
+            begin m += 1 end.to_s while false
+            [k, j, m]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(1), Value::FixNum(3), Value::FixNum(0)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn break_with_value() {
+        let program = "
+            i = 0
+            x = until i == 10 do i = i + 1; if i == 4 then break i * 10 end end
+            y = [1, 2, 3].each do |v|
+              if v == 2
+                break v + 100
+              end
+            end
+            [x, y]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(40), Value::FixNum(102)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn next_in_block() {
+        let program = "
+            r = 0
+            5.times do |k|
+              if k == 1
+                next
+              end
+              r = r + k
+            end
+            r
+        ";
+        let expected = Value::FixNum(9);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn redo1() {
+        let program = "
+            n = 0
+            c = 0
+            while n < 2
+              n = n + 1
+              c = c + 1
+              if c == 1
+                redo
+              end
+            end
+            [n, c]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(2), Value::FixNum(2)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn for_break_next() {
+        let program = "
+            r = 0
+            for a in (1..10)
+              if a == 2
+                next
+              end
+              if a == 5
+                break
+              end
+              r = r + a
+            end
+            r
+        ";
+        let expected = Value::FixNum(8);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn break_runs_ensure() {
+        let program = "
+            r = 0
+            x = while 1 == 1
+              begin
+                [1, 2].each do |v|
+                  begin
+                    break
+                  ensure
+                    r = r * 10 + v
+                  end
+                end
+                break 5
+              ensure
+                r = r * 10 + 3
+              end
+            end
+            [r, x]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(13), Value::FixNum(5)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn case_when() {
+        let program = "
+            def kind(x)
+              case x
+              when 1, 2 then 10
+              when 3..5
+                20
+              when String then 30
+              when Integer, Float then 40
+              else
+                50
+              end
+            end
+            [kind(2), kind(4), kind('a'), kind(9), kind(2.5), kind(nil)]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(10),
+            Value::FixNum(20),
+            Value::FixNum(30),
+            Value::FixNum(40),
+            Value::FixNum(40),
+            Value::FixNum(50),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn case_dispatch() {
+        let program = "
+            def lit(x)
+              case x
+              when 1 then 'one'
+              when 2, 3 then 'few'
+              end
+            end
+            [lit(1), lit(3), lit(3.0), lit(7)]
+        ";
+        let expected = Value::Array(vec![
+            Value::String("one".to_string()),
+            Value::String("few".to_string()),
+            Value::String("few".to_string()),
+            Value::Nil,
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn case_without_subject() {
+        let program = "
+            a = 5
+            case
+            when a < 3 then 1
+            when a > 3 then 2
+            end
+        ";
+        let expected = Value::FixNum(2);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn case_in() {
+        let program = "
+            a = case 7 in 1 | 7 then 1 end
+            b = case 8 in 1..5 then 1 in Numeric then 2 end
+            [a, b]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(1), Value::FixNum(2)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_no_matching_pattern() {
+        let program = "case 9 in 1 then 2 end";
+        let expected = RuntimeErrorKind::NoMatchingPattern("9".to_string());
+        eval_script_error(program, expected, program);
+    }
+
+    #[test]
+    fn hash_literal() {
+        let program = "
+            h = {'a' => 1, b: 2, 3 => [4, 5], 'c': nil}
+            [h['a'], h[3][1], h['b'], h.size, h.keys.size]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(1),
+            Value::FixNum(5),
+            Value::Nil,
+            Value::FixNum(4),
+            Value::FixNum(4),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn hash_label_and_ternary() {
+        let program = r#"
+            a = nil
+            b = 1
+            c = 2
+            h = {k: a ? b : c, "s": 1}
+            assert(true, h == {k: 2, s: 1})
+            assert(2, a ? b : c)
+            false ? 1 : true ? 3 : 4
+        "#;
+        let expected = Value::FixNum(3);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn hash_assign() {
+        let program = "
+            h = {}
+            g = h
+            g['x'] = 1
+            h['y'] = 2
+            h['x'] = 3
+            [h.keys, h.values, h.key?('y'), h.key?('z'), h.delete('y'), h.delete('y')]
+        ";
+        let expected = Value::Array(vec![
+            Value::Array(vec![
+                Value::String("x".to_string()),
+                Value::String("y".to_string()),
+            ]),
+            Value::Array(vec![Value::FixNum(3), Value::FixNum(2)]),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::FixNum(2),
+            Value::Nil,
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn hash_keys_eql() {
+        let program = "
+            h = {1 => 'int', 1.5 => 'float', [1, 'a'] => 'array'}
+            [h[1], h[1.0], h[1.5], h[[1, 'a']]]
+        ";
+        let expected = Value::Array(vec![
+            Value::String("int".to_string()),
+            Value::Nil,
+            Value::String("float".to_string()),
+            Value::String("array".to_string()),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn hash_keys_by_content() {
+        let program = "
+            h = {{a: 1, b: 2} => 'hash', [{c: 3}] => 'nested'}
+            [h[{b: 2, a: 1}], h[{a: 1}], h[[{c: 3}]], h.key?({a: 1, b: 2}), {{} => 1}[{}]]
+        ";
+        let expected = Value::Array(vec![
+            Value::String("hash".to_string()),
+            Value::Nil,
+            Value::String("nested".to_string()),
+            Value::Bool(true),
+            Value::FixNum(1),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn include_p() {
+        let program = "
+            a = [1, 2].include? 2
+            [a, [1, 2].include?(3), 'taiko'.include?('ik'), {a: 1}.include?(:a)]
+        ";
+        let expected = Value::Array(vec![
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::Bool(true),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn fetch_block() {
+        let program = "
+            h = {'a' => 1}
+            [h.fetch('a') do |key| 0 end, h.fetch('b') do |key| key + '!' end, h.fetch('b', 2) do 3 end]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(1),
+            Value::String("b!".to_string()),
+            Value::FixNum(3),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn hash_iteration() {
+        let program = "
+            h = {'a' => 1, 'b' => 2, 'c' => 3}
+            sum = 0
+            h.each do |pair|
+              sum = sum + pair[1]
+            end
+            h.each_pair do |pair|
+              sum = sum + pair[1]
+            end
+            big = h.select do |pair| pair[1] > 1 end
+            [sum, h.map do |pair| pair[0] end, big.to_a, h.fetch('c'), h.fetch('d', 0)]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(12),
+            Value::Array(vec![
+                Value::String("a".to_string()),
+                Value::String("b".to_string()),
+                Value::String("c".to_string()),
+            ]),
+            Value::Array(vec![
+                Value::Array(vec![Value::String("b".to_string()), Value::FixNum(2)]),
+                Value::Array(vec![Value::String("c".to_string()), Value::FixNum(3)]),
+            ]),
+            Value::FixNum(3),
+            Value::FixNum(0),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn hash_merge_and_equality() {
+        let program = "
+            a = {'x' => 1, 'y' => 2}
+            b = a.merge({'y' => 3, 'z' => 4})
+            [a == {'y' => 2, 'x' => 1}, b == {'x' => 1, 'y' => 3, 'z' => 4}, a == b, a.size]
+        ";
+        let expected = Value::Array(vec![
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::FixNum(2),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn hash_to_s() {
+        let program = "{'a' => 1, b: [nil, 'c'], 2 => {}}.to_s";
+        let expected = Value::String("{\"a\" => 1, b: [nil, \"c\"], 2 => {}}".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_key() {
+        let program = "{'a' => 1}.fetch('b')";
+        let expected = RuntimeErrorKind::Key("key not found: \"b\"".to_string());
+        eval_script_error(program, expected, program);
+    }
+
+    #[test]
+    fn symbol_literal() {
+        let program = "[:foo, :\"with space\", :+, :[]=, :key?, :name=, {a: :b}[:a]].inspect";
+        let expected =
+            Value::String("[:foo, :\"with space\", :+, :[]=, :key?, :name=, :b]".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn symbol_conversions() {
+        let program = r#"
+            assert(:abc, "abc".to_sym)
+            assert("false", (:abc == :abd).to_s)
+            assert(:abc, :abc.to_s.to_sym)
+            assert("abc", :abc.to_s)
+            :to_s.to_proc.call(42)
+        "#;
+        let expected = Value::String("42".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn send1() {
+        let program = r#"
+            class Foo
+                def add(a, b)
+                    a + b
+                end
+            end
+            assert("7", 7.send("to_s"))
+            Foo.new.send(:add, 3, 4)
+        "#;
+        let expected = Value::FixNum(7);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn send_splat() {
+        let program = r#"
+            class Foo
+                def add(a, b, c)
+                    a + b + c
+                end
+            end
+            args = [1, 2, 3]
+            assert(6, Foo.new.send(:add, *args))
+            Foo.new.send(:add, *[10], *[20, 30])
+        "#;
+        let expected = Value::FixNum(60);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn call_splat() {
+        let program = r#"
+            def m(*a)
+                a
+            end
+            def add(a, b, c)
+                a + b + c
+            end
+            def apply(*a, &block)
+                block.call(*a)
+            end
+            arr = [1, 2, 3]
+            assert(6, add(*arr))
+            assert(6, add 1, *[2, 3])
+            assert(6, add(*[1], 2, *3))
+            assert([], m(*nil))
+            assert([[:a, 1]], m(*{a: 1}))
+            assert([1, 2, 3], m(*(1..3)))
+            assert([1, 2], m *[1, 2])
+            assert([2, 3], [1, 2].map(*[]) { |v| v + 1 })
+            assert(3, apply(*[1, 2], &:+))
+            x = 4
+            x * 2
+        "#;
+        let expected = Value::FixNum(8);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn yield_splat() {
+        let program = r#"
+            def y
+                yield 1, *[2, 3]
+            end
+            y { |a, b, c| a * 100 + b * 10 + c }
+        "#;
+        let expected = Value::FixNum(123);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_send() {
+        let program = "5.send(:frobnicate)";
+        let expected =
+            RuntimeErrorKind::NoMethod("undefined method `frobnicate' for Integer".to_string());
+        eval_script_error(program, expected, program);
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let program = r##"
+            class Point
+                def to_s
+                    "(1, 2)"
+                end
+            end
+            name = "world"
+            "Hello #{name}! #{1 + 2} #{[1, :a]} #{Point.new} #{"#{nil}"}"
+        "##;
+        let expected = Value::String("Hello world! 3 [1, :a] (1, 2) ".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn heredoc() {
+        let program = r##"
+            name = "taiko"
+            a = <<~EOS
+              Hello, #{name}
+                indented\tline
+            EOS
+            b = <<-'RAW'
+              #{name}
+              RAW
+            a + b
+        "##;
+        let expected =
+            Value::String("Hello, taiko\n  indented\tline\n              #{name}\n".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn float_to_s() {
+        let program =
+            "[3.0, 1.5e-3, 1e20, 1e15, 0.00001, -0.0, 0.1 + 0.2, 0.0 / 0.0, -1.0 / 0].to_s";
+        let expected = Value::String(
+            "[3.0, 0.0015, 1.0e+20, 1000000000000000.0, 1.0e-05, -0.0, 0.30000000000000004, NaN, -Infinity]"
+                .to_string(),
+        );
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn float_rounding() {
+        let program = "
+            assert(4, 3.5.round)
+            assert(-3, -2.5.round)
+            assert(3.14, 3.14159.round(2))
+            assert(1200, 1234.5.round(-2))
+            assert(-4, -3.2.floor)
+            assert(3.141, 3.14159.floor(3))
+            assert(4, 3.2.ceil)
+            assert(-20, -15.round(-1))
+            assert(3, 3.99.to_i)
+            assert(1.0, 1.to_f)
+            assert(nil, 1.5.infinite?)
+            (1.0 / 0).infinite?
+        ";
+        let expected = Value::FixNum(1);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn modulo_and_divmod() {
+        let program = "
+            assert(1.5, 7.5 % 2)
+            assert(0.5, -7.5 % 2)
+            assert(2, -7 % 3)
+            assert(-2, 7 % -3)
+            assert(-4, -7 / 2)
+            assert([3, 1.5], 7.5.divmod(2))
+            -7.divmod(2)
+        ";
+        let expected = Value::Array(vec![Value::FixNum(-4), Value::FixNum(1)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_float_domain() {
+        let program = "nan = 0.0 / 0.0\nnan.to_i";
+        let expected = RuntimeErrorKind::FloatDomain("NaN".to_string());
+        eval_script_error(program, expected, "nan.to_i");
+    }
+
+    #[test]
+    fn radix_literal() {
+        let program = "
+            assert(255, 0xff)
+            assert(8, 0o10)
+            assert(10, 0b1010)
+            assert(8, 010)
+            0x7FFF_FFFF_FFFF_FFFF + 0b1
+        ";
+        let expected = Value::BigNum("9223372036854775808".parse().unwrap());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn bignum_factorial() {
+        let program = "
+            def fact(a)
+              if a == 1
+                1
+              else
+                a * fact(a - 1)
+              end
+            end
+            f = fact(25)
+            assert(\"15511210043330985984000000\", f.to_s)
+            fact(30) / fact(28)
+        ";
+        let expected = Value::FixNum(870);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn bignum_arithmetic() {
+        let program = "
+            big = 9223372036854775807 + 1
+            assert(\"9223372036854775808\", big.to_s)
+            assert(9223372036854775807, big - 1)
+            small = -9223372036854775807 - 2
+            assert(\"-9223372036854775809\", small.to_s)
+
+            assert(1, big % 9223372036854775807)
+            assert([1, 1], big.divmod(9223372036854775807))
+            assert(big, \"9223372036854775808\".to_i)
+            assert(9.223372036854776e18, big.to_f)
+            big * big == 85070591730234615865843651857942052864
+        ";
+        let expected = Value::Bool(true);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn bignum_comparison() {
+        let program = "
+            big = 9223372036854775807 + 1
+            small = -big - 1
+            small < -9223372036854775807 && big > 9223372036854775807
+        ";
+        let expected = Value::Bool(true);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn arithmetic_operators() {
+        let program = "
+            assert(1, 8 / 4 / 2)
+            assert(3, 7 - 2 - 2)
+            assert(-4, -2 ** 2)
+            assert(4, (-2) ** 2)
+            assert(512, 2 ** 3 ** 2)
+            assert(0.5, 2 ** -1)
+            assert(2.25, 1.5 ** 2)
+            assert(1267650600228229401496703205376, 2 ** 100)
+            x = 3
+            assert(-9, -x ** 2)
+            assert(3, +x)
+            -x * 2
+        ";
+        let expected = Value::FixNum(-6);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let program = "
+            assert(2, 6 & 3)
+            assert(7, 6 | 3)
+            assert(5, 6 ^ 3)
+            assert(-7, ~6)
+            assert(40, 5 << 3)
+            assert(-3, -5 >> 1)
+            assert(2, 8 << -2)
+            assert(7, 1 | 2 & 6 ^ 4)
+            assert(18446744073709551616, 1 << 64)
+            assert(1, (1 << 64) >> 64)
+            assert(true, true & 1)
+            assert(false, nil | false)
+            assert(true, true ^ nil)
+            assert([1, 2, 3], [1, 2] | [2, 3])
+            [1, 2, 2] & [2, 3]
+        ";
+        let expected = Value::Array(vec![Value::FixNum(2)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn comparison_and_logical_operators() {
+        let program = "
+            assert(-1, 1 <=> 2)
+            assert(0, 2 <=> 2.0)
+            assert(1, \"b\" <=> \"a\")
+            assert(nil, 1 <=> \"a\")
+            assert(true, Integer === 3)
+            assert(false, (1..5) === 6)
+            assert(true, !nil)
+            assert(false, !1)
+            assert(true, (not false))
+            assert(true, 1 > 2 || 2 > 1)
+            assert(false, 1 > 2 || false)
+            1 < 2 && !(2 < 1)
+        ";
+        let expected = Value::Bool(true);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn operator_methods() {
+        let program = "
+            class Money
+              def set(cents)
+                @cents = cents
+                self
+              end
+              def cents
+                @cents
+              end
+              def +(other)
+                Money.new.set(@cents + other.cents)
+              end
+              def -@
+                Money.new.set(-@cents)
+              end
+              def ==(other)
+                @cents == other.cents
+              end
+              def <=>(other)
+                @cents <=> other.cents
+              end
+            end
+            a = Money.new.set(150)
+            b = Money.new.set(250)
+            sum = a + b
+            assert(400, sum.cents)
+            neg = -a
+            assert(-150, neg.cents)
+            assert(true, a == Money.new.set(150))
+            assert(true, a != b)
+            assert(-1, a <=> b)
+            sum = a.send(:+, b)
+            assert(400, sum.cents)
+            assert(8, 2.send(:**, 3))
+            :+.to_proc.call(1, 2)
+        ";
+        let expected = Value::FixNum(3);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_operator_method() {
+        let program = "class Foo\nend\nfoo = Foo.new\nfoo + 1";
+        let expected = RuntimeErrorKind::NoMethod("undefined method `+' for Foo".to_string());
+        eval_script_error(program, expected, "foo + 1");
+    }
+
+    #[test]
+    fn op_assign_variables() {
+        let program = "
+            a = 10
+            a += 5
+            a -= 3
+            a *= 2
+            a /= 4
+            a **= 2
+            a %= 7
+            assert(1, a)
+            b ||= 3
+            b ||= 4
+            assert(3, b)
+            b &&= b + 1
+            assert(4, b)
+            c = nil
+            c &&= 1
+            assert(nil, c)
+            @count ||= 0
+            @count += 2
+            assert(2, @count)
+            $total = 1
+            $total <<= 4
+            assert(16, $total)
+            $unset ||= 7
+            $unset
+        ";
+        let expected = Value::FixNum(7);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn op_assign_elements() {
+        let program = "
+            arr = [1, 2, 3]
+            arr[1] += 10
+            arr[-1] *= 2
+            arr[4] = 5
+            assert([1, 12, 6, nil, 5], arr)
+            arr[3] ||= 4
+            assert(4, arr[3])
+            h = {}
+            h[:a] ||= 1
+            h[:a] ||= 2
+            h[:a] += 1
+            assert(2, h[:a])
+            x = (arr[0] = 9)
+            assert(9, x)
+            arr[0]
+        ";
+        let expected = Value::FixNum(9);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn assign_nested_elements() {
+        let program = "
+            a = [1, [2, 3], [[4]]]
+            a[1][0] += 10
+            assert([1, [12, 3], [[4]]], a)
+            x = (a[1][1] = 7)
+            assert(7, x)
+            a[2][0][0] = 9
+            assert([1, [12, 7], [[9]]], a)
+            i = 0
+            a[i += 1][0] -= 2
+            assert(1, i)
+            a[1][1] ||= 100
+            a[1][1] &&= a[1][1] * 2
+            b, a[2][0][0] = 1, 2
+            assert([1, [10, 14], [[2]]], a)
+            h = {k: [1, 2]}
+            h[:k][0] = 5
+            h[:k][1] += 1
+            h[:k][1]
+        ";
+        let expected = Value::FixNum(3);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn op_assign_class_var() {
+        let program = "
+            class Config
+              def level
+                @@level ||= 5
+                @@level ||= 6
+                @@level
+              end
+            end
+            Config.new.level
+        ";
+        let expected = Value::FixNum(5);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_op_assign_undefined_class_var() {
+        let program = "
+            class Config
+              def bump
+                @@count += 1
+              end
+            end
+            Config.new.bump
+        ";
+        let expected =
+            RuntimeErrorKind::Name("uninitialized class variable @@count in Config".to_string());
+        eval_script_error(program, expected, "@@count");
+    }
+
+    #[test]
+    fn op_assign_attributes() {
+        let program = "
+            class Counter
+              def count
+                @calls = @calls + 1
+                @count
+              end
+              def count=(n)
+                @count = n
+              end
+              def calls
+                @calls
+              end
+              def reset
+                @calls = 0
+                self.count = nil
+              end
+            end
+            c = Counter.new
+            assert(nil, c.reset)
+            c.count ||= 5
+            c.count += 1
+            c.count ||= 100
+            assert(6, c.count)
+            c.calls
+        ";
+        let expected = Value::FixNum(4);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_index_assign() {
+        let program = "arr = [1, 2]\narr[-3] = 0";
+        let expected =
+            RuntimeErrorKind::Index("index -3 too small for array; minimum: -2".to_string());
+        eval_script_error(program, expected, "arr[-3] = 0");
+    }
+
+    #[test]
+    fn multi_assign_swap() {
+        let program = "
+            a, b = 1, 2
+            a, b = b, a
+            assert([2, 1], [a, b])
+            arr = [1, 2, 3]
+            arr[0], arr[2] = arr[2], arr[0]
+            assert([3, 2, 1], arr)
+            x = 5, 6
+            assert([5, 6], x)
+            c, d = 7
+            assert([7, nil], [c, d])
+            e, = [8, 9]
+            e
+        ";
+        let expected = Value::FixNum(8);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn multi_assign_splat() {
+        let program = "
+            first, *rest = [1, 2, 3, 4]
+            assert(1, first)
+            assert([2, 3, 4], rest)
+            *init, last = [1, 2, 3]
+            assert([[1, 2], 3], [init, last])
+            a, *b, c = [1]
+            assert([1, [], nil], [a, b, c])
+            a, *, c = 1, 2, 3, 4
+            assert([1, 4], [a, c])
+            (p, q), r = [1, 2], 3
+            assert([1, 2, 3], [p, q, r])
+            s, (t, *u), v = 1, [2, 3, 4], 5
+            [s, t, u, v]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(1),
+            Value::FixNum(2),
+            Value::Array(vec![Value::FixNum(3), Value::FixNum(4)]),
+            Value::FixNum(5),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn multi_assign_method_return() {
+        let program = "
+            class Point
+              def x=(v)
+                @x = v
+              end
+              def x
+                @x
+              end
+            end
+            def min_max(list)
+              [list[0], list[-1]]
+            end
+            lo, hi = min_max([1, 3, 5])
+            assert([1, 5], [lo, hi])
+            pt = Point.new
+            pt.x, @y, $z = min_max([2, 7])
+            assert([2, 7, nil], [pt.x, @y, $z])
+            r = (m, n = 10, 20)
+            r
+        ";
+        let expected = Value::Array(vec![Value::FixNum(10), Value::FixNum(20)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn method_optional_and_rest_params() {
+        let program = "
+            def pad(text, left = '-', right = left)
+              left + text + right
+            end
+            assert('-x-', pad('x'))
+            assert('<x<', pad('x', '<'))
+            assert('<x>', pad('x', '<', '>'))
+            def ends(first, *middle, last)
+              [first, middle, last]
+            end
+            assert([1, [], 2], ends(1, 2))
+            assert([1, [2, 3], 4], ends(1, 2, 3, 4))
+            def count(*)
+              0
+            end
+            count(1, 2, 3)
+        ";
+        let expected = Value::FixNum(0);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn method_keyword_params() {
+        let program = "
+            def box(width:, height: width * 2, **opts)
+              [width, height, opts]
+            end
+            w, h, opts = box(width: 1)
+            assert([1, 2, 0], [w, h, opts.size])
+            w, h, opts = box(height: 5, width: 1)
+            assert([1, 5], [w, h])
+            w, h, opts = box(width: 3, color: :red)
+            assert([3, 6, :red], [w, h, opts[:color]])
+            def tagged(label, *items, sep: ', ')
+              label + sep + items.size.to_s
+            end
+            assert('a, 2', tagged('a', 'x', 'y'))
+            tagged('b', 'x', 'y', sep: '-')
+        ";
+        let expected = Value::String("b-2".to_string());
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn method_keyword_trailing_hash() {
+        let program = "
+            def f(h, k: 1)
+              [h[:a], k]
+            end
+            assert([1, 1], f({a: 1}))
+            assert([1, 2], f({a: 1}, k: 2))
+            def g(**opts)
+              opts
+            end
+            g('x' => 1, y: 2)['x']
+        ";
+        let expected = Value::FixNum(1);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn method_block_param() {
+        let program = "
+            def run(&block)
+              if block
+                block.call(3)
+              else
+                :no_block
+              end
+            end
+            assert(:no_block, run())
+            self.run do |n|
+              n * 10
+            end
+        ";
+        let expected = Value::FixNum(30);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn method_argument_errors() {
+        let program = "
+            def one(a, b = 1)
+            end
+            def kw(a:, b:)
+            end
+            def many(a, *rest)
+            end
+            messages = []
+            begin
+              one()
+            rescue ArgumentError => e
+              messages[messages.size] = e.message
+            end
+            begin
+              one(1, 2, 3)
+            rescue ArgumentError => e
+              messages[messages.size] = e.message
+            end
+            begin
+              many()
+            rescue ArgumentError => e
+              messages[messages.size] = e.message
+            end
+            begin
+              kw(b: 1)
+            rescue ArgumentError => e
+              messages[messages.size] = e.message
+            end
+            begin
+              kw(a: 1, b: 2, c: 3, d: 4)
+            rescue ArgumentError => e
+              messages[messages.size] = e.message
+            end
+            messages
+        ";
+        let expected = Value::Array(vec![
+            Value::String("wrong number of arguments (given 0, expected 1..2)".to_string()),
+            Value::String("wrong number of arguments (given 3, expected 1..2)".to_string()),
+            Value::String("wrong number of arguments (given 0, expected 1+)".to_string()),
+            Value::String("missing keyword: :a".to_string()),
+            Value::String("unknown keywords: :c, :d".to_string()),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_wrong_number_of_arguments() {
+        let program = "
+            def pair(a, b)
+            end
+            pair(1)";
+        let expected = RuntimeErrorKind::Argument(
+            "wrong number of arguments (given 1, expected 2)".to_string(),
+        );
+        eval_script_error(program, expected, "pair(1)");
+    }
+
+    #[test]
+    fn command_call() {
+        let program = "
+            def add(a, b = 10, scale: 1)
+              (a + b) * scale
+            end
+            def two
+              2
+            end
+            assert 11, add(1)
+            assert 3, add 1, 2
+            assert 6, add 1, 2, scale: 2
+            x = 4
+            assert 3, x -1
+            assert -1, add -11
+            assert 5, two + 3
+            assert [1, 2], [1, 2]
+            class Stack
+              def push(item)
+                @items ||= []
+                @items[@items.size] = item
+                self
+              end
+              def items
+                @items
+              end
+            end
+            s = Stack.new
+            s.push 1
+            s.push two
+            s.items
+        ";
+        let expected = Value::Array(vec![Value::FixNum(1), Value::FixNum(2)]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn command_call_block() {
+        let program = "
+            def describe(value, &block)
+              if block
+                block.call(value)
+              else
+                value
+              end
+            end
+            a = describe [1, 2].size do |n|
+              n * 10
+            end
+            b = describe [1, 2].map { |n| n * 10 }
+            c = [1, 2].map do |n| n + 1 end
+            [a, b, c]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(20),
+            Value::Array(vec![Value::FixNum(10), Value::FixNum(20)]),
+            Value::Array(vec![Value::FixNum(2), Value::FixNum(3)]),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn yield_to_user_iterator() {
+        let program = "
+            def my_each(items)
+              i = 0
+              while i < items.size
+                yield items[i]
+                i += 1
+              end
+              items
+            end
+            def each_pair_of(items)
+              my_each(items) { |item| yield item, item * 10 }
+            end
+            sum = 0
+            my_each([1, 2, 3]) { |x| sum += x }
+            pairs = []
+            each_pair_of([1, 2]) do |a, b|
+              pairs[pairs.size] = a + b
+            end
+            found = my_each([4, 5, 6]) { |x| break x if x > 4 }
+            [sum, pairs, found]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(6),
+            Value::Array(vec![Value::FixNum(11), Value::FixNum(22)]),
+            Value::FixNum(5),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn block_params_destructuring() {
+        let program = "
+            def pairs
+              yield 1, 2
+              yield([3, 4])
+              yield 5
+            end
+            sums = []
+            pairs { |a, b| sums[sums.size] = [a, b] }
+            total = 0
+            {a: 1, b: 2}.each { |key, value| total += value }
+            [[1, [2, 3]], [4, [5, 6]]].each { |a, (b, c)| total += a * b * c }
+            rests = [[1, 2, 3]].map { |first, *rest| rest }
+            counts = 0
+            [1, 2].each { || counts += 1 }
+            [sums, total, rests, counts]
+        ";
+        let expected = Value::Array(vec![
+            Value::Array(vec![
+                Value::Array(vec![Value::FixNum(1), Value::FixNum(2)]),
+                Value::Array(vec![Value::FixNum(3), Value::FixNum(4)]),
+                Value::Array(vec![Value::FixNum(5), Value::Nil]),
+            ]),
+            Value::FixNum(129),
+            Value::Array(vec![Value::Array(vec![Value::FixNum(2), Value::FixNum(3)])]),
+            Value::FixNum(2),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn block_given() {
+        let program = "
+            def maybe
+              if block_given?
+                yield
+              else
+                \"none\"
+              end
+            end
+            [maybe, maybe { \"some\" }]
+        ";
+        let expected = Value::Array(vec![
+            Value::String("none".to_string()),
+            Value::String("some".to_string()),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_yield_without_block() {
+        let program = "
+            def each_one
+              yield 1
+            end
+            each_one";
+        let expected = RuntimeErrorKind::LocalJump("no block given (yield)".to_string());
+        eval_script_error(program, expected, "yield 1");
+    }
+
+    #[test]
+    fn closure_outlives_frame() {
+        let program = "
+            def counter
+              count = 0
+              increment = -> { count += 1 }
+              get = lambda { count }
+              [increment, get]
+            end
+            increment, get = counter
+            increment.call
+            increment.()
+            other, _ = counter
+            other.call
+            get.call
+        ";
+        eval_script(program, Value::FixNum(2));
+    }
+
+    #[test]
+    fn closure_captures_by_reference() {
+        let program = "
+            x = 10
+            add = proc { |a, b| a + (b || 0) + x }
+            x = 20
+            bump = -> { x += 1 }
+            bump.call
+            [add.call(1), add.call([1, 2]), add.(1, 2, 3), x]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(22),
+            Value::FixNum(24),
+            Value::FixNum(24),
+            Value::FixNum(21),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn lambda_arity_and_break() {
+        let program = "
+            square = ->(n) { n * n }
+            pair = -> a, b do [a, b] end
+            first = lambda { |v| break v * 2 }
+            [square[4], pair.call(1, 2), first.call(4), square.lambda?, proc { }.lambda?]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(16),
+            Value::Array(vec![Value::FixNum(1), Value::FixNum(2)]),
+            Value::FixNum(8),
+            Value::Bool(true),
+            Value::Bool(false),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn block_arg_conversion() {
+        let program = "
+            def run(&blk)
+              blk.call(5)
+            end
+            def each_item(items, &blk)
+              items.each(&blk)
+            end
+            square = ->(n) { n * n }
+            sum = 0
+            each_item([1, 2]) { |v| sum += v }
+            [run(&square), [1, 2].map(&square), [1, 2].map(&:to_s), sum]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(25),
+            Value::Array(vec![Value::FixNum(1), Value::FixNum(4)]),
+            Value::Array(vec![
+                Value::String("1".to_string()),
+                Value::String("2".to_string()),
+            ]),
+            Value::FixNum(3),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_lambda_arity() {
+        let program = "
+            pair = lambda { |a, b| [a, b] }
+            pair.call(1)";
+        let expected = RuntimeErrorKind::Argument(
+            "wrong number of arguments (given 1, expected 2)".to_string(),
+        );
+        eval_script_error(program, expected, "pair.call(1)");
+    }
+
+    #[test]
+    fn return_early() {
+        let program = "
+            def first_over(items, limit)
+              i = 0
+              while i < items.size
+                if items[i] > limit
+                  return items[i]
+                end
+                i += 1
+              end
+              return
+            end
+            def pair(a)
+              return a, a * 2
+              0
+            end
+            [first_over([1, 5, 9], 3), first_over([1], 3), pair(4)]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(5),
+            Value::Nil,
+            Value::Array(vec![Value::FixNum(4), Value::FixNum(8)]),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn return_from_block() {
+        let program = "
+            def find(items)
+              items.each { |x| return x * 10 if x == 2 }
+              nil
+            end
+            def nested(items)
+              items.each do |x|
+                [1].each { |y| return x + y if x > 1 }
+              end
+              0
+            end
+            def run(&blk)
+              blk.call
+              2
+            end
+            def through
+              run { return 1 }
+              3
+            end
+            def with_lambda
+              f = -> { return 1 }
+              f.call + 1
+            end
+            [find([1, 2, 3]), nested([1, 5]), through, with_lambda]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(20),
+            Value::FixNum(6),
+            Value::FixNum(1),
+            Value::FixNum(2),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn return_runs_ensure() {
+        let program = "
+            $log = 0
+            def leave
+              begin
+                return 1
+              ensure
+                $log += 1
+              end
+            end
+            def leave_block
+              [1].each do |x|
+                begin
+                  return x
+                ensure
+                  $log += 10
+                end
+              end
+            ensure
+              $log += 100
+            end
+            def stop
+              [1].each do
+                begin
+                  break 2
+                rescue
+                  $log += 10000
+                ensure
+                  $log += 1000
+                end
+              end
+            end
+            [leave, leave_block, stop, $log]
+        ";
+        let expected = Value::Array(vec![
+            Value::FixNum(1),
+            Value::FixNum(1),
+            Value::FixNum(2),
+            Value::FixNum(1111),
+        ]);
+        eval_script(program, expected);
+    }
+
+    #[test]
+    fn error_return_after_method() {
+        let program = "
+            def make
+              proc { return 1 }
+            end
+            make.call";
+        let expected = RuntimeErrorKind::LocalJump("unexpected return".to_string());
+        eval_script_error(program, expected, "return 1");
+    }
+
+    #[test]
+    fn error_stack_level_too_deep() {
+        let program = "
+            def f(n)
+              f(n + 1)
+            end
+            f(0)";
+        let mut parser = Parser::new();
+        let node = parser.parse_program(program.to_string()).unwrap();
+        let mut vm = VM::new();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        let err = vm.eval().unwrap_err();
+        let expected = RuntimeErrorKind::SystemStack("stack level too deep".to_string());
+        assert_eq!(err.kind, expected);
+        assert_eq!(&program[err.loc.0..=err.loc.1], "f(n + 1)");
+
+        let program = "
+            def g(n)
+              g(n - 1) if n > 0
+            end
+            g(20)";
+        let mut vm = VM::new();
+        vm.max_call_depth = 10;
+        let mut parser = Parser::new();
+        let node = parser.parse_program(program.to_string()).unwrap();
+        vm.init(parser.lexer.source_info, parser.ident_table, node);
+        assert_eq!(vm.eval().unwrap_err().kind, expected);
+    }
 }